@@ -1,172 +1,5036 @@
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{BuildHasher, Hasher};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use ncmapi::{NcmApi, ResourceType, SearchType, types::{Album, Playlist, RecommendedSongs, ResourceComments, Song, UserProfile}};
+use bytes::Bytes;
+use log::{debug, error, warn};
+
+use ncmapi::{ResourceType, SearchType, types::{Album, Artist, Playlist, Song, UserProfile}};
 use serde_json::Value;
+use termion::event::{Key, MouseButton, MouseEvent};
+
+use crate::{browser::{self, Opener, Page, SystemOpener}, cache::TrackCache, config::AppConfig, checkpoint::{self, Checkpoint}, clipboard::{self, Clipboard, SystemClipboard}, event::{DataEvent, IoEvent, LoginStatus}, fuzzy, keybindings::{Action, KeyBindings}, logger::{self, LoggerError}, lyric::Lyric, m3u, media::{MediaCommand, MediaMetadata, NowPlaying}, scrobble::{self, ScrobbleEvent}, theme::Theme, undo::{UndoAction, UndoStack}, player::{AudioPlayer, LAudioPlayer, NullAudioPlayer, PlaybackContext, PlaybackEvent, PlaybackSnapshot, PlayerError, RepeatMode, TrackKind, PREV_RESTART_MS}, ui::{format_duration, Marquee}};
+
+// how long the volume and the rest of the playback state stay untouched before they are saved
+const VOLUME_DEBOUNCE: Duration = Duration::from_secs(1);
+// lyrics fetched ahead for the upcoming queue, at most
+const MAX_LYRIC_PREFETCH: usize = 3;
+// personal FM asks for the next batch once this few songs are left after the playing one
+const FM_REFILL_LEFT: usize = 1;
+// ids given to imported local files count down from here, far above any
+// NetEase song id
+const LOCAL_ID_TOP: usize = usize::MAX;
+// local files the player can decode
+const LOCAL_EXTENSIONS: [&str; 7] = ["mp3", "flac", "ogg", "opus", "wav", "m4a", "aac"];
+// recommendations are refreshed once a day by the api
+const RECOMMENDATIONS_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+// progress between two ticks beyond this is a seek, not listening
+const LISTEN_STEP_MAX_MS: u64 = 5000;
+// a streamed episode not moving on for this long is waiting for its download
+const BUFFERING_AFTER: Duration = Duration::from_secs(1);
+// how long a notification stays up as a toast
+pub const TOAST_TTL: Duration = Duration::from_secs(4);
+// toasts stacked at once, older ones make room
+const TOASTS_SHOWN: usize = 3;
+// notifications kept for `notifications`
+const NOTIFICATIONS_MAX: usize = 50;
+
+/// A page of search results, one variant per kind searched.
+#[derive(Debug, Clone)]
+pub enum SearchResult {
+    Song(Vec<Song>),
+    Album(Vec<Album>),
+    Artist(Vec<Artist>),
+    Playlist(Vec<Playlist>),
+    Podcast(Vec<Podcast>),
+}
+
+impl SearchResult {
+    /// The search these results answer, to ask for another page of them.
+    pub fn kind(&self) -> SearchType {
+        match self {
+            SearchResult::Song(_) => SearchType::Song,
+            SearchResult::Album(_) => SearchType::Album,
+            SearchResult::Artist(_) => SearchType::Artist,
+            SearchResult::Playlist(_) => SearchType::Playlist,
+            SearchResult::Podcast(_) => SearchType::Podcast,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            SearchResult::Song(songs) => songs.len(),
+            SearchResult::Album(albums) => albums.len(),
+            SearchResult::Artist(artists) => artists.len(),
+            SearchResult::Playlist(playlists) => playlists.len(),
+            SearchResult::Podcast(podcasts) => podcasts.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Route {
+    Home,
+    Login,
+    // results of the last search
+    Search,
+    // lyrics of the playing song
+    Lyrics,
+    Recommended,
+    PersonalFm,
+    LastPlayed,
+    Album(usize),
+    Artist(usize),
+    Playlist(usize),
+    // episodes of a podcast
+    Podcast(usize),
+    // comments of the resource last asked for
+    Comments,
+}
+
+/// A podcast found by searching, its episodes are listed by `Route::Podcast`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Podcast {
+    pub id: usize,
+    pub name: String,
+    // nickname of the host
+    pub dj: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Comment {
+    pub id: usize,
+    // nickname of the commenter
+    pub author: String,
+    pub content: String,
+    pub liked_count: u64,
+    // milliseconds since epoch
+    pub time: i64,
+}
+
+/// Order comments are listed in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommentSort {
+    // most liked first
+    Hot,
+    // newest first
+    Latest,
+}
+
+impl CommentSort {
+    pub fn name(self) -> &'static str {
+        match self {
+            CommentSort::Hot => "Hot",
+            CommentSort::Latest => "Latest",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    Info,
+    Warning,
+    Error,
+}
+
+/// A message for the user, shown as a toast for `TOAST_TTL`.
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub level: Level,
+    pub message: String,
+    pub at: Instant,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PodcastEpisode {
+    pub id: usize,
+    // song played through `SongUrls` for this episode
+    pub song_id: usize,
+    pub title: String,
+    pub duration_ms: u64,
+    // milliseconds since epoch
+    pub publish_time: i64,
+    pub played: bool,
+    // where to pick up again after switching away mid-episode
+    pub resume_ms: Option<u64>,
+}
 
-use crate::{event::IoEvent, player::{AudioPlayer, LAudioPlayer, PlaybackContext}};
+impl Route {
+    /// The route a `startup_route` setting names, `None` for unknown names.
+    pub fn from_startup_name(name: &str) -> Option<Self> {
+        match name {
+            "home" => Some(Route::Home),
+            "recommended" => Some(Route::Recommended),
+            "personal_fm" => Some(Route::PersonalFm),
+            "last_played" => Some(Route::LastPlayed),
+            _ => None,
+        }
+    }
+}
+
+/// Choosing which of the user's playlists songs get added to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaylistPicker {
+    pub song_ids: Vec<usize>,
+    // selected row in `user_playlists`
+    pub index: usize,
+}
 
-struct AppConfig {}
+// songs already taken out of the track table, put back if the api refuses
+struct PendingRemoval {
+    playlist_id: usize,
+    song_ids: Vec<usize>,
+    // (row in the track table, song)
+    rows: Vec<(usize, Song)>,
+}
 
-pub(crate) enum SearchResult {
-    Song,
-    Album,
-    Artist,
-    Playlist,
-    Podcast,
+// the song playing, as Last.fm will be told about it
+struct Listen {
+    track: scrobble::Track,
+    duration_ms: Option<u64>,
 }
 
 pub struct App {
     config: AppConfig,
+    // screens navigated through, the last one is shown and it's never empty
+    routes: Vec<Route>,
 
     player: Box<dyn AudioPlayer>,
     playback_context: Option<PlaybackContext>,
 
     user: Option<UserProfile>,
+    liked_song_ids: HashSet<usize>,
     fm: Option<Vec<Song>>,
+    // the queue and its playing index before personal FM took over, `Some` in FM mode
+    pre_fm_queue: Option<(Vec<Song>, Option<usize>)>,
+    // a batch of FM songs was asked for and hasn't arrived
+    fm_fetching: bool,
     cloud: Option<Vec<Song>>,
     user_playlists: Option<Vec<Playlist>>,
     user_fav_playlists: Option<Vec<Playlist>>,
     user_fav_albums: Option<Vec<Album>>,
     // user_fav_artists: Option<Vec<Artist>>,
-    recommended_songs: Option<RecommendedSongs>,
+    recommended_songs: Option<Vec<Song>>,
+    // when `recommended_songs` were fetched
+    recommended_at: Option<Instant>,
+    // play the daily mix as soon as fresh recommendations arrive
+    pending_daily: bool,
     recommended_playlists: Option<Vec<Playlist>>,
     recently_played: Option<Vec<Song>>,
-    comments: Option<Vec<ResourceComments>>,
+    // comments loaded so far of `comment_resource`, page after page
+    comments: Option<Vec<Comment>>,
+    comment_resource: Option<(usize, ResourceType)>,
+    comment_sort: CommentSort,
+    comment_index: usize,
+    // the api has more pages, and the next one is being fetched
+    comments_more: bool,
+    comments_fetching: bool,
+    // lyrics by song id, with the ids still being fetched
+    lyrics: HashMap<usize, Lyric>,
+    lyric_requests: HashSet<usize>,
     search_limit: u8,
+    // last query searched and the offset of the page shown
+    search_query: Option<String>,
+    search_offset: usize,
     search_results: Option<SearchResult>,
+    // selected row of album, artist, playlist and podcast results
+    search_index: usize,
     selected_playlist_index: usize,
     active_playlist_index: Option<usize>,
     seek_ms: Option<u128>,
     track_table: Option<Vec<Song>>,
     // artist_table: Option<Vec<Song>>,
     track_table_index: usize,
+    // song waiting for its url/bytes before being played
+    pending_play: Option<usize>,
+    // with gapless on, the song after the playing one while it's fetched for
+    // the player's queue: its queue index and id
+    prefetch: Option<(usize, usize)>,
+    // the same once it's in the player's queue
+    prefetched: Option<(usize, usize)>,
+    // tracks being played, independent from what the track table shows
+    queue: Vec<Song>,
+    queue_index: Option<usize>,
+    // while shuffling: queue indices still to come, and the ones played before the current one
+    shuffle_order: VecDeque<usize>,
+    shuffle_history: Vec<usize>,
+    // songs without a playable url, grayed out and skipped
+    unavailable: HashSet<usize>,
+    // current track already counted as played
+    scrobbled: bool,
+    // Last.fm scrobbling, and how long the playing song was listened to
+    scrobble_tx: Option<Sender<ScrobbleEvent>>,
+    listen: Option<Listen>,
+    // progress of the playing track actually played through, and the
+    // progress at the last tick
+    listened_ms: u64,
+    listen_progress_ms: u64,
+    // replays left of the current track in `RepeatMode::Count`
+    repeats_left: u32,
+    podcast_episodes: Vec<PodcastEpisode>,
+    // selected row of the episodes shown
+    episode_index: usize,
+    // episode id being played
+    playing_episode: Option<usize>,
+    // resume positions by episode id, kept while other podcasts are shown
+    episode_positions: HashMap<usize, u64>,
+    // progress last seen and since when it hasn't moved
+    stall: Option<(u64, Instant)>,
 
-    loading: bool,
+    // search input, `Some` while the user is typing a query
+    search_input: Option<String>,
+    // narrows the track table down, `Some` while it applies
+    track_filter: Option<String>,
+    // keys go to the filter until it's committed with enter
+    filter_typing: bool,
+    // song selected before filtering, selected again once the filter is cleared
+    pre_filter_selection: Option<usize>,
+    // committed queries, most recent first
+    search_history: VecDeque<String>,
+    // suggestion picked with the arrows while the input is empty
+    search_history_index: Option<usize>,
+    // no persistence without a path
+    search_history_path: Option<PathBuf>,
+    // crash checkpoints are only kept with a path
+    checkpoint_path: Option<PathBuf>,
+    // volume, speed, repeat and mute kept across restarts, written once they
+    // stay untouched for `VOLUME_DEBOUNCE`
+    playback_state_path: Option<PathBuf>,
+    playback_state_changed: Option<Instant>,
+    // progress of the last checkpoint written for the current track
+    checkpoint_ms: Option<u64>,
+    // left by a crashed session, offered on start
+    resume_offer: Option<Checkpoint>,
+    // where to seek once the resumed track is downloaded
+    resume_at: Option<u64>,
+    // the song being played, `None` for episodes or once ejected
+    now_playing: Option<Song>,
+    // now playing title in the playbar
+    title_marquee: Marquee,
+    playlist_picker: Option<PlaylistPicker>,
+    // (playlist id, song ids) waiting for the user to confirm their removal
+    confirm_remove: Option<(usize, Vec<usize>)>,
+    pending_removals: Vec<PendingRemoval>,
+    undo: UndoStack,
+    // oldest first, at most `NOTIFICATIONS_MAX`
+    notifications: Vec<Notification>,
+    // toasts shown at the last tick, a change redraws
+    live_toasts: usize,
+    // last request that failed, until the next key press
+    error: Option<String>,
+    // picUrl of the cover wanted by the art renderer, with its bytes once fetched
+    cover_url: Option<String>,
+    cover: Option<Bytes>,
+    // played tracks on disk, nothing is cached without one
+    track_cache: Option<TrackCache>,
+    // what media controls like MPRIS show
+    media_now_playing: Option<NowPlaying>,
+    // files imported from m3u playlists, by the id of their queued song
+    local_tracks: HashMap<usize, PathBuf>,
+    // opened on first use, stays `None` when there's no clipboard
+    clipboard: Option<Box<dyn Clipboard>>,
+    opener: Box<dyn Opener>,
+    key_bindings: KeyBindings,
+    theme: Theme,
+
+    // requests sent to the io worker still unanswered
+    loading: usize,
+    // frame of the loading spinner, it steps every tick while loading
+    spinner_frame: usize,
+    // the first help row shown while the help is open
+    help_scroll: Option<usize>,
+    // quit asked for, waiting for the user to confirm
+    confirming_quit: bool,
+    // the main loop exits once set
+    should_quit: bool,
+    // something visible changed since the last frame
+    dirty: bool,
+    // progress second shown by the last frame
+    drawn_second: Option<u64>,
     // logged in or not
     auth: bool,
 
     io_tx: Option<Sender<IoEvent>>,
+    playback_tx: Option<Sender<PlaybackEvent>>,
 }
 
 impl Default for App {
     fn default() -> Self {
+        let player: Box<dyn AudioPlayer> = match LAudioPlayer::try_new() {
+            Ok(player) => Box::new(player),
+            Err(e) => {
+                // keep the rest of the app usable, just silent
+                warn!("{}, playing without sound", e);
+                Box::new(NullAudioPlayer::new())
+            }
+        };
+        Self::with_player(player)
+    }
+}
+
+impl App {
+    fn with_player(player: Box<dyn AudioPlayer>) -> Self {
+        let config = AppConfig::default();
+        let undo = UndoStack::new(config.undo_depth);
         Self {
-            config: AppConfig {},
+            now_playing: None,
+            title_marquee: Marquee::new("").with_speed(config.marquee_speed),
+            config,
+            routes: vec![Route::Home],
             playback_context: None,
-            player: Box::new(LAudioPlayer::try_new().unwrap()),
+            player,
             user: None,
+            liked_song_ids: HashSet::new(),
             fm: None,
+            pre_fm_queue: None,
+            fm_fetching: false,
             cloud: None,
             user_playlists: None,
             user_fav_albums: None,
             user_fav_playlists: None,
             recommended_songs: None,
+            recommended_at: None,
+            pending_daily: false,
             recommended_playlists: None,
             recently_played: None,
             comments: None,
-            search_limit: 20,
+            comment_resource: None,
+            comment_sort: CommentSort::Hot,
+            comment_index: 0,
+            comments_more: false,
+            comments_fetching: false,
+            lyrics: HashMap::new(),
+            lyric_requests: HashSet::new(),
+            search_limit: config.search_limit,
+            search_query: None,
+            search_offset: 0,
             search_results: None,
+            search_index: 0,
             selected_playlist_index: 0,
             active_playlist_index: None,
             seek_ms: None,
             track_table: None,
             track_table_index: 0,
+            pending_play: None,
+            prefetch: None,
+            prefetched: None,
+            queue: Vec::new(),
+            queue_index: None,
+            shuffle_order: VecDeque::new(),
+            shuffle_history: Vec::new(),
+            unavailable: HashSet::new(),
+            scrobbled: false,
+            scrobble_tx: None,
+            listen: None,
+            listened_ms: 0,
+            listen_progress_ms: 0,
+            repeats_left: 0,
+            podcast_episodes: Vec::new(),
+            episode_index: 0,
+            playing_episode: None,
+            episode_positions: HashMap::new(),
+            stall: None,
+            search_input: None,
+            track_filter: None,
+            filter_typing: false,
+            pre_filter_selection: None,
+            search_history: VecDeque::new(),
+            search_history_index: None,
+            search_history_path: None,
+            checkpoint_path: None,
+            playback_state_path: None,
+            playback_state_changed: None,
+            checkpoint_ms: None,
+            resume_offer: None,
+            resume_at: None,
+            playlist_picker: None,
+            confirm_remove: None,
+            pending_removals: Vec::new(),
+            undo,
+            notifications: Vec::new(),
+            live_toasts: 0,
+            error: None,
+            cover_url: None,
+            cover: None,
+            track_cache: None,
+            media_now_playing: None,
+            local_tracks: HashMap::new(),
+            clipboard: None,
+            opener: Box::new(SystemOpener),
+            key_bindings: KeyBindings::default(),
+            theme: Theme::default(),
 
-            loading: false,
+            loading: 0,
+            spinner_frame: 0,
+            help_scroll: None,
+            confirming_quit: false,
+            should_quit: false,
+            dirty: true,
+            drawn_second: None,
             io_tx: None,
+            playback_tx: None,
             auth: false,
             //
         }
     }
-}
 
-impl App {
     pub fn new(player: Box<dyn AudioPlayer>, io_tx: Sender<IoEvent>) -> Self {
         Self {
-            player,
             io_tx: Some(io_tx),
-            ..Self::default()
+            ..Self::with_player(player)
         }
     }
 
     pub fn set_player(mut self, player: Box<dyn AudioPlayer>) -> Self {
         self.player = player;
+        self.player.set_gapless(self.config.gapless);
+        self.watch_track_end();
+        self
+    }
+
+    pub fn set_clipboard(mut self, clipboard: Box<dyn Clipboard>) -> Self {
+        self.clipboard = Some(clipboard);
+        self
+    }
+
+    pub fn set_opener(mut self, opener: Box<dyn Opener>) -> Self {
+        self.opener = opener;
+        self
+    }
+
+    /// Applies the user's settings, see `AppConfig::load`. Call it before the
+    /// other setters, the cache and key bindings it sets up can be replaced by them.
+    pub fn set_config(mut self, config: AppConfig) -> Self {
+        self.title_marquee = Marquee::new("").with_speed(config.marquee_speed);
+        self.undo = UndoStack::new(config.undo_depth);
+        self.search_limit = config.search_limit;
+        self.player.playback_context().set_volume_percent(config.volume);
+        self.player.set_gapless(config.gapless);
+        if let Some(path) = &config.key_bindings_path {
+            match KeyBindings::load(path) {
+                Ok(key_bindings) => self.key_bindings = key_bindings,
+                Err(e) => error!("key bindings {:?}: {}", path, e),
+            }
+        }
+        if let Some(cache) = &self.track_cache {
+            self.track_cache = Some(TrackCache::new(cache.dir(), config.cache_max_bytes));
+        }
+        let (theme, warnings) = Theme::load(config.theme(), config.themes());
+        for warning in warnings {
+            warn!("{}", warning);
+            self.notify(Level::Warning, warning);
+        }
+        self.theme = theme;
+        self.config = config;
+        self
+    }
+
+    /// Time between two ticks of the event loop.
+    pub fn tick_rate(&self) -> Duration {
+        self.config.tick_rate()
+    }
+
+    /// Name of the configured color theme.
+    pub fn theme_name(&self) -> &str {
+        self.config.theme()
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    pub fn set_key_bindings(mut self, key_bindings: KeyBindings) -> Self {
+        self.key_bindings = key_bindings;
+        self
+    }
+
+    /// Keeps the search history in `path`, loading what's there.
+    pub fn set_search_history_path(mut self, path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        self.search_history = read_search_history(&path, self.config.search_history_len);
+        self.search_history_path = Some(path);
+        self
+    }
+
+    /// Keeps `now_playing` up to date for media controls, see `media`.
+    pub fn set_media_now_playing(mut self, now_playing: NowPlaying) -> Self {
+        self.media_now_playing = Some(now_playing);
+        self
+    }
+
+    /// Reports plays to Last.fm through `tx`, see `scrobble::spawn`.
+    pub fn set_scrobbler(mut self, tx: Sender<ScrobbleEvent>) -> Self {
+        self.scrobble_tx = Some(tx);
+        self
+    }
+
+    /// Keeps played tracks in `dir`, replaying them from there. See `TrackCache::default_dir`.
+    pub fn set_cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.track_cache = Some(TrackCache::new(dir, self.config.cache_max_bytes));
+        self
+    }
+
+    /// Writes crash checkpoints to `path`, offering the one found there on start.
+    pub fn set_checkpoint_path(mut self, path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        self.resume_offer = checkpoint::read(&path);
+        self.checkpoint_path = Some(path);
+        self
+    }
+
+    /// Keeps volume, speed, repeat mode and mute in `path`, restoring what's there.
+    pub fn set_playback_state_path(mut self, path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        match self.player.playback_context().load(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                error!("load playback state {:?} error: {}", path, e)
+            }
+            _ => {}
+        }
+        self.playback_state_path = Some(path);
+        self
+    }
+
+    // writes the playback state right away, on quit or once changes settled
+    fn save_playback_state(&mut self) {
+        self.playback_state_changed = None;
+        if let Some(path) = &self.playback_state_path {
+            if let Err(e) = self.player.playback_context().save(path) {
+                error!("save playback state {:?} error: {}", path, e);
+            }
+        }
+    }
+
+    /// Installs the file logger set up in the config, call it before the tui takes the terminal.
+    pub fn init_logging(&self) -> Result<(), LoggerError> {
+        match &self.config.log_path {
+            Some(path) => logger::init(path, self.config.log_level),
+            None => Ok(()),
+        }
+    }
+
+    pub fn cache_dir(&self) -> Option<&Path> {
+        self.track_cache.as_ref().map(TrackCache::dir)
+    }
+
+    /// Removes every cached track, they're downloaded again when played.
+    pub fn clear_cache(&mut self) {
+        if let Some(cache) = &self.track_cache {
+            if let Err(e) = cache.clear() {
+                error!("clear track cache {:?} error: {}", cache.dir(), e);
+            }
+        }
+    }
+
+    /// Quits right away, or shows the confirmation prompt when `confirm_quit` is set.
+    pub fn request_quit(&mut self) {
+        if self.config.confirm_quit {
+            self.confirming_quit = true;
+        } else {
+            self.should_quit = true;
+        }
+    }
+
+    pub fn is_confirming_quit(&self) -> bool {
+        self.confirming_quit
+    }
+
+    pub fn key_bindings(&self) -> &KeyBindings {
+        &self.key_bindings
+    }
+
+    /// The first help row shown, `None` while the help is closed.
+    pub fn help_scroll(&self) -> Option<usize> {
+        self.help_scroll
+    }
+
+    // a row per category and action, a blank one between categories
+    fn help_rows(&self) -> usize {
+        let help = self.key_bindings.help();
+        let actions: usize = help.iter().map(|(_, actions)| actions.len()).sum();
+        actions + 2 * help.len() - 1
+    }
+
+    // scrolls the help with the keys selecting rows, any other key closes it
+    fn handle_help_action(&mut self, scroll: usize, key: Key) {
+        let last = self.help_rows() - 1;
+        self.help_scroll = match (key, self.key_bindings.action(key)) {
+            (Key::Down, _) | (_, Some(Action::SelectNext)) => Some((scroll + 1).min(last)),
+            (Key::Up, _) | (_, Some(Action::SelectPrev)) => Some(scroll.saturating_sub(1)),
+            _ => None,
+        };
+    }
+
+    /// Set once the user quit, the main loop should then call `quit`.
+    pub fn should_quit(&self) -> bool {
+        self.should_quit
+    }
+
+    /// Consumes the app on quit. With `keep_playing` the current song is
+    /// played to the end before this returns, otherwise playback stops.
+    pub fn quit(mut self) {
+        if self.playback_state_changed.is_some() {
+            self.save_playback_state();
+        }
+        if let Some(path) = &self.checkpoint_path {
+            checkpoint::clear(path);
+        }
+        if self.config.keep_playing {
+            self.detach_player();
+        } else {
+            self.player.stop();
+        }
+    }
+
+    pub fn detach_player(self) {
+        self.player.detach();
+    }
+
+    /// Playback events go to `playback_tx`, `Ended` too once the player finishes a track.
+    pub fn set_playback_tx(mut self, playback_tx: Sender<PlaybackEvent>) -> Self {
+        self.playback_tx = Some(playback_tx);
+        self.watch_track_end();
         self
     }
 
+    // has the player report the end of a track on the playback channel
+    fn watch_track_end(&self) {
+        if let Some(playback_tx) = &self.playback_tx {
+            let ended_tx = playback_tx.clone();
+            self.player.playback_context().on_track_end(Box::new(move || {
+                let _ = ended_tx.send(PlaybackEvent::Ended);
+            }));
+        }
+    }
+
+    fn emit(&self, evt: PlaybackEvent) {
+        if let Some(playback_tx) = &self.playback_tx {
+            if let Err(e) = playback_tx.send(evt) {
+                error!("emit playback event error: {:?}", e);
+            }
+        }
+    }
+
     // network
-    fn dispatch(&self, action: IoEvent) {
+    fn dispatch(&mut self, action: IoEvent) {
         if let Some(io_tx) = &self.io_tx {
-            if let Err(e) = io_tx.send(action) {
-                println!("dispatch io event error: {:?}", e);
+            let awaits_reply = action.awaits_reply();
+            match io_tx.send(action) {
+                Ok(()) if awaits_reply => self.loading += 1,
+                Ok(()) => {}
+                Err(e) => {
+                    error!("dispatch io event error: {:?}", e);
+                    // the io worker is gone, nothing asked for comes back
+                    self.notify(Level::Error, "Lost the connection to the io worker");
+                }
             }
         }
     }
 
-    pub fn signin(&self, phone: String, passwd: String) {
+    /// Tries the stored session first, the login screen is only shown when it's expired.
+    pub fn start(&mut self) {
+        self.refresh_login();
+        if let Some(offer) = &self.resume_offer {
+            let msg = format!(
+                "Resume {} from {}? Press R",
+                offer.name,
+                format_duration(offer.progress_ms)
+            );
+            self.notify(Level::Info, msg);
+        }
+
+        let name = self.config.startup_route.as_str();
+        let route = Route::from_startup_name(name).unwrap_or_else(|| {
+            log::warn!("unknown startup route {:?}, starting at home", name);
+            Route::Home
+        });
+        match route {
+            Route::Recommended => self.recommended_songs(),
+            Route::PersonalFm => self.fetch_fm(),
+            Route::LastPlayed => self.recently_played(),
+            _ => {}
+        }
+        self.routes = vec![route];
+    }
+
+    pub fn refresh_login(&mut self) {
+        self.dispatch(IoEvent::RefreshLogin);
+    }
+
+    pub fn signin(&mut self, phone: String, passwd: String) {
         self.dispatch(IoEvent::Signin(phone, passwd));
     }
 
-    pub fn user(&self) {
+    /// Forgets the user and their playlists, the stored session is removed too.
+    pub fn logout(&mut self) {
+        self.user = None;
+        self.auth = false;
+        self.user_playlists = None;
+        self.user_fav_playlists = None;
+        self.liked_song_ids.clear();
+        self.selected_playlist_index = 0;
+        self.active_playlist_index = None;
+        self.routes = vec![Route::Login];
+        self.dirty = true;
+        self.dispatch(IoEvent::Logout);
+    }
+
+    pub fn user(&mut self) {
         self.dispatch(IoEvent::UserProfile);
     }
 
-    pub fn user_playlists(&self) {
+    pub fn user_playlists(&mut self) {
         self.dispatch(IoEvent::UserPlaylists);
     }
 
-    pub fn recommended_songs(&self) {
+    pub fn recommended_songs(&mut self) {
         self.dispatch(IoEvent::RecommendedSongs);
     }
 
-    pub fn recommended_playlists(&self) {
+    pub fn recommended_playlists(&mut self) {
         self.dispatch(IoEvent::RecommendedPlaylists);
     }
 
-    pub fn playlist_detail(&self, id: usize) {
+    pub fn playlist_detail(&mut self, id: usize) {
         self.dispatch(IoEvent::PlaylistDetail(id));
     }
 
-    pub fn search(&self, key: String, t: SearchType) {
-        self.dispatch(IoEvent::Search(key, t));
+    /// Asks for a page of up to `search_limit` results, skipping the first `offset`.
+    pub fn search(&mut self, key: String, t: SearchType, offset: usize) {
+        self.dispatch(IoEvent::Search {
+            key,
+            kind: t,
+            offset,
+            limit: self.search_limit as usize,
+        });
+    }
+
+    /// Shows the comments of resource `id`, fetching their first page.
+    pub fn comments(&mut self, id: usize, t: ResourceType) {
+        self.comment_resource = Some((id, t));
+        self.comments = None;
+        self.comment_index = 0;
+        self.comments_more = true;
+        self.comments_fetching = false;
+        self.push_route(Route::Comments);
+        self.load_more_comments();
+    }
+
+    /// Fetches the next page of comments, unless they were all loaded.
+    pub fn load_more_comments(&mut self) {
+        let (id, kind) = match &self.comment_resource {
+            Some((id, kind)) if self.comments_more && !self.comments_fetching => (*id, kind.clone()),
+            _ => return,
+        };
+        let loaded = self.comments.as_deref().unwrap_or_default();
+        // latest comments go on from the oldest one loaded
+        let before = match self.comment_sort {
+            CommentSort::Latest => loaded.last().map(|comment| comment.time),
+            CommentSort::Hot => None,
+        };
+        let offset = loaded.len();
+        self.comments_fetching = true;
+        self.dispatch(IoEvent::Comments {
+            id,
+            kind,
+            sort: self.comment_sort,
+            offset,
+            before,
+        });
+    }
+
+    /// Lists the comments in `sort` order instead, from the first page.
+    pub fn set_comment_sort(&mut self, sort: CommentSort) {
+        if sort == self.comment_sort {
+            return;
+        }
+        self.comment_sort = sort;
+        if let Some((id, kind)) = self.comment_resource.take() {
+            self.comments(id, kind);
+        }
+    }
+
+    pub fn toggle_comment_sort(&mut self) {
+        let sort = match self.comment_sort {
+            CommentSort::Hot => CommentSort::Latest,
+            CommentSort::Latest => CommentSort::Hot,
+        };
+        self.set_comment_sort(sort);
+    }
+
+    // whether the page at `offset` is the next one of the comments shown
+    fn is_comment_page(
+        &self,
+        id: usize,
+        kind: &ResourceType,
+        sort: CommentSort,
+        offset: usize,
+    ) -> bool {
+        let shown = match &self.comment_resource {
+            // `ResourceType` can't be compared itself
+            Some((shown, shown_kind)) => {
+                *shown == id && std::mem::discriminant(shown_kind) == std::mem::discriminant(kind)
+            }
+            None => false,
+        };
+        shown && sort == self.comment_sort && offset == self.comment_list().len()
+    }
+
+    pub fn comment_list(&self) -> &[Comment] {
+        self.comments.as_deref().unwrap_or_default()
+    }
+
+    pub fn comment_sort(&self) -> CommentSort {
+        self.comment_sort
+    }
+
+    /// Selected comment, the list scrolls to keep it visible.
+    pub fn comment_index(&self) -> usize {
+        self.comment_index
+    }
+
+    pub fn has_more_comments(&self) -> bool {
+        self.comments_more
     }
 
-    pub fn comments(&self, id: usize, t: ResourceType) {
-        self.dispatch(IoEvent::Comments(id, t));
+    // the next page loads once the last comment is selected
+    fn select_next_comment(&mut self) {
+        if self.comment_index + 1 < self.comment_list().len() {
+            self.comment_index += 1;
+        }
+        if self.comment_index + 1 >= self.comment_list().len() {
+            self.load_more_comments();
+        }
     }
 
-    pub fn song_urls(&self, ids: Vec<usize>) {
+    pub fn song_urls(&mut self, ids: Vec<usize>) {
         self.dispatch(IoEvent::SongUrls(ids));
     }
 
-    pub fn fav(&self, id: usize) {
+    pub fn fav(&mut self, id: usize) {
         self.dispatch(IoEvent::Fav(id));
     }
 
-    pub fn lyric(&self, id: usize) {
+    pub fn is_liked(&self, id: usize) -> bool {
+        self.liked_song_ids.contains(&id)
+    }
+
+    pub fn like(&mut self, id: usize) {
+        self.liked_song_ids.insert(id);
+        self.fav(id);
+    }
+
+    pub fn unlike(&mut self, id: usize) {
+        self.liked_song_ids.remove(&id);
+        self.undo.push(UndoAction::Unlike(id));
+        self.dispatch(IoEvent::Unfav(id));
+    }
+
+    /// Likes `song_id` or unlikes it when it's liked already. The change shows
+    /// right away and is rolled back if the api refuses it.
+    pub fn toggle_like(&mut self, song_id: usize) {
+        if self.is_liked(song_id) {
+            self.unlike(song_id);
+        } else {
+            self.like(song_id);
+        }
+    }
+
+    pub fn liked_songs(&mut self) {
+        self.dispatch(IoEvent::LikedSongs);
+    }
+
+    /// Drops `id` from personal FM for good, there's no undoing it. The FM
+    /// moves on when it's the song playing.
+    pub fn fm_trash(&mut self, id: usize) {
+        if let Some(fm) = self.fm.as_mut() {
+            fm.retain(|song| song.id != id);
+        }
+        self.undo.push(UndoAction::FmTrash(id));
+        self.dispatch(IoEvent::FmTrash(id));
+        if self.is_fm() && self.now_playing.as_ref().map(|song| song.id) == Some(id) {
+            self.next_track();
+        }
+    }
+
+    /// Plays personal FM, which replaces the queue until `exit_fm`.
+    pub fn start_fm(&mut self) {
+        if self.is_fm() {
+            return;
+        }
+        let saved = (std::mem::take(&mut self.queue), self.queue_index);
+        self.set_queue(Vec::new());
+        self.player.stop();
+        self.pending_play = None;
+        self.queue_done();
+        self.pre_fm_queue = Some(saved);
+        self.fm = None;
+        self.fetch_fm();
+        self.push_route(Route::PersonalFm);
+    }
+
+    /// Leaves personal FM, going back to the queue played before it.
+    pub fn exit_fm(&mut self) {
+        let (queue, index) = match self.pre_fm_queue.take() {
+            Some(saved) => saved,
+            None => return,
+        };
+        self.set_queue(queue);
+        match index {
+            Some(index) => {
+                let _ = self.play_index(index);
+            }
+            None => {
+                self.player.stop();
+                self.queue_done();
+            }
+        }
+    }
+
+    pub fn is_fm(&self) -> bool {
+        self.pre_fm_queue.is_some()
+    }
+
+    fn fetch_fm(&mut self) {
+        if !self.fm_fetching {
+            self.fm_fetching = true;
+            self.dispatch(IoEvent::PersonalFm);
+        }
+    }
+
+    /// Reverses the latest destructive action that can be reversed.
+    pub fn undo(&mut self) {
+        let action = match self.undo.pop() {
+            Some(action) => action,
+            None => return self.notify(Level::Info, "Nothing to undo"),
+        };
+        match &action {
+            UndoAction::Unlike(id) => {
+                self.liked_song_ids.insert(*id);
+            }
+            UndoAction::RemoveFromPlaylist { rows, .. } => self.restore_rows(rows),
+            UndoAction::FmTrash(_) => {}
+        }
+        if let Some(inverse) = action.inverse() {
+            self.dispatch(inverse);
+        }
+    }
+
+    // puts removed rows back where they were, ascending, unless they're back already
+    fn restore_rows(&mut self, rows: &[(usize, Song)]) {
+        if let Some(tracks) = self.track_table.as_mut() {
+            for (index, song) in rows {
+                if !tracks.iter().any(|track| track.id == song.id) {
+                    let index = (*index).min(tracks.len());
+                    tracks.insert(index, song.clone());
+                }
+            }
+        }
+    }
+
+    pub fn lyric(&mut self, id: usize) {
         self.dispatch(IoEvent::Lyric(id));
     }
 
-    pub fn user_podcasts(&self, id: usize) {
+    /// Lyrics of `id` if they've been fetched already.
+    pub fn cached_lyric(&self, id: usize) -> Option<&Lyric> {
+        self.lyrics.get(&id)
+    }
+
+    /// Lyric of the playing song, once fetched.
+    pub fn now_playing_lyric(&self) -> Option<&Lyric> {
+        self.now_playing.as_ref().and_then(|song| self.cached_lyric(song.id))
+    }
+
+    // fetches lyrics unless cached or on their way
+    fn request_lyric(&mut self, id: usize) {
+        if self.lyrics.contains_key(&id) || !self.lyric_requests.insert(id) {
+            return;
+        }
+        self.lyric(id);
+    }
+
+    // lyrics of the queue index just started, then of the tracks after it
+    fn prefetch_lyrics(&mut self, index: usize) {
+        let depth = self.config.lyric_prefetch.min(MAX_LYRIC_PREFETCH);
+        let ids: Vec<usize> = self
+            .queue
+            .iter()
+            .skip(index)
+            .take(depth + 1)
+            .map(|song| song.id)
+            .filter(|id| !self.local_tracks.contains_key(id))
+            .collect();
+        for id in ids {
+            self.request_lyric(id);
+        }
+    }
+
+    pub fn user_podcasts(&mut self, id: usize) {
         self.dispatch(IoEvent::UserPodCasts(id));
     }
 
-    pub fn podcast_audios(&self, id: usize) {
+    pub fn podcast_audios(&mut self, id: usize) {
         self.dispatch(IoEvent::PodcastAudios(id));
     }
 
-    pub fn user_cloud(&self) {
+    pub fn user_cloud(&mut self) {
         self.dispatch(IoEvent::UserCloud);
     }
 
-    pub fn recently_played(&self) {
+    pub fn recently_played(&mut self) {
         self.dispatch(IoEvent::RecentlyPlayed);
     }
 
-    pub fn artist_sublist(&self) {
+    pub fn artist_sublist(&mut self) {
         self.dispatch(IoEvent::ArtistSublist);
     }
-}
 
-#[cfg(test)]
-mod tests {}
+    pub fn download(&mut self, id: usize, url: String) {
+        self.dispatch(IoEvent::Download(id, url));
+    }
+
+    pub fn add_to_playlist(&mut self, playlist_id: usize, song_ids: Vec<usize>) {
+        self.dispatch(IoEvent::AddToPlaylist {
+            playlist_id,
+            song_ids,
+        });
+    }
+
+    // state
+    /// Fetches the cover at `url` for the art renderer, the placeholder is
+    /// shown until it arrives.
+    pub fn fetch_cover(&mut self, url: String) {
+        if self.cover_url.as_ref() == Some(&url) {
+            return;
+        }
+        self.cover = None;
+        self.cover_url = Some(url.clone());
+        self.publish_now_playing();
+        self.dispatch(IoEvent::FetchCover(url));
+    }
+
+    /// Current cover image, `None` means the placeholder.
+    pub fn cover(&self) -> Option<&Bytes> {
+        self.cover.as_ref()
+    }
+
+    /// Asks to remove `song_ids` from playlist `playlist_id`, nothing happens
+    /// until it's confirmed.
+    pub fn remove_from_playlist(&mut self, playlist_id: usize, song_ids: Vec<usize>) {
+        self.confirm_remove = Some((playlist_id, song_ids));
+    }
+
+    /// The removal waiting for confirmation, the ui shows a prompt while it's `Some`.
+    pub fn confirm_remove(&self) -> Option<&(usize, Vec<usize>)> {
+        self.confirm_remove.as_ref()
+    }
+
+    pub fn cancel_remove(&mut self) {
+        self.confirm_remove = None;
+    }
+
+    /// Removes the confirmed songs from the track table right away and asks
+    /// the api to follow, the rows come back if it fails.
+    pub fn confirm_remove_from_playlist(&mut self) {
+        let (playlist_id, song_ids) = match self.confirm_remove.take() {
+            Some(confirmed) => confirmed,
+            None => return,
+        };
+        let mut rows = Vec::new();
+        if let Some(tracks) = self.track_table.as_mut() {
+            let mut index = 0;
+            tracks.retain(|song| {
+                let keep = !song_ids.contains(&song.id);
+                if !keep {
+                    rows.push((index, song.clone()));
+                }
+                index += 1;
+                keep
+            });
+            let len = tracks.len();
+            self.track_table_index = self.track_table_index.min(len.saturating_sub(1));
+        }
+        self.undo.push(UndoAction::RemoveFromPlaylist {
+            playlist_id,
+            rows: rows.clone(),
+        });
+        self.pending_removals.push(PendingRemoval {
+            playlist_id,
+            song_ids: song_ids.clone(),
+            rows,
+        });
+        self.dispatch(IoEvent::RemoveFromPlaylist {
+            playlist_id,
+            song_ids,
+        });
+    }
+
+    fn take_pending_removal(&mut self, playlist_id: usize, song_ids: &[usize]) -> Option<PendingRemoval> {
+        let index = self
+            .pending_removals
+            .iter()
+            .position(|r| r.playlist_id == playlist_id && r.song_ids == song_ids)?;
+        Some(self.pending_removals.remove(index))
+    }
+
+    pub fn track_table(&self) -> Option<&Vec<Song>> {
+        self.track_table.as_ref()
+    }
+
+    pub fn track_table_index(&self) -> usize {
+        self.track_table_index
+    }
+
+    pub fn search_input(&self) -> Option<&str> {
+        self.search_input.as_deref()
+    }
+
+    pub fn track_filter(&self) -> Option<&str> {
+        self.track_filter.as_deref()
+    }
+
+    /// Rows of the track table the filter leaves, best matches first, or
+    /// all of them in order without a filter.
+    pub fn filtered_indices(&self) -> Vec<usize> {
+        let len = self.track_table.as_ref().map_or(0, |tracks| tracks.len());
+        match self.track_filter.as_deref() {
+            Some(query) if !query.is_empty() => {
+                self.fuzzy_find(query).into_iter().map(|(i, _)| i).collect()
+            }
+            _ => (0..len).collect(),
+        }
+    }
+
+    /// Starts typing a filter over the track table.
+    pub fn start_track_filter(&mut self) {
+        if self.track_filter.is_none() {
+            let selected = self.track_table.as_ref().and_then(|tracks| tracks.get(self.track_table_index));
+            self.pre_filter_selection = selected.map(|song| song.id);
+        }
+        self.track_filter.get_or_insert_with(String::new);
+        self.filter_typing = true;
+    }
+
+    /// Shows every track again, reselecting the song selected before filtering.
+    pub fn clear_track_filter(&mut self) {
+        self.track_filter = None;
+        self.filter_typing = false;
+        let selected = self.pre_filter_selection.take();
+        let row = selected.and_then(|id| {
+            self.track_table
+                .as_ref()
+                .and_then(|tracks| tracks.iter().position(|song| song.id == id))
+        });
+        if let Some(row) = row {
+            self.track_table_index = row;
+        }
+    }
+
+    // the song of the selected row, none while the filter hides it or leaves
+    // no rows at all
+    fn selected_track(&self) -> Option<&Song> {
+        if !self.filtered_indices().contains(&self.track_table_index) {
+            return None;
+        }
+        self.track_table.as_ref()?.get(self.track_table_index)
+    }
+
+    // keeps the selection on a row the filter leaves, the best match otherwise
+    fn clamp_to_filter(&mut self) {
+        let rows = self.filtered_indices();
+        if !rows.contains(&self.track_table_index) {
+            if let Some(&first) = rows.first() {
+                self.track_table_index = first;
+            }
+        }
+    }
+
+    pub fn search_history(&self) -> &VecDeque<String> {
+        &self.search_history
+    }
+
+    /// Suggestion selected in the search view.
+    pub fn search_history_index(&self) -> Option<usize> {
+        self.search_history_index
+    }
+
+    /// Moves `query` to the front of the history, dropping the oldest past the cap.
+    pub fn record_search(&mut self, query: &str) {
+        let query = query.trim();
+        if query.is_empty() {
+            return;
+        }
+        self.search_history.retain(|q| q != query);
+        self.search_history.push_front(query.to_owned());
+        self.search_history.truncate(self.config.search_history_len);
+        self.save_search_history();
+    }
+
+    pub fn remove_search_history(&mut self, index: usize) {
+        if self.search_history.remove(index).is_some() {
+            self.search_history_index = None;
+            self.save_search_history();
+        }
+    }
+
+    pub fn clear_search_history(&mut self) {
+        self.search_history.clear();
+        self.search_history_index = None;
+        self.save_search_history();
+    }
+
+    fn save_search_history(&self) {
+        if let Some(path) = &self.search_history_path {
+            let lines: Vec<&str> = self.search_history.iter().map(String::as_str).collect();
+            let saved = path
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::write(path, lines.join("\n")));
+            if let Err(e) = saved {
+                error!("save search history error: {}", e);
+            }
+        }
+    }
+
+    fn commit_search(&mut self, query: String) {
+        if query.is_empty() {
+            return;
+        }
+        self.record_search(&query);
+        self.push_route(Route::Search);
+        self.search_query = Some(query.clone());
+        self.search_offset = 0;
+        self.search(query, SearchType::Song, 0);
+    }
+
+    /// Replaces the results with the next page, unless the current one wasn't full.
+    pub fn search_next_page(&mut self) {
+        let limit = self.search_limit as usize;
+        let full = self.search_results.as_ref().map_or(false, |results| results.len() >= limit);
+        if full {
+            self.turn_search_page(self.search_offset + limit);
+        }
+    }
+
+    /// Replaces the results with the previous page, if there's one.
+    pub fn search_prev_page(&mut self) {
+        if self.search_offset > 0 {
+            let offset = self.search_offset.saturating_sub(self.search_limit as usize);
+            self.turn_search_page(offset);
+        }
+    }
+
+    fn turn_search_page(&mut self, offset: usize) {
+        let query = match &self.search_query {
+            Some(query) => query.clone(),
+            None => return,
+        };
+        let kind = self
+            .search_results
+            .as_ref()
+            .map_or(SearchType::Song, SearchResult::kind);
+        self.search_offset = offset;
+        self.search(query, kind, offset);
+    }
+
+    /// The page of results of the last search, `None` before any arrived.
+    pub fn search_results(&self) -> Option<&SearchResult> {
+        self.search_results.as_ref()
+    }
+
+    #[inline]
+    pub fn search_index(&self) -> usize {
+        self.search_index
+    }
+
+    // results the search route lists itself, songs go to the track table
+    fn other_search_results(&self) -> Option<&SearchResult> {
+        let results = self.search_results.as_ref().filter(|_| *self.route() == Route::Search)?;
+        let listed = !matches!(results, SearchResult::Song(_)) && !results.is_empty();
+        Some(results).filter(|_| listed)
+    }
+
+    /// Page of the search results shown, counting from 1.
+    pub fn search_page(&self) -> usize {
+        self.search_offset / (self.search_limit as usize).max(1) + 1
+    }
+
+    /// Whether any request sent to the io worker is still unanswered.
+    pub fn is_loading(&self) -> bool {
+        self.loading > 0
+    }
+
+    /// Frame of the loading spinner, counting up while loading.
+    pub fn spinner_frame(&self) -> usize {
+        self.spinner_frame
+    }
+
+    pub fn notify(&mut self, level: Level, msg: impl Into<String>) {
+        self.notifications.push(Notification {
+            level,
+            message: msg.into(),
+            at: Instant::now(),
+        });
+        if self.notifications.len() > NOTIFICATIONS_MAX {
+            self.notifications.remove(0);
+        }
+        self.dirty = true;
+    }
+
+    pub fn notifications(&self) -> &[Notification] {
+        &self.notifications
+    }
+
+    /// The latest notifications still shown, oldest first.
+    pub fn toasts(&self) -> &[Notification] {
+        let live = self
+            .notifications
+            .iter()
+            .rev()
+            .take(TOASTS_SHOWN)
+            .take_while(|n| n.at.elapsed() < TOAST_TTL)
+            .count();
+        &self.notifications[self.notifications.len() - live..]
+    }
+
+    /// Why the last request failed, cleared by the next key press.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Row of the sidebar's playlist list that's selected.
+    pub fn selected_playlist_index(&self) -> usize {
+        self.selected_playlist_index
+    }
+
+    pub fn user_playlists_loaded(&self) -> Option<&Vec<Playlist>> {
+        self.user_playlists.as_ref()
+    }
+
+    pub fn playlist_picker(&self) -> Option<&PlaylistPicker> {
+        self.playlist_picker.as_ref()
+    }
+
+    /// Lets the user pick one of their playlists to add `song_ids` to.
+    pub fn open_playlist_picker(&mut self, song_ids: Vec<usize>) {
+        if self.user_playlists.as_ref().map_or(true, |pls| pls.is_empty()) {
+            self.notify(Level::Warning, "No playlist to add to");
+            return;
+        }
+        self.playlist_picker = Some(PlaylistPicker { song_ids, index: 0 });
+    }
+
+    fn handle_picker_action(&mut self, key: Key) {
+        let count = self.user_playlists.as_ref().map_or(0, |pls| pls.len());
+        let picker = match self.playlist_picker.as_mut() {
+            Some(picker) => picker,
+            None => return,
+        };
+        match key {
+            Key::Char('j') | Key::Down if picker.index + 1 < count => picker.index += 1,
+            Key::Char('k') | Key::Up => picker.index = picker.index.saturating_sub(1),
+            Key::Char('\n') => {
+                let picker = self.playlist_picker.take().unwrap();
+                let playlist_id = self
+                    .user_playlists
+                    .as_ref()
+                    .and_then(|pls| pls.get(picker.index))
+                    .map(|pl| pl.id);
+                if let Some(playlist_id) = playlist_id {
+                    self.add_to_playlist(playlist_id, picker.song_ids);
+                }
+            }
+            Key::Esc => self.playlist_picker = None,
+            _ => {}
+        }
+    }
+
+    pub fn is_authed(&self) -> bool {
+        self.auth
+    }
+
+    pub fn user_profile(&self) -> Option<&UserProfile> {
+        self.user.as_ref()
+    }
+
+    /// Raises the volume by the configured step, returning the new percentage.
+    pub fn volume_up(&mut self) -> u8 {
+        let vol = self.player.increase_volume(self.config.volume_step);
+        self.playback_state_changed = Some(Instant::now());
+        vol
+    }
+
+    /// Lowers the volume by the configured step, returning the new percentage.
+    pub fn volume_down(&mut self) -> u8 {
+        let vol = self.player.decrease_volume(self.config.volume_step);
+        self.playback_state_changed = Some(Instant::now());
+        vol
+    }
+
+    /// Requests a redraw on the next frame.
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Whether a frame has to be drawn, clearing the flag. The main loop
+    /// draws only when this is `true` or the terminal was resized.
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+
+    pub fn on_tick(&mut self) {
+        self.title_marquee.tick();
+        if self.title_marquee.stepped() {
+            self.dirty = true;
+        }
+        // the progress bar only shows whole seconds, or spins while the duration is unknown
+        let context = self.player.playback_context();
+        let second = context.progress_ms() / 1000;
+        if self.drawn_second != Some(second) {
+            self.drawn_second = Some(second);
+            self.dirty = true;
+        }
+        if context.duration_ms().is_none() && self.now_playing.is_some() && !context.is_paused() {
+            self.dirty = true;
+        }
+
+        if let Some(changed_at) = self.playback_state_changed {
+            if changed_at.elapsed() >= VOLUME_DEBOUNCE {
+                self.save_playback_state();
+            }
+        }
+        if self.is_loading() {
+            self.spinner_frame = self.spinner_frame.wrapping_add(1);
+            self.dirty = true;
+        }
+        let live = self.toasts().len();
+        if live != self.live_toasts {
+            self.live_toasts = live;
+            self.dirty = true;
+        }
+        self.track_listen();
+        self.scrobble();
+        self.watch_stall();
+        self.write_checkpoint();
+    }
+
+    // saves the playing track and progress every `checkpoint_interval_ms` of playback
+    fn write_checkpoint(&mut self) {
+        let path = match &self.checkpoint_path {
+            Some(path) if self.pending_play.is_none() && self.playing_episode.is_none() => path,
+            _ => return,
+        };
+        let song = match self.queue_index.and_then(|i| self.queue.get(i)) {
+            Some(song) => song,
+            None => return,
+        };
+        let progress_ms = self.player.playback_context().progress_ms();
+        let due = self.checkpoint_ms.map_or(true, |last| {
+            progress_ms.max(last) - progress_ms.min(last) >= self.config.checkpoint_interval_ms
+        });
+        if !due {
+            return;
+        }
+        let checkpoint = Checkpoint {
+            song_id: song.id,
+            name: song.name.clone(),
+            progress_ms,
+        };
+        match checkpoint::write(path, &checkpoint) {
+            Ok(()) => self.checkpoint_ms = Some(progress_ms),
+            Err(e) => error!("write checkpoint error: {}", e),
+        }
+    }
+
+    /// The checkpoint a crashed session left behind, until it's resumed.
+    pub fn resume_offer(&self) -> Option<&Checkpoint> {
+        self.resume_offer.as_ref()
+    }
+
+    /// Plays the offered checkpoint from where it was.
+    pub fn resume_checkpoint(&mut self) {
+        let offer = match self.resume_offer.take() {
+            Some(offer) => offer,
+            None => return,
+        };
+        self.set_queue(vec![Song {
+            id: offer.song_id,
+            name: offer.name,
+            ..Default::default()
+        }]);
+        if self.play_index(0).is_ok() {
+            self.resume_at = Some(offer.progress_ms);
+        }
+    }
+
+    // records the current track as played once the threshold is crossed
+    fn scrobble(&mut self) {
+        if self.scrobbled || self.pending_play.is_some() {
+            return;
+        }
+        let id = match self.queue_index.and_then(|i| self.queue.get(i)) {
+            Some(song) if !self.is_local(song.id) => song.id,
+            _ => return,
+        };
+        let context = self.player.playback_context();
+        let threshold = match context.duration_ms() {
+            Some(duration) => {
+                let fraction = (duration as f32 * self.config.scrobble_fraction) as u64;
+                fraction.min(self.config.scrobble_after_ms)
+            }
+            None => self.config.scrobble_after_ms,
+        };
+        // seeks past the threshold aren't listening
+        if self.listened_ms >= threshold {
+            self.scrobbled = true;
+            self.dispatch(IoEvent::ScrobblePlay(id));
+        }
+    }
+
+    // tells Last.fm the playing song started, then counts how long it's listened to
+    fn start_listen(&mut self) {
+        self.finish_listen();
+        self.listened_ms = 0;
+        self.listen_progress_ms = self.player.playback_context().progress_ms();
+        let tx = match &self.scrobble_tx {
+            Some(tx) if self.playing_episode.is_none() => tx,
+            _ => return,
+        };
+        let song = match &self.now_playing {
+            Some(song) if !self.is_local(song.id) => song,
+            _ => return,
+        };
+        let artist = match song.artists.first() {
+            Some(artist) => artist.name.clone(),
+            None => return,
+        };
+        let context = self.player.playback_context();
+        let track = scrobble::Track {
+            artist,
+            title: song.name.clone(),
+            duration_secs: context.duration_ms().map(|ms| ms / 1000),
+            started_at: scrobble::unix_now(),
+        };
+        let _ = tx.send(ScrobbleEvent::NowPlaying(track.clone()));
+        self.listen = Some(Listen {
+            track,
+            duration_ms: context.duration_ms(),
+        });
+    }
+
+    // adds the progress since the last tick, seeks left out
+    fn track_listen(&mut self) {
+        let context = self.player.playback_context();
+        let progress_ms = context.progress_ms();
+        let step = progress_ms.saturating_sub(self.listen_progress_ms);
+        if !context.is_paused() && step <= LISTEN_STEP_MAX_MS {
+            self.listened_ms += step;
+        }
+        self.listen_progress_ms = progress_ms;
+        if let Some(listen) = self.listen.as_mut().filter(|listen| listen.duration_ms.is_none()) {
+            listen.duration_ms = context.duration_ms();
+        }
+    }
+
+    // scrobbles the song that stopped, if it was listened to long enough
+    fn finish_listen(&mut self) {
+        let listened_ms = self.listened_ms;
+        let mut listen = match self.listen.take() {
+            Some(listen) if scrobble::should_scrobble(listen.duration_ms, listened_ms) => listen,
+            _ => return,
+        };
+        listen.track.duration_secs = listen.duration_ms.map(|ms| ms / 1000);
+        if let Some(tx) = &self.scrobble_tx {
+            let _ = tx.send(ScrobbleEvent::Scrobble(listen.track));
+        }
+    }
+
+    /// Fuzzy matches `query` against the titles and artists of the track table,
+    /// returning `(row, score)` best first.
+    pub fn fuzzy_find(&self, query: &str) -> Vec<(usize, i64)> {
+        let tracks = match &self.track_table {
+            Some(tracks) => tracks,
+            None => return Vec::new(),
+        };
+        let mut found: Vec<(usize, i64)> = tracks
+            .iter()
+            .enumerate()
+            .filter_map(|(i, song)| {
+                let artists = song
+                    .artists
+                    .iter()
+                    .map(|ar| ar.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let score = fuzzy::score(query, &song.name).max(fuzzy::score(query, &artists))?;
+                Some((i, score))
+            })
+            .collect();
+        found.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        found
+    }
+
+    pub fn title_marquee(&self) -> &Marquee {
+        &self.title_marquee
+    }
+
+    pub fn queue(&self) -> &[Song] {
+        &self.queue
+    }
+
+    // a new queue is shuffled afresh from the track it starts with, and ends FM mode
+    fn set_queue(&mut self, songs: Vec<Song>) {
+        self.pre_fm_queue = None;
+        self.queue = songs;
+        self.shuffle_order.clear();
+        self.shuffle_history.clear();
+        self.prefetch = None;
+        self.prefetched = None;
+        self.player.clear_queue();
+    }
+
+    /// Index of the playing track in the queue, not the selected row.
+    pub fn current_index(&self) -> Option<usize> {
+        self.queue_index
+    }
+
+    /// The song being played, kept in step with the queue.
+    pub fn now_playing(&self) -> Option<&Song> {
+        self.now_playing.as_ref()
+    }
+
+    /// Stops playback and forgets the playing song, the queue is kept.
+    pub fn eject(&mut self) {
+        self.player.stop();
+        self.save_episode_progress();
+        self.playing_episode = None;
+        self.pending_play = None;
+        self.queue_done();
+    }
+
+    // nothing left to play
+    fn queue_done(&mut self) {
+        self.finish_listen();
+        self.queue_index = None;
+        self.now_playing = None;
+        self.title_marquee.set_text("");
+        self.publish_now_playing();
+    }
+
+    fn publish_now_playing(&self) {
+        if let Some(shared) = &self.media_now_playing {
+            let metadata = self.now_playing.as_ref().map(|song| MediaMetadata {
+                art_url: self.cover_url.clone(),
+                ..MediaMetadata::from_song(song)
+            });
+            *shared.lock().unwrap() = metadata;
+        }
+    }
+
+    /// Carries out a command of the media controls.
+    pub fn handle_media(&mut self, cmd: MediaCommand) {
+        self.dirty = true;
+        let context = self.player.playback_context();
+        match cmd {
+            MediaCommand::Play => self.player.resume(),
+            MediaCommand::Pause => self.player.pause(),
+            MediaCommand::PlayPause => self.toggle_pause(),
+            MediaCommand::Stop => self.eject(),
+            MediaCommand::Next => self.next_track(),
+            MediaCommand::Previous => self.prev_track(),
+            MediaCommand::Seek(offset_ms) => {
+                let progress = context.progress_ms() as i64 + offset_ms;
+                self.player.seek_ms(progress.max(0) as u64);
+            }
+            MediaCommand::SetPosition(progress_ms) => self.player.seek_ms(progress_ms),
+            MediaCommand::SetVolume(volume) => {
+                let vol = (volume * 100.0).round().max(0.0).min(100.0) as u8;
+                context.set_volume_percent(vol);
+                self.playback_state_changed = Some(Instant::now());
+            }
+        }
+    }
+
+    /// Copies the share link of the playing song. Without a clipboard the
+    /// link is shown in a notification instead.
+    pub fn copy_share_link(&mut self) {
+        let id = match self.queue_index.and_then(|i| self.queue.get(i)) {
+            Some(song) => song.id,
+            None => return self.notify(Level::Info, "Nothing is playing"),
+        };
+        let url = clipboard::share_url(id);
+
+        if self.clipboard.is_none() {
+            self.clipboard = SystemClipboard::open().map(|c| Box::new(c) as Box<dyn Clipboard>);
+        }
+        let copied = match self.clipboard.as_mut() {
+            Some(clipboard) => clipboard.set_text(url.clone()),
+            None => Err("no clipboard".to_owned()),
+        };
+        match copied {
+            Ok(()) => self.notify(Level::Info, format!("Copied {}", url)),
+            Err(e) => {
+                error!("copy share link error: {}", e);
+                self.notify(Level::Info, url);
+            }
+        }
+    }
+
+    /// Queues today's recommendations and starts playing them, fetching them
+    /// first if they are missing or older than a day.
+    pub fn play_daily(&mut self) {
+        if !self.auth {
+            return self.notify(Level::Warning, "Log in to play daily recommendations");
+        }
+        let fresh = self
+            .recommended_at
+            .map_or(false, |at| at.elapsed() < RECOMMENDATIONS_TTL);
+        if !fresh || self.recommended_songs.is_none() {
+            self.pending_daily = true;
+            self.recommended_songs();
+            return;
+        }
+
+        let songs = self.daily_songs();
+        if songs.is_empty() {
+            return self.notify(Level::Info, "Nothing new in today's recommendations");
+        }
+        self.set_queue(songs);
+        let _ = self.play_index(0);
+    }
+
+    // recommendations, minus recent history when configured to
+    fn daily_songs(&self) -> Vec<Song> {
+        let songs = self.recommended_songs.iter().flatten();
+        let played: Vec<usize> = match (&self.recently_played, self.config.daily_skip_played) {
+            (Some(history), true) => history.iter().map(|song| song.id).collect(),
+            _ => Vec::new(),
+        };
+        songs
+            .filter(|song| !played.contains(&song.id))
+            .cloned()
+            .collect()
+    }
+
+    /// `false` once the api gave no playable url for `song_id`.
+    pub fn is_available(&self, song_id: usize) -> bool {
+        !self.unavailable.contains(&song_id)
+    }
+
+    // first queued track from `index` on that isn't known to be unavailable
+    fn next_available(&self, index: usize) -> Option<usize> {
+        (index..self.queue.len()).find(|&i| self.is_available(self.queue[i].id))
+    }
+
+    pub fn repeat_mode(&self) -> RepeatMode {
+        self.player.playback_context().repeat_mode()
+    }
+
+    /// Changes the repeat mode, a `Count` starts over for the current track.
+    pub fn set_repeat_mode(&mut self, mode: RepeatMode) {
+        self.player.set_repeat_mode(mode);
+        self.reset_repeats();
+        self.playback_state_changed = Some(Instant::now());
+    }
+
+    /// Replays left of the current track, `None` unless repeating a fixed count.
+    pub fn repeats_left(&self) -> Option<u32> {
+        match self.repeat_mode() {
+            RepeatMode::Count(_) => Some(self.repeats_left),
+            _ => None,
+        }
+    }
+
+    /// The player's live playback state, for the progress bar and lyrics.
+    pub fn playback_context(&self) -> Arc<PlaybackContext> {
+        self.player.playback_context()
+    }
+
+    /// Repeat, shuffle and mute for the playbar indicators.
+    pub fn playback_snapshot(&self) -> PlaybackSnapshot {
+        self.player.playback_context().snapshot()
+    }
+
+    fn reset_repeats(&mut self) {
+        self.repeats_left = match self.repeat_mode() {
+            RepeatMode::Count(n) => n.saturating_sub(1),
+            _ => 0,
+        };
+    }
+
+    /// Web page of what the current route focuses: the album, artist,
+    /// playlist or podcast shown, else the selected or playing song.
+    pub fn focused_url(&self) -> Option<String> {
+        let (page, id) = match *self.route() {
+            Route::Album(id) => (Page::Album, id),
+            Route::Artist(id) => (Page::Artist, id),
+            Route::Playlist(id) => (Page::Playlist, id),
+            Route::Podcast(id) => (Page::Podcast, id),
+            _ => {
+                let selected = self.selected_track();
+                let playing = self.queue_index.and_then(|i| self.queue.get(i));
+                (Page::Song, selected.or(playing)?.id)
+            }
+        };
+        Some(browser::web_url(page, id))
+    }
+
+    /// Opens `focused_url` in the browser, or shows it when there's none to open it.
+    pub fn open_in_browser(&mut self) {
+        let url = match self.focused_url() {
+            Some(url) => url,
+            None => return self.notify(Level::Info, "Nothing to open"),
+        };
+        if let Err(e) = self.opener.open(&url) {
+            error!("open {} error: {}", url, e);
+            self.notify(Level::Info, url);
+        }
+    }
+
+    /// Jumps to the queued track at `index`, restarting progress from 0.
+    pub fn play_index(&mut self, index: usize) -> Result<(), PlayerError> {
+        let id = self.start_index(index)?;
+        self.pending_play = Some(id);
+        match self.local_tracks.get(&id).cloned() {
+            Some(path) => self.play_local(id, &path),
+            None => self.song_urls(vec![id]),
+        }
+        Ok(())
+    }
+
+    // what playing the queued track at `index` changes, but getting the track
+    fn start_index(&mut self, index: usize) -> Result<usize, PlayerError> {
+        let song = match self.queue.get(index) {
+            Some(song) => song,
+            None => return Err(PlayerError::InvalidIndex(index)),
+        };
+        let id = song.id;
+        self.title_marquee.set_text(playbar_title(song));
+        self.now_playing = Some(song.clone());
+        self.publish_now_playing();
+        // the highlighted row follows playback while the table shows the queue
+        let row = self.track_table.as_ref().and_then(|tracks| tracks.get(index));
+        if row.map(|row| row.id) == Some(id) {
+            self.track_table_index = index;
+        }
+        self.reset_repeats();
+        self.scrobbled = false;
+        self.finish_listen();
+        self.save_episode_progress();
+        self.playing_episode = None;
+        self.queue_index = Some(index);
+        self.seek_ms = None;
+        self.checkpoint_ms = None;
+        self.resume_at = None;
+        self.prefetch = None;
+        self.prefetched = None;
+        self.emit(PlaybackEvent::Started(index));
+        if !self.is_local(id) {
+            self.record_played(self.queue[index].clone());
+        }
+        if self.is_fm() && self.queue.len() - index - 1 <= FM_REFILL_LEFT {
+            self.fetch_fm();
+        }
+        Ok(id)
+    }
+
+    // with gapless on, gets the song after the playing one into the player's
+    // queue before it's needed
+    fn prefetch_next(&mut self) {
+        if !self.config.gapless || self.playing_episode.is_some() {
+            return;
+        }
+        let next = match self.queue_index.and_then(|index| self.peek_next(index)) {
+            Some(next) => next,
+            None => return,
+        };
+        let id = self.queue[next].id;
+        self.prefetch = Some((next, id));
+        if let Some(path) = self.local_tracks.get(&id).cloned() {
+            match fs::read(&path) {
+                Ok(track) => self.enqueue_prefetched(id, Bytes::from(track)),
+                Err(e) => warn!("read local track {:?} error: {}", path, e),
+            }
+        } else if let Some(track) = self.track_cache.as_ref().and_then(|cache| cache.get(id)) {
+            self.enqueue_prefetched(id, track);
+        } else {
+            self.song_urls(vec![id]);
+        }
+    }
+
+    // the prefetched track goes behind the playing one
+    fn enqueue_prefetched(&mut self, id: usize, track: Bytes) {
+        let index = match self.prefetch {
+            Some((index, prefetching)) if prefetching == id => index,
+            _ => return,
+        };
+        self.prefetch = None;
+        self.cache_track(id, &track);
+        match self.player.enqueue(track) {
+            Ok(()) => self.prefetched = Some((index, id)),
+            Err(e) => debug!("queue track {} error: {}", id, e),
+        }
+    }
+
+    // the player went on to the prefetched song by itself
+    fn take_prefetched(&mut self, index: usize) {
+        if self.start_index(index).is_err() {
+            return;
+        }
+        self.player.trim_queue();
+        self.track_started();
+        self.prefetch_next();
+    }
+
+    fn cache_track(&self, id: usize, track: &Bytes) {
+        if let Some(cache) = self.track_cache.as_ref().filter(|_| !self.is_local(id)) {
+            if !cache.path_for(id).exists() {
+                if let Err(e) = cache.put(id, track) {
+                    error!("cache track {} error: {}", id, e);
+                }
+            }
+        }
+    }
+
+    // an imported file is read right away instead of asking for its url
+    fn play_local(&mut self, id: usize, path: &Path) {
+        match fs::read(path) {
+            Ok(track) => self.apply_data(DataEvent::Track(id, Bytes::from(track))),
+            Err(e) => {
+                warn!("read local track {:?} error: {}", path, e);
+                self.unavailable.insert(id);
+                self.skip_unavailable(id);
+            }
+        }
+    }
+
+    pub fn is_local(&self, song_id: usize) -> bool {
+        self.local_tracks.contains_key(&song_id)
+    }
+
+    /// Writes the track table shown, or the queue without one, to `path` as
+    /// an m3u8 playlist. Songs are listed by their NetEase page.
+    pub fn export_m3u(&self, path: &Path) -> io::Result<()> {
+        let songs = match &self.track_table {
+            Some(tracks) if !tracks.is_empty() => tracks,
+            _ => &self.queue,
+        };
+        let entries: Vec<m3u::Entry> = songs
+            .iter()
+            .map(|song| m3u::Entry {
+                // 0 when the length isn't known
+                duration_secs: Some(song.dt as u64 / 1000).filter(|secs| *secs > 0),
+                title: Some(playbar_title(song)),
+                location: match self.local_tracks.get(&song.id) {
+                    Some(file) => file.display().to_string(),
+                    None => browser::web_url(Page::Song, song.id),
+                },
+            })
+            .collect();
+        fs::write(path, m3u::write(&entries))
+    }
+
+    /// Queues the m3u playlist at `path` and plays it: local files, and the
+    /// songs of playlists from `export_m3u`. What can't be played is left out,
+    /// with a warning each in the returned list.
+    pub fn import_m3u(&mut self, path: &Path) -> io::Result<Vec<String>> {
+        let raw = fs::read_to_string(path)?;
+        // relative entries are relative to the playlist
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut songs = Vec::new();
+        let mut warnings = Vec::new();
+        for entry in m3u::parse(&raw) {
+            if let Some(id) = web_song_id(&entry.location) {
+                songs.push(Song {
+                    id,
+                    name: entry.title.unwrap_or_default(),
+                    dt: entry.duration_secs.map_or(0, |secs| secs as usize * 1000),
+                    ..Default::default()
+                });
+                continue;
+            }
+            if entry.location.contains("://") {
+                warnings.push(format!("{}: not a local file", entry.location));
+                continue;
+            }
+            let file = base.join(&entry.location);
+            let ext = file.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase);
+            if !ext.map_or(false, |ext| LOCAL_EXTENSIONS.contains(&ext.as_str())) {
+                warnings.push(format!("{}: unsupported format", entry.location));
+                continue;
+            }
+            if !file.is_file() {
+                warnings.push(format!("{}: not found", entry.location));
+                continue;
+            }
+            let id = LOCAL_ID_TOP - self.local_tracks.len();
+            let stem = file.file_stem().map(|stem| stem.to_string_lossy().into_owned());
+            songs.push(Song {
+                id,
+                name: entry.title.or(stem).unwrap_or_default(),
+                dt: entry.duration_secs.map_or(0, |secs| secs as usize * 1000),
+                ..Default::default()
+            });
+            self.local_tracks.insert(id, file);
+        }
+        if !warnings.is_empty() {
+            self.notify(Level::Warning, format!("Skipped {} playlist entries", warnings.len()));
+        }
+        if !songs.is_empty() {
+            self.set_queue(songs);
+            let _ = self.play_index(0);
+        }
+        Ok(warnings)
+    }
+
+    /// The screen shown, on top of the navigation stack.
+    pub fn route(&self) -> &Route {
+        self.routes.last().unwrap()
+    }
+
+    /// Shows `route`, going back leads to the current one again.
+    pub fn push_route(&mut self, route: Route) {
+        if *self.route() != route {
+            self.routes.push(route);
+            self.track_filter = None;
+            self.filter_typing = false;
+        }
+        self.dirty = true;
+    }
+
+    /// Goes back to the previous screen, staying on the first one.
+    pub fn pop_route(&mut self) -> Option<Route> {
+        if self.routes.len() == 1 {
+            return None;
+        }
+        self.dirty = true;
+        self.track_filter = None;
+        self.filter_typing = false;
+        self.routes.pop()
+    }
+
+    /// Shows the episodes of podcast `id`, fetching them.
+    pub fn open_podcast(&mut self, id: usize) {
+        if *self.route() != Route::Podcast(id) {
+            self.podcast_episodes.clear();
+            self.episode_index = 0;
+        }
+        self.push_route(Route::Podcast(id));
+        self.podcast_audios(id);
+    }
+
+    pub fn podcast_episodes(&self) -> &[PodcastEpisode] {
+        &self.podcast_episodes
+    }
+
+    #[inline]
+    pub fn episode_index(&self) -> usize {
+        self.episode_index
+    }
+
+    /// Whether the playing episode waits for its stream, before it starts or
+    /// while it's stalled.
+    pub fn is_buffering(&self) -> bool {
+        if self.playing_episode.is_none() {
+            return false;
+        }
+        if self.pending_play.is_some() {
+            return true;
+        }
+        let paused = self.player.playback_context().is_paused();
+        !paused && matches!(self.stall, Some((_, since)) if since.elapsed() >= BUFFERING_AFTER)
+    }
+
+    // notes when the progress last moved, a stream waiting for data stops it
+    fn watch_stall(&mut self) {
+        let progress = self.player.playback_context().progress_ms();
+        match self.stall {
+            Some((last, _)) if last == progress => {}
+            _ => self.stall = Some((progress, Instant::now())),
+        }
+        if self.is_buffering() {
+            self.dirty = true;
+        }
+    }
+
+    // episodes run long, they play while they download rather than after
+    fn stream_episode(&mut self, id: usize, url: String) {
+        self.player.set_track_kind(self.track_kind());
+        match self.player.play_url(&url) {
+            Ok(()) => {
+                self.pending_play = None;
+                self.track_started();
+            }
+            // this player only takes whole tracks
+            Err(PlayerError::Unsupported(_)) => self.download(id, url),
+            Err(e) => {
+                self.pending_play = None;
+                error!("stream episode {} error: {}", id, e);
+                self.notify(Level::Error, "Couldn't stream the episode");
+            }
+        }
+    }
+
+    // of what's about to play, episodes keep a speed of their own
+    fn track_kind(&self) -> TrackKind {
+        if self.playing_episode.is_some() {
+            TrackKind::Podcast
+        } else {
+            TrackKind::Music
+        }
+    }
+
+    // picks up where the episode or the checkpoint was left, now that it plays
+    fn track_started(&mut self) {
+        self.start_listen();
+        self.stall = None;
+        let resume = self.playing_episode_mut().and_then(|ep| ep.resume_ms);
+        let resume = resume.or_else(|| self.resume_at.take());
+        if let Some(resume) = resume {
+            self.player.seek_ms(resume);
+        }
+    }
+
+    /// Plays the episode at `index`, resuming where it was left.
+    pub fn play_episode(&mut self, index: usize) -> Result<(), PlayerError> {
+        let episode = match self.podcast_episodes.get(index) {
+            Some(episode) => episode,
+            None => return Err(PlayerError::InvalidIndex(index)),
+        };
+        let (id, song_id) = (episode.id, episode.song_id);
+        self.title_marquee.set_text(episode.title.clone());
+        self.now_playing = None;
+        self.save_episode_progress();
+        self.playing_episode = Some(id);
+        self.prefetch = None;
+        self.prefetched = None;
+        self.queue_index = None;
+        self.pending_play = Some(song_id);
+        self.song_urls(vec![song_id]);
+        Ok(())
+    }
+
+    fn playing_episode_mut(&mut self) -> Option<&mut PodcastEpisode> {
+        let id = self.playing_episode?;
+        self.podcast_episodes.iter_mut().find(|ep| ep.id == id)
+    }
+
+    // remember the position of the episode we're switching away from
+    fn save_episode_progress(&mut self) {
+        // its track never got loaded
+        if self.pending_play.is_some() {
+            return;
+        }
+        let progress = self.player.playback_context().progress_ms();
+        let id = match self.playing_episode_mut() {
+            Some(episode) if !episode.played && progress > 0 => {
+                episode.resume_ms = Some(progress);
+                episode.id
+            }
+            _ => return,
+        };
+        self.episode_positions.insert(id, progress);
+    }
+
+    pub fn handle_playback(&mut self, evt: PlaybackEvent) {
+        self.dirty = true;
+        match evt {
+            PlaybackEvent::Started(index) => self.prefetch_lyrics(index),
+            PlaybackEvent::Ended => {
+                if let Some(episode) = self.playing_episode_mut() {
+                    episode.played = true;
+                    episode.resume_ms = None;
+                }
+                if let Some(id) = self.playing_episode.take() {
+                    self.episode_positions.remove(&id);
+                    return;
+                }
+                self.on_track_end();
+            }
+        }
+    }
+
+    // replay or advance the queue, per repeat mode
+    fn on_track_end(&mut self) {
+        let index = match self.queue_index {
+            Some(index) => index,
+            None => return,
+        };
+        let mode = self.repeat_mode();
+        let replay = match mode {
+            RepeatMode::One => true,
+            RepeatMode::Count(_) if self.repeats_left > 0 => {
+                self.repeats_left -= 1;
+                true
+            }
+            _ => false,
+        };
+        if replay {
+            self.scrobbled = false;
+            self.player.seek_ms(0);
+            self.start_listen();
+            self.emit(PlaybackEvent::Started(index));
+        } else if let Some(next) = self.advance(index) {
+            // gapless, the player is already playing it
+            if self.prefetched == Some((next, self.queue[next].id)) && self.player.next().is_ok() {
+                self.take_prefetched(next);
+            } else {
+                let _ = self.play_index(next);
+            }
+        } else {
+            self.queue_done();
+        }
+    }
+
+    // input
+    pub fn handle_action(&mut self, key: Key) {
+        self.dirty = true;
+        self.error = None;
+        if self.playlist_picker.is_some() {
+            return self.handle_picker_action(key);
+        }
+        if self.confirming_quit {
+            self.confirming_quit = false;
+            self.should_quit = matches!(key, Key::Char('y') | Key::Char('\n'));
+            return;
+        }
+        if self.confirm_remove.is_some() {
+            return match key {
+                Key::Char('y') | Key::Char('\n') => self.confirm_remove_from_playlist(),
+                _ => self.cancel_remove(),
+            };
+        }
+        if let Some(scroll) = self.help_scroll {
+            return self.handle_help_action(scroll, key);
+        }
+
+        if let Some(input) = self.search_input.as_mut() {
+            // suggestions from the history are browsed while nothing is typed
+            let browsing = input.is_empty() && !self.search_history.is_empty();
+            match key {
+                Key::Char('\n') => {
+                    let picked = self.search_history_index.take();
+                    let mut query = self.search_input.take().unwrap_or_default();
+                    if let Some(past) = picked.and_then(|i| self.search_history.get(i)) {
+                        query = past.clone();
+                    }
+                    self.commit_search(query);
+                }
+                Key::Down if browsing => {
+                    let last = self.search_history.len() - 1;
+                    self.search_history_index =
+                        Some(self.search_history_index.map_or(0, |i| (i + 1).min(last)));
+                }
+                Key::Up if browsing => {
+                    self.search_history_index = match self.search_history_index {
+                        Some(0) | None => None,
+                        Some(i) => Some(i - 1),
+                    };
+                }
+                Key::Delete if browsing => {
+                    if let Some(i) = self.search_history_index {
+                        self.remove_search_history(i);
+                    }
+                }
+                Key::Char(c) => {
+                    input.push(c);
+                    self.search_history_index = None;
+                }
+                Key::Backspace => {
+                    input.pop();
+                }
+                Key::Esc => {
+                    self.search_input = None;
+                    self.search_history_index = None;
+                }
+                _ => {}
+            }
+            return;
+        }
+
+        let typing = self.filter_typing;
+        if let Some(filter) = self.track_filter.as_mut().filter(|_| typing) {
+            match key {
+                Key::Char('\n') => self.filter_typing = false,
+                Key::Esc => self.clear_track_filter(),
+                Key::Down => self.select_next_track(),
+                Key::Up => self.select_prev_track(),
+                Key::Char(c) => {
+                    filter.push(c);
+                    self.clamp_to_filter();
+                }
+                Key::Backspace => {
+                    filter.pop();
+                    self.clamp_to_filter();
+                }
+                _ => {}
+            }
+            return;
+        }
+
+        let action = match self.key_bindings.action(key) {
+            Some(action) => action,
+            None => return,
+        };
+        match action {
+            Action::Quit => self.request_quit(),
+            Action::Back if self.track_filter.is_some() => self.clear_track_filter(),
+            Action::Back => {
+                self.pop_route();
+            }
+            Action::FilterTracks => self.start_track_filter(),
+            Action::Help => self.help_scroll = Some(0),
+            Action::ResumeCheckpoint => self.resume_checkpoint(),
+            Action::Search => self.search_input = Some(String::new()),
+            Action::Lyrics => self.push_route(Route::Lyrics),
+            Action::Comments => {
+                if let Some(id) = self.now_playing.as_ref().map(|song| song.id) {
+                    self.comments(id, ResourceType::Song);
+                }
+            }
+            Action::ToggleCommentSort if *self.route() == Route::Comments => self.toggle_comment_sort(),
+            Action::ToggleCommentSort => {}
+            Action::NextPage if *self.route() == Route::Comments => self.load_more_comments(),
+            Action::NextPage if *self.route() == Route::Search => self.search_next_page(),
+            Action::PrevPage if *self.route() == Route::Search => self.search_prev_page(),
+            Action::NextPage | Action::PrevPage => {}
+            Action::SelectNext if *self.route() == Route::Comments => self.select_next_comment(),
+            Action::SelectPrev if *self.route() == Route::Comments => {
+                self.comment_index = self.comment_index.saturating_sub(1)
+            }
+            Action::SelectNext if matches!(self.route(), Route::Podcast(_)) => {
+                let last = self.podcast_episodes.len().saturating_sub(1);
+                self.episode_index = (self.episode_index + 1).min(last);
+            }
+            Action::SelectPrev if matches!(self.route(), Route::Podcast(_)) => {
+                self.episode_index = self.episode_index.saturating_sub(1)
+            }
+            Action::SelectNext if self.other_search_results().is_some() => {
+                let last = self.other_search_results().map_or(0, SearchResult::len);
+                self.search_index = (self.search_index + 1).min(last.saturating_sub(1));
+            }
+            Action::SelectPrev if self.other_search_results().is_some() => {
+                self.search_index = self.search_index.saturating_sub(1)
+            }
+            Action::SelectNext => self.select_next_track(),
+            Action::SelectPrev => self.select_prev_track(),
+            Action::PlaySelected => self.play_selected(),
+            Action::PlayPause => self.toggle_pause(),
+            Action::NextTrack => self.next_track(),
+            Action::PrevTrack => self.prev_track(),
+            Action::ToggleShuffle => self.toggle_shuffle(),
+            Action::ToggleFm if self.is_fm() => self.exit_fm(),
+            Action::ToggleFm => self.start_fm(),
+            Action::FmTrash => {
+                let playing = self.now_playing.as_ref().map(|song| song.id);
+                if let Some(id) = playing.filter(|_| self.is_fm()) {
+                    self.fm_trash(id);
+                }
+            }
+            Action::ToggleLike => {
+                if let Some(id) = self.now_playing.as_ref().map(|song| song.id) {
+                    self.toggle_like(id);
+                }
+            }
+            Action::VolumeUp => {
+                self.volume_up();
+            }
+            Action::VolumeDown => {
+                self.volume_down();
+            }
+            Action::AddToPlaylist => {
+                if let Some(id) = self.selected_track().map(|song| song.id) {
+                    self.open_playlist_picker(vec![id]);
+                }
+            }
+        }
+    }
+
+    fn toggle_pause(&mut self) {
+        if self.player.playback_context().is_paused() {
+            self.player.resume();
+        } else {
+            self.player.pause();
+        }
+    }
+
+    /// Skips to the next playable track, wrapping around with repeat all and
+    /// following the shuffled order while shuffling.
+    pub fn next_track(&mut self) {
+        let index = match self.queue_index {
+            Some(index) => index,
+            None => return,
+        };
+        if let Some(next) = self.advance(index) {
+            let _ = self.play_index(next);
+        }
+    }
+
+    /// Goes back to the previous playable track, wrapping around with repeat all.
+    /// While shuffling it's the track heard before this one. Past the first
+    /// `PREV_RESTART_MS` of a track, or with nothing to go back to, the track
+    /// restarts instead.
+    pub fn prev_track(&mut self) {
+        let index = match self.queue_index {
+            Some(index) => index,
+            None => return,
+        };
+        if self.player.playback_context().progress_ms() >= PREV_RESTART_MS {
+            return self.player.seek_ms(0);
+        }
+        if self.is_shuffled() {
+            while let Some(prev) = self.shuffle_history.pop() {
+                if self.is_available(self.queue[prev].id) {
+                    self.shuffle_order.push_front(index);
+                    let _ = self.play_index(prev);
+                    return;
+                }
+            }
+            return self.player.seek_ms(0);
+        }
+        let available = |i: &usize| self.is_available(self.queue[*i].id);
+        let prev = match (0..index).rev().find(available) {
+            None if self.repeat_mode() == RepeatMode::All => {
+                (index + 1..self.queue.len()).rev().find(available)
+            }
+            prev => prev,
+        };
+        match prev {
+            Some(prev) => {
+                let _ = self.play_index(prev);
+            }
+            None => self.player.seek_ms(0),
+        }
+    }
+
+    // the track to play after queue `index`, taken off the shuffled order while shuffling
+    fn advance(&mut self, index: usize) -> Option<usize> {
+        if self.is_fm() || !self.is_shuffled() {
+            return self.peek_next(index);
+        }
+        if self.shuffle_order.is_empty() {
+            if self.shuffle_history.is_empty() {
+                // nothing played yet since the queue was set
+                self.reshuffle(Some(index));
+            } else if self.repeat_mode() == RepeatMode::All {
+                self.reshuffle(None);
+                // the song just heard doesn't come right back
+                if self.shuffle_order.front() == Some(&index) && self.shuffle_order.len() > 1 {
+                    self.shuffle_order.swap(0, 1);
+                }
+            }
+        }
+        while let Some(next) = self.shuffle_order.pop_front() {
+            if self.is_available(self.queue[next].id) {
+                self.shuffle_history.push(index);
+                // no further back than one pass through the queue
+                let over = self.shuffle_history.len().saturating_sub(self.queue.len());
+                self.shuffle_history.drain(..over);
+                return Some(next);
+            }
+        }
+        None
+    }
+
+    // the track `advance` would move on to, without moving
+    fn peek_next(&self, index: usize) -> Option<usize> {
+        // FM songs come in the order the api picked them, once
+        if self.is_fm() {
+            return self.next_available(index + 1);
+        }
+        if !self.is_shuffled() {
+            return match self.next_available(index + 1) {
+                None if self.repeat_mode() == RepeatMode::All => self.next_available(0),
+                next => next,
+            };
+        }
+        // not before the shuffled order is dealt again
+        let mut order = self.shuffle_order.iter().copied();
+        order.find(|&next| self.is_available(self.queue[next].id))
+    }
+
+    // a random order of the whole queue, but `playing` which is heard first
+    fn reshuffle(&mut self, playing: Option<usize>) {
+        let indices = (0..self.queue.len()).filter(|&i| Some(i) != playing).collect();
+        self.shuffle_order = shuffled(indices).into();
+    }
+
+    pub fn is_shuffled(&self) -> bool {
+        self.player.playback_context().shuffle_enabled()
+    }
+
+    /// Turns shuffle on, from the playing track on, or off, going on in
+    /// queue order after the playing track.
+    pub fn set_shuffle(&mut self, enabled: bool) {
+        self.player.playback_context().set_shuffle(enabled);
+        self.shuffle_order.clear();
+        self.shuffle_history.clear();
+        if enabled {
+            self.reshuffle(self.queue_index);
+        }
+        self.playback_state_changed = Some(Instant::now());
+    }
+
+    pub fn toggle_shuffle(&mut self) {
+        self.set_shuffle(!self.is_shuffled());
+    }
+
+    /// The wheel scrolls the track table, other mouse events aren't used yet.
+    pub fn handle_mouse(&mut self, evt: MouseEvent) {
+        match evt {
+            MouseEvent::Press(MouseButton::WheelUp, _, _) => self.select_prev_track(),
+            MouseEvent::Press(MouseButton::WheelDown, _, _) => self.select_next_track(),
+            _ => return,
+        }
+        self.dirty = true;
+    }
+
+    fn select_next_track(&mut self) {
+        if self.track_filter.is_some() {
+            return self.step_filtered(1);
+        }
+        if let Some(tracks) = &self.track_table {
+            if self.track_table_index + 1 < tracks.len() {
+                self.track_table_index += 1;
+            }
+        }
+    }
+
+    fn select_prev_track(&mut self) {
+        if self.track_filter.is_some() {
+            return self.step_filtered(-1);
+        }
+        self.track_table_index = self.track_table_index.saturating_sub(1);
+    }
+
+    // moves the selection `step` rows through the filtered ones
+    fn step_filtered(&mut self, step: isize) {
+        let rows = self.filtered_indices();
+        let next = match rows.iter().position(|&i| i == self.track_table_index) {
+            Some(pos) => (pos as isize + step).max(0) as usize,
+            None => 0,
+        };
+        if let Some(&row) = rows.get(next) {
+            self.track_table_index = row;
+        }
+    }
+
+    fn play_selected(&mut self) {
+        if let Route::Podcast(_) = self.route() {
+            let _ = self.play_track_at(self.episode_index);
+            return;
+        }
+        let tracks = match &self.track_table {
+            Some(tracks) => tracks,
+            None => return,
+        };
+        // on from the selected row through those the filter leaves
+        let rows = self.filtered_indices();
+        let at = match rows.iter().position(|&i| i == self.track_table_index) {
+            Some(at) => at,
+            None => return,
+        };
+        let playable = rows[at..].iter().copied().find(|&i| self.is_available(tracks[i].id));
+        match playable {
+            Some(index) => {
+                let _ = self.play_track_at(index);
+            }
+            None => self.notify(Level::Warning, "No playable songs left"),
+        }
+    }
+
+    /// Plays row `index` of the track table, which becomes the queue, or
+    /// the episode at `index` while a podcast is shown.
+    pub fn play_track_at(&mut self, index: usize) -> Result<(), PlayerError> {
+        if let Route::Podcast(_) = self.route() {
+            return self.play_episode(index);
+        }
+        match &self.track_table {
+            Some(tracks) if index < tracks.len() => self.set_queue(tracks.clone()),
+            _ => return Err(PlayerError::InvalidIndex(index)),
+        }
+        self.active_playlist_index = match *self.route() {
+            Route::Playlist(id) => self
+                .user_playlists
+                .iter()
+                .flatten()
+                .position(|playlist| playlist.id == id),
+            _ => None,
+        };
+        self.play_index(index)
+    }
+
+    // puts `song` first in the recently played, until the next fetch replaces them
+    fn record_played(&mut self, song: Song) {
+        let recent = self.recently_played.get_or_insert_with(Vec::new);
+        // a song played before moves up as it was, keeping what's known of its past plays
+        let entry = match recent.iter().position(|played| played.id == song.id) {
+            Some(i) => recent.remove(i),
+            None => song,
+        };
+        recent.insert(0, entry);
+        recent.truncate(self.config.recently_played_len);
+    }
+
+    /// Row of the sidebar's playlists the queue was taken from.
+    pub fn active_playlist_index(&self) -> Option<usize> {
+        self.active_playlist_index
+    }
+
+    // the pending song can't be played, move on to the next one that might
+    fn skip_unavailable(&mut self, id: usize) {
+        self.pending_play = None;
+        let name = self
+            .queue
+            .iter()
+            .find(|song| song.id == id)
+            .map_or_else(|| id.to_string(), |song| song.name.clone());
+        self.notify(Level::Warning, format!("{} is unavailable, skipped", name));
+
+        if self.playing_episode.is_some() {
+            return;
+        }
+        let next = self.queue_index.and_then(|index| self.next_available(index + 1));
+        match next {
+            Some(next) => {
+                let _ = self.play_index(next);
+            }
+            None => self.queue_done(),
+        }
+    }
+
+    // data
+    pub fn handle_data(&mut self, data: DataEvent) {
+        if data.is_reply() {
+            self.loading = self.loading.saturating_sub(1);
+        }
+        self.apply_data(data);
+    }
+
+    // what `data` changes, also for data at hand without asking the io worker
+    fn apply_data(&mut self, data: DataEvent) {
+        self.dirty = true;
+        match data {
+            DataEvent::SearchResults(results) => {
+                // songs are played from the track table like any other list
+                if let SearchResult::Song(songs) = &results {
+                    self.track_table = Some(songs.clone());
+                    self.track_table_index = 0;
+                }
+                self.search_results = Some(results);
+                self.search_index = 0;
+            }
+            DataEvent::RecommendedSongs(songs) => {
+                self.recommended_songs = Some(songs);
+                self.recommended_at = Some(Instant::now());
+                if self.pending_daily {
+                    self.pending_daily = false;
+                    self.play_daily();
+                }
+            }
+            DataEvent::Comments {
+                id,
+                kind,
+                sort,
+                offset,
+                comments,
+                more,
+            } => {
+                // a page of comments no longer shown, or shown already
+                if !self.is_comment_page(id, &kind, sort, offset) {
+                    return;
+                }
+                self.comments_fetching = false;
+                self.comments_more = more && !comments.is_empty();
+                self.comments.get_or_insert_with(Vec::new).extend(comments);
+            }
+            DataEvent::CommentsFailed {
+                id,
+                kind,
+                sort,
+                offset,
+                reason,
+            } => {
+                if !self.is_comment_page(id, &kind, sort, offset) {
+                    return;
+                }
+                // scrolling to the end tries the page again
+                self.comments_fetching = false;
+                self.notify(Level::Error, format!("Loading comments failed: {}", reason));
+            }
+            DataEvent::RecentlyPlayed(songs) => self.recently_played = Some(songs),
+            DataEvent::PersonalFm(songs) => {
+                self.fm_fetching = false;
+                self.fm.get_or_insert_with(Vec::new).extend(songs.iter().cloned());
+                if !self.is_fm() {
+                    return;
+                }
+                // queued behind what's left, or started when the FM ran dry
+                let start = self.queue.len();
+                self.queue.extend(songs);
+                if self.queue_index.is_none() && self.pending_play.is_none() {
+                    if let Some(next) = self.next_available(start) {
+                        let _ = self.play_index(next);
+                    }
+                }
+            }
+            DataEvent::PersonalFmFailed(reason) => {
+                // asked again when the FM runs low next
+                self.fm_fetching = false;
+                self.notify(Level::Error, format!("Personal FM failed: {}", reason));
+            }
+            DataEvent::UserPlaylists(playlists) => {
+                let last = playlists.len().saturating_sub(1);
+                self.selected_playlist_index = self.selected_playlist_index.min(last);
+                self.user_playlists = Some(playlists);
+            }
+            DataEvent::PlaylistTracks(id, songs) => {
+                if *self.route() != Route::Playlist(id) {
+                    return;
+                }
+                self.track_table = Some(songs);
+                self.track_table_index = 0;
+            }
+            DataEvent::SongUrls(urls) => {
+                let mut pending_url = None;
+                let prefetching = self.prefetch.map(|(_, id)| id);
+                for (id, url) in urls {
+                    match url {
+                        Some(url) => {
+                            self.unavailable.remove(&id);
+                            if Some(id) == self.pending_play {
+                                pending_url = Some((id, url));
+                            } else if Some(id) == prefetching {
+                                self.download(id, url);
+                            }
+                        }
+                        None => {
+                            self.unavailable.insert(id);
+                            if Some(id) == prefetching {
+                                self.prefetch = None;
+                            }
+                        }
+                    }
+                }
+                if let Some((id, url)) = pending_url {
+                    match self.track_cache.as_ref().and_then(|cache| cache.get(id)) {
+                        Some(track) => self.apply_data(DataEvent::Track(id, track)),
+                        None if self.playing_episode.is_some() => self.stream_episode(id, url),
+                        None => self.download(id, url),
+                    }
+                } else if let Some(id) = self.pending_play.filter(|id| !self.is_available(*id)) {
+                    self.skip_unavailable(id);
+                }
+            }
+            DataEvent::Track(id, track) => {
+                if self.pending_play != Some(id) {
+                    return self.enqueue_prefetched(id, track);
+                }
+                self.pending_play = None;
+                self.cache_track(id, &track);
+                self.player.set_track_kind(self.track_kind());
+                if let Err(e) = self.player.play(track) {
+                    error!("play track {} error: {}", id, e);
+                    self.notify(Level::Error, format!("Couldn't play the track: {}", e));
+                    return;
+                }
+                self.track_started();
+                self.prefetch_next();
+            }
+            DataEvent::Lyric(id, lyric) => {
+                self.lyric_requests.remove(&id);
+                self.lyrics.insert(id, Lyric::parse(&lyric));
+            }
+            // asked again the next time the song comes up
+            DataEvent::LyricFailed(id, reason) => {
+                self.lyric_requests.remove(&id);
+                warn!("fetch lyric {} error: {}", id, reason);
+            }
+            DataEvent::PodcastEpisodes(id, mut episodes) => {
+                if *self.route() != Route::Podcast(id) {
+                    return;
+                }
+                // keep what we know locally about episodes already listed
+                for episode in episodes.iter_mut() {
+                    if let Some(known) = self.podcast_episodes.iter().find(|ep| ep.id == episode.id) {
+                        episode.played |= known.played;
+                        episode.resume_ms = known.resume_ms;
+                    }
+                    if episode.resume_ms.is_none() && !episode.played {
+                        episode.resume_ms = self.episode_positions.get(&episode.id).copied();
+                    }
+                }
+                self.episode_index = self.episode_index.min(episodes.len().saturating_sub(1));
+                self.podcast_episodes = episodes;
+            }
+            DataEvent::LoginStatus(LoginStatus::Valid(user)) => {
+                self.user = Some(user);
+                self.auth = true;
+                if *self.route() == Route::Login {
+                    self.routes = vec![Route::Home];
+                }
+                self.user_playlists();
+                self.liked_songs();
+            }
+            DataEvent::LoginStatus(LoginStatus::Expired) => {
+                self.user = None;
+                self.auth = false;
+                self.routes = vec![Route::Login];
+            }
+            DataEvent::AddedToPlaylist {
+                playlist_id,
+                song_ids,
+            } => {
+                let playlist = self
+                    .user_playlists
+                    .as_mut()
+                    .and_then(|pls| pls.iter_mut().find(|pl| pl.id == playlist_id));
+                if let Some(playlist) = playlist {
+                    playlist.track_count += song_ids.len();
+                    let msg = format!("Added {} song(s) to {}", song_ids.len(), playlist.name);
+                    self.notify(Level::Info, msg);
+                }
+            }
+            DataEvent::LikedSongs(ids) => self.liked_song_ids = ids.into_iter().collect(),
+            DataEvent::LikeFailed {
+                song_id,
+                like,
+                reason,
+            } => {
+                if like {
+                    self.liked_song_ids.remove(&song_id);
+                } else {
+                    self.liked_song_ids.insert(song_id);
+                    // the unlike never happened, there's nothing to undo
+                    let unlike = |action: &UndoAction| {
+                        matches!(action, UndoAction::Unlike(id) if *id == song_id)
+                    };
+                    self.undo.remove(unlike);
+                }
+                let verb = if like { "Like" } else { "Unlike" };
+                self.notify(Level::Error, format!("{} failed: {}", verb, reason));
+            }
+            DataEvent::AddToPlaylistFailed { reason, .. } => {
+                self.notify(Level::Error, format!("Add to playlist failed: {}", reason));
+            }
+            DataEvent::RemovedFromPlaylist {
+                playlist_id,
+                song_ids,
+            } => {
+                self.take_pending_removal(playlist_id, &song_ids);
+                let playlist = self
+                    .user_playlists
+                    .as_mut()
+                    .and_then(|pls| pls.iter_mut().find(|pl| pl.id == playlist_id));
+                if let Some(playlist) = playlist {
+                    playlist.track_count = playlist.track_count.saturating_sub(song_ids.len());
+                    let msg = format!("Removed {} song(s) from {}", song_ids.len(), playlist.name);
+                    self.notify(Level::Info, msg);
+                }
+            }
+            DataEvent::RemoveFromPlaylistFailed {
+                playlist_id,
+                song_ids,
+                reason,
+            } => {
+                if let Some(removal) = self.take_pending_removal(playlist_id, &song_ids) {
+                    // nothing to undo either, unless it's been undone already
+                    let ids = row_ids(&removal.rows);
+                    self.undo.remove(|action| {
+                        matches!(action, UndoAction::RemoveFromPlaylist { playlist_id: id, rows }
+                            if *id == playlist_id && row_ids(rows) == ids)
+                    });
+                    self.restore_rows(&removal.rows);
+                }
+                self.notify(Level::Error, format!("Remove from playlist failed: {}", reason));
+            }
+            DataEvent::Cover { url, bytes } => {
+                if self.cover_url.as_ref() == Some(&url) {
+                    self.cover = Some(bytes);
+                }
+            }
+            DataEvent::CoverFailed(url) => {
+                if self.cover_url.as_ref() == Some(&url) {
+                    self.cover = None;
+                }
+            }
+            DataEvent::RequestFailed(e) | DataEvent::Error(e) => {
+                error!("io error: {}", e);
+                self.notify(Level::Error, e.clone());
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+// song ids of removed `(row, song)`s
+fn row_ids(rows: &[(usize, Song)]) -> Vec<usize> {
+    rows.iter().map(|(_, song)| song.id).collect()
+}
+
+// id of the song behind a `browser::web_url(Page::Song, id)`
+fn web_song_id(location: &str) -> Option<usize> {
+    let (_, id) = location.split_once("music.163.com/song?id=")?;
+    id.parse().ok()
+}
+
+// `items` in random order, Fisher-Yates rolled with a randomly seeded hasher
+fn shuffled(mut items: Vec<usize>) -> Vec<usize> {
+    let state = RandomState::new();
+    for i in (1..items.len()).rev() {
+        let mut hasher = state.build_hasher();
+        hasher.write_usize(i);
+        let j = hasher.finish() as usize % (i + 1);
+        items.swap(i, j);
+    }
+    items
+}
+
+// "name - artist / artist" shown in the playbar
+pub(crate) fn playbar_title(song: &Song) -> String {
+    let artists: Vec<&str> = song.artists.iter().map(|ar| ar.name.as_str()).collect();
+    if artists.is_empty() {
+        song.name.clone()
+    } else {
+        format!("{} - {}", song.name, artists.join(" / "))
+    }
+}
+
+// one query per line, most recent first
+fn read_search_history(path: &Path, cap: usize) -> VecDeque<String> {
+    fs::read_to_string(path)
+        .map(|raw| {
+            raw.lines()
+                .filter(|line| !line.trim().is_empty())
+                .take(cap)
+                .map(str::to_owned)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use bytes::Bytes;
+    use log::LevelFilter;
+    use ncmapi::types::{Playlist, Song, UserProfile};
+    use ncmapi::ResourceType;
+    use termion::event::{Key, MouseButton, MouseEvent};
+    use tui::style::Color;
+
+    use super::{
+        App, Comment, CommentSort, Level, PlaylistPicker, Podcast, PodcastEpisode, Route,
+        SearchResult, BUFFERING_AFTER, RECOMMENDATIONS_TTL, TOAST_TTL, VOLUME_DEBOUNCE,
+    };
+    use crate::browser::Opener;
+    use crate::checkpoint::{self, Checkpoint};
+    use crate::clipboard::Clipboard;
+    use crate::config::AppConfig;
+    use crate::media::{MediaCommand, NowPlaying};
+    use crate::scrobble::ScrobbleEvent;
+    use crate::event::{DataEvent, IoEvent, LoginStatus};
+    use crate::keybindings::KeyBindings;
+    use crate::lyric::Lyric;
+    use crate::player::{
+        AudioPlayer, NullAudioPlayer, PlaybackEvent, PlayerError, RecordingPlayer, RepeatMode,
+    };
+    use crate::theme::Theme;
+
+    // a player standing still until the test moves it, at the default launch volume
+    fn test_player() -> RecordingPlayer {
+        let player = RecordingPlayer::new(NullAudioPlayer::new().without_clock());
+        player.playback_context().set_volume_percent(90);
+        player
+    }
+
+    fn songs(ids: &[usize]) -> Vec<Song> {
+        ids.iter()
+            .map(|&id| Song {
+                id,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn new_app() -> (App, mpsc::Receiver<IoEvent>, mpsc::Receiver<PlaybackEvent>) {
+        let (io_tx, io_rx) = mpsc::channel();
+        let (playback_tx, playback_rx) = mpsc::channel();
+        let app = App::new(Box::new(test_player()), io_tx).set_playback_tx(playback_tx);
+        (app, io_rx, playback_rx)
+    }
+
+    #[test]
+    fn test_play_index() {
+        let (mut app, io_rx, playback_rx) = new_app();
+        app.queue = songs(&[10, 11, 12]);
+        app.seek_ms = Some(3000);
+
+        app.play_index(2).unwrap();
+
+        assert_eq!(app.current_index(), Some(2));
+        assert_eq!(app.seek_ms, None);
+        assert!(matches!(io_rx.try_recv(), Ok(IoEvent::SongUrls(ids)) if ids == vec![12]));
+        assert_eq!(playback_rx.try_recv(), Ok(PlaybackEvent::Started(2)));
+    }
+
+    #[test]
+    fn test_track_end_advances() {
+        let (io_tx, io_rx) = mpsc::channel();
+        let (playback_tx, playback_rx) = mpsc::channel();
+        let player = NullAudioPlayer::new().with_duration_ms(Some(100));
+        let mut app = App::new(Box::new(player), io_tx).set_playback_tx(playback_tx);
+        app.queue = songs(&[10, 11]);
+        app.play_index(0).unwrap();
+        app.handle_data(DataEvent::Track(10, Bytes::from("song")));
+        assert_eq!(playback_rx.try_recv(), Ok(PlaybackEvent::Started(0)));
+
+        // the player itself tells the track is over
+        let ended = playback_rx.recv_timeout(Duration::from_secs(2)).unwrap();
+        assert_eq!(ended, PlaybackEvent::Ended);
+        app.handle_playback(ended);
+        assert_eq!(app.current_index(), Some(1));
+        assert!(io_rx
+            .try_iter()
+            .any(|e| matches!(e, IoEvent::SongUrls(ids) if ids == vec![11])));
+    }
+
+    #[test]
+    fn test_gapless_prefetch() {
+        let (io_tx, io_rx) = mpsc::channel();
+        let player = test_player();
+        let queued = player.queued();
+        let mut config = AppConfig::from_toml("gapless = true").unwrap();
+        config.key_bindings_path = None;
+        let mut app = App::new(Box::new(player), io_tx).set_config(config);
+        app.queue = songs(&[10, 11, 12]);
+        app.play_index(0).unwrap();
+        app.handle_data(DataEvent::Track(10, Bytes::from("song")));
+        assert!(io_rx
+            .try_iter()
+            .any(|e| matches!(e, IoEvent::SongUrls(ids) if ids == vec![11])));
+
+        // the next song is fetched into the player's queue while this one plays
+        app.handle_data(DataEvent::SongUrls(vec![(11, Some("url".to_owned()))]));
+        assert!(io_rx.try_iter().any(|e| matches!(e, IoEvent::Download(11, _))));
+        app.handle_data(DataEvent::Track(11, Bytes::from("next")));
+        assert_eq!(*queued.lock().unwrap(), vec![Bytes::from("next")]);
+        assert_eq!(app.current_index(), Some(0));
+
+        app.handle_playback(PlaybackEvent::Ended);
+        assert_eq!(app.current_index(), Some(1));
+        let ids: Vec<_> = io_rx
+            .try_iter()
+            .filter_map(|e| match e {
+                IoEvent::SongUrls(ids) => Some(ids),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(ids, vec![vec![12]]);
+    }
+
+    #[test]
+    fn test_play_index_out_of_range() {
+        let (mut app, io_rx, playback_rx) = new_app();
+        app.queue = songs(&[10, 11]);
+
+        assert!(matches!(app.play_index(2), Err(PlayerError::InvalidIndex(2))));
+        assert_eq!(app.current_index(), None);
+        assert!(io_rx.try_recv().is_err());
+        assert!(playback_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_play_track_at() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        assert!(matches!(app.play_track_at(0), Err(PlayerError::InvalidIndex(0))));
+
+        app.user_playlists = Some(vec![
+            Playlist {
+                id: 7,
+                ..Default::default()
+            },
+            Playlist {
+                id: 8,
+                ..Default::default()
+            },
+        ]);
+        app.push_route(Route::Playlist(8));
+        app.track_table = Some(songs(&[10, 11, 12]));
+        app.play_track_at(1).unwrap();
+        assert_eq!(app.queue().len(), 3);
+        assert_eq!(app.track_table_index(), 1);
+        assert_eq!(app.active_playlist_index(), Some(1));
+        assert!(matches!(io_rx.try_recv(), Ok(IoEvent::SongUrls(ids)) if ids == vec![11]));
+
+        // the highlight follows the playing track
+        app.next_track();
+        assert_eq!(app.current_index(), Some(2));
+        assert_eq!(app.track_table_index(), 2);
+        app.next_track();
+        assert_eq!(app.current_index(), Some(2));
+        app.prev_track();
+        app.prev_track();
+        assert_eq!(app.current_index(), Some(0));
+        app.prev_track();
+        assert_eq!(app.current_index(), Some(0));
+    }
+
+    #[test]
+    fn test_next_prev_wrap_with_repeat_all() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.track_table = Some(songs(&[10, 11, 12]));
+        app.set_repeat_mode(RepeatMode::All);
+        app.play_track_at(2).unwrap();
+        app.next_track();
+        assert_eq!(app.current_index(), Some(0));
+        app.prev_track();
+        assert_eq!(app.current_index(), Some(2));
+
+        app.player.playback_context().set_shuffle(true);
+        for _ in 0..10 {
+            let before = app.current_index();
+            app.next_track();
+            assert_ne!(app.current_index(), before);
+        }
+    }
+
+    #[test]
+    fn test_shuffle_history() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.track_table = Some(songs(&[10, 11, 12, 13, 14]));
+        app.play_track_at(2).unwrap();
+        app.handle_action(Key::Ctrl('s'));
+        assert!(app.is_shuffled());
+
+        let mut heard = vec![2];
+        for _ in 0..4 {
+            app.next_track();
+            heard.push(app.current_index().unwrap());
+        }
+        let mut sorted = heard.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, vec![0, 1, 2, 3, 4]);
+        // the end of the order without repeat all
+        app.next_track();
+        assert_eq!(app.current_index(), heard.last().copied());
+
+        for &expected in heard.iter().rev().skip(1) {
+            app.prev_track();
+            assert_eq!(app.current_index(), Some(expected));
+        }
+        // forward again through the same songs
+        app.next_track();
+        assert_eq!(app.current_index(), Some(heard[1]));
+
+        // linear order goes on from the playing song
+        app.toggle_shuffle();
+        let playing = app.current_index().unwrap();
+        app.next_track();
+        assert_eq!(app.current_index(), Some((playing + 1).min(4)));
+    }
+
+    #[test]
+    fn test_prev_restarts_past_first_seconds() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.track_table = Some(songs(&[10, 11, 12]));
+        app.play_track_at(1).unwrap();
+        let context = app.player.playback_context();
+
+        context.set_progress_ms(PREV_RESTART_MS);
+        app.prev_track();
+        assert_eq!(app.current_index(), Some(1));
+        assert_eq!(context.progress_ms(), 0);
+
+        // early on it goes back
+        context.set_progress_ms(PREV_RESTART_MS - 1);
+        app.prev_track();
+        assert_eq!(app.current_index(), Some(0));
+    }
+
+    #[test]
+    fn test_prev_without_history_restarts() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.track_table = Some(songs(&[10, 11, 12]));
+        app.play_track_at(1).unwrap();
+        app.toggle_shuffle();
+        let context = app.player.playback_context();
+
+        // nothing heard before it since shuffling
+        context.set_progress_ms(1000);
+        app.prev_track();
+        assert_eq!(app.current_index(), Some(1));
+        assert_eq!(context.progress_ms(), 0);
+    }
+
+    #[test]
+    fn test_shuffle_repeat_all_reshuffles() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.track_table = Some(songs(&[10, 11]));
+        app.set_repeat_mode(RepeatMode::All);
+        app.play_track_at(0).unwrap();
+        app.set_shuffle(true);
+        for _ in 0..10 {
+            let before = app.current_index();
+            app.next_track();
+            assert_ne!(app.current_index(), before);
+        }
+    }
+
+    #[test]
+    fn test_selection_differs_from_play_index() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.track_table = Some(songs(&[10, 11, 12]));
+        app.track_table_index = 1;
+        app.play_selected();
+
+        app.select_next_track();
+
+        assert_eq!(app.track_table_index(), 2);
+        assert_eq!(app.current_index(), Some(1));
+    }
+
+    #[test]
+    fn test_volume_step_from_config() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.config.volume_step = 7;
+
+        assert_eq!(app.volume_up(), 97);
+        assert_eq!(app.volume_down(), 90);
+        assert_eq!(app.volume_down(), 83);
+    }
+
+    #[test]
+    fn test_volume_clamped_by_player() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.config.volume_step = 6;
+
+        assert_eq!(app.volume_up(), 96);
+        assert_eq!(app.volume_up(), 100);
+        assert_eq!(app.volume_up(), 100);
+    }
+
+    #[test]
+    fn test_volume_kept_across_restarts() {
+        let dir = std::env::temp_dir().join(format!("lekima-volume-{}", std::process::id()));
+        let path = dir.join("playback.json");
+        let (app, _io_rx, _playback_rx) = new_app();
+        let mut app = app.set_playback_state_path(&path);
+        app.volume_up();
+        app.volume_up();
+        app.on_tick();
+        assert!(!path.exists());
+
+        // pretend the last change happened long enough ago
+        if let Some(changed_at) = app.playback_state_changed.as_mut() {
+            *changed_at = Instant::now() - VOLUME_DEBOUNCE;
+        }
+        app.on_tick();
+        assert!(path.exists());
+
+        // the saved volume wins over the one configured for launch
+        let (app, _io_rx, _playback_rx) = new_app();
+        let mut config = AppConfig::from_toml("volume = 40").unwrap();
+        config.key_bindings_path = None;
+        let app = app.set_config(config).set_playback_state_path(&path);
+        assert_eq!(app.player.playback_context().volume_percent(), 100);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_playback_state_saved_once_settled() {
+        let dir = std::env::temp_dir().join(format!("lekima-state-{}", std::process::id()));
+        let path = dir.join("playback.json");
+        let (app, _io_rx, _playback_rx) = new_app();
+        let mut app = app.set_playback_state_path(&path);
+
+        app.set_repeat_mode(RepeatMode::All);
+        app.on_tick();
+        assert!(!path.exists());
+        if let Some(changed_at) = app.playback_state_changed.as_mut() {
+            *changed_at = Instant::now() - VOLUME_DEBOUNCE;
+        }
+        app.on_tick();
+        assert!(path.exists());
+        assert!(app.playback_state_changed.is_none());
+
+        let (app, _io_rx, _playback_rx) = new_app();
+        let app = app.set_playback_state_path(&path);
+        assert_eq!(app.repeat_mode(), RepeatMode::All);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_noop_tick_stays_clean() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.on_tick();
+        assert!(app.take_dirty());
+
+        app.player.seek_ms(400);
+        app.on_tick();
+        assert!(!app.is_dirty());
+
+        // the displayed second changed
+        app.player.seek_ms(1200);
+        app.on_tick();
+        assert!(app.take_dirty());
+
+        app.handle_action(Key::Char('j'));
+        assert!(app.take_dirty());
+    }
+
+    #[test]
+    fn test_wheel_scrolls_tracks() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.track_table = Some(songs(&[1, 2, 3]));
+        app.handle_mouse(MouseEvent::Press(MouseButton::WheelDown, 1, 1));
+        app.handle_mouse(MouseEvent::Press(MouseButton::WheelDown, 1, 1));
+        assert_eq!(app.track_table_index, 2);
+        app.handle_mouse(MouseEvent::Press(MouseButton::WheelUp, 1, 1));
+        assert_eq!(app.track_table_index, 1);
+
+        app.take_dirty();
+        app.handle_mouse(MouseEvent::Release(1, 1));
+        assert!(!app.is_dirty());
+    }
+
+    #[test]
+    fn test_configured_key_bindings() {
+        let (app, _io_rx, _playback_rx) = new_app();
+        let keys = KeyBindings::from_toml("select_next = \"s\"").unwrap();
+        let mut app = app.set_key_bindings(keys);
+        app.track_table = Some(songs(&[1, 2, 3]));
+        app.handle_action(Key::Char('j'));
+        assert_eq!(app.track_table_index, 0);
+        app.handle_action(Key::Char('s'));
+        assert_eq!(app.track_table_index, 1);
+        app.handle_action(Key::Char('k'));
+        assert_eq!(app.track_table_index, 0);
+    }
+
+    #[test]
+    fn test_fuzzy_find() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        let mut tracks = songs(&[1, 2, 3]);
+        tracks[0].name = "Lost Over Vega Echo".to_owned();
+        tracks[1].name = "Hello".to_owned();
+        tracks[2].name = "Love Story".to_owned();
+        app.track_table = Some(tracks);
+
+        let found = app.fuzzy_find("love");
+        assert_eq!(found.iter().map(|(i, _)| *i).collect::<Vec<_>>(), vec![2, 0]);
+        assert!(found[0].1 > found[1].1);
+    }
+
+    #[test]
+    fn test_loading_until_all_answered() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.fav(1);
+        assert!(!app.is_loading());
+        app.user_playlists();
+        app.liked_songs();
+        assert!(app.is_loading());
+
+        app.on_tick();
+        app.dirty = false;
+        app.on_tick();
+        assert_eq!(app.spinner_frame(), 2);
+        assert!(app.dirty);
+
+        app.handle_data(DataEvent::UserPlaylists(Vec::new()));
+        assert!(app.is_loading());
+        // an error of something nobody waits for isn't an answer
+        app.handle_data(DataEvent::Error("timed out".to_owned()));
+        assert!(app.is_loading());
+        app.handle_data(DataEvent::RequestFailed("timed out".to_owned()));
+        assert!(!app.is_loading());
+        // a stray failure doesn't go below zero
+        app.handle_data(DataEvent::RequestFailed("timed out".to_owned()));
+        assert!(!app.is_loading());
+
+        app.podcast_audios(7);
+        assert!(app.is_loading());
+        app.handle_data(DataEvent::PodcastEpisodes(7, Vec::new()));
+        assert!(!app.is_loading());
+    }
+
+    #[test]
+    fn test_help() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.handle_action(Key::Char('?'));
+        assert_eq!(app.help_scroll(), Some(0));
+        app.handle_action(Key::Char('j'));
+        app.handle_action(Key::Down);
+        assert_eq!(app.help_scroll(), Some(2));
+        app.handle_action(Key::Up);
+        assert_eq!(app.help_scroll(), Some(1));
+        for _ in 0..100 {
+            app.handle_action(Key::Down);
+        }
+        assert_eq!(app.help_scroll(), Some(app.help_rows() - 1));
+
+        // closes without quitting
+        app.handle_action(Key::Char('q'));
+        assert_eq!(app.help_scroll(), None);
+        assert!(!app.is_confirming_quit());
+    }
+
+    #[test]
+    fn test_toasts() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.handle_data(DataEvent::Error("timed out".to_owned()));
+        for i in 0..3 {
+            app.notify(Level::Info, format!("note {}", i));
+        }
+        assert_eq!(app.notifications()[0].level, Level::Error);
+        let toasts: Vec<&str> = app.toasts().iter().map(|n| n.message.as_str()).collect();
+        assert_eq!(toasts, vec!["note 0", "note 1", "note 2"]);
+
+        app.on_tick();
+        app.dirty = false;
+        for notification in app.notifications.iter_mut() {
+            notification.at -= TOAST_TTL;
+        }
+        app.on_tick();
+        assert!(app.toasts().is_empty());
+        assert!(app.dirty);
+        // still listed after the toasts expired
+        assert_eq!(app.notifications().len(), 4);
+    }
+
+    #[test]
+    fn test_track_filter() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        let mut tracks = songs(&[1, 2, 3, 4]);
+        tracks[0].name = "Lost Over Vega Echo".to_owned();
+        tracks[1].name = "Hello".to_owned();
+        tracks[2].name = "Love Story".to_owned();
+        tracks[3].name = "Yesterday".to_owned();
+        app.track_table = Some(tracks);
+        app.track_table_index = 1;
+
+        app.handle_action(Key::Ctrl('f'));
+        for c in "lo".chars() {
+            app.handle_action(Key::Char(c));
+        }
+        // "Hello" still matches and stays selected
+        assert_eq!(app.filtered_indices(), vec![0, 2, 1]);
+        assert_eq!(app.track_table_index(), 1);
+        app.handle_action(Key::Char('v'));
+        assert_eq!(app.filtered_indices(), vec![2, 0]);
+        assert_eq!(app.track_table_index(), 2);
+        app.handle_action(Key::Down);
+        assert_eq!(app.track_table_index(), 0);
+        app.handle_action(Key::Down);
+        assert_eq!(app.track_table_index(), 0);
+
+        // committed, keys are actions again
+        app.handle_action(Key::Char('\n'));
+        app.handle_action(Key::Char('k'));
+        assert_eq!(app.track_table_index(), 2);
+        assert_eq!(app.track_filter(), Some("lov"));
+
+        app.handle_action(Key::Esc);
+        assert_eq!(app.track_filter(), None);
+        assert_eq!(app.filtered_indices(), vec![0, 1, 2, 3]);
+        assert_eq!(app.track_table_index(), 1);
+        assert_eq!(app.route(), &Route::Home);
+
+        // nothing left to select, nor to play
+        app.handle_action(Key::Ctrl('f'));
+        for c in "zz".chars() {
+            app.handle_action(Key::Char(c));
+        }
+        assert!(app.filtered_indices().is_empty());
+        assert!(app.selected_track().is_none());
+        app.handle_action(Key::Char('\n'));
+        app.play_selected();
+        assert_eq!(app.current_index(), None);
+    }
+
+    fn episodes(ids: &[usize]) -> Vec<PodcastEpisode> {
+        ids.iter()
+            .map(|&id| PodcastEpisode {
+                id,
+                song_id: id * 100,
+                title: format!("episode {}", id),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_open_podcast() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.open_podcast(7);
+
+        assert_eq!(app.route(), &Route::Podcast(7));
+        assert!(matches!(io_rx.try_recv(), Ok(IoEvent::PodcastAudios(7))));
+
+        // stale episodes of another podcast are ignored
+        app.handle_data(DataEvent::PodcastEpisodes(8, episodes(&[1])));
+        assert!(app.podcast_episodes().is_empty());
+
+        app.handle_data(DataEvent::PodcastEpisodes(7, episodes(&[1, 2])));
+        assert_eq!(app.podcast_episodes().len(), 2);
+        assert!(!app.is_loading());
+    }
+
+    #[test]
+    fn test_route_stack() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.open_podcast(7);
+        app.handle_action(Key::Char('/'));
+        for c in "bfs\n".chars() {
+            app.handle_action(Key::Char(c));
+        }
+        assert_eq!(app.route(), &Route::Search);
+        // the same route twice is one step back
+        app.push_route(Route::Search);
+
+        app.handle_action(Key::Esc);
+        assert_eq!(app.route(), &Route::Podcast(7));
+        app.handle_action(Key::Esc);
+        assert_eq!(app.route(), &Route::Home);
+        app.handle_action(Key::Esc);
+        assert_eq!(app.route(), &Route::Home);
+        assert_eq!(app.pop_route(), None);
+    }
+
+    #[test]
+    fn test_episode_resume() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.open_podcast(7);
+        app.handle_data(DataEvent::PodcastEpisodes(7, episodes(&[1, 2])));
+        app.play_episode(0).unwrap();
+        app.handle_data(DataEvent::Track(100, Bytes::from("ep1")));
+        app.player.seek_ms(42_000);
+
+        app.play_episode(1).unwrap();
+        assert_eq!(app.podcast_episodes()[0].resume_ms, Some(42_000));
+
+        // a refresh keeps the local resume position
+        app.handle_data(DataEvent::PodcastEpisodes(7, episodes(&[1, 2])));
+        app.play_episode(0).unwrap();
+        app.handle_data(DataEvent::Track(100, Bytes::from("ep1")));
+        assert_eq!(app.player.playback_context().progress_ms(), 42_000);
+
+        let dispatched: Vec<IoEvent> = io_rx.try_iter().collect();
+        assert!(dispatched
+            .iter()
+            .any(|e| matches!(e, IoEvent::SongUrls(ids) if ids == &vec![200])));
+    }
+
+    #[test]
+    fn test_episode_speed() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.open_podcast(7);
+        app.handle_data(DataEvent::PodcastEpisodes(7, episodes(&[1])));
+        app.play_episode(0).unwrap();
+        app.handle_data(DataEvent::Track(100, Bytes::from("ep1")));
+        app.player.speed(1.5);
+
+        // songs don't take the podcast's speed
+        app.queue = songs(&[10]);
+        app.play_index(0).unwrap();
+        app.handle_data(DataEvent::Track(10, Bytes::from("song")));
+        assert_eq!(app.player.playback_context().speed(), 1.0);
+
+        app.play_episode(0).unwrap();
+        app.handle_data(DataEvent::Track(100, Bytes::from("ep1")));
+        assert_eq!(app.player.playback_context().speed(), 1.5);
+    }
+
+    #[test]
+    fn test_episode_streamed() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.open_podcast(7);
+        app.handle_data(DataEvent::PodcastEpisodes(7, episodes(&[1, 2])));
+        app.handle_action(Key::Down);
+        assert_eq!(app.episode_index(), 1);
+        app.handle_action(Key::Up);
+        app.handle_action(Key::Char('\n'));
+        assert!(app.is_buffering());
+
+        app.handle_data(DataEvent::SongUrls(vec![(100, Some("http://fake/ep1.mp3".to_owned()))]));
+        assert!(!app.is_buffering());
+        let dispatched: Vec<IoEvent> = io_rx.try_iter().collect();
+        assert!(!dispatched.iter().any(|e| matches!(e, IoEvent::Download(..))));
+
+        // the stream ran dry
+        app.on_tick();
+        app.stall = app.stall.map(|(progress, _)| (progress, Instant::now() - BUFFERING_AFTER));
+        assert!(app.is_buffering());
+        app.player.seek_ms(1000);
+        app.on_tick();
+        assert!(!app.is_buffering());
+
+        // its position outlives showing another podcast
+        app.play_episode(1).unwrap();
+        app.open_podcast(8);
+        app.open_podcast(7);
+        app.handle_data(DataEvent::PodcastEpisodes(7, episodes(&[1, 2])));
+        assert_eq!(app.podcast_episodes()[0].resume_ms, Some(1000));
+    }
+
+    #[test]
+    fn test_episode_finished() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.open_podcast(7);
+        let mut eps = episodes(&[1]);
+        eps[0].resume_ms = Some(1000);
+        app.handle_data(DataEvent::PodcastEpisodes(7, eps));
+
+        app.play_episode(0).unwrap();
+        app.handle_playback(PlaybackEvent::Ended);
+
+        let episode = &app.podcast_episodes()[0];
+        assert!(episode.played);
+        assert_eq!(episode.resume_ms, None);
+    }
+
+    #[test]
+    fn test_start_refreshes_login() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.start();
+
+        assert!(matches!(io_rx.try_recv(), Ok(IoEvent::RefreshLogin)));
+        assert_eq!(app.route(), &Route::Home);
+        assert!(app.is_loading());
+    }
+
+    #[test]
+    fn test_startup_route() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.config.startup_route = "personal_fm".to_owned();
+        app.start();
+
+        let events: Vec<IoEvent> = io_rx.try_iter().collect();
+        assert!(matches!(events[..], [IoEvent::RefreshLogin, IoEvent::PersonalFm]));
+        assert_eq!(app.route(), &Route::PersonalFm);
+    }
+
+    #[test]
+    fn test_unknown_startup_route() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.config.startup_route = "radio".to_owned();
+        app.start();
+
+        assert_eq!(io_rx.try_iter().count(), 1);
+        assert_eq!(app.route(), &Route::Home);
+    }
+
+    #[test]
+    fn test_login_valid() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.start();
+        app.handle_data(DataEvent::LoginStatus(LoginStatus::Valid(UserProfile::default())));
+
+        assert!(app.is_authed());
+        assert!(app.user_profile().is_some());
+        assert_eq!(app.route(), &Route::Home);
+    }
+
+    #[test]
+    fn test_user_playlists_after_login() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.handle_data(DataEvent::LoginStatus(LoginStatus::Valid(UserProfile::default())));
+        assert!(io_rx.try_iter().any(|e| matches!(e, IoEvent::UserPlaylists)));
+
+        app.selected_playlist_index = 5;
+        let playlists = vec![Playlist::default(), Playlist::default()];
+        app.handle_data(DataEvent::UserPlaylists(playlists));
+        assert_eq!(app.user_playlists_loaded().map(Vec::len), Some(2));
+        assert_eq!(app.selected_playlist_index(), 1);
+    }
+
+    #[test]
+    fn test_error_until_key_press() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.loading = 1;
+        app.handle_data(DataEvent::RequestFailed("timed out".to_owned()));
+        assert_eq!(app.error(), Some("timed out"));
+        assert!(!app.is_loading());
+
+        app.handle_action(Key::Char('j'));
+        assert_eq!(app.error(), None);
+    }
+
+    #[test]
+    fn test_logout() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.handle_data(DataEvent::LoginStatus(LoginStatus::Valid(UserProfile::default())));
+        app.handle_data(DataEvent::UserPlaylists(vec![Playlist::default()]));
+        app.logout();
+
+        assert!(!app.is_authed());
+        assert!(app.user_profile().is_none());
+        assert!(app.user_playlists_loaded().is_none());
+        assert_eq!(app.route(), &Route::Login);
+        assert!(io_rx.try_iter().any(|e| matches!(e, IoEvent::Logout)));
+    }
+
+    #[test]
+    fn test_login_expired() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.start();
+        app.handle_data(DataEvent::LoginStatus(LoginStatus::Expired));
+
+        assert!(!app.is_authed());
+        assert!(app.user_profile().is_none());
+        assert_eq!(app.route(), &Route::Login);
+        assert!(!app.is_loading());
+    }
+
+    fn playlists(ids: &[usize]) -> Vec<Playlist> {
+        ids.iter()
+            .map(|&id| Playlist {
+                id,
+                name: format!("playlist {}", id),
+                track_count: 10,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_add_to_playlist_dispatch() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.user_playlists = Some(playlists(&[5, 6]));
+        app.track_table = Some(songs(&[10, 11]));
+        app.track_table_index = 1;
+
+        app.handle_action(Key::Char('a'));
+        assert_eq!(
+            app.playlist_picker(),
+            Some(&PlaylistPicker {
+                song_ids: vec![11],
+                index: 0
+            })
+        );
+        app.handle_action(Key::Down);
+        app.handle_action(Key::Char('\n'));
+
+        assert!(app.playlist_picker().is_none());
+        assert!(matches!(
+            io_rx.try_recv(),
+            Ok(IoEvent::AddToPlaylist { playlist_id: 6, song_ids }) if song_ids == vec![11]
+        ));
+    }
+
+    #[test]
+    fn test_added_to_playlist() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.user_playlists = Some(playlists(&[5, 6]));
+
+        app.handle_data(DataEvent::AddedToPlaylist {
+            playlist_id: 6,
+            song_ids: vec![11, 12],
+        });
+
+        let pls = app.user_playlists_loaded().unwrap();
+        assert_eq!(pls[0].track_count, 10);
+        assert_eq!(pls[1].track_count, 12);
+        assert_eq!(app.notifications().len(), 1);
+    }
+
+    #[test]
+    fn test_add_to_playlist_duplicate() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.user_playlists = Some(playlists(&[5]));
+
+        app.handle_data(DataEvent::AddToPlaylistFailed {
+            playlist_id: 5,
+            reason: "song already exists".to_owned(),
+        });
+
+        assert_eq!(app.user_playlists_loaded().unwrap()[0].track_count, 10);
+        assert!(app.notifications()[0].message.contains("song already exists"));
+    }
+
+    fn confirm_removal(app: &mut App, playlist_id: usize, song_ids: Vec<usize>) {
+        app.remove_from_playlist(playlist_id, song_ids);
+        app.handle_action(Key::Char('y'));
+    }
+
+    fn track_ids(app: &App) -> Vec<usize> {
+        app.track_table().unwrap().iter().map(|song| song.id).collect()
+    }
+
+    #[test]
+    fn test_remove_from_playlist_optimistic() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.track_table = Some(songs(&[1, 2, 3, 4]));
+
+        app.remove_from_playlist(5, vec![2, 4]);
+        assert!(app.confirm_remove().is_some());
+        assert!(io_rx.try_recv().is_err());
+        assert_eq!(track_ids(&app).len(), 4);
+
+        app.handle_action(Key::Char('y'));
+        assert!(app.confirm_remove().is_none());
+        assert_eq!(track_ids(&app), vec![1, 3]);
+        assert!(matches!(
+            io_rx.try_recv(),
+            Ok(IoEvent::RemoveFromPlaylist { playlist_id: 5, song_ids }) if song_ids == vec![2, 4]
+        ));
+    }
+
+    #[test]
+    fn test_remove_from_playlist_cancelled() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.track_table = Some(songs(&[1, 2]));
+
+        app.remove_from_playlist(5, vec![2]);
+        app.handle_action(Key::Esc);
+        assert!(app.confirm_remove().is_none());
+        assert!(io_rx.try_recv().is_err());
+        assert_eq!(track_ids(&app), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_removed_from_playlist() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.user_playlists = Some(playlists(&[5]));
+        app.track_table = Some(songs(&[1, 2, 3]));
+        confirm_removal(&mut app, 5, vec![2]);
+
+        app.handle_data(DataEvent::RemovedFromPlaylist {
+            playlist_id: 5,
+            song_ids: vec![2],
+        });
+        assert_eq!(track_ids(&app), vec![1, 3]);
+        assert_eq!(app.user_playlists_loaded().unwrap()[0].track_count, 9);
+        assert!(app.pending_removals.is_empty());
+        assert_eq!(app.notifications().len(), 1);
+    }
+
+    #[test]
+    fn test_remove_from_playlist_rollback() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.user_playlists = Some(playlists(&[5]));
+        app.track_table = Some(songs(&[1, 2, 3, 4, 5]));
+        confirm_removal(&mut app, 5, vec![1, 3, 5]);
+        assert_eq!(track_ids(&app), vec![2, 4]);
+
+        app.handle_data(DataEvent::RemoveFromPlaylistFailed {
+            playlist_id: 5,
+            song_ids: vec![1, 3, 5],
+            reason: "network".to_owned(),
+        });
+        assert_eq!(track_ids(&app), vec![1, 2, 3, 4, 5]);
+        assert_eq!(app.user_playlists_loaded().unwrap()[0].track_count, 10);
+        assert!(app.notifications()[0].message.contains("network"));
+    }
+
+    #[test]
+    fn test_personal_fm() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.track_table = Some(songs(&[10, 11]));
+        app.play_track_at(1).unwrap();
+
+        app.handle_action(Key::Char('F'));
+        assert!(app.is_fm());
+        assert_eq!(app.route(), &Route::PersonalFm);
+        assert!(app.now_playing().is_none());
+        app.handle_data(DataEvent::PersonalFm(songs(&[1, 2, 3])));
+        assert_eq!(app.now_playing().map(|song| song.id), Some(1));
+        let fetches = |io_rx: &mpsc::Receiver<IoEvent>| {
+            io_rx.try_iter().filter(|e| matches!(e, IoEvent::PersonalFm)).count()
+        };
+        assert_eq!(fetches(&io_rx), 1);
+
+        // trashed and skipped, one song left so the next batch is on its way
+        app.handle_action(Key::Char('t'));
+        assert_eq!(app.now_playing().map(|song| song.id), Some(2));
+        let events: Vec<IoEvent> = io_rx.try_iter().collect();
+        assert!(events.iter().any(|e| matches!(e, IoEvent::FmTrash(1))));
+        assert_eq!(events.iter().filter(|e| matches!(e, IoEvent::PersonalFm)).count(), 1);
+        app.next_track();
+        assert_eq!(fetches(&io_rx), 0);
+        app.handle_data(DataEvent::PersonalFm(songs(&[4, 5])));
+        assert_eq!(app.queue().len(), 5);
+        app.next_track();
+        assert_eq!(app.now_playing().map(|song| song.id), Some(4));
+
+        app.handle_action(Key::Char('F'));
+        assert!(!app.is_fm());
+        let queued: Vec<usize> = app.queue().iter().map(|song| song.id).collect();
+        assert_eq!(queued, vec![10, 11]);
+        assert_eq!(app.current_index(), Some(1));
+    }
+
+    #[test]
+    fn test_personal_fm_failed() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.handle_action(Key::Char('F'));
+        app.handle_data(DataEvent::PersonalFmFailed("timed out".to_owned()));
+        assert_eq!(app.loading, 0);
+        assert!(!app.toasts().is_empty());
+
+        // the next try isn't held back by the failed one
+        app.fetch_fm();
+        let fetches = io_rx.try_iter().filter(|e| matches!(e, IoEvent::PersonalFm)).count();
+        assert_eq!(fetches, 2);
+    }
+
+    #[test]
+    fn test_toggle_like() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.handle_data(DataEvent::LoginStatus(LoginStatus::Valid(UserProfile::default())));
+        assert!(io_rx.try_iter().any(|e| matches!(e, IoEvent::LikedSongs)));
+        app.handle_data(DataEvent::LikedSongs(vec![7]));
+
+        app.toggle_like(7);
+        assert!(!app.is_liked(7));
+        app.toggle_like(8);
+        assert!(app.is_liked(8));
+        let events: Vec<IoEvent> = io_rx.try_iter().collect();
+        assert!(matches!(events[..], [IoEvent::Unfav(7), IoEvent::Fav(8)]));
+
+        // refused by the api
+        app.handle_data(DataEvent::LikeFailed {
+            song_id: 8,
+            like: true,
+            reason: "offline".to_owned(),
+        });
+        assert!(!app.is_liked(8));
+        app.handle_data(DataEvent::LikeFailed {
+            song_id: 7,
+            like: false,
+            reason: "offline".to_owned(),
+        });
+        assert!(app.is_liked(7));
+    }
+
+    #[test]
+    fn test_undo_unlike() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.like(7);
+        app.unlike(7);
+        assert!(!app.is_liked(7));
+        let events: Vec<IoEvent> = io_rx.try_iter().collect();
+        assert!(matches!(events[..], [IoEvent::Fav(7), IoEvent::Unfav(7)]));
+
+        app.undo();
+        assert!(app.is_liked(7));
+        assert!(matches!(io_rx.try_recv(), Ok(IoEvent::Fav(7))));
+
+        app.undo();
+        assert!(io_rx.try_recv().is_err());
+        assert_eq!(app.notifications()[0].message, "Nothing to undo");
+    }
+
+    #[test]
+    fn test_undo_remove_from_playlist() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.track_table = Some(songs(&[1, 2, 3]));
+        confirm_removal(&mut app, 5, vec![2]);
+        app.handle_data(DataEvent::RemovedFromPlaylist {
+            playlist_id: 5,
+            song_ids: vec![2],
+        });
+        io_rx.try_iter().count();
+
+        app.undo();
+        assert_eq!(track_ids(&app), vec![1, 2, 3]);
+        assert!(matches!(
+            io_rx.try_recv(),
+            Ok(IoEvent::AddToPlaylist { playlist_id: 5, song_ids }) if song_ids == vec![2]
+        ));
+    }
+
+    #[test]
+    fn test_undo_after_failure() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.track_table = Some(songs(&[1, 2, 3]));
+        app.unlike(7);
+        app.handle_data(DataEvent::LikeFailed {
+            song_id: 7,
+            like: false,
+            reason: "offline".to_owned(),
+        });
+        confirm_removal(&mut app, 5, vec![2]);
+        app.handle_data(DataEvent::RemoveFromPlaylistFailed {
+            playlist_id: 5,
+            song_ids: vec![2],
+            reason: "offline".to_owned(),
+        });
+        io_rx.try_iter().count();
+
+        // neither happened, so neither is reversed
+        app.undo();
+        assert!(io_rx.try_recv().is_err());
+        assert!(app.is_liked(7));
+        assert_eq!(track_ids(&app), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_failure_after_undo() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.track_table = Some(songs(&[1, 2, 3]));
+        app.unlike(7);
+        confirm_removal(&mut app, 5, vec![1, 3]);
+        app.undo();
+        app.undo();
+        assert_eq!(track_ids(&app), vec![1, 2, 3]);
+        assert!(app.is_liked(7));
+
+        app.handle_data(DataEvent::RemoveFromPlaylistFailed {
+            playlist_id: 5,
+            song_ids: vec![1, 3],
+            reason: "offline".to_owned(),
+        });
+        app.handle_data(DataEvent::LikeFailed {
+            song_id: 7,
+            like: false,
+            reason: "offline".to_owned(),
+        });
+        // the rows undo put back aren't put back twice
+        assert_eq!(track_ids(&app), vec![1, 2, 3]);
+        assert!(app.is_liked(7));
+        io_rx.try_iter().count();
+        app.undo();
+        assert!(io_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_fm_trash_not_undone() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.unlike(1);
+        app.fm_trash(2);
+        io_rx.try_iter().count();
+
+        // the trash is skipped, the unlike before it is undone
+        app.undo();
+        assert!(matches!(io_rx.try_recv(), Ok(IoEvent::Fav(1))));
+        assert!(!app.is_liked(2));
+    }
+
+    // the only test installing the global logger
+    #[test]
+    fn test_dispatch_error_logged() {
+        let path = std::env::temp_dir().join(format!("lekima-{}.log", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.config.log_path = Some(path.clone());
+        app.config.log_level = LevelFilter::Error;
+        app.init_logging().unwrap();
+
+        drop(io_rx);
+        app.user_cloud();
+        log::logger().flush();
+
+        let logged = fs::read_to_string(&path).unwrap();
+        assert!(logged.contains("dispatch io event error"), "{}", logged);
+        assert_eq!(app.notifications()[0].level, Level::Error);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[derive(Clone, Default)]
+    struct FakeClipboard {
+        text: Arc<Mutex<Option<String>>>,
+        broken: bool,
+    }
+
+    impl Clipboard for FakeClipboard {
+        fn set_text(&mut self, text: String) -> Result<(), String> {
+            if self.broken {
+                return Err("unavailable".to_owned());
+            }
+            *self.text.lock().unwrap() = Some(text);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_copy_share_link() {
+        let clipboard = FakeClipboard::default();
+        let (app, _io_rx, _playback_rx) = new_app();
+        let mut app = app.set_clipboard(Box::new(clipboard.clone()));
+        app.queue = songs(&[10, 186016]);
+        app.play_index(1).unwrap();
+
+        app.copy_share_link();
+        let url = "https://music.163.com/song?id=186016";
+        assert_eq!(clipboard.text.lock().unwrap().as_deref(), Some(url));
+        assert_eq!(app.notifications().last().unwrap().message, format!("Copied {}", url));
+    }
+
+    #[test]
+    fn test_copy_share_link_without_clipboard() {
+        let clipboard = FakeClipboard {
+            broken: true,
+            ..Default::default()
+        };
+        let (app, _io_rx, _playback_rx) = new_app();
+        let mut app = app.set_clipboard(Box::new(clipboard));
+        app.queue = songs(&[186016]);
+        app.play_index(0).unwrap();
+
+        app.copy_share_link();
+        assert_eq!(
+            app.notifications().last().unwrap().message,
+            "https://music.163.com/song?id=186016"
+        );
+    }
+
+    #[test]
+    fn test_play_daily_skips_history() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.auth = true;
+        app.recently_played = Some(songs(&[2, 4]));
+        app.handle_data(DataEvent::RecommendedSongs(songs(&[1, 2, 3, 4])));
+
+        app.play_daily();
+        let queued: Vec<usize> = app.queue().iter().map(|song| song.id).collect();
+        assert_eq!(queued, vec![1, 3]);
+        assert_eq!(app.current_index(), Some(0));
+        assert!(matches!(io_rx.try_recv(), Ok(IoEvent::SongUrls(ids)) if ids == vec![1]));
+
+        app.config.daily_skip_played = false;
+        app.play_daily();
+        assert_eq!(app.queue().len(), 4);
+    }
+
+    #[test]
+    fn test_play_daily_refreshes_stale() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.auth = true;
+        app.recommended_songs = Some(songs(&[1]));
+        app.recommended_at = Instant::now().checked_sub(RECOMMENDATIONS_TTL + VOLUME_DEBOUNCE);
+
+        app.play_daily();
+        assert!(matches!(io_rx.try_recv(), Ok(IoEvent::RecommendedSongs)));
+        assert!(app.queue().is_empty());
+
+        // played right away once the fresh ones arrive
+        app.handle_data(DataEvent::RecommendedSongs(songs(&[5, 6])));
+        assert_eq!(app.queue().len(), 2);
+        assert!(matches!(io_rx.try_recv(), Ok(IoEvent::SongUrls(ids)) if ids == vec![5]));
+    }
+
+    #[test]
+    fn test_play_daily_logged_out() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.play_daily();
+        assert!(io_rx.try_recv().is_err());
+        assert_eq!(app.notifications().len(), 1);
+    }
+
+    #[test]
+    fn test_cover() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.fetch_cover("http://a/1.png".to_owned());
+        assert!(matches!(io_rx.try_recv(), Ok(IoEvent::FetchCover(url)) if url == "http://a/1.png"));
+
+        // cover of a song we're not showing anymore
+        app.handle_data(DataEvent::Cover {
+            url: "http://a/0.png".to_owned(),
+            bytes: Bytes::from_static(b"old"),
+        });
+        assert!(app.cover().is_none());
+
+        app.handle_data(DataEvent::Cover {
+            url: "http://a/1.png".to_owned(),
+            bytes: Bytes::from_static(b"png"),
+        });
+        assert_eq!(app.cover().unwrap(), &Bytes::from_static(b"png"));
+
+        app.fetch_cover("http://a/2.png".to_owned());
+        app.handle_data(DataEvent::CoverFailed("http://a/2.png".to_owned()));
+        assert!(app.cover().is_none());
+    }
+
+    // the track plays to its end once it's been loaded
+    fn finish_track(app: &mut App, id: usize) {
+        app.handle_data(DataEvent::Track(id, Bytes::from_static(b"track")));
+        app.handle_playback(PlaybackEvent::Ended);
+    }
+
+    #[test]
+    fn test_repeat_count() {
+        let (mut app, io_rx, playback_rx) = new_app();
+        app.queue = songs(&[10, 11]);
+        app.set_repeat_mode(RepeatMode::Count(3));
+        app.play_index(0).unwrap();
+        assert_eq!(app.repeats_left(), Some(2));
+
+        finish_track(&mut app, 10);
+        assert_eq!(app.repeats_left(), Some(1));
+        finish_track(&mut app, 10);
+        assert_eq!(app.repeats_left(), Some(0));
+        assert_eq!(app.current_index(), Some(0));
+        let started: Vec<PlaybackEvent> = playback_rx.try_iter().collect();
+        assert_eq!(started, vec![PlaybackEvent::Started(0); 3]);
+
+        // third play done, on to the next track with a fresh count
+        finish_track(&mut app, 10);
+        assert_eq!(app.current_index(), Some(1));
+        assert_eq!(app.repeats_left(), Some(2));
+        let urls: Vec<IoEvent> = io_rx.try_iter().collect();
+        assert!(matches!(urls.last(), Some(IoEvent::SongUrls(ids)) if ids == &vec![11]));
+    }
+
+    #[test]
+    fn test_repeat_count_zero_plays_once() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.queue = songs(&[10, 11]);
+        app.set_repeat_mode(RepeatMode::Count(0));
+        app.play_index(0).unwrap();
+
+        finish_track(&mut app, 10);
+        assert_eq!(app.current_index(), Some(1));
+    }
+
+    #[test]
+    fn test_checkpoint_cadence() {
+        let path = std::env::temp_dir().join(format!("lekima-crash-{}", std::process::id()));
+        let (app, _io_rx, _playback_rx) = new_app();
+        let mut app = app.set_checkpoint_path(&path);
+        app.queue = songs(&[10]);
+        app.play_index(0).unwrap();
+        app.on_tick();
+        // nothing is playing before the download
+        assert!(checkpoint::read(&path).is_none());
+
+        app.handle_data(DataEvent::Track(10, Bytes::from_static(b"track")));
+        let checkpoint_at = |app: &mut App, ms: u64| {
+            app.player.seek_ms(ms);
+            app.on_tick();
+            checkpoint::read(&path).map(|c| (c.song_id, c.progress_ms))
+        };
+        assert_eq!(checkpoint_at(&mut app, 1000), Some((10, 1000)));
+        assert_eq!(checkpoint_at(&mut app, 3000), Some((10, 1000)));
+        assert_eq!(checkpoint_at(&mut app, 5900), Some((10, 1000)));
+        assert_eq!(checkpoint_at(&mut app, 6000), Some((10, 6000)));
+        assert_eq!(checkpoint_at(&mut app, 11_500), Some((10, 11_500)));
+
+        app.quit();
+        assert!(checkpoint::read(&path).is_none());
+    }
+
+    #[test]
+    fn test_resume_checkpoint() {
+        let path = std::env::temp_dir().join(format!("lekima-resume-{}", std::process::id()));
+        let crashed = Checkpoint {
+            song_id: 10,
+            name: "bfs".to_owned(),
+            progress_ms: 61_000,
+        };
+        checkpoint::write(&path, &crashed).unwrap();
+
+        let (app, io_rx, _playback_rx) = new_app();
+        let mut app = app.set_checkpoint_path(&path);
+        app.start();
+        assert_eq!(app.resume_offer(), Some(&crashed));
+        assert_eq!(app.notifications().len(), 1);
+        assert_eq!(app.notifications()[0].message, "Resume bfs from 01:01? Press R");
+
+        app.handle_action(Key::Char('R'));
+        assert!(app.resume_offer().is_none());
+        assert!(io_rx.try_iter().any(|e| matches!(e, IoEvent::SongUrls(ids) if ids == vec![10])));
+        app.handle_data(DataEvent::Track(10, Bytes::from_static(b"track")));
+        assert_eq!(app.player.playback_context().progress_ms(), 61_000);
+        checkpoint::clear(&path);
+    }
+
+    #[test]
+    fn test_confirm_quit() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.handle_action(Key::Char('q'));
+        assert!(app.is_confirming_quit());
+        app.handle_action(Key::Char('n'));
+        assert!(!app.is_confirming_quit());
+        assert!(!app.should_quit());
+
+        app.handle_action(Key::Char('q'));
+        app.handle_action(Key::Esc);
+        assert!(!app.should_quit());
+
+        app.handle_action(Key::Char('q'));
+        app.handle_action(Key::Char('y'));
+        assert!(app.should_quit());
+    }
+
+    #[test]
+    fn test_quit_without_confirm() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.config.confirm_quit = false;
+        app.handle_action(Key::Char('q'));
+        assert!(!app.is_confirming_quit());
+        assert!(app.should_quit());
+    }
+
+    #[test]
+    fn test_now_playing_follows_queue() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        let mut tracks = songs(&[10, 11]);
+        tracks[0].name = "bfs".to_owned();
+        app.queue = tracks;
+        assert!(app.now_playing().is_none());
+
+        app.play_index(0).unwrap();
+        assert_eq!(app.now_playing().map(|s| s.id), Some(10));
+        assert_eq!(app.title_marquee().text(), "bfs");
+        app.lyrics.insert(10, Lyric::parse("[00:01.00]la"));
+        assert_eq!(app.now_playing_lyric(), Some(&Lyric::parse("[00:01.00]la")));
+
+        finish_track(&mut app, 10);
+        assert_eq!(app.now_playing().map(|s| s.id), Some(11));
+        finish_track(&mut app, 11);
+        assert!(app.now_playing().is_none());
+    }
+
+    #[test]
+    fn test_eject_clears_now_playing() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.queue = songs(&[10, 11]);
+        app.play_index(1).unwrap();
+
+        app.eject();
+        assert!(app.now_playing().is_none());
+        assert_eq!(app.current_index(), None);
+        assert_eq!(app.queue().len(), 2);
+        assert_eq!(app.title_marquee().text(), "");
+    }
+
+    #[test]
+    fn test_repeat_all_wraps() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.queue = songs(&[10, 11]);
+        app.set_repeat_mode(RepeatMode::All);
+        app.play_index(1).unwrap();
+
+        finish_track(&mut app, 11);
+        assert_eq!(app.current_index(), Some(0));
+        assert_eq!(app.playback_snapshot().repeat_mode, RepeatMode::All);
+    }
+
+    #[test]
+    fn test_skip_resets_repeat_count() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.queue = songs(&[10, 11]);
+        app.set_repeat_mode(RepeatMode::Count(3));
+        app.play_index(0).unwrap();
+        finish_track(&mut app, 10);
+        assert_eq!(app.repeats_left(), Some(1));
+
+        app.play_index(1).unwrap();
+        assert_eq!(app.repeats_left(), Some(2));
+    }
+
+    #[test]
+    fn test_unavailable_songs() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.queue = songs(&[1, 2, 3, 4]);
+        app.play_index(1).unwrap();
+        io_rx.try_iter().count();
+
+        app.handle_data(DataEvent::SongUrls(vec![
+            (1, Some("http://a/1.mp3".to_owned())),
+            (2, None),
+            (3, None),
+            (4, Some("http://a/4.mp3".to_owned())),
+        ]));
+        assert!(app.is_available(1));
+        assert!(!app.is_available(2));
+        assert!(!app.is_available(3));
+        assert!(app.is_available(4));
+
+        // the pending song is skipped along with the next known unavailable one
+        assert_eq!(app.current_index(), Some(3));
+        assert!(matches!(io_rx.try_recv(), Ok(IoEvent::SongUrls(ids)) if ids == vec![4]));
+        assert_eq!(app.notifications().len(), 1);
+
+        // available again once a url shows up
+        app.handle_data(DataEvent::SongUrls(vec![(3, Some("http://a/3.mp3".to_owned()))]));
+        assert!(app.is_available(3));
+    }
+
+    #[test]
+    fn test_focused_url() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        assert_eq!(app.focused_url(), None);
+
+        app.track_table = Some(songs(&[1, 2]));
+        app.track_table_index = 1;
+        assert_eq!(app.focused_url().unwrap(), "https://music.163.com/song?id=2");
+
+        app.push_route(Route::Album(30));
+        assert_eq!(app.focused_url().unwrap(), "https://music.163.com/album?id=30");
+        app.push_route(Route::Artist(40));
+        assert_eq!(app.focused_url().unwrap(), "https://music.163.com/artist?id=40");
+        app.push_route(Route::Playlist(50));
+        assert_eq!(app.focused_url().unwrap(), "https://music.163.com/playlist?id=50");
+    }
+
+    struct NoBrowser;
+
+    impl Opener for NoBrowser {
+        fn open(&mut self, _url: &str) -> Result<(), String> {
+            Err("no display".to_owned())
+        }
+    }
+
+    #[test]
+    fn test_open_in_browser_headless() {
+        let (app, _io_rx, _playback_rx) = new_app();
+        let mut app = app.set_opener(Box::new(NoBrowser));
+        app.push_route(Route::Artist(40));
+
+        app.open_in_browser();
+        assert_eq!(app.notifications()[0].message, "https://music.163.com/artist?id=40");
+    }
+
+    fn lyric_ids(io_rx: &mpsc::Receiver<IoEvent>) -> Vec<usize> {
+        io_rx
+            .try_iter()
+            .filter_map(|e| match e {
+                IoEvent::Lyric(id) => Some(id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_lyric_prefetch() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.queue = songs(&[1, 2, 3, 4]);
+
+        app.handle_playback(PlaybackEvent::Started(0));
+        assert_eq!(lyric_ids(&io_rx), vec![1, 2, 3]);
+        app.handle_data(DataEvent::Lyric(2, "[00:01.00]next".to_owned()));
+        let lines = app.cached_lyric(2).map(|lyric| lyric.lines().to_vec());
+        assert_eq!(lines, Some(vec![(1000, "next".to_owned())]));
+
+        // cached or still on their way, only the new tail is fetched
+        app.handle_playback(PlaybackEvent::Started(1));
+        assert_eq!(lyric_ids(&io_rx), vec![4]);
+
+        // a failed fetch is tried again
+        app.handle_data(DataEvent::LyricFailed(3, "timed out".to_owned()));
+        app.handle_playback(PlaybackEvent::Started(2));
+        assert_eq!(lyric_ids(&io_rx), vec![3]);
+    }
+
+    #[test]
+    fn test_lyric_prefetch_capped() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.config.lyric_prefetch = 100;
+        app.queue = songs(&[1, 2, 3, 4, 5, 6, 7]);
+
+        app.handle_playback(PlaybackEvent::Started(0));
+        assert_eq!(lyric_ids(&io_rx).len(), 4);
+    }
+
+    fn scrobbles(io_rx: &mpsc::Receiver<IoEvent>) -> Vec<usize> {
+        io_rx
+            .try_iter()
+            .filter_map(|e| match e {
+                IoEvent::ScrobblePlay(id) => Some(id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // plays `from_ms..=to_ms` a second per tick
+    fn listen(app: &mut App, from_ms: u64, to_ms: u64) {
+        let context = app.player.playback_context();
+        for progress in (from_ms..=to_ms).step_by(1000) {
+            context.set_progress_ms(progress);
+            app.on_tick();
+        }
+    }
+
+    #[test]
+    fn test_scrobble_threshold() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.queue = songs(&[10]);
+        app.play_index(0).unwrap();
+        app.handle_data(DataEvent::Track(10, Bytes::from_static(b"track")));
+        let context = app.player.playback_context();
+        context.set_duration_ms(Some(40_000));
+
+        // a skip after 10s isn't a play
+        listen(&mut app, 0, 10_000);
+        assert!(scrobbles(&io_rx).is_empty());
+
+        // half of 40s comes before 30s
+        listen(&mut app, 10_000, 25_000);
+        assert_eq!(scrobbles(&io_rx), vec![10]);
+    }
+
+    #[test]
+    fn test_seek_isnt_a_play() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.queue = songs(&[10]);
+        app.play_index(0).unwrap();
+        app.handle_data(DataEvent::Track(10, Bytes::from_static(b"track")));
+        let context = app.player.playback_context();
+        context.set_duration_ms(Some(40_000));
+
+        // straight past the threshold, then a few seconds of listening
+        listen(&mut app, 0, 3_000);
+        context.set_progress_ms(30_000);
+        app.on_tick();
+        listen(&mut app, 30_000, 35_000);
+        assert!(scrobbles(&io_rx).is_empty());
+        listen(&mut app, 35_000, 40_000);
+        assert!(scrobbles(&io_rx).is_empty());
+    }
+
+    #[test]
+    fn test_scrobble_long_track() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.queue = songs(&[10, 11]);
+        app.play_index(0).unwrap();
+        app.handle_data(DataEvent::Track(10, Bytes::from_static(b"track")));
+        let context = app.player.playback_context();
+        context.set_duration_ms(Some(600_000));
+
+        listen(&mut app, 0, 30_000);
+        assert_eq!(scrobbles(&io_rx), vec![10]);
+
+        // counted again for the next track only
+        app.play_index(1).unwrap();
+        app.handle_data(DataEvent::Track(11, Bytes::from_static(b"track")));
+        listen(&mut app, 0, 30_000);
+        assert_eq!(scrobbles(&io_rx), vec![11]);
+    }
+
+    fn comment_requests(io_rx: &mpsc::Receiver<IoEvent>) -> Vec<(CommentSort, usize)> {
+        io_rx
+            .try_iter()
+            .filter_map(|e| match e {
+                IoEvent::Comments { sort, offset, .. } => Some((sort, offset)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_recently_played() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.queue = songs(&[1, 2, 3]);
+        let mut played = songs(&[2, 9]);
+        played[0].name = "fetched".to_owned();
+        app.recently_played = Some(played);
+        let recent_ids = |app: &App| -> Vec<usize> {
+            app.recently_played.iter().flatten().map(|song| song.id).collect()
+        };
+
+        app.play_index(0).unwrap();
+        assert_eq!(recent_ids(&app), vec![1, 2, 9]);
+        app.play_index(1).unwrap();
+        assert_eq!(recent_ids(&app), vec![2, 1, 9]);
+        assert_eq!(app.recently_played.as_ref().unwrap()[0].name, "fetched");
+
+        app.config.recently_played_len = 2;
+        app.play_index(2).unwrap();
+        assert_eq!(recent_ids(&app), vec![3, 2]);
+    }
+
+    #[test]
+    fn test_comments() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.comments(1, ResourceType::Song);
+        assert_eq!(app.route(), &Route::Comments);
+        assert_eq!(comment_requests(&io_rx), vec![(CommentSort::Hot, 0)]);
+        // the first page is on its way
+        app.load_more_comments();
+        assert!(comment_requests(&io_rx).is_empty());
+
+        let page = |offset, n| DataEvent::Comments {
+            id: 1,
+            kind: ResourceType::Song,
+            sort: CommentSort::Hot,
+            offset,
+            comments: (offset..offset + n)
+                .map(|id| Comment {
+                    id,
+                    ..Default::default()
+                })
+                .collect(),
+            more: true,
+        };
+        app.handle_data(page(0, 2));
+        assert_eq!(app.comment_list().len(), 2);
+        // selecting the last comment loads the next page
+        app.handle_action(Key::Char('j'));
+        assert_eq!(app.comment_index(), 1);
+        assert_eq!(comment_requests(&io_rx), vec![(CommentSort::Hot, 2)]);
+        app.handle_data(page(2, 2));
+        app.handle_data(page(2, 2));
+        assert_eq!(app.comment_list().len(), 4);
+
+        app.handle_action(Key::Char('o'));
+        assert_eq!(app.comment_sort(), CommentSort::Latest);
+        assert!(app.comment_list().is_empty());
+        assert_eq!(comment_requests(&io_rx), vec![(CommentSort::Latest, 0)]);
+        // hot comments asked for before are no longer shown
+        app.handle_data(page(0, 2));
+        assert!(app.comment_list().is_empty());
+
+        // a failed page can be asked for again
+        app.handle_data(DataEvent::CommentsFailed {
+            id: 1,
+            kind: ResourceType::Song,
+            sort: CommentSort::Latest,
+            offset: 0,
+            reason: "timed out".to_owned(),
+        });
+        app.load_more_comments();
+        assert_eq!(comment_requests(&io_rx), vec![(CommentSort::Latest, 0)]);
+    }
+
+    #[test]
+    fn test_lastfm_scrobble() {
+        let (scrobble_tx, scrobble_rx) = mpsc::channel();
+        let (app, _io_rx, _playback_rx) = new_app();
+        let mut app = app.set_scrobbler(scrobble_tx);
+        app.queue = songs(&[10, 11]);
+        for song in app.queue.iter_mut() {
+            song.artists = vec![ncmapi::types::Artist {
+                name: "Jay Chou".to_owned(),
+                ..Default::default()
+            }];
+        }
+        app.play_index(0).unwrap();
+        app.handle_data(DataEvent::Track(10, Bytes::from_static(b"track")));
+        assert!(matches!(
+            scrobble_rx.try_recv(),
+            Ok(ScrobbleEvent::NowPlaying(track)) if track.artist == "Jay Chou"
+        ));
+        let context = app.player.playback_context();
+        context.set_duration_ms(Some(180_000));
+
+        // seeking past the middle isn't listening to it
+        context.set_progress_ms(100_000);
+        app.on_tick();
+        app.play_index(1).unwrap();
+        assert!(scrobble_rx.try_recv().is_err());
+
+        app.handle_data(DataEvent::Track(11, Bytes::from_static(b"track")));
+        assert!(matches!(scrobble_rx.try_recv(), Ok(ScrobbleEvent::NowPlaying(_))));
+        context.set_duration_ms(Some(180_000));
+        for second in 1..=90 {
+            context.set_progress_ms(second * 1000);
+            app.on_tick();
+        }
+        app.eject();
+        match scrobble_rx.try_recv() {
+            Ok(ScrobbleEvent::Scrobble(track)) => assert_eq!(track.duration_secs, Some(180)),
+            _ => panic!("half of the track was listened to"),
+        }
+    }
+
+    fn history(app: &App) -> Vec<&str> {
+        app.search_history().iter().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn test_search_history_dedup() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.record_search("jay chou");
+        app.record_search("eason");
+        app.record_search(" jay chou ");
+        app.record_search("");
+
+        assert_eq!(history(&app), vec!["jay chou", "eason"]);
+    }
+
+    #[test]
+    fn test_search_history_cap() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.config.search_history_len = 3;
+        for query in ["a", "b", "c", "d"] {
+            app.record_search(query);
+        }
+
+        assert_eq!(history(&app), vec!["d", "c", "b"]);
+    }
+
+    #[test]
+    fn test_search_history_clear() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        for query in ["a", "b", "c"] {
+            app.record_search(query);
+        }
+        app.remove_search_history(1);
+        assert_eq!(history(&app), vec!["c", "a"]);
+        app.remove_search_history(5);
+        assert_eq!(history(&app).len(), 2);
+
+        app.clear_search_history();
+        assert!(app.search_history().is_empty());
+    }
+
+    #[test]
+    fn test_search_history_rerun() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.record_search("old");
+        app.record_search("older");
+
+        app.handle_action(Key::Char('/'));
+        app.handle_action(Key::Down);
+        app.handle_action(Key::Down);
+        assert_eq!(app.search_history_index(), Some(1));
+        app.handle_action(Key::Char('\n'));
+
+        assert!(matches!(io_rx.try_recv(), Ok(IoEvent::Search { key, .. }) if key == "old"));
+        assert_eq!(history(&app), vec!["old", "older"]);
+    }
+
+    fn search_offsets(io_rx: &mpsc::Receiver<IoEvent>) -> Vec<usize> {
+        io_rx
+            .try_iter()
+            .filter_map(|e| match e {
+                IoEvent::Search { offset, .. } => Some(offset),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_search_pages() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.search_limit = 2;
+        app.handle_action(Key::Char('/'));
+        app.handle_action(Key::Char('x'));
+        app.handle_action(Key::Char('\n'));
+        app.handle_data(DataEvent::SearchResults(SearchResult::Song(songs(&[1, 2]))));
+        assert_eq!(search_offsets(&io_rx), vec![0]);
+
+        app.handle_action(Key::Char(']'));
+        assert_eq!(search_offsets(&io_rx), vec![2]);
+        assert_eq!(app.search_page(), 2);
+        // a short page is the last one
+        app.handle_data(DataEvent::SearchResults(SearchResult::Song(songs(&[3]))));
+        app.handle_action(Key::Char(']'));
+        assert!(search_offsets(&io_rx).is_empty());
+
+        app.handle_action(Key::Char('['));
+        app.handle_action(Key::Char('['));
+        assert_eq!(search_offsets(&io_rx), vec![0]);
+        assert_eq!(app.search_page(), 1);
+    }
+
+    #[test]
+    fn test_search_results() {
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.search_limit = 1;
+        app.handle_action(Key::Char('/'));
+        app.handle_action(Key::Char('x'));
+        app.handle_action(Key::Char('\n'));
+        app.handle_data(DataEvent::SearchResults(SearchResult::Song(songs(&[1]))));
+        assert_eq!(app.track_table.as_ref().map(Vec::len), Some(1));
+
+        let podcast = Podcast {
+            id: 7,
+            name: "talk".to_owned(),
+            dj: "host".to_owned(),
+        };
+        app.handle_data(DataEvent::SearchResults(SearchResult::Podcast(vec![podcast.clone()])));
+        assert!(matches!(app.search_results(), Some(SearchResult::Podcast(found)) if found == &vec![podcast]));
+        // only song results replace the track table
+        assert_eq!(app.track_table.as_ref().map(Vec::len), Some(1));
+
+        // the next page asks for more of the same kind
+        let _ = io_rx.try_iter().count();
+        app.search_next_page();
+        let kinds: Vec<_> = io_rx
+            .try_iter()
+            .filter_map(|e| match e {
+                IoEvent::Search { kind, .. } => Some(format!("{:?}", kind)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(kinds, vec!["Podcast"]);
+    }
+
+    #[test]
+    fn test_select_search_results() {
+        let (mut app, _io_rx, _playback_rx) = new_app();
+        app.handle_action(Key::Char('/'));
+        app.handle_action(Key::Char('x'));
+        app.handle_action(Key::Char('\n'));
+        app.handle_data(DataEvent::SearchResults(SearchResult::Song(songs(&[1, 2]))));
+        let podcasts: Vec<_> = (1..=2)
+            .map(|id| Podcast {
+                id,
+                name: format!("talk {}", id),
+                dj: "host".to_owned(),
+            })
+            .collect();
+        app.handle_data(DataEvent::SearchResults(SearchResult::Podcast(podcasts.clone())));
+
+        // the keys move through the listed results, not the track table behind
+        for _ in 0..3 {
+            app.handle_action(Key::Down);
+        }
+        assert_eq!(app.search_index(), 1);
+        assert_eq!(app.track_table_index(), 0);
+        app.handle_action(Key::Up);
+        assert_eq!(app.search_index(), 0);
+
+        // new results start from their first row
+        app.handle_action(Key::Down);
+        app.handle_data(DataEvent::SearchResults(SearchResult::Podcast(podcasts)));
+        assert_eq!(app.search_index(), 0);
+    }
+
+    #[test]
+    fn test_m3u() {
+        let dir = std::env::temp_dir().join(format!("lekima-m3u-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.mp3"), "local").unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        let (mut app, io_rx, _playback_rx) = new_app();
+        app.queue = songs(&[5, 6]);
+        app.queue[0].dt = 215_300;
+        let exported = dir.join("out.m3u8");
+        app.export_m3u(&exported).unwrap();
+        let raw = fs::read_to_string(&exported).unwrap();
+        assert_eq!(
+            raw,
+            concat!(
+                "#EXTM3U\n",
+                "#EXTINF:215,\nhttps://music.163.com/song?id=5\n",
+                "#EXTINF:-1,\nhttps://music.163.com/song?id=6\n",
+            )
+        );
+
+        let playlist = format!(
+            "#EXTINF:10,Local A\na.mp3\nnotes.txt\nmissing.flac\nhttp://stream/x\n{}",
+            raw
+        );
+        fs::write(dir.join("in.m3u"), playlist).unwrap();
+        let warnings = app.import_m3u(&dir.join("in.m3u")).unwrap();
+        assert_eq!(warnings.len(), 3, "{:?}", warnings);
+        let names: Vec<&str> = app.queue().iter().map(|song| song.name.as_str()).collect();
+        assert_eq!(names, vec!["Local A", "", ""]);
+        assert_eq!(app.queue()[1].id, 5);
+        let lengths: Vec<_> = app.queue().iter().map(|song| song.dt).collect();
+        assert_eq!(lengths, vec![10_000, 215_000, 0]);
+        // the local file plays without asking for a url
+        assert!(app.is_local(app.queue()[0].id));
+        assert!(app.pending_play.is_none());
+        assert!(!io_rx.try_iter().any(|e| matches!(e, IoEvent::SongUrls(_) | IoEvent::Lyric(_))));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_media_controls() {
+        let (app, _io_rx, _playback_rx) = new_app();
+        let now_playing: NowPlaying = Default::default();
+        let mut app = app.set_media_now_playing(now_playing.clone());
+        app.track_table = Some(songs(&[10, 11]));
+        app.play_track_at(0).unwrap();
+        assert_eq!(now_playing.lock().unwrap().as_ref().map(|m| m.song_id), Some(10));
+        app.fetch_cover("http://a/10.png".to_owned());
+        let art = now_playing.lock().unwrap().as_ref().and_then(|m| m.art_url.clone());
+        assert_eq!(art.as_deref(), Some("http://a/10.png"));
+
+        app.handle_media(MediaCommand::Next);
+        assert_eq!(app.current_index(), Some(1));
+        app.handle_media(MediaCommand::SetPosition(5000));
+        app.handle_media(MediaCommand::Seek(-2000));
+        assert_eq!(app.player.playback_context().progress_ms(), 3000);
+        app.handle_media(MediaCommand::SetVolume(0.25));
+        assert_eq!(app.player.playback_context().volume_percent(), 25);
+
+        app.handle_media(MediaCommand::Stop);
+        assert!(now_playing.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_set_config() {
+        let (app, _io_rx, _playback_rx) = new_app();
+        let mut config = AppConfig::from_toml("volume = 40\nsearch_limit = 5").unwrap();
+        config.key_bindings_path = None;
+        let app = app.set_config(config);
+        assert_eq!(app.search_limit, 5);
+        assert_eq!(app.player.playback_context().volume_percent(), 40);
+        assert_eq!(app.tick_rate().as_millis(), 250);
+        assert_eq!(app.theme_name(), "default");
+        assert_eq!(app.theme(), &Theme::default());
+    }
+
+    #[test]
+    fn test_invalid_theme() {
+        let (app, _io_rx, _playback_rx) = new_app();
+        let raw = "theme = \"mine\"\n[themes.mine]\nprimary = \"#1e2127\"\nerror = \"red\"";
+        let mut config = AppConfig::from_toml(raw).unwrap();
+        config.key_bindings_path = None;
+        let app = app.set_config(config);
+        assert_eq!(app.theme().primary, Color::Rgb(0x1e, 0x21, 0x27));
+        assert_eq!(app.theme().error, Theme::default().error);
+        let warning = &app.notifications()[0];
+        assert_eq!(warning.level, Level::Warning);
+        assert!(warning.message.contains("error \"red\""));
+    }
+
+    #[test]
+    fn test_track_cache() {
+        let dir = std::env::temp_dir().join(format!("lekima-app-tracks-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let (app, io_rx, _playback_rx) = new_app();
+        let mut app = app.set_cache_dir(&dir);
+        assert_eq!(app.cache_dir(), Some(dir.as_path()));
+        app.track_table = Some(songs(&[1]));
+        let url = || DataEvent::SongUrls(vec![(1, Some("http://fake/1.mp3".to_owned()))]);
+        let downloads = |io_rx: &mpsc::Receiver<IoEvent>| {
+            io_rx.try_iter().filter(|e| matches!(e, IoEvent::Download(1, _))).count()
+        };
+
+        app.play_track_at(0).unwrap();
+        app.handle_data(url());
+        assert_eq!(downloads(&io_rx), 1);
+        app.handle_data(DataEvent::Track(1, Bytes::from("track")));
+        assert_eq!(fs::read(dir.join("1")).unwrap(), b"track");
+
+        // replayed from disk
+        app.play_track_at(0).unwrap();
+        app.handle_data(url());
+        assert_eq!(downloads(&io_rx), 0);
+        assert!(app.pending_play.is_none());
+
+        app.clear_cache();
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_search_history_persisted() {
+        let path = std::env::temp_dir().join(format!("lekima-search-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let (app, _io_rx, _playback_rx) = new_app();
+        let mut app = app.set_search_history_path(&path);
+        app.record_search("a");
+        app.record_search("b");
+
+        let (app, _io_rx, _playback_rx) = new_app();
+        let app = app.set_search_history_path(&path);
+        assert_eq!(history(&app), vec!["b", "a"]);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,49 @@
+//! Opening NetEase web pages in the default browser.
+
+/// Kinds of items with a page on music.163.com.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Page {
+    Song,
+    Album,
+    Artist,
+    Playlist,
+    Podcast,
+}
+
+pub fn web_url(page: Page, id: usize) -> String {
+    let path = match page {
+        Page::Song => "song",
+        Page::Album => "album",
+        Page::Artist => "artist",
+        Page::Playlist => "playlist",
+        Page::Podcast => "djradio",
+    };
+    format!("https://music.163.com/{}?id={}", path, id)
+}
+
+pub trait Opener {
+    fn open(&mut self, url: &str) -> Result<(), String>;
+}
+
+/// The desktop's default handler, fails when there is none, e.g. over ssh.
+pub struct SystemOpener;
+
+impl Opener for SystemOpener {
+    fn open(&mut self, url: &str) -> Result<(), String> {
+        open::that(url).map(|_| ()).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{web_url, Page};
+
+    #[test]
+    fn test_web_url() {
+        assert_eq!(web_url(Page::Song, 1), "https://music.163.com/song?id=1");
+        assert_eq!(web_url(Page::Album, 2), "https://music.163.com/album?id=2");
+        assert_eq!(web_url(Page::Artist, 3), "https://music.163.com/artist?id=3");
+        assert_eq!(web_url(Page::Playlist, 4), "https://music.163.com/playlist?id=4");
+        assert_eq!(web_url(Page::Podcast, 5), "https://music.163.com/djradio?id=5");
+    }
+}
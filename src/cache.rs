@@ -0,0 +1,162 @@
+//! Downloaded tracks kept on disk by song id, so a replay doesn't fetch them
+//! again. The least recently played ones go once the cache outgrows its limit.
+
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use bytes::Bytes;
+
+use crate::session;
+
+pub struct TrackCache {
+    dir: PathBuf,
+    // total size of the cached tracks, at most
+    max_bytes: u64,
+}
+
+impl TrackCache {
+    pub fn new(dir: impl Into<PathBuf>, max_bytes: u64) -> Self {
+        Self {
+            dir: dir.into(),
+            max_bytes,
+        }
+    }
+
+    /// `cache_dir()/tracks`.
+    pub fn default_dir() -> Option<PathBuf> {
+        session::cache_dir().map(|dir| dir.join("tracks"))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Where the track of song `id` is cached.
+    pub fn path_for(&self, id: usize) -> PathBuf {
+        self.dir.join(id.to_string())
+    }
+
+    /// The cached track of song `id`, marking it as just played.
+    pub fn get(&self, id: usize) -> Option<Bytes> {
+        let path = self.path_for(id);
+        let track = fs::read(&path).ok()?;
+        // the modification time doubles as the last access
+        if let Err(e) = touch(&path) {
+            log::warn!("touch cached track {:?}: {}", path, e);
+        }
+        Some(Bytes::from(track))
+    }
+
+    /// Caches `track` as song `id`, then evicts the least recently played
+    /// tracks until the cache fits its limit again.
+    pub fn put(&self, id: usize, track: &[u8]) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        // written aside first so a half written track is never played
+        let part = self.dir.join(format!("{}.part", id));
+        fs::write(&part, track)?;
+        fs::rename(&part, self.path_for(id))?;
+        self.evict()
+    }
+
+    /// Removes every cached track.
+    pub fn clear(&self) -> io::Result<()> {
+        match fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Total size of the cached tracks.
+    pub fn size(&self) -> io::Result<u64> {
+        Ok(self.entries()?.iter().map(|(_, len, _)| len).sum())
+    }
+
+    fn evict(&self) -> io::Result<()> {
+        let mut entries = self.entries()?;
+        let mut total: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        // oldest access first
+        entries.sort_by_key(|(_, _, accessed)| *accessed);
+        for (path, len, _) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            fs::remove_file(&path)?;
+            total -= len;
+        }
+        Ok(())
+    }
+
+    // (path, size, last access) of every cached track
+    fn entries(&self) -> io::Result<Vec<(PathBuf, u64, SystemTime)>> {
+        let dir = match fs::read_dir(&self.dir) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            dir => dir?,
+        };
+        let mut entries = Vec::new();
+        for entry in dir {
+            let entry = entry?;
+            let meta = entry.metadata()?;
+            if meta.is_file() {
+                entries.push((entry.path(), meta.len(), meta.modified()?));
+            }
+        }
+        Ok(entries)
+    }
+}
+
+fn touch(path: &Path) -> io::Result<()> {
+    File::options()
+        .append(true)
+        .open(path)?
+        .set_modified(SystemTime::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, thread, time::Duration};
+
+    use super::TrackCache;
+
+    fn cache(name: &str, max_bytes: u64) -> TrackCache {
+        let dir = std::env::temp_dir().join(format!("lekima-tracks-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        TrackCache::new(dir, max_bytes)
+    }
+
+    #[test]
+    fn test_put_get() {
+        let cache = cache("put", 100);
+        assert!(cache.get(1).is_none());
+        cache.put(1, b"track").unwrap();
+        assert_eq!(&cache.get(1).unwrap()[..], b"track");
+        assert_eq!(cache.size().unwrap(), 5);
+
+        cache.clear().unwrap();
+        assert!(cache.get(1).is_none());
+        // nothing left to clear
+        cache.clear().unwrap();
+    }
+
+    #[test]
+    fn test_evicts_least_recently_played() {
+        let cache = cache("evict", 10);
+        cache.put(1, b"aaaa").unwrap();
+        thread::sleep(Duration::from_millis(20));
+        cache.put(2, b"bbbb").unwrap();
+        thread::sleep(Duration::from_millis(20));
+        // played again, so 2 is the oldest now
+        cache.get(1).unwrap();
+        thread::sleep(Duration::from_millis(20));
+
+        cache.put(3, b"cccc").unwrap();
+        assert!(cache.get(1).is_some());
+        assert!(cache.get(2).is_none());
+        assert!(cache.get(3).is_some());
+        assert_eq!(cache.size().unwrap(), 8);
+        cache.clear().unwrap();
+    }
+}
@@ -0,0 +1,80 @@
+//! Where playback was, written every few seconds so a crash can resume close to it.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde_json::{json, Value};
+
+use crate::session;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub song_id: usize,
+    pub name: String,
+    pub progress_ms: u64,
+}
+
+/// `<config dir>/checkpoint.json`.
+pub fn default_path() -> Option<PathBuf> {
+    session::config_dir().map(|dir| dir.join("checkpoint.json"))
+}
+
+/// Replaces the checkpoint at `path` atomically: a crash mid write leaves the old one.
+pub fn write(path: &Path, checkpoint: &Checkpoint) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let raw = json!({
+        "song_id": checkpoint.song_id,
+        "name": checkpoint.name,
+        "progress_ms": checkpoint.progress_ms,
+    });
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, raw.to_string())?;
+    fs::rename(&tmp, path)
+}
+
+/// `None` when there's no checkpoint or it can't be read.
+pub fn read(path: &Path) -> Option<Checkpoint> {
+    let raw: Value = serde_json::from_str(&fs::read_to_string(path).ok()?).ok()?;
+    Some(Checkpoint {
+        song_id: raw.get("song_id")?.as_u64()? as usize,
+        name: raw.get("name")?.as_str()?.to_owned(),
+        progress_ms: raw.get("progress_ms")?.as_u64()?,
+    })
+}
+
+/// Forgets the checkpoint, after a clean exit there's nothing to resume.
+pub fn clear(path: &Path) {
+    let _ = fs::remove_file(path);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{clear, read, write, Checkpoint};
+
+    #[test]
+    fn test_round_trip() {
+        let dir = std::env::temp_dir().join(format!("lekima-checkpoint-{}", std::process::id()));
+        let path = dir.join("checkpoint.json");
+        let checkpoint = Checkpoint {
+            song_id: 42,
+            name: "bfs".to_owned(),
+            progress_ms: 61_000,
+        };
+        write(&path, &checkpoint).unwrap();
+        assert_eq!(read(&path), Some(checkpoint));
+        assert!(!path.with_extension("tmp").exists());
+
+        fs::write(&path, "{\"song_id\": 42}").unwrap();
+        assert_eq!(read(&path), None);
+
+        clear(&path);
+        assert_eq!(read(&path), None);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
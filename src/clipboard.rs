@@ -0,0 +1,38 @@
+//! System clipboard access. There may be no clipboard at all, e.g. over ssh
+//! or on a bare tty, so callers must be ready for it to fail.
+
+use crate::browser::{self, Page};
+
+pub trait Clipboard {
+    fn set_text(&mut self, text: String) -> Result<(), String>;
+}
+
+pub struct SystemClipboard(arboard::Clipboard);
+
+impl SystemClipboard {
+    /// `None` when no clipboard is reachable from this session.
+    pub fn open() -> Option<Self> {
+        arboard::Clipboard::new().ok().map(Self)
+    }
+}
+
+impl Clipboard for SystemClipboard {
+    fn set_text(&mut self, text: String) -> Result<(), String> {
+        self.0.set_text(text).map_err(|e| e.to_string())
+    }
+}
+
+/// Link to the NetEase web page of song `id`.
+pub fn share_url(id: usize) -> String {
+    browser::web_url(Page::Song, id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::share_url;
+
+    #[test]
+    fn test_share_url() {
+        assert_eq!(share_url(1824020871), "https://music.163.com/song?id=1824020871");
+    }
+}
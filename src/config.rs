@@ -0,0 +1,395 @@
+//! User settings read from `config.toml` on launch, e.g.
+//!
+//! ```toml
+//! volume = 70
+//! tick_rate_ms = 100
+//! theme = "default"
+//! cache_size_mb = 512
+//! gapless = true
+//! search_limit = 30
+//! key_bindings = "/home/me/lekima-keys.toml"
+//! lastfm_api_key = "..."
+//! lastfm_api_secret = "..."
+//! lastfm_session_key = "..."
+//!
+//! [themes.mine]
+//! primary = "#d0d0d0"
+//! ```
+//!
+//! Missing keys keep their defaults, values out of range are rejected.
+
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use log::{error, LevelFilter};
+use toml::Value;
+
+use crate::{keybindings::KeyBindings, scrobble::Credentials, session};
+
+pub struct AppConfig {
+    // percent added/removed by one volume key press
+    pub(crate) volume_step: u8,
+    // volume percentage on launch, until one is saved with the playback state
+    pub(crate) volume: u8,
+    // a play counts once this much of a track was listened to, or `scrobble_after_ms`
+    pub(crate) scrobble_fraction: f32,
+    pub(crate) scrobble_after_ms: u64,
+    // upcoming tracks whose lyrics are fetched when a track starts
+    pub(crate) lyric_prefetch: usize,
+    // ticks per char scrolled by the now playing title
+    pub(crate) marquee_speed: u64,
+    // view shown on launch: home, recommended, personal_fm or last_played
+    pub(crate) startup_route: String,
+    // past queries remembered
+    pub(crate) search_history_len: usize,
+    // songs kept in the recently played, newest first
+    pub(crate) recently_played_len: usize,
+    // destructive actions that can be undone
+    pub(crate) undo_depth: usize,
+    // let the current song finish after quitting
+    pub(crate) keep_playing: bool,
+    // play the queue back to back, the next song fetched ahead
+    pub(crate) gapless: bool,
+    // ask before quitting
+    pub(crate) confirm_quit: bool,
+    // playback between two crash checkpoints
+    pub(crate) checkpoint_interval_ms: u64,
+    // leave recently played songs out of the daily mix
+    pub(crate) daily_skip_played: bool,
+    // downloaded tracks kept on disk, in bytes
+    pub(crate) cache_max_bytes: u64,
+    // time between two ticks of the event loop
+    pub(crate) tick_rate_ms: u64,
+    // a built-in theme or one of `themes`
+    pub(crate) theme: String,
+    // custom themes by name: color name -> hex, checked once the theme is loaded
+    pub(crate) themes: HashMap<String, HashMap<String, String>>,
+    // results asked for per search page
+    pub(crate) search_limit: u8,
+    // no custom key bindings without a path
+    pub(crate) key_bindings_path: Option<PathBuf>,
+    // no Last.fm scrobbling without them
+    pub(crate) lastfm: Option<Credentials>,
+    pub(crate) log_level: LevelFilter,
+    // no logging without a path
+    pub(crate) log_path: Option<PathBuf>,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            volume_step: 5,
+            volume: 90,
+            scrobble_fraction: 0.5,
+            scrobble_after_ms: 30_000,
+            lyric_prefetch: 2,
+            marquee_speed: 2,
+            startup_route: "home".to_owned(),
+            search_history_len: 20,
+            recently_played_len: 100,
+            undo_depth: 20,
+            keep_playing: false,
+            gapless: false,
+            confirm_quit: true,
+            checkpoint_interval_ms: 5000,
+            daily_skip_played: true,
+            cache_max_bytes: 1 << 30,
+            tick_rate_ms: 250,
+            theme: "default".to_owned(),
+            themes: HashMap::new(),
+            search_limit: 20,
+            key_bindings_path: KeyBindings::default_path(),
+            lastfm: None,
+            log_level: LevelFilter::Info,
+            log_path: session::config_dir().map(|dir| dir.join("lekima.log")),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(io::Error),
+    Parse(toml::de::Error),
+    UnknownKey(String),
+    // the value of `key` has the wrong type or is out of range
+    Invalid { key: String, expected: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(e) => write!(f, "read config: {}", e),
+            ConfigError::Parse(e) => write!(f, "parse config: {}", e),
+            ConfigError::UnknownKey(key) => write!(f, "unknown setting {:?}", key),
+            ConfigError::Invalid { key, expected } => write!(f, "{}: expected {}", key, expected),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl AppConfig {
+    /// `config_dir()/config.toml`.
+    pub fn default_path() -> Option<PathBuf> {
+        session::config_dir().map(|dir| dir.join("config.toml"))
+    }
+
+    /// The settings in the default config file, the defaults when it's
+    /// missing or can't be used.
+    pub fn load() -> Self {
+        let path = match Self::default_path() {
+            Some(path) => path,
+            None => return Self::default(),
+        };
+        Self::load_from(&path).unwrap_or_else(|e| {
+            error!("config {:?}: {}, using the defaults", path, e);
+            Self::default()
+        })
+    }
+
+    /// The settings in `path`, just the defaults when there's no such file.
+    pub fn load_from(path: &Path) -> Result<Self, ConfigError> {
+        match fs::read_to_string(path) {
+            Ok(raw) => Self::from_toml(&raw),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(ConfigError::Read(e)),
+        }
+    }
+
+    pub fn from_toml(raw: &str) -> Result<Self, ConfigError> {
+        let table = match raw.parse::<Value>().map_err(ConfigError::Parse)? {
+            Value::Table(table) => table,
+            _ => return Err(invalid("config", "a table of settings")),
+        };
+        let mut config = Self::default();
+        // (api key, api secret, session key), set all together or not at all
+        let mut lastfm = (None, None, None);
+        for (key, value) in &table {
+            let key = key.as_str();
+            match key {
+                "volume_step" => config.volume_step = int_in(key, value, 1, 100)? as u8,
+                "volume" => config.volume = int_in(key, value, 0, 100)? as u8,
+                "scrobble_fraction" => config.scrobble_fraction = fraction(key, value)?,
+                "scrobble_after_ms" => config.scrobble_after_ms = int_in(key, value, 0, i64::MAX)? as u64,
+                "lyric_prefetch" => config.lyric_prefetch = int_in(key, value, 0, 10)? as usize,
+                "marquee_speed" => config.marquee_speed = int_in(key, value, 1, 100)? as u64,
+                "startup_route" => config.startup_route = string(key, value)?.to_owned(),
+                "search_history_len" => config.search_history_len = int_in(key, value, 0, 1000)? as usize,
+                "recently_played_len" => config.recently_played_len = int_in(key, value, 1, 1000)? as usize,
+                "undo_depth" => config.undo_depth = int_in(key, value, 0, 1000)? as usize,
+                "keep_playing" => config.keep_playing = boolean(key, value)?,
+                "gapless" => config.gapless = boolean(key, value)?,
+                "confirm_quit" => config.confirm_quit = boolean(key, value)?,
+                "checkpoint_interval_ms" => {
+                    config.checkpoint_interval_ms = int_in(key, value, 1000, i64::MAX)? as u64
+                }
+                "daily_skip_played" => config.daily_skip_played = boolean(key, value)?,
+                "cache_size_mb" => config.cache_max_bytes = (int_in(key, value, 0, 1 << 20)? as u64) << 20,
+                "tick_rate_ms" => config.tick_rate_ms = int_in(key, value, 10, 1000)? as u64,
+                "theme" => config.theme = string(key, value)?.to_owned(),
+                "themes" => config.themes = themes(key, value)?,
+                "search_limit" => config.search_limit = int_in(key, value, 1, 100)? as u8,
+                "key_bindings" => config.key_bindings_path = Some(PathBuf::from(string(key, value)?)),
+                "lastfm_api_key" => lastfm.0 = Some(string(key, value)?.to_owned()),
+                "lastfm_api_secret" => lastfm.1 = Some(string(key, value)?.to_owned()),
+                "lastfm_session_key" => lastfm.2 = Some(string(key, value)?.to_owned()),
+                "log_level" => {
+                    let level = string(key, value)?;
+                    config.log_level = LevelFilter::from_str(level)
+                        .map_err(|_| invalid(key, "off, error, warn, info, debug or trace"))?;
+                }
+                "log_path" => config.log_path = Some(PathBuf::from(string(key, value)?)),
+                _ => return Err(ConfigError::UnknownKey(key.to_owned())),
+            }
+        }
+        config.lastfm = match lastfm {
+            (Some(api_key), Some(api_secret), Some(session_key)) => Some(Credentials {
+                api_key,
+                api_secret,
+                session_key,
+            }),
+            (None, None, None) => None,
+            _ => {
+                return Err(invalid(
+                    "lastfm",
+                    "lastfm_api_key, lastfm_api_secret and lastfm_session_key together",
+                ))
+            }
+        };
+        Ok(config)
+    }
+
+    pub fn tick_rate(&self) -> Duration {
+        Duration::from_millis(self.tick_rate_ms)
+    }
+
+    pub fn theme(&self) -> &str {
+        &self.theme
+    }
+
+    /// The custom themes, see `Theme::load`.
+    pub fn themes(&self) -> &HashMap<String, HashMap<String, String>> {
+        &self.themes
+    }
+
+    /// What to scrobble to Last.fm with, if set up.
+    pub fn lastfm(&self) -> Option<&Credentials> {
+        self.lastfm.as_ref()
+    }
+}
+
+fn invalid(key: &str, expected: &str) -> ConfigError {
+    ConfigError::Invalid {
+        key: key.to_owned(),
+        expected: expected.to_owned(),
+    }
+}
+
+fn int_in(key: &str, value: &Value, min: i64, max: i64) -> Result<i64, ConfigError> {
+    match value.as_integer() {
+        Some(n) if (min..=max).contains(&n) => Ok(n),
+        _ if max == i64::MAX => Err(invalid(key, &format!("a whole number from {}", min))),
+        _ => Err(invalid(key, &format!("a whole number from {} to {}", min, max))),
+    }
+}
+
+fn fraction(key: &str, value: &Value) -> Result<f32, ConfigError> {
+    match value.as_float() {
+        Some(x) if (0.0..=1.0).contains(&x) => Ok(x as f32),
+        _ => Err(invalid(key, "a number from 0.0 to 1.0")),
+    }
+}
+
+fn boolean(key: &str, value: &Value) -> Result<bool, ConfigError> {
+    value.as_bool().ok_or_else(|| invalid(key, "true or false"))
+}
+
+fn string<'a>(key: &str, value: &'a Value) -> Result<&'a str, ConfigError> {
+    value.as_str().ok_or_else(|| invalid(key, "a string"))
+}
+
+// tables of strings by theme name
+fn themes(
+    key: &str,
+    value: &Value,
+) -> Result<HashMap<String, HashMap<String, String>>, ConfigError> {
+    let expected = "tables of colors by theme name";
+    let table = value.as_table().ok_or_else(|| invalid(key, expected))?;
+    table
+        .iter()
+        .map(|(name, colors)| {
+            let colors = colors.as_table().ok_or_else(|| invalid(key, expected))?;
+            let colors = colors
+                .iter()
+                .map(|(field, hex)| {
+                    let key = format!("{}.{}.{}", key, name, field);
+                    Ok((field.clone(), string(&key, hex)?.to_owned()))
+                })
+                .collect::<Result<_, ConfigError>>()?;
+            Ok((name.clone(), colors))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{AppConfig, ConfigError};
+
+    #[test]
+    fn test_from_toml() {
+        let config = AppConfig::from_toml(
+            r#"
+            volume = 70
+            tick_rate_ms = 100
+            theme = "dark"
+            cache_size_mb = 2
+            search_limit = 30
+            gapless = true
+            key_bindings = "/tmp/keys.toml"
+            log_level = "debug"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.volume, 70);
+        assert_eq!(config.tick_rate().as_millis(), 100);
+        assert_eq!(config.theme(), "dark");
+        assert_eq!(config.cache_max_bytes, 2 << 20);
+        assert_eq!(config.search_limit, 30);
+        assert!(config.gapless);
+        assert_eq!(config.key_bindings_path.as_deref(), Some("/tmp/keys.toml".as_ref()));
+        assert_eq!(config.log_level, log::LevelFilter::Debug);
+        // untouched settings keep their defaults
+        assert_eq!(config.volume_step, 5);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let err = AppConfig::from_toml("volume = 150").unwrap_err();
+        assert_eq!(err.to_string(), "volume: expected a whole number from 0 to 100");
+        assert!(matches!(
+            AppConfig::from_toml("theme = 3"),
+            Err(ConfigError::Invalid { key, .. }) if key == "theme"
+        ));
+        assert!(matches!(AppConfig::from_toml("colour = 1"), Err(ConfigError::UnknownKey(_))));
+        assert!(matches!(AppConfig::from_toml("volume ="), Err(ConfigError::Parse(_))));
+    }
+
+    #[test]
+    fn test_themes() {
+        let config = AppConfig::from_toml(
+            r##"
+            theme = "mine"
+            [themes.mine]
+            primary = "#d0d0d0"
+            error = "nope"
+            "##,
+        )
+        .unwrap();
+        let mine = &config.themes()["mine"];
+        assert_eq!(mine["primary"], "#d0d0d0");
+        // left for the theme to warn about
+        assert_eq!(mine["error"], "nope");
+        assert!(matches!(
+            AppConfig::from_toml("[themes.mine]\nprimary = 1"),
+            Err(ConfigError::Invalid { key, .. }) if key == "themes.mine.primary"
+        ));
+        assert!(AppConfig::from_toml("themes = \"dark\"").is_err());
+    }
+
+    #[test]
+    fn test_lastfm() {
+        assert!(AppConfig::from_toml("").unwrap().lastfm().is_none());
+        let config = AppConfig::from_toml(
+            r#"
+            lastfm_api_key = "key"
+            lastfm_api_secret = "secret"
+            lastfm_session_key = "sk"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.lastfm().unwrap().session_key, "sk");
+        // the key alone can't sign anything
+        assert!(matches!(
+            AppConfig::from_toml(r#"lastfm_api_key = "key""#),
+            Err(ConfigError::Invalid { key, .. }) if key == "lastfm"
+        ));
+    }
+
+    #[test]
+    fn test_load_missing() {
+        let path = std::env::temp_dir().join(format!("lekima-config-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        assert_eq!(AppConfig::load_from(&path).unwrap().search_limit, 20);
+
+        fs::write(&path, "search_limit = 0").unwrap();
+        assert!(AppConfig::load_from(&path).is_err());
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,164 @@
+//! Album covers downloaded from a song's `picUrl`, cached on disk so the
+//! same cover is only ever fetched once.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt, fs,
+    hash::{Hash, Hasher},
+    io,
+    path::PathBuf,
+};
+
+use bytes::Bytes;
+
+use crate::session;
+
+#[derive(Debug)]
+pub enum CoverError {
+    Request(reqwest::Error),
+    // http status other than success, e.g. 404
+    Status(u16),
+    // not an image we know how to show
+    Decode,
+    Cache(io::Error),
+}
+
+impl fmt::Display for CoverError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CoverError::Request(e) => write!(f, "request cover: {}", e),
+            CoverError::Status(code) => write!(f, "cover responded with {}", code),
+            CoverError::Decode => write!(f, "cover is not an image"),
+            CoverError::Cache(e) => write!(f, "cache cover: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CoverError {}
+
+pub struct CoverCache {
+    dir: PathBuf,
+}
+
+impl CoverCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// `cache_dir()/covers`.
+    pub fn default_dir() -> Option<PathBuf> {
+        session::cache_dir().map(|dir| dir.join("covers"))
+    }
+
+    /// Where the cover of `url` is cached.
+    pub fn path_for(&self, url: &str) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        url.hash(&mut hasher);
+        self.dir.join(format!("{:016x}", hasher.finish()))
+    }
+
+    /// The cover behind `url`, from the cache when it's been fetched before.
+    pub async fn fetch(&self, url: &str) -> Result<Bytes, CoverError> {
+        let path = self.path_for(url);
+        if let Ok(cached) = fs::read(&path) {
+            return Ok(Bytes::from(cached));
+        }
+
+        let resp = reqwest::get(url).await.map_err(CoverError::Request)?;
+        if !resp.status().is_success() {
+            return Err(CoverError::Status(resp.status().as_u16()));
+        }
+        let bytes = resp.bytes().await.map_err(CoverError::Request)?;
+        if !is_image(&bytes) {
+            return Err(CoverError::Decode);
+        }
+
+        fs::create_dir_all(&self.dir).map_err(CoverError::Cache)?;
+        fs::write(&path, &bytes).map_err(CoverError::Cache)?;
+        Ok(bytes)
+    }
+}
+
+fn is_image(buf: &[u8]) -> bool {
+    let webp = buf.len() >= 12 && &buf[..4] == b"RIFF" && &buf[8..12] == b"WEBP";
+    webp || [&b"\x89PNG\r\n\x1a\n"[..], &b"\xFF\xD8\xFF"[..], &b"GIF8"[..]]
+        .iter()
+        .any(|magic| buf.starts_with(magic))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::{Read, Write},
+        net::TcpListener,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    use super::{CoverCache, CoverError};
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR";
+
+    // answers every request with `status` and `body`, counting the requests
+    fn serve(status: &'static str, body: &'static [u8]) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/cover.png", listener.local_addr().unwrap());
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        thread::spawn(move || {
+            for mut stream in listener.incoming().filter_map(Result::ok) {
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf);
+                counter.fetch_add(1, Ordering::SeqCst);
+                let head = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                );
+                let _ = stream.write_all(head.as_bytes());
+                let _ = stream.write_all(body);
+            }
+        });
+        (url, hits)
+    }
+
+    fn cache(name: &str) -> CoverCache {
+        let dir = std::env::temp_dir().join(format!("lekima-covers-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        CoverCache::new(dir)
+    }
+
+    #[tokio::test]
+    async fn test_fetch_cached() {
+        let (url, hits) = serve("200 OK", PNG);
+        let cache = cache("cached");
+
+        assert_eq!(&cache.fetch(&url).await.unwrap()[..], PNG);
+        assert_eq!(fs::read(cache.path_for(&url)).unwrap(), PNG);
+        assert_eq!(&cache.fetch(&url).await.unwrap()[..], PNG);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_fetch_not_found() {
+        let (url, _) = serve("404 Not Found", b"");
+        let cache = cache("missing");
+
+        assert!(matches!(cache.fetch(&url).await, Err(CoverError::Status(404))));
+        assert!(!cache.path_for(&url).exists());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_not_image() {
+        let (url, _) = serve("200 OK", b"<html></html>");
+        let cache = cache("html");
+
+        assert!(matches!(cache.fetch(&url).await, Err(CoverError::Decode)));
+        assert!(!cache.path_for(&url).exists());
+    }
+}
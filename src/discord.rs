@@ -0,0 +1,247 @@
+//! Discord Rich Presence showing the song playing. Only built with the
+//! `discord` feature. Discord not running is no error: the connection is
+//! retried in the background for as long as the handle lives.
+
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use discord_rich_presence::{activity, DiscordIpc, DiscordIpcClient};
+use log::{debug, warn};
+
+use crate::{
+    media::{MediaMetadata, NowPlaying},
+    player::PlaybackContext,
+    scrobble::unix_now,
+};
+
+// how often what's playing is checked
+const POLL: Duration = Duration::from_secs(1);
+// between attempts to reach Discord
+const RETRY: Duration = Duration::from_secs(15);
+// drift of the start time not worth an update, Discord limits how often
+// the activity may change
+const DRIFT_SECS: i64 = 2;
+
+/// The presence thread, it clears the activity and stops when this is dropped.
+pub struct DiscordHandle {
+    running: Arc<AtomicBool>,
+}
+
+impl Drop for DiscordHandle {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+}
+
+/// What the activity shows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Presence {
+    pub details: String,
+    // "Paused" while paused
+    pub state: Option<String>,
+    pub art_url: Option<String>,
+    // unix seconds the track would have started at and ends at, while playing
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+}
+
+impl Presence {
+    // the same as `other` but for the clock drifting a little
+    fn same(&self, other: &Presence) -> bool {
+        let near = |a: Option<i64>, b: Option<i64>| match (a, b) {
+            (Some(a), Some(b)) => (a - b).abs() <= DRIFT_SECS,
+            (a, b) => a == b,
+        };
+        self.details == other.details
+            && self.state == other.state
+            && self.art_url == other.art_url
+            && near(self.start, other.start)
+            && near(self.end, other.end)
+    }
+}
+
+/// The presence for `playing` at `now` unix seconds, `None` clears it.
+pub fn presence(
+    playing: Option<&MediaMetadata>,
+    paused: bool,
+    progress_ms: u64,
+    duration_ms: Option<u64>,
+    now: i64,
+) -> Option<Presence> {
+    let playing = playing?;
+    let mut details = format!("Listening to {}", playing.title);
+    if !playing.artists.is_empty() {
+        details += &format!(" — {}", playing.artists.join(", "));
+    }
+    let (start, end) = if paused {
+        (None, None)
+    } else {
+        let start = now - (progress_ms / 1000) as i64;
+        (Some(start), duration_ms.map(|ms| start + (ms / 1000) as i64))
+    };
+    Some(Presence {
+        details,
+        state: Some("Paused".to_owned()).filter(|_| paused),
+        art_url: playing.art_url.clone(),
+        start,
+        end,
+    })
+}
+
+/// Keeps the Discord activity of application `client_id` following
+/// `context` and `now_playing`, on its own thread.
+pub fn spawn(
+    client_id: String,
+    context: Arc<PlaybackContext>,
+    now_playing: NowPlaying,
+) -> io::Result<DiscordHandle> {
+    let running = Arc::new(AtomicBool::new(true));
+    let handle = DiscordHandle {
+        running: running.clone(),
+    };
+    thread::Builder::new()
+        .name("discord".to_owned())
+        .spawn(move || serve(&client_id, &context, &now_playing, &running))?;
+    Ok(handle)
+}
+
+fn serve(
+    client_id: &str,
+    context: &PlaybackContext,
+    now_playing: &NowPlaying,
+    running: &AtomicBool,
+) {
+    let mut client = None;
+    // when the last connection attempt failed
+    let mut failed_at: Option<Instant> = None;
+    // what the activity shows, `Some(None)` once cleared
+    let mut shown: Option<Option<Presence>> = None;
+    while running.load(Ordering::SeqCst) {
+        if client.is_none() && failed_at.map_or(true, |at| at.elapsed() >= RETRY) {
+            match connect(client_id) {
+                Ok(connected) => {
+                    client = Some(connected);
+                    failed_at = None;
+                    shown = None;
+                }
+                Err(e) => {
+                    // also when Discord just isn't running
+                    debug!("discord unreachable: {}", e);
+                    failed_at = Some(Instant::now());
+                }
+            }
+        }
+
+        if let Some(connected) = client.as_mut() {
+            let playing = now_playing.lock().ok().and_then(|playing| playing.clone());
+            let current = presence(
+                playing.as_ref(),
+                context.is_paused(),
+                context.progress_ms(),
+                context.duration_ms(),
+                unix_now() as i64,
+            );
+            let unchanged = match (&shown, &current) {
+                (Some(Some(shown)), Some(current)) => shown.same(current),
+                (Some(None), None) => true,
+                _ => false,
+            };
+            if !unchanged {
+                match show(connected, current.as_ref()) {
+                    Ok(()) => shown = Some(current),
+                    Err(e) => {
+                        warn!("discord presence lost: {}", e);
+                        let _ = connected.close();
+                        client = None;
+                        failed_at = Some(Instant::now());
+                    }
+                }
+            }
+        }
+        thread::sleep(POLL);
+    }
+    if let Some(mut client) = client {
+        let _ = client.clear_activity();
+        let _ = client.close();
+    }
+}
+
+fn connect(client_id: &str) -> Result<DiscordIpcClient, Box<dyn std::error::Error>> {
+    let mut client = DiscordIpcClient::new(client_id)?;
+    client.connect()?;
+    Ok(client)
+}
+
+fn show(
+    client: &mut DiscordIpcClient,
+    presence: Option<&Presence>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let presence = match presence {
+        Some(presence) => presence,
+        None => return client.clear_activity(),
+    };
+    let mut payload = activity::Activity::new().details(&presence.details);
+    if let Some(state) = &presence.state {
+        payload = payload.state(state);
+    }
+    if let Some(url) = &presence.art_url {
+        payload = payload.assets(activity::Assets::new().large_image(url));
+    }
+    if let Some(start) = presence.start {
+        let mut timestamps = activity::Timestamps::new().start(start);
+        if let Some(end) = presence.end {
+            timestamps = timestamps.end(end);
+        }
+        payload = payload.timestamps(timestamps);
+    }
+    client.set_activity(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::presence;
+    use crate::media::MediaMetadata;
+
+    fn playing() -> MediaMetadata {
+        MediaMetadata {
+            song_id: 3,
+            title: "晴天".to_owned(),
+            artists: vec!["Jay Chou".to_owned()],
+            art_url: Some("http://p1.music.126.net/cover.jpg".to_owned()),
+        }
+    }
+
+    #[test]
+    fn test_presence() {
+        let playing = playing();
+        let shown = presence(Some(&playing), false, 30_000, Some(269_000), 1_000).unwrap();
+        assert_eq!(shown.details, "Listening to 晴天 — Jay Chou");
+        assert_eq!(shown.state, None);
+        assert_eq!(shown.art_url, playing.art_url);
+        assert_eq!((shown.start, shown.end), (Some(970), Some(1_239)));
+
+        let paused = presence(Some(&playing), true, 30_000, Some(269_000), 1_000).unwrap();
+        assert_eq!(paused.state.as_deref(), Some("Paused"));
+        assert_eq!((paused.start, paused.end), (None, None));
+
+        assert_eq!(presence(None, false, 0, None, 1_000), None);
+    }
+
+    #[test]
+    fn test_drift() {
+        let playing = playing();
+        let shown = presence(Some(&playing), false, 30_000, Some(269_000), 1_000).unwrap();
+        let later = presence(Some(&playing), false, 31_000, Some(269_000), 1_002).unwrap();
+        assert!(shown.same(&later));
+        // a seek moves the start well past the drift
+        let seeked = presence(Some(&playing), false, 90_000, Some(269_000), 1_002).unwrap();
+        assert!(!shown.same(&seeked));
+    }
+}
@@ -0,0 +1,1169 @@
+//! The music service answering the io worker, swappable for a fake in tests.
+
+#[cfg(test)]
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::PathBuf;
+use std::sync::mpsc::Receiver;
+
+use bytes::Bytes;
+use ncmapi::types::{LyricResp, Playlist, PlaylistDetailResp, RecommendedSongsResp, SearchSongResp, Song, SongUrlResp, UserProfile};
+use ncmapi::{NcmApi, ResourceType, SearchType};
+use serde_json::{json, Value};
+use tokio::runtime::{Builder, Runtime};
+
+use log::warn;
+
+use super::{spawn_worker, DataEvent, IoEvent, LoginStatus};
+use crate::app::{Comment, CommentSort, Podcast, PodcastEpisode, SearchResult};
+use crate::cover::CoverCache;
+use crate::session;
+
+/// Comments fetched per page.
+pub const COMMENT_PAGE: usize = 20;
+
+// episodes listed of a podcast, the newest
+const PODCAST_EPISODES: usize = 100;
+
+// `op` of `/playlist/tracks` adding songs, and deleting them
+const PLAYLIST_ADD: u8 = 1;
+const PLAYLIST_DEL: u8 = 0;
+
+/// The requests the worker needs, errors are reported as plain text.
+pub trait MusicBackend {
+    fn search(
+        &mut self,
+        key: &str,
+        kind: SearchType,
+        offset: usize,
+        limit: usize,
+    ) -> Result<SearchResult, String>;
+    fn playlist_detail(&mut self, id: usize) -> Result<Vec<Song>, String>;
+    /// A page of `COMMENT_PAGE` comments of `id`, and whether more follow.
+    /// Latest comments go on from `before`, the time of the last one loaded.
+    fn comments(
+        &mut self,
+        id: usize,
+        kind: ResourceType,
+        sort: CommentSort,
+        offset: usize,
+        before: Option<i64>,
+    ) -> Result<(Vec<Comment>, bool), String>;
+    /// A `None` url when the song can't be played, e.g. VIP only.
+    fn song_urls(&mut self, ids: &[usize]) -> Result<Vec<(usize, Option<String>)>, String>;
+    fn lyric(&mut self, id: usize) -> Result<String, String>;
+    fn like(&mut self, id: usize, like: bool) -> Result<(), String>;
+    /// Ids of the songs the logged in user liked.
+    fn liked_songs(&mut self) -> Result<Vec<usize>, String>;
+    fn recommended_songs(&mut self) -> Result<Vec<Song>, String>;
+    /// The next few songs of personal FM.
+    fn personal_fm(&mut self) -> Result<Vec<Song>, String>;
+    /// Never plays `id` in personal FM again.
+    fn fm_trash(&mut self, id: usize) -> Result<(), String>;
+    /// Counts a play of `id`, listened to long enough, in the user's records.
+    fn scrobble(&mut self, id: usize) -> Result<(), String>;
+    /// Episodes of the podcast `id`, newest first.
+    fn podcast_episodes(&mut self, id: usize) -> Result<Vec<PodcastEpisode>, String>;
+    fn login(&mut self, phone: &str, passwd: &str) -> Result<UserProfile, String>;
+    /// Playlists of the logged in user.
+    fn user_playlists(&mut self) -> Result<Vec<Playlist>, String>;
+    /// Fails for songs already in the playlist.
+    fn add_to_playlist(&mut self, playlist_id: usize, song_ids: &[usize]) -> Result<(), String>;
+    fn remove_from_playlist(
+        &mut self,
+        playlist_id: usize,
+        song_ids: &[usize],
+    ) -> Result<(), String>;
+    fn download(&mut self, url: &str) -> Result<Bytes, String>;
+    /// The album cover at `url`, fetched only once.
+    fn cover(&mut self, url: &str) -> Result<Bytes, String>;
+    /// Whether the session kept from the last login is still good.
+    fn refresh_login(&mut self) -> LoginStatus;
+    /// Ends the session and forgets it.
+    fn logout(&mut self) -> Result<(), String>;
+}
+
+/// Answers `evt` through `backend`, `None` for events it doesn't cover.
+pub fn handle<B: MusicBackend>(backend: &mut B, evt: IoEvent) -> Option<DataEvent> {
+    let awaited = evt.awaits_reply();
+    let data = match evt {
+        IoEvent::Search {
+            key,
+            kind,
+            offset,
+            limit,
+        } => backend
+            .search(&key, kind, offset, limit)
+            .map(DataEvent::SearchResults),
+        IoEvent::PlaylistDetail(id) => backend
+            .playlist_detail(id)
+            .map(|songs| DataEvent::PlaylistTracks(id, songs)),
+        IoEvent::Comments {
+            id,
+            kind,
+            sort,
+            offset,
+            before,
+        } => {
+            let page = backend.comments(id, kind.clone(), sort, offset, before);
+            return Some(match page {
+                Ok((comments, more)) => DataEvent::Comments {
+                    id,
+                    kind,
+                    sort,
+                    offset,
+                    comments,
+                    more,
+                },
+                Err(reason) => DataEvent::CommentsFailed {
+                    id,
+                    kind,
+                    sort,
+                    offset,
+                    reason,
+                },
+            });
+        }
+        IoEvent::SongUrls(ids) => backend.song_urls(&ids).map(DataEvent::SongUrls),
+        IoEvent::Lyric(id) => {
+            let lyric = backend.lyric(id);
+            return Some(match lyric {
+                Ok(lyric) => DataEvent::Lyric(id, lyric),
+                Err(reason) => DataEvent::LyricFailed(id, reason),
+            });
+        }
+        IoEvent::Fav(id) => return like(backend, id, true),
+        IoEvent::Unfav(id) => return like(backend, id, false),
+        IoEvent::LikedSongs => backend.liked_songs().map(DataEvent::LikedSongs),
+        IoEvent::RecommendedSongs => backend.recommended_songs().map(DataEvent::RecommendedSongs),
+        IoEvent::PersonalFm => {
+            let fm = backend.personal_fm();
+            return Some(fm.map_or_else(DataEvent::PersonalFmFailed, DataEvent::PersonalFm));
+        }
+        IoEvent::FmTrash(id) => return backend.fm_trash(id).err().map(DataEvent::Error),
+        IoEvent::ScrobblePlay(id) => return backend.scrobble(id).err().map(DataEvent::Error),
+        IoEvent::PodcastAudios(id) => backend
+            .podcast_episodes(id)
+            .map(|episodes| DataEvent::PodcastEpisodes(id, episodes)),
+        IoEvent::Signin(phone, passwd) => backend
+            .login(&phone, &passwd)
+            .map(|user| DataEvent::LoginStatus(LoginStatus::Valid(user))),
+        IoEvent::UserPlaylists => backend.user_playlists().map(DataEvent::UserPlaylists),
+        IoEvent::AddToPlaylist {
+            playlist_id,
+            song_ids,
+        } => {
+            let added = backend.add_to_playlist(playlist_id, &song_ids);
+            return Some(match added {
+                Ok(()) => DataEvent::AddedToPlaylist {
+                    playlist_id,
+                    song_ids,
+                },
+                Err(reason) => DataEvent::AddToPlaylistFailed {
+                    playlist_id,
+                    reason,
+                },
+            });
+        }
+        IoEvent::RemoveFromPlaylist {
+            playlist_id,
+            song_ids,
+        } => {
+            let removed = backend.remove_from_playlist(playlist_id, &song_ids);
+            return Some(match removed {
+                Ok(()) => DataEvent::RemovedFromPlaylist {
+                    playlist_id,
+                    song_ids,
+                },
+                Err(reason) => DataEvent::RemoveFromPlaylistFailed {
+                    playlist_id,
+                    song_ids,
+                    reason,
+                },
+            });
+        }
+        IoEvent::RefreshLogin => Ok(DataEvent::LoginStatus(backend.refresh_login())),
+        IoEvent::Logout => return backend.logout().err().map(DataEvent::Error),
+        IoEvent::Download(id, url) => backend
+            .download(&url)
+            .map(|bytes| DataEvent::Track(id, bytes)),
+        IoEvent::FetchCover(url) => {
+            let cover = backend.cover(&url);
+            return Some(match cover {
+                Ok(bytes) => DataEvent::Cover { url, bytes },
+                Err(e) => {
+                    warn!("fetch cover {}: {}", url, e);
+                    DataEvent::CoverFailed(url)
+                }
+            });
+        }
+        _ => return None,
+    };
+    // only a failure of what's waited for counts as its reply
+    Some(data.unwrap_or_else(|e| {
+        if awaited {
+            DataEvent::RequestFailed(e)
+        } else {
+            DataEvent::Error(e)
+        }
+    }))
+}
+
+// nothing to answer unless it failed
+fn like<B: MusicBackend>(backend: &mut B, song_id: usize, like: bool) -> Option<DataEvent> {
+    let reason = backend.like(song_id, like).err()?;
+    Some(DataEvent::LikeFailed {
+        song_id,
+        like,
+        reason,
+    })
+}
+
+/// `spawn_worker` answering through `backend`.
+pub fn spawn_backend<B>(io_rx: Receiver<IoEvent>, mut backend: B) -> Receiver<DataEvent>
+where
+    B: MusicBackend + Send + 'static,
+{
+    spawn_worker(io_rx, move |evt| handle(&mut backend, evt))
+}
+
+/// NetEase Cloud Music through `ncmapi`, blocking on its own runtime.
+pub struct NcmBackend {
+    api: NcmApi,
+    runtime: Runtime,
+    // logged in user, whose playlists are listed
+    uid: Option<usize>,
+    // where the api keeps its cookies between launches
+    cookie_path: Option<PathBuf>,
+    // `None` when there's no directory to keep covers in
+    covers: Option<CoverCache>,
+}
+
+impl NcmBackend {
+    pub fn new(api: NcmApi) -> io::Result<Self> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        Ok(Self {
+            api,
+            runtime,
+            uid: None,
+            cookie_path: None,
+            covers: CoverCache::default_dir().map(CoverCache::new),
+        })
+    }
+
+    /// Keeps fetched covers in `dir` instead of `CoverCache::default_dir`.
+    pub fn with_cover_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.covers = Some(CoverCache::new(dir));
+        self
+    }
+
+    /// The cookie jar `api` was created with, checked on launch and removed on logout.
+    pub fn with_cookie_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.cookie_path = Some(path.into());
+        self
+    }
+
+    // profile the current cookies belong to
+    fn login_profile(&mut self) -> Option<UserProfile> {
+        let resp = self.runtime.block_on(self.api.login_status()).ok()?;
+        let mut resp: Value = serde_json::from_slice(resp.data()).ok()?;
+        let profile = resp.pointer_mut("/data/profile").map(Value::take)?;
+        let user: UserProfile = serde_json::from_value(profile).ok()?;
+        self.uid = Some(user.user_id);
+        Some(user)
+    }
+
+    // `/playlist/tracks` with `op`, which answers a refusal with a code
+    // other than 200, e.g. for songs already in the playlist
+    fn playlist_tracks(
+        &mut self,
+        playlist_id: usize,
+        op: u8,
+        song_ids: &[usize],
+    ) -> Result<(), String> {
+        let resp = self
+            .runtime
+            .block_on(self.api.playlist_tracks(playlist_id, op, song_ids.to_vec()))
+            .map_err(to_string)?;
+        let resp: Value = serde_json::from_slice(resp.data()).map_err(to_string)?;
+        match resp.get("code").and_then(Value::as_i64) {
+            Some(200) => Ok(()),
+            code => Err(resp
+                .get("message")
+                .and_then(Value::as_str)
+                .map_or_else(|| format!("refused with code {:?}", code), str::to_owned)),
+        }
+    }
+}
+
+fn to_string<E: ToString>(e: E) -> String {
+    e.to_string()
+}
+
+// `djRadios` of a search response, skipping entries without an id
+fn parse_podcasts(radios: &Value) -> Vec<Podcast> {
+    let radios = radios.as_array().map(Vec::as_slice).unwrap_or_default();
+    radios
+        .iter()
+        .filter_map(|radio| {
+            Some(Podcast {
+                id: radio.get("id")?.as_u64()? as usize,
+                name: radio.get("name").and_then(Value::as_str).unwrap_or_default().to_owned(),
+                dj: radio
+                    .pointer("/dj/nickname")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_owned(),
+            })
+        })
+        .collect()
+}
+
+// `programs` of a `/dj/program` response, skipping entries without an id
+// or a song to play
+fn parse_episodes(programs: &Value) -> Vec<PodcastEpisode> {
+    let programs = programs.as_array().map(Vec::as_slice).unwrap_or_default();
+    programs
+        .iter()
+        .filter_map(|program| {
+            Some(PodcastEpisode {
+                id: program.get("id")?.as_u64()? as usize,
+                song_id: program.pointer("/mainSong/id")?.as_u64()? as usize,
+                title: program.get("name").and_then(Value::as_str).unwrap_or_default().to_owned(),
+                duration_ms: program.get("duration").and_then(Value::as_u64).unwrap_or_default(),
+                publish_time: program.get("createTime").and_then(Value::as_i64).unwrap_or_default(),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+// `data` of a `/comment/new` response: (comments, whether more follow)
+fn parse_comments(data: &Value) -> (Vec<Comment>, bool) {
+    let comments = data.get("comments").and_then(Value::as_array).map(Vec::as_slice);
+    let comments = comments
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|comment| {
+            Some(Comment {
+                id: comment.get("commentId")?.as_u64()? as usize,
+                author: comment
+                    .pointer("/user/nickname")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_owned(),
+                content: comment.get("content").and_then(Value::as_str).unwrap_or_default().to_owned(),
+                liked_count: comment.get("likedCount").and_then(Value::as_u64).unwrap_or_default(),
+                time: comment.get("time").and_then(Value::as_i64).unwrap_or_default(),
+            })
+        })
+        .collect();
+    (comments, data.get("hasMore").and_then(Value::as_bool).unwrap_or(false))
+}
+
+impl MusicBackend for NcmBackend {
+    fn search(
+        &mut self,
+        key: &str,
+        kind: SearchType,
+        offset: usize,
+        limit: usize,
+    ) -> Result<SearchResult, String> {
+        // the api takes the kind as its numeric code
+        let code = match kind {
+            SearchType::Song => 1,
+            SearchType::Album => 10,
+            SearchType::Artist => 100,
+            SearchType::Playlist => 1000,
+            SearchType::Podcast => 1009,
+            _ => return Err(format!("can't search {:?}", kind)),
+        };
+        let opt = Some(json!({ "type": code, "offset": offset, "limit": limit }));
+        let resp = self.runtime.block_on(self.api.search(key, opt)).map_err(to_string)?;
+        if code == 1 {
+            let resp: SearchSongResp = serde_json::from_slice(resp.data()).map_err(to_string)?;
+            return Ok(SearchResult::Song(resp.result.songs));
+        }
+        let mut resp: Value = serde_json::from_slice(resp.data()).map_err(to_string)?;
+        // listed under a key of their own, missing when nothing was found
+        let mut found = |key: &str| {
+            resp.pointer_mut(&format!("/result/{}", key))
+                .map(Value::take)
+                .filter(|found| !found.is_null())
+                .unwrap_or_else(|| json!([]))
+        };
+        Ok(match code {
+            10 => SearchResult::Album(serde_json::from_value(found("albums")).map_err(to_string)?),
+            100 => SearchResult::Artist(serde_json::from_value(found("artists")).map_err(to_string)?),
+            1000 => {
+                SearchResult::Playlist(serde_json::from_value(found("playlists")).map_err(to_string)?)
+            }
+            _ => SearchResult::Podcast(parse_podcasts(&found("djRadios"))),
+        })
+    }
+
+    fn playlist_detail(&mut self, id: usize) -> Result<Vec<Song>, String> {
+        let resp = self
+            .runtime
+            .block_on(self.api.playlist_detail(id, None))
+            .map_err(to_string)?;
+        let resp: PlaylistDetailResp = serde_json::from_slice(resp.data()).map_err(to_string)?;
+        Ok(resp.playlist.map(|p| p.tracks).unwrap_or_default())
+    }
+
+    fn comments(
+        &mut self,
+        id: usize,
+        kind: ResourceType,
+        sort: CommentSort,
+        offset: usize,
+        before: Option<i64>,
+    ) -> Result<(Vec<Comment>, bool), String> {
+        let sort_type = match sort {
+            CommentSort::Hot => 2,
+            CommentSort::Latest => 3,
+        };
+        let mut opt = json!({
+            "pageNo": offset / COMMENT_PAGE + 1,
+            "pageSize": COMMENT_PAGE,
+            "sortType": sort_type,
+        });
+        if let Some(before) = before {
+            opt["cursor"] = json!(before);
+        }
+        let resp = self
+            .runtime
+            .block_on(self.api.comment(id, kind, Some(opt)))
+            .map_err(to_string)?;
+        let resp: Value = serde_json::from_slice(resp.data()).map_err(to_string)?;
+        Ok(parse_comments(&resp["data"]))
+    }
+
+    fn song_urls(&mut self, ids: &[usize]) -> Result<Vec<(usize, Option<String>)>, String> {
+        let resp = self
+            .runtime
+            .block_on(self.api.song_url(&ids.to_vec()))
+            .map_err(to_string)?;
+        let resp: SongUrlResp = serde_json::from_slice(resp.data()).map_err(to_string)?;
+        Ok(resp
+            .data
+            .into_iter()
+            .map(|song| (song.id, Some(song.url).filter(|url| !url.is_empty())))
+            .collect())
+    }
+
+    fn lyric(&mut self, id: usize) -> Result<String, String> {
+        let resp = self.runtime.block_on(self.api.lyric(id)).map_err(to_string)?;
+        let resp: LyricResp = serde_json::from_slice(resp.data()).map_err(to_string)?;
+        Ok(resp.lrc.lyric)
+    }
+
+    fn like(&mut self, id: usize, like: bool) -> Result<(), String> {
+        let opt = Some(json!({ "like": like }));
+        self.runtime.block_on(self.api.like(id, opt)).map_err(to_string)?;
+        Ok(())
+    }
+
+    fn liked_songs(&mut self) -> Result<Vec<usize>, String> {
+        let uid = self.uid.ok_or_else(|| "not logged in".to_owned())?;
+        let resp = self.runtime.block_on(self.api.likelist(uid)).map_err(to_string)?;
+        let mut resp: Value = serde_json::from_slice(resp.data()).map_err(to_string)?;
+        let ids = resp.get_mut("ids").map(Value::take).unwrap_or_default();
+        serde_json::from_value(ids).map_err(to_string)
+    }
+
+    fn recommended_songs(&mut self) -> Result<Vec<Song>, String> {
+        let resp = self
+            .runtime
+            .block_on(self.api.recommend_songs())
+            .map_err(to_string)?;
+        let resp: RecommendedSongsResp = serde_json::from_slice(resp.data()).map_err(to_string)?;
+        Ok(resp.data.daily_songs)
+    }
+
+    fn personal_fm(&mut self) -> Result<Vec<Song>, String> {
+        let resp = self.runtime.block_on(self.api.personal_fm()).map_err(to_string)?;
+        let mut resp: Value = serde_json::from_slice(resp.data()).map_err(to_string)?;
+        let songs = resp.get_mut("data").map(Value::take).unwrap_or_default();
+        serde_json::from_value(songs).map_err(to_string)
+    }
+
+    fn podcast_episodes(&mut self, id: usize) -> Result<Vec<PodcastEpisode>, String> {
+        let opt = Some(json!({ "limit": PODCAST_EPISODES, "asc": false }));
+        let resp = self.runtime.block_on(self.api.dj_program(id, opt)).map_err(to_string)?;
+        let resp: Value = serde_json::from_slice(resp.data()).map_err(to_string)?;
+        Ok(parse_episodes(&resp["programs"]))
+    }
+
+    fn fm_trash(&mut self, id: usize) -> Result<(), String> {
+        self.runtime.block_on(self.api.fm_trash(id)).map_err(to_string)?;
+        Ok(())
+    }
+
+    fn scrobble(&mut self, id: usize) -> Result<(), String> {
+        // played from no playlist or album in particular
+        self.runtime.block_on(self.api.scrobble(id, 0)).map_err(to_string)?;
+        Ok(())
+    }
+
+    fn login(&mut self, phone: &str, passwd: &str) -> Result<UserProfile, String> {
+        // before the api writes the login to it
+        if let Some(path) = &self.cookie_path {
+            if let Err(e) = session::protect_cookie(path) {
+                warn!("protect cookie {:?} error: {}", path, e);
+            }
+        }
+        let resp = self
+            .runtime
+            .block_on(self.api.login_phone(phone, passwd))
+            .map_err(to_string)?;
+        let mut resp: Value = serde_json::from_slice(resp.data()).map_err(to_string)?;
+        let profile = resp
+            .get_mut("profile")
+            .map(Value::take)
+            .filter(|profile| !profile.is_null())
+            .ok_or_else(|| "wrong phone or password".to_owned())?;
+        let user: UserProfile = serde_json::from_value(profile).map_err(to_string)?;
+        self.uid = Some(user.user_id);
+        Ok(user)
+    }
+
+    fn user_playlists(&mut self) -> Result<Vec<Playlist>, String> {
+        let uid = self.uid.ok_or_else(|| "not logged in".to_owned())?;
+        let resp = self
+            .runtime
+            .block_on(self.api.user_playlist(uid, None))
+            .map_err(to_string)?;
+        let mut resp: Value = serde_json::from_slice(resp.data()).map_err(to_string)?;
+        let playlists = resp.get_mut("playlist").map(Value::take).unwrap_or_default();
+        serde_json::from_value(playlists).map_err(to_string)
+    }
+
+    fn add_to_playlist(&mut self, playlist_id: usize, song_ids: &[usize]) -> Result<(), String> {
+        self.playlist_tracks(playlist_id, PLAYLIST_ADD, song_ids)
+    }
+
+    fn remove_from_playlist(
+        &mut self,
+        playlist_id: usize,
+        song_ids: &[usize],
+    ) -> Result<(), String> {
+        self.playlist_tracks(playlist_id, PLAYLIST_DEL, song_ids)
+    }
+
+    fn download(&mut self, url: &str) -> Result<Bytes, String> {
+        let resp = reqwest::blocking::get(url).map_err(to_string)?;
+        resp.error_for_status().and_then(|resp| resp.bytes()).map_err(to_string)
+    }
+
+    fn cover(&mut self, url: &str) -> Result<Bytes, String> {
+        let covers = self.covers.as_ref().ok_or_else(|| "no cover cache".to_owned())?;
+        self.runtime.block_on(covers.fetch(url)).map_err(to_string)
+    }
+
+    fn refresh_login(&mut self) -> LoginStatus {
+        let path = match self.cookie_path.clone() {
+            Some(path) => path,
+            None => return LoginStatus::Expired,
+        };
+        session::refresh_login(&path, |_| self.login_profile())
+    }
+
+    fn logout(&mut self) -> Result<(), String> {
+        self.uid = None;
+        let logout = self.runtime.block_on(self.api.logout()).map(|_| ()).map_err(to_string);
+        if let Some(path) = &self.cookie_path {
+            session::clear_cookie(path).map_err(to_string)?;
+        }
+        logout
+    }
+}
+
+/// In memory backend for tests: fixed songs, urls and lyrics.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct FakeBackend {
+    pub songs: Vec<Song>,
+    pub playlists: HashMap<usize, Vec<Song>>,
+    pub urls: HashMap<usize, String>,
+    pub lyrics: HashMap<usize, String>,
+    pub comments: HashMap<usize, Vec<Comment>>,
+    pub liked: HashSet<usize>,
+    // left out of personal FM
+    pub trashed: HashSet<usize>,
+    // plays counted, oldest first
+    pub scrobbled: Vec<usize>,
+    // episodes by podcast id
+    pub podcasts: HashMap<usize, Vec<PodcastEpisode>>,
+    // (phone, password, profile) accepted by `login`
+    pub account: Option<(String, String, UserProfile)>,
+    // logged in through `login` and not logged out since
+    pub session: Option<UserProfile>,
+    pub user_playlists: Vec<Playlist>,
+    // track bytes by url
+    pub tracks: HashMap<String, Bytes>,
+    // cover bytes by url
+    pub covers: HashMap<String, Bytes>,
+}
+
+#[cfg(test)]
+impl MusicBackend for FakeBackend {
+    fn search(
+        &mut self,
+        key: &str,
+        kind: SearchType,
+        offset: usize,
+        limit: usize,
+    ) -> Result<SearchResult, String> {
+        match kind {
+            SearchType::Song => {
+                let found = self.songs.iter().filter(|s| s.name.contains(key));
+                Ok(SearchResult::Song(found.skip(offset).take(limit).cloned().collect()))
+            }
+            SearchType::Playlist => {
+                let found = self.user_playlists.iter().filter(|p| p.name.contains(key));
+                Ok(SearchResult::Playlist(found.skip(offset).take(limit).cloned().collect()))
+            }
+            _ => Err(format!("can't search {:?}", kind)),
+        }
+    }
+
+    fn playlist_detail(&mut self, id: usize) -> Result<Vec<Song>, String> {
+        self.playlists
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| format!("no playlist {}", id))
+    }
+
+    fn comments(
+        &mut self,
+        id: usize,
+        _kind: ResourceType,
+        sort: CommentSort,
+        offset: usize,
+        _before: Option<i64>,
+    ) -> Result<(Vec<Comment>, bool), String> {
+        let mut comments = self.comments.get(&id).cloned().unwrap_or_default();
+        match sort {
+            CommentSort::Hot => comments.sort_by(|a, b| b.liked_count.cmp(&a.liked_count)),
+            CommentSort::Latest => comments.sort_by(|a, b| b.time.cmp(&a.time)),
+        }
+        let more = comments.len() > offset + COMMENT_PAGE;
+        Ok((comments.into_iter().skip(offset).take(COMMENT_PAGE).collect(), more))
+    }
+
+    fn song_urls(&mut self, ids: &[usize]) -> Result<Vec<(usize, Option<String>)>, String> {
+        Ok(ids.iter().map(|id| (*id, self.urls.get(id).cloned())).collect())
+    }
+
+    fn lyric(&mut self, id: usize) -> Result<String, String> {
+        Ok(self.lyrics.get(&id).cloned().unwrap_or_default())
+    }
+
+    fn like(&mut self, id: usize, like: bool) -> Result<(), String> {
+        if !self.songs.iter().any(|song| song.id == id) {
+            return Err(format!("no song {}", id));
+        }
+        if like {
+            self.liked.insert(id);
+        } else {
+            self.liked.remove(&id);
+        }
+        Ok(())
+    }
+
+    fn liked_songs(&mut self) -> Result<Vec<usize>, String> {
+        Ok(self.liked.iter().copied().collect())
+    }
+
+    fn recommended_songs(&mut self) -> Result<Vec<Song>, String> {
+        Ok(self.songs.clone())
+    }
+
+    fn personal_fm(&mut self) -> Result<Vec<Song>, String> {
+        let trashed = &self.trashed;
+        Ok(self.songs.iter().filter(|s| !trashed.contains(&s.id)).cloned().collect())
+    }
+
+    fn fm_trash(&mut self, id: usize) -> Result<(), String> {
+        self.trashed.insert(id);
+        Ok(())
+    }
+
+    fn scrobble(&mut self, id: usize) -> Result<(), String> {
+        if !self.songs.iter().any(|song| song.id == id) {
+            return Err(format!("no song {}", id));
+        }
+        self.scrobbled.push(id);
+        Ok(())
+    }
+
+    fn podcast_episodes(&mut self, id: usize) -> Result<Vec<PodcastEpisode>, String> {
+        self.podcasts.get(&id).cloned().ok_or_else(|| format!("no podcast {}", id))
+    }
+
+    fn login(&mut self, phone: &str, passwd: &str) -> Result<UserProfile, String> {
+        match &self.account {
+            Some((p, pw, user)) if p == phone && pw == passwd => {
+                self.session = Some(user.clone());
+                Ok(user.clone())
+            }
+            _ => Err("wrong phone or password".to_owned()),
+        }
+    }
+
+    fn user_playlists(&mut self) -> Result<Vec<Playlist>, String> {
+        Ok(self.user_playlists.clone())
+    }
+
+    fn add_to_playlist(&mut self, playlist_id: usize, song_ids: &[usize]) -> Result<(), String> {
+        let songs = song_ids
+            .iter()
+            .map(|id| {
+                let song = self.songs.iter().find(|song| song.id == *id);
+                song.cloned().ok_or_else(|| format!("no song {}", id))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let tracks = self
+            .playlists
+            .get_mut(&playlist_id)
+            .ok_or_else(|| format!("no playlist {}", playlist_id))?;
+        if songs.iter().any(|song| tracks.iter().any(|track| track.id == song.id)) {
+            return Err("already in the playlist".to_owned());
+        }
+        tracks.extend(songs);
+        Ok(())
+    }
+
+    fn remove_from_playlist(
+        &mut self,
+        playlist_id: usize,
+        song_ids: &[usize],
+    ) -> Result<(), String> {
+        let tracks = self
+            .playlists
+            .get_mut(&playlist_id)
+            .ok_or_else(|| format!("no playlist {}", playlist_id))?;
+        if let Some(id) = song_ids.iter().find(|id| !tracks.iter().any(|song| song.id == **id)) {
+            return Err(format!("song {} isn't in the playlist", id));
+        }
+        tracks.retain(|song| !song_ids.contains(&song.id));
+        Ok(())
+    }
+
+    fn download(&mut self, url: &str) -> Result<Bytes, String> {
+        self.tracks.get(url).cloned().ok_or_else(|| format!("404 {}", url))
+    }
+
+    fn cover(&mut self, url: &str) -> Result<Bytes, String> {
+        self.covers.get(url).cloned().ok_or_else(|| format!("404 {}", url))
+    }
+
+    fn refresh_login(&mut self) -> LoginStatus {
+        match &self.session {
+            Some(user) => LoginStatus::Valid(user.clone()),
+            None => LoginStatus::Expired,
+        }
+    }
+
+    fn logout(&mut self) -> Result<(), String> {
+        self.session = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use ncmapi::types::{Playlist, Song, UserProfile};
+    use ncmapi::{ResourceType, SearchType};
+
+    use serde_json::json;
+
+    use super::{
+        handle, parse_comments, parse_episodes, parse_podcasts, spawn_backend, FakeBackend,
+        COMMENT_PAGE,
+    };
+    use crate::app::{Comment, CommentSort, Podcast, SearchResult};
+    use crate::event::{DataEvent, IoEvent, LoginStatus};
+
+    fn song(id: usize, name: &str) -> Song {
+        Song {
+            id,
+            name: name.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    fn backend() -> FakeBackend {
+        let mut backend = FakeBackend {
+            songs: vec![song(1, "foo"), song(2, "bar"), song(3, "foobar")],
+            ..Default::default()
+        };
+        backend.urls.insert(1, "http://fake/1.mp3".to_owned());
+        backend
+    }
+
+    #[test]
+    fn test_search() {
+        let (io_tx, io_rx) = mpsc::channel();
+        let data_rx = spawn_backend(io_rx, backend());
+
+        let search = |offset| IoEvent::Search {
+            key: "foo".to_owned(),
+            kind: SearchType::Song,
+            offset,
+            limit: 1,
+        };
+        for (offset, id) in [(0, 1), (1, 3)].iter() {
+            io_tx.send(search(*offset)).unwrap();
+            match data_rx.recv_timeout(Duration::from_secs(1)).unwrap() {
+                DataEvent::SearchResults(SearchResult::Song(songs)) => {
+                    assert_eq!(songs.iter().map(|s| s.id).collect::<Vec<_>>(), vec![*id]);
+                }
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_search_kinds() {
+        let mut backend = backend();
+        backend.user_playlists = vec![Playlist {
+            id: 5,
+            name: "foo mix".to_owned(),
+            ..Default::default()
+        }];
+        let search = |kind| IoEvent::Search {
+            key: "foo".to_owned(),
+            kind,
+            offset: 0,
+            limit: 10,
+        };
+        assert!(matches!(
+            handle(&mut backend, search(SearchType::Playlist)),
+            Some(DataEvent::SearchResults(SearchResult::Playlist(found))) if found.len() == 1 && found[0].id == 5
+        ));
+        assert!(matches!(
+            handle(&mut backend, search(SearchType::Album)),
+            Some(DataEvent::RequestFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_podcasts() {
+        let radios = json!([
+            { "id": 1, "name": "talk", "dj": { "nickname": "host" } },
+            { "name": "no id" },
+            { "id": 2 },
+        ]);
+        assert_eq!(
+            parse_podcasts(&radios),
+            vec![
+                Podcast {
+                    id: 1,
+                    name: "talk".to_owned(),
+                    dj: "host".to_owned(),
+                },
+                Podcast {
+                    id: 2,
+                    ..Default::default()
+                },
+            ]
+        );
+        assert!(parse_podcasts(&json!(null)).is_empty());
+    }
+
+    #[test]
+    fn test_parse_episodes() {
+        let programs = json!([
+            {
+                "id": 3,
+                "name": "ep 2",
+                "duration": 1_800_000,
+                "createTime": 1_600_000_000_000i64,
+                "mainSong": { "id": 30 },
+            },
+            { "id": 2, "name": "no song" },
+            { "mainSong": { "id": 10 } },
+            { "id": 1, "mainSong": { "id": 11 } },
+        ]);
+        let episodes = parse_episodes(&programs);
+        let ids: Vec<(usize, usize)> = episodes.iter().map(|ep| (ep.id, ep.song_id)).collect();
+        assert_eq!(ids, vec![(3, 30), (1, 11)]);
+        assert_eq!(episodes[0].title, "ep 2");
+        assert_eq!(episodes[0].duration_ms, 1_800_000);
+        assert_eq!(episodes[0].publish_time, 1_600_000_000_000);
+        assert!(!episodes[0].played && episodes[0].resume_ms.is_none());
+        assert!(parse_episodes(&json!(null)).is_empty());
+    }
+
+    #[test]
+    fn test_parse_comments() {
+        let data = json!({
+            "comments": [
+                {
+                    "commentId": 7,
+                    "user": { "nickname": "fan" },
+                    "content": "好听",
+                    "likedCount": 12,
+                    "time": 1_600_000_000_000i64,
+                },
+                { "content": "no id" },
+            ],
+            "hasMore": true,
+        });
+        let (comments, more) = parse_comments(&data);
+        assert_eq!(
+            comments,
+            vec![Comment {
+                id: 7,
+                author: "fan".to_owned(),
+                content: "好听".to_owned(),
+                liked_count: 12,
+                time: 1_600_000_000_000,
+            }]
+        );
+        assert!(more);
+        assert_eq!(parse_comments(&json!(null)), (vec![], false));
+    }
+
+    #[test]
+    fn test_comment_pages() {
+        let mut backend = backend();
+        let comments = (0..COMMENT_PAGE + 5)
+            .map(|i| Comment {
+                id: i,
+                liked_count: i as u64,
+                time: -(i as i64),
+                ..Default::default()
+            })
+            .collect();
+        backend.comments.insert(1, comments);
+        let page = |backend: &mut FakeBackend, sort, offset| {
+            let evt = IoEvent::Comments {
+                id: 1,
+                kind: ResourceType::Song,
+                sort,
+                offset,
+                before: None,
+            };
+            match handle(backend, evt) {
+                Some(DataEvent::Comments { comments, more, .. }) => (comments, more),
+                _ => panic!("no comments"),
+            }
+        };
+
+        // most liked first
+        let (hot, more) = page(&mut backend, CommentSort::Hot, 0);
+        assert_eq!(hot.len(), COMMENT_PAGE);
+        assert_eq!(hot[0].id, COMMENT_PAGE + 4);
+        assert!(more);
+        let (rest, more) = page(&mut backend, CommentSort::Hot, COMMENT_PAGE);
+        assert_eq!(rest.len(), 5);
+        assert!(!more);
+
+        let (latest, _) = page(&mut backend, CommentSort::Latest, 0);
+        assert_eq!(latest[0].id, 0);
+    }
+
+    #[test]
+    fn test_personal_fm() {
+        let mut backend = backend();
+        assert!(handle(&mut backend, IoEvent::FmTrash(2)).is_none());
+        let data = handle(&mut backend, IoEvent::PersonalFm);
+        assert!(matches!(
+            data,
+            Some(DataEvent::PersonalFm(songs)) if songs.iter().map(|s| s.id).collect::<Vec<_>>() == vec![1, 3]
+        ));
+    }
+
+    #[test]
+    fn test_scrobble_play() {
+        let mut backend = backend();
+        assert!(handle(&mut backend, IoEvent::ScrobblePlay(1)).is_none());
+        assert!(handle(&mut backend, IoEvent::ScrobblePlay(3)).is_none());
+        assert_eq!(backend.scrobbled, vec![1, 3]);
+        let missing = handle(&mut backend, IoEvent::ScrobblePlay(9));
+        assert!(matches!(missing, Some(DataEvent::Error(_))));
+    }
+
+    #[test]
+    fn test_song_urls() {
+        let mut backend = backend();
+        let data = handle(&mut backend, IoEvent::SongUrls(vec![1, 2]));
+        assert!(matches!(
+            data,
+            Some(DataEvent::SongUrls(urls))
+                if urls == vec![(1, Some("http://fake/1.mp3".to_owned())), (2, None)]
+        ));
+    }
+
+    #[test]
+    fn test_errors_and_likes() {
+        let mut backend = backend();
+        assert!(matches!(handle(&mut backend, IoEvent::PlaylistDetail(9)), Some(DataEvent::RequestFailed(_))));
+
+        assert!(handle(&mut backend, IoEvent::Fav(2)).is_none());
+        assert!(backend.liked.contains(&2));
+        assert!(handle(&mut backend, IoEvent::Unfav(2)).is_none());
+        assert!(backend.liked.is_empty());
+        assert!(matches!(
+            handle(&mut backend, IoEvent::Fav(9)),
+            Some(DataEvent::LikeFailed { song_id: 9, like: true, .. })
+        ));
+        handle(&mut backend, IoEvent::Fav(3));
+        assert!(matches!(handle(&mut backend, IoEvent::LikedSongs), Some(DataEvent::LikedSongs(ids)) if ids == vec![3]));
+
+        // left to other handlers
+        assert!(handle(&mut backend, IoEvent::UserCloud).is_none());
+    }
+
+    #[test]
+    fn test_signin_playlists_download() {
+        let mut backend = backend();
+        backend.account = Some(("123".to_owned(), "pw".to_owned(), UserProfile::default()));
+        backend.user_playlists = vec![Playlist::default()];
+        backend.tracks.insert("http://fake/1.mp3".to_owned(), Bytes::from_static(b"mp3"));
+
+        let wrong = handle(&mut backend, IoEvent::Signin("123".to_owned(), "nope".to_owned()));
+        assert!(matches!(wrong, Some(DataEvent::RequestFailed(_))));
+        let right = handle(&mut backend, IoEvent::Signin("123".to_owned(), "pw".to_owned()));
+        assert!(matches!(right, Some(DataEvent::LoginStatus(LoginStatus::Valid(_)))));
+
+        let playlists = handle(&mut backend, IoEvent::UserPlaylists);
+        assert!(matches!(playlists, Some(DataEvent::UserPlaylists(p)) if p.len() == 1));
+
+        let track = handle(&mut backend, IoEvent::Download(1, "http://fake/1.mp3".to_owned()));
+        assert!(matches!(track, Some(DataEvent::Track(1, bytes)) if bytes == "mp3"));
+        let missing = handle(&mut backend, IoEvent::Download(2, "http://fake/2.mp3".to_owned()));
+        assert!(matches!(missing, Some(DataEvent::RequestFailed(_))));
+    }
+
+    #[test]
+    fn test_awaited_events_answered() {
+        let mut backend = backend();
+        let events = vec![
+            IoEvent::Search {
+                key: "foo".to_owned(),
+                kind: SearchType::Song,
+                offset: 0,
+                limit: 10,
+            },
+            IoEvent::PlaylistDetail(9),
+            IoEvent::SongUrls(vec![1]),
+            IoEvent::Lyric(1),
+            IoEvent::LikedSongs,
+            IoEvent::RecommendedSongs,
+            IoEvent::PersonalFm,
+            IoEvent::Signin("123".to_owned(), "pw".to_owned()),
+            IoEvent::UserPlaylists,
+            IoEvent::RefreshLogin,
+            IoEvent::Download(2, "http://fake/2.mp3".to_owned()),
+            IoEvent::PodcastAudios(7),
+        ];
+        for evt in events {
+            assert!(evt.awaits_reply(), "{:?}", evt);
+            let data = handle(&mut backend, evt).unwrap();
+            assert!(data.is_reply(), "{:?}", data);
+        }
+        assert!(!IoEvent::Fav(2).awaits_reply());
+        assert!(handle(&mut backend, IoEvent::Fav(2)).is_none());
+    }
+
+    #[test]
+    fn test_add_to_playlist() {
+        let mut backend = backend();
+        backend.playlists.insert(5, vec![song(1, "foo")]);
+        let add = |playlist_id, song_ids| IoEvent::AddToPlaylist {
+            playlist_id,
+            song_ids,
+        };
+
+        assert!(matches!(
+            handle(&mut backend, add(5, vec![2, 3])),
+            Some(DataEvent::AddedToPlaylist { playlist_id: 5, song_ids }) if song_ids == vec![2, 3]
+        ));
+        let ids: Vec<usize> = backend.playlists[&5].iter().map(|song| song.id).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+
+        // duplicates are refused as a whole
+        assert!(matches!(
+            handle(&mut backend, add(5, vec![3, 2])),
+            Some(DataEvent::AddToPlaylistFailed { playlist_id: 5, .. })
+        ));
+        assert_eq!(backend.playlists[&5].len(), 3);
+        assert!(matches!(
+            handle(&mut backend, add(9, vec![1])),
+            Some(DataEvent::AddToPlaylistFailed { playlist_id: 9, .. })
+        ));
+    }
+
+    #[test]
+    fn test_remove_from_playlist() {
+        let mut backend = backend();
+        let tracks = vec![song(1, "foo"), song(2, "bar"), song(3, "foobar")];
+        backend.playlists.insert(5, tracks);
+        let remove = |playlist_id, song_ids| IoEvent::RemoveFromPlaylist {
+            playlist_id,
+            song_ids,
+        };
+
+        assert!(matches!(
+            handle(&mut backend, remove(5, vec![1, 3])),
+            Some(DataEvent::RemovedFromPlaylist { playlist_id: 5, song_ids })
+                if song_ids == vec![1, 3]
+        ));
+        let ids: Vec<usize> = backend.playlists[&5].iter().map(|song| song.id).collect();
+        assert_eq!(ids, vec![2]);
+
+        // the failure names what to put back
+        assert!(matches!(
+            handle(&mut backend, remove(5, vec![2, 3])),
+            Some(DataEvent::RemoveFromPlaylistFailed { playlist_id: 5, song_ids, .. })
+                if song_ids == vec![2, 3]
+        ));
+        assert_eq!(backend.playlists[&5].len(), 1);
+        assert!(matches!(
+            handle(&mut backend, remove(9, vec![1])),
+            Some(DataEvent::RemoveFromPlaylistFailed { playlist_id: 9, .. })
+        ));
+    }
+
+    #[test]
+    fn test_fetch_cover() {
+        let mut backend = backend();
+        backend.covers.insert("http://fake/1.jpg".to_owned(), Bytes::from_static(b"jpg"));
+
+        let cover = handle(&mut backend, IoEvent::FetchCover("http://fake/1.jpg".to_owned()));
+        assert!(matches!(
+            cover,
+            Some(DataEvent::Cover { url, bytes }) if url == "http://fake/1.jpg" && bytes == "jpg"
+        ));
+        let missing = handle(&mut backend, IoEvent::FetchCover("http://fake/2.jpg".to_owned()));
+        assert!(matches!(missing, Some(DataEvent::CoverFailed(url)) if url == "http://fake/2.jpg"));
+    }
+
+    fn logged_in(backend: &mut FakeBackend) -> bool {
+        match handle(backend, IoEvent::RefreshLogin) {
+            Some(DataEvent::LoginStatus(status)) => matches!(status, LoginStatus::Valid(_)),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_session_kept_until_logout() {
+        let mut backend = backend();
+        assert!(!logged_in(&mut backend));
+
+        backend.account = Some(("123".to_owned(), "pw".to_owned(), UserProfile::default()));
+        handle(&mut backend, IoEvent::Signin("123".to_owned(), "pw".to_owned()));
+        assert!(logged_in(&mut backend));
+
+        assert!(handle(&mut backend, IoEvent::Logout).is_none());
+        assert!(!logged_in(&mut backend));
+    }
+}
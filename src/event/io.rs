@@ -1,4 +1,7 @@
-use ncmapi::{ResourceType, SearchType};
+use bytes::Bytes;
+use ncmapi::{types::{Playlist, Song, UserProfile}, ResourceType, SearchType};
+
+use crate::app::{Comment, CommentSort, PodcastEpisode, SearchResult};
 
 #[derive(Debug)]
 pub enum IoEvent {
@@ -7,15 +10,189 @@ pub enum IoEvent {
     UserPlaylists,
     RecommendedSongs,
     RecommendedPlaylists,
+    PersonalFm,
     PlaylistDetail(usize),
-    Search(String, SearchType),
-    Comments(usize, ResourceType),
+    // a page of `limit` results, skipping the first `offset`
+    Search {
+        key: String,
+        kind: SearchType,
+        offset: usize,
+        limit: usize,
+    },
+    // a page of comments skipping the first `offset`, `before` the time of the
+    // last comment loaded
+    Comments {
+        id: usize,
+        kind: ResourceType,
+        sort: CommentSort,
+        offset: usize,
+        before: Option<i64>,
+    },
     SongUrls(Vec<usize>),
     Fav(usize),
+    Unfav(usize),
+    // ids of the songs the user liked
+    LikedSongs,
+    // never recommend this song in personal FM again
+    FmTrash(usize),
     Lyric(usize),
     UserPodCasts(usize),
     PodcastAudios(usize),
     UserCloud,
     RecentlyPlayed,
     ArtistSublist,
+    // download a resolved song url: (song id, url)
+    Download(usize, String),
+    // check the session stored on disk
+    RefreshLogin,
+    // end the session and remove it from disk
+    Logout,
+    AddToPlaylist {
+        playlist_id: usize,
+        song_ids: Vec<usize>,
+    },
+    RemoveFromPlaylist {
+        playlist_id: usize,
+        song_ids: Vec<usize>,
+    },
+    // the song was listened to long enough to count as a play
+    ScrobblePlay(usize),
+    // album cover at the given picUrl
+    FetchCover(String),
+}
+
+impl IoEvent {
+    /// Whether the worker always answers with one `DataEvent`, for which the
+    /// app shows it's loading until `DataEvent::is_reply` arrives.
+    pub fn awaits_reply(&self) -> bool {
+        matches!(
+            self,
+            IoEvent::Search { .. }
+                | IoEvent::PlaylistDetail(_)
+                | IoEvent::Comments { .. }
+                | IoEvent::SongUrls(_)
+                | IoEvent::Lyric(_)
+                | IoEvent::LikedSongs
+                | IoEvent::RecommendedSongs
+                | IoEvent::PersonalFm
+                | IoEvent::Signin(..)
+                | IoEvent::UserPlaylists
+                | IoEvent::RefreshLogin
+                | IoEvent::Download(..)
+                | IoEvent::PodcastAudios(_)
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum LoginStatus {
+    Valid(UserProfile),
+    // missing, unreadable or rejected by the api
+    Expired,
+}
+
+/// Results sent back from the io worker to the app.
+#[derive(Debug)]
+pub enum DataEvent {
+    // a page of the results of `IoEvent::Search`
+    SearchResults(SearchResult),
+    // today's recommendations
+    RecommendedSongs(Vec<Song>),
+    RecentlyPlayed(Vec<Song>),
+    // a page of the comments of `id`, `more` when there are more pages
+    Comments {
+        id: usize,
+        kind: ResourceType,
+        sort: CommentSort,
+        offset: usize,
+        comments: Vec<Comment>,
+        more: bool,
+    },
+    // the page of `IoEvent::Comments` asked for with these couldn't be fetched
+    CommentsFailed {
+        id: usize,
+        kind: ResourceType,
+        sort: CommentSort,
+        offset: usize,
+        reason: String,
+    },
+    // the next batch of personal FM
+    PersonalFm(Vec<Song>),
+    // the next batch couldn't be fetched, with why
+    PersonalFmFailed(String),
+    // playlists of the logged in user
+    UserPlaylists(Vec<Playlist>),
+    // (playlist id, tracks)
+    PlaylistTracks(usize, Vec<Song>),
+    // (song id, playable url), no url for VIP only or region locked songs
+    SongUrls(Vec<(usize, Option<String>)>),
+    // (song id, raw audio bytes)
+    Track(usize, Bytes),
+    // (song id, lrc text)
+    Lyric(usize, String),
+    // (song id, why it couldn't be fetched)
+    LyricFailed(usize, String),
+    LikedSongs(Vec<usize>),
+    // the api refused to like (or unlike) the song, the local state is rolled back
+    LikeFailed {
+        song_id: usize,
+        like: bool,
+        reason: String,
+    },
+    // (podcast id, episodes)
+    PodcastEpisodes(usize, Vec<PodcastEpisode>),
+    LoginStatus(LoginStatus),
+    AddedToPlaylist {
+        playlist_id: usize,
+        song_ids: Vec<usize>,
+    },
+    // the api refuses e.g. songs already in the playlist
+    AddToPlaylistFailed {
+        playlist_id: usize,
+        reason: String,
+    },
+    RemovedFromPlaylist {
+        playlist_id: usize,
+        song_ids: Vec<usize>,
+    },
+    RemoveFromPlaylistFailed {
+        playlist_id: usize,
+        song_ids: Vec<usize>,
+        reason: String,
+    },
+    Cover {
+        url: String,
+        bytes: Bytes,
+    },
+    // not found or not an image, the placeholder is shown instead
+    CoverFailed(String),
+    // an event that `awaits_reply` failed
+    RequestFailed(String),
+    // anything else that failed
+    Error(String),
+}
+
+impl DataEvent {
+    /// Whether this answers an event that `awaits_reply`, also when it failed.
+    pub fn is_reply(&self) -> bool {
+        matches!(
+            self,
+            DataEvent::SearchResults(_)
+                | DataEvent::PlaylistTracks(..)
+                | DataEvent::Comments { .. }
+                | DataEvent::CommentsFailed { .. }
+                | DataEvent::SongUrls(_)
+                | DataEvent::Lyric(..)
+                | DataEvent::LyricFailed(..)
+                | DataEvent::LikedSongs(_)
+                | DataEvent::RecommendedSongs(_)
+                | DataEvent::PersonalFm(_)
+                | DataEvent::PersonalFmFailed(_)
+                | DataEvent::LoginStatus(_)
+                | DataEvent::UserPlaylists(_)
+                | DataEvent::Track(..)
+                | DataEvent::PodcastEpisodes(..)
+                | DataEvent::RequestFailed(_)
+        )
+    }
 }
@@ -1,53 +1,310 @@
+mod backend;
 mod io;
 
-pub use io::IoEvent;
+#[cfg(test)]
+pub use backend::FakeBackend;
+pub use backend::{handle, spawn_backend, MusicBackend, NcmBackend};
+pub use io::{DataEvent, IoEvent, LoginStatus};
 
 use std::{
-    io::stdin,
-    sync::mpsc::{self, Receiver},
-    thread,
+    io::{self, stdin},
+    sync::mpsc::{self, Receiver, RecvError, RecvTimeoutError, Sender, TryRecvError},
+    sync::atomic::{AtomicU64, Ordering},
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
     time::Duration,
 };
 
-use termion::{event::Key, input::TermRead};
+use signal_hook::{consts::SIGWINCH, iterator::Handle, iterator::Signals};
+use termion::{
+    async_stdin,
+    event::{Event as TermEvent, Key, MouseEvent},
+    input::TermRead,
+    terminal_size,
+};
+
+// how often stdin is checked for keys, short enough not to be noticed
+const STDIN_POLL: Duration = Duration::from_millis(10);
+// a burst of resizes, e.g. dragging the window border, is reported once over
+const RESIZE_DEBOUNCE: Duration = Duration::from_millis(50);
 
 pub enum Event<I> {
     Input(I),
+    /// clicks, drags and the wheel, reported while the terminal is in mouse mode
+    Mouse(MouseEvent),
+    /// the terminal is now this many columns and rows
+    Resize(u16, u16),
     Tick,
 }
 
-pub fn poll(tick_rate: Duration) -> Receiver<Event<Key>> {
+// what the app handles of a terminal event, `None` for what it doesn't
+fn from_termion(evt: TermEvent) -> Option<Event<Key>> {
+    match evt {
+        TermEvent::Key(key) => Some(Event::Input(key)),
+        TermEvent::Mouse(mouse) => Some(Event::Mouse(mouse)),
+        TermEvent::Unsupported(_) => None,
+    }
+}
+
+// wakes the polling threads up when it's time for them to exit
+#[derive(Default)]
+struct Shutdown {
+    stopped: Mutex<bool>,
+    cvar: Condvar,
+}
+
+impl Shutdown {
+    fn is_set(&self) -> bool {
+        *self.stopped.lock().unwrap()
+    }
+
+    fn set(&self) {
+        *self.stopped.lock().unwrap() = true;
+        self.cvar.notify_all();
+    }
+
+    // sleeps for `dur` or until set, `true` once set
+    fn wait(&self, dur: Duration) -> bool {
+        let stopped = self.stopped.lock().unwrap();
+        let (stopped, _) = self.cvar.wait_timeout_while(stopped, dur, |s| !*s).unwrap();
+        *stopped
+    }
+}
+
+/// Keys and ticks from the polling threads, `shutdown` stops them.
+pub struct EventStream {
+    rx: Receiver<Event<Key>>,
+    shutdown: Arc<Shutdown>,
+    // milliseconds between ticks, read by the ticker before every tick
+    tick_ms: Arc<AtomicU64>,
+    // stops the resize thread waiting for signals
+    resize: Option<Handle>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl EventStream {
+    /// Ticks faster, e.g. while animating, or slower to idle. It applies
+    /// from the tick after the next one.
+    pub fn set_tick_rate(&self, tick_rate: Duration) {
+        self.tick_ms.store(tick_rate.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub fn tick_rate(&self) -> Duration {
+        Duration::from_millis(self.tick_ms.load(Ordering::Relaxed))
+    }
+
+    pub fn recv(&self) -> Result<Event<Key>, RecvError> {
+        self.rx.recv()
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Event<Key>, RecvTimeoutError> {
+        self.rx.recv_timeout(timeout)
+    }
+
+    pub fn try_recv(&self) -> Result<Event<Key>, TryRecvError> {
+        self.rx.try_recv()
+    }
+
+    /// Tells the polling threads to exit and waits until they did.
+    pub fn shutdown(self) {
+        self.shutdown.set();
+        if let Some(resize) = &self.resize {
+            resize.close();
+        }
+        drop(self.rx);
+        for thread in self.threads {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Keys and mouse events from stdin and a tick every `tick_rate`. Stdin is
+/// read without blocking so `shutdown` doesn't wait for a key press. The
+/// terminal only reports the mouse through a `termion::input::MouseTerminal`.
+pub fn poll(tick_rate: Duration) -> EventStream {
     let (tx, rx) = mpsc::channel();
-    thread::spawn(move || {
-        let std = stdin();
-        for res in std.keys() {
-            if let Ok(e) = res {
-                tx.send(Event::Input(e)).unwrap();
+    let shutdown = Arc::new(Shutdown::default());
+    let input = {
+        let (tx, shutdown) = (tx.clone(), shutdown.clone());
+        thread::spawn(move || {
+            let mut events = async_stdin().events();
+            while !shutdown.is_set() {
+                match events.next() {
+                    Some(Ok(evt)) => match from_termion(evt) {
+                        Some(evt) if tx.send(evt).is_err() => break,
+                        _ => {}
+                    },
+                    Some(Err(_)) => {}
+                    None if shutdown.wait(STDIN_POLL) => break,
+                    None => {}
+                }
             }
+        })
+    };
+    let mut threads = vec![input];
+    let resize = match spawn_resize(tx.clone(), terminal_size) {
+        Ok((handle, thread)) => {
+            threads.push(thread);
+            Some(handle)
         }
-    });
+        Err(e) => {
+            log::error!("no resize events: {}", e);
+            None
+        }
+    };
+    let tick_ms = Arc::new(AtomicU64::new(tick_rate.as_millis() as u64));
+    threads.push(spawn_ticker(Ticker { tx }, tick_ms.clone(), shutdown.clone()));
+    EventStream {
+        rx,
+        shutdown,
+        tick_ms,
+        resize,
+        threads,
+    }
+}
+
+/// Same as `poll`, but keys are read from `input` instead of stdin. An
+/// `input` blocking for its next key holds `shutdown` up until it comes.
+pub fn poll_from<I>(input: I, tick_rate: Duration) -> EventStream
+where
+    I: Iterator<Item = Key> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel();
+    let shutdown = Arc::new(Shutdown::default());
+    let reader = {
+        let (tx, shutdown) = (tx.clone(), shutdown.clone());
+        thread::spawn(move || {
+            for key in input {
+                if shutdown.is_set() || tx.send(Event::Input(key)).is_err() {
+                    break;
+                }
+            }
+        })
+    };
+    // another thread to generate tick events
+    let tick_ms = Arc::new(AtomicU64::new(tick_rate.as_millis() as u64));
+    let ticker = spawn_ticker(Ticker { tx }, tick_ms.clone(), shutdown.clone());
+    EventStream {
+        rx,
+        shutdown,
+        tick_ms,
+        resize: None,
+        threads: vec![reader, ticker],
+    }
+}
 
-    let (tx1, rx1) = mpsc::channel();
-    let _tx1 = tx1.clone();
-    thread::spawn(move || loop {
-        if let Ok(e) = rx.recv() {
-            _tx1.send(e).unwrap();
+// sends the terminal `size` after every burst of SIGWINCH until the handle is closed
+fn spawn_resize<F>(tx: Sender<Event<Key>>, size: F) -> io::Result<(Handle, JoinHandle<()>)>
+where
+    F: Fn() -> io::Result<(u16, u16)> + Send + 'static,
+{
+    let mut signals = Signals::new(&[SIGWINCH])?;
+    let handle = signals.handle();
+    let thread = thread::spawn(move || {
+        while signals.forever().next().is_some() {
+            thread::sleep(RESIZE_DEBOUNCE);
+            while signals.pending().next().is_some() {
+                thread::sleep(RESIZE_DEBOUNCE);
+            }
+            let (cols, rows) = match size() {
+                Ok(size) => size,
+                Err(_) => continue,
+            };
+            if tx.send(Event::Resize(cols, rows)).is_err() {
+                break;
+            }
         }
     });
+    Ok((handle, thread))
+}
 
-    // another thread to generate tick events
-    thread::spawn(move || loop {
-        tx1.send(Event::Tick).unwrap();
-        thread::sleep(tick_rate);
+// ticks like `Ticker::spawn`, at whatever `tick_ms` is now, until `shutdown`
+fn spawn_ticker(
+    ticker: Ticker,
+    tick_ms: Arc<AtomicU64>,
+    shutdown: Arc<Shutdown>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        while ticker.tick() {
+            let tick_rate = Duration::from_millis(tick_ms.load(Ordering::Relaxed));
+            if shutdown.wait(tick_rate) {
+                break;
+            }
+        }
+    })
+}
+
+/// Key events from stdin only, the caller drives ticks through the `Ticker`.
+pub fn poll_input_only() -> (Receiver<Event<Key>>, Ticker) {
+    poll_input_only_from(stdin().keys().filter_map(Result::ok))
+}
+
+pub fn poll_input_only_from<I>(input: I) -> (Receiver<Event<Key>>, Ticker)
+where
+    I: Iterator<Item = Key> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel();
+    let ticker = Ticker { tx: tx.clone() };
+    thread::spawn(move || {
+        for key in input {
+            if tx.send(Event::Input(key)).is_err() {
+                break;
+            }
+        }
     });
+    (rx, ticker)
+}
 
-    rx1
+/// Pushes `Event::Tick` into an event channel, by hand or on a timer.
+#[derive(Clone)]
+pub struct Ticker {
+    tx: Sender<Event<Key>>,
+}
+
+impl Ticker {
+    /// Sends one tick, `false` once nobody listens anymore.
+    pub fn tick(&self) -> bool {
+        self.tx.send(Event::Tick).is_ok()
+    }
+
+    /// Ticks every `tick_rate` on its own thread until the receiver is dropped.
+    pub fn spawn(self, tick_rate: Duration) -> JoinHandle<()> {
+        thread::spawn(move || {
+            while self.tick() {
+                thread::sleep(tick_rate);
+            }
+        })
+    }
+}
+
+/// Runs `handler` for every `IoEvent` on a worker thread and forwards the
+/// produced `DataEvent`s to the returned receiver.
+pub fn spawn_worker<F>(io_rx: Receiver<IoEvent>, mut handler: F) -> Receiver<DataEvent>
+where
+    F: FnMut(IoEvent) -> Option<DataEvent> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        while let Ok(e) = io_rx.recv() {
+            if let Some(data) = handler(e) {
+                if tx.send(data).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+    rx
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{poll, Event};
-    use std::time::Duration;
+    use super::{from_termion, poll, poll_from, poll_input_only_from, spawn_resize, Event};
+    use std::sync::mpsc;
+    use std::time::{Duration, Instant};
+
+    use signal_hook::{consts::SIGWINCH, low_level::raise};
+
+    use termion::event::{Event as TermEvent, Key, MouseButton, MouseEvent};
 
     #[test]
     fn test_poll() {
@@ -57,8 +314,79 @@ mod tests {
                 Event::Input(key) => {
                     println!("{:#?}", key);
                 }
+                Event::Mouse(mouse) => println!("{:#?}", mouse),
+                Event::Resize(cols, rows) => println!("resized to {}x{}", cols, rows),
                 Event::Tick => println!("tick!"),
             }
         }
     }
+
+    #[test]
+    fn test_poll_input_only() {
+        let (evts, ticker) = poll_input_only_from(vec![Key::Char('a'), Key::Char('b')].into_iter());
+
+        assert!(matches!(evts.recv(), Ok(Event::Input(Key::Char('a')))));
+        assert!(matches!(evts.recv(), Ok(Event::Input(Key::Char('b')))));
+        // no tick thread behind it
+        assert!(evts.recv_timeout(Duration::from_millis(200)).is_err());
+
+        assert!(ticker.tick());
+        assert!(matches!(evts.recv(), Ok(Event::Tick)));
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let events = poll_from(vec![Key::Char('a')].into_iter(), Duration::from_secs(3600));
+        assert!(matches!(events.recv(), Ok(Event::Tick) | Ok(Event::Input(_))));
+
+        // the ticker is asleep for an hour, it's woken up
+        let started = Instant::now();
+        events.shutdown();
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_set_tick_rate() {
+        let events = poll_from(std::iter::empty(), Duration::from_millis(300));
+        assert!(matches!(events.recv(), Ok(Event::Tick)));
+        events.set_tick_rate(Duration::from_millis(20));
+        assert_eq!(events.tick_rate(), Duration::from_millis(20));
+        // the pending 300ms tick, then the fast ones
+        assert!(matches!(events.recv(), Ok(Event::Tick)));
+        let fast = Instant::now();
+        for _ in 0..5 {
+            assert!(matches!(events.recv(), Ok(Event::Tick)));
+        }
+        assert!(fast.elapsed() < Duration::from_millis(250), "{:?}", fast.elapsed());
+        events.shutdown();
+    }
+
+    #[test]
+    fn test_from_termion() {
+        let wheel = MouseEvent::Press(MouseButton::WheelDown, 3, 7);
+        assert!(matches!(
+            from_termion(TermEvent::Mouse(wheel)),
+            Some(Event::Mouse(MouseEvent::Press(MouseButton::WheelDown, 3, 7)))
+        ));
+        assert!(matches!(
+            from_termion(TermEvent::Key(Key::Char('q'))),
+            Some(Event::Input(Key::Char('q')))
+        ));
+        assert!(from_termion(TermEvent::Unsupported(vec![27])).is_none());
+    }
+
+    #[test]
+    fn test_resize_debounced() {
+        let (tx, rx) = mpsc::channel();
+        let (handle, thread) = spawn_resize(tx, || Ok((120, 40))).unwrap();
+        for _ in 0..20 {
+            raise(SIGWINCH).unwrap();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(matches!(rx.recv_timeout(Duration::from_secs(1)), Ok(Event::Resize(120, 40))));
+        assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+
+        handle.close();
+        thread.join().unwrap();
+    }
 }
@@ -0,0 +1,137 @@
+//! Subsequence fuzzy matching, scored roughly like fzf: consecutive matches
+//! and matches at the start of words are worth more, gaps cost a little.
+
+const SCORE_MATCH: i64 = 16;
+const BONUS_CONSECUTIVE: i64 = 16;
+const BONUS_START: i64 = 24;
+const BONUS_BOUNDARY: i64 = 8;
+const PENALTY_GAP: i64 = 1;
+
+// stays far from overflow when penalties are subtracted
+const NONE: i64 = i64::MIN / 2;
+
+fn fold(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+fn bonus(text: &[char], j: usize) -> i64 {
+    if j == 0 {
+        return BONUS_START;
+    }
+    let prev = text[j - 1];
+    if prev.is_whitespace() || (prev.is_ascii_punctuation() && prev != '\'') {
+        BONUS_BOUNDARY
+    } else {
+        0
+    }
+}
+
+/// Scores `text` against `pattern`, case-insensitively. `None` when `pattern`
+/// isn't a subsequence of `text`; an empty pattern matches everything with 0.
+pub fn score(pattern: &str, text: &str) -> Option<i64> {
+    let pattern: Vec<char> = pattern.chars().map(fold).collect();
+    let text: Vec<char> = text.chars().map(fold).collect();
+    if pattern.is_empty() {
+        return Some(0);
+    }
+    if pattern.len() > text.len() {
+        return None;
+    }
+
+    // prev[j]: best score with the previous pattern char matched at text[j]
+    let mut prev: Vec<i64> = text
+        .iter()
+        .enumerate()
+        .map(|(j, &c)| {
+            if c == pattern[0] {
+                SCORE_MATCH + bonus(&text, j)
+            } else {
+                NONE
+            }
+        })
+        .collect();
+
+    for &p in &pattern[1..] {
+        let mut cur = vec![NONE; text.len()];
+        // best prev[k] for k < j, minus the gap between k and j
+        let mut run = NONE;
+        for j in 1..text.len() {
+            run = (run - PENALTY_GAP).max(prev[j - 1]);
+            if text[j] != p || run < NONE / 2 {
+                continue;
+            }
+            let consecutive = if prev[j - 1] < NONE / 2 {
+                NONE
+            } else {
+                prev[j - 1] + BONUS_CONSECUTIVE
+            };
+            cur[j] = SCORE_MATCH + bonus(&text, j) + run.max(consecutive);
+        }
+        prev = cur;
+    }
+
+    prev.into_iter().filter(|&s| s > NONE / 2).max()
+}
+
+/// Scores every candidate, dropping the ones that don't match. Results are
+/// `(index, score)` sorted by score descending, then by index.
+pub fn find<'a, I>(pattern: &str, candidates: I) -> Vec<(usize, i64)>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut found: Vec<(usize, i64)> = candidates
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, text)| score(pattern, text).map(|s| (i, s)))
+        .collect();
+    found.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find, score};
+
+    #[test]
+    fn test_prefix_beats_scattered() {
+        let prefix = score("love", "Love Story").unwrap();
+        let scattered = score("love", "Lost Over Vega Echo").unwrap();
+        assert!(prefix > scattered);
+    }
+
+    #[test]
+    fn test_consecutive_beats_gaps() {
+        let consecutive = score("sun", "Sunday").unwrap();
+        let gaps = score("sun", "Shut Up Now").unwrap();
+        let mid = score("sun", "A sunny day").unwrap();
+        assert!(consecutive > mid);
+        assert!(mid > gaps);
+    }
+
+    #[test]
+    fn test_not_matching() {
+        assert_eq!(score("xyz", "Love Story"), None);
+        assert_eq!(score("yrots", "Love Story"), None);
+        assert_eq!(score("long pattern", "short"), None);
+    }
+
+    #[test]
+    fn test_cjk() {
+        assert!(score("晴天", "晴天").is_some());
+        assert!(score("周伦", "周杰伦").is_some());
+        assert!(score("晴天", "周杰伦").is_none());
+        assert!(score("晴天", "晴天") > score("晴天", "晴朗的天"));
+    }
+
+    #[test]
+    fn test_find_sorted_and_filtered() {
+        let titles = ["Lost Over Vega Echo", "Hello", "Love Story", "lover"];
+        let found = find("love", titles.iter().copied());
+        let indices: Vec<usize> = found.iter().map(|(i, _)| *i).collect();
+
+        assert_eq!(indices.len(), 3);
+        assert!(!indices.contains(&1));
+        assert_eq!(indices[2], 0);
+        assert!(found.windows(2).all(|w| w[0].1 >= w[1].1));
+    }
+}
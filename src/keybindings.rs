@@ -0,0 +1,553 @@
+//! Which key triggers which action, read from `<config dir>/keys.toml`.
+//!
+//! Every entry names an action and the key, or list of keys, for it:
+//!
+//! ```toml
+//! quit = "ctrl-q"
+//! select_next = ["j", "down"]
+//! ```
+//!
+//! An action listed there loses its default keys, the others keep theirs.
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use termion::event::Key;
+
+use crate::session;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    // to the previous screen
+    Back,
+    ResumeCheckpoint,
+    Search,
+    // type to narrow the track table down
+    FilterTracks,
+    Lyrics,
+    // of the playing song, hot or latest first
+    Comments,
+    ToggleCommentSort,
+    // pages of search results
+    NextPage,
+    PrevPage,
+    SelectNext,
+    SelectPrev,
+    PlaySelected,
+    AddToPlaylist,
+    PlayPause,
+    NextTrack,
+    PrevTrack,
+    ToggleShuffle,
+    // the playing song
+    ToggleLike,
+    // personal FM, and never playing its current song again
+    ToggleFm,
+    FmTrash,
+    VolumeUp,
+    VolumeDown,
+    // the list of what every key does
+    Help,
+}
+
+/// The groups of actions in the help.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Playback,
+    Navigation,
+    Library,
+}
+
+impl Category {
+    pub const ALL: [Category; 3] = [Category::Playback, Category::Navigation, Category::Library];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::Playback => "Playback",
+            Category::Navigation => "Navigation",
+            Category::Library => "Library",
+        }
+    }
+}
+
+impl Action {
+    pub const ALL: [Action; 24] = [
+        Action::Quit,
+        Action::Back,
+        Action::ResumeCheckpoint,
+        Action::Search,
+        Action::FilterTracks,
+        Action::Lyrics,
+        Action::Comments,
+        Action::ToggleCommentSort,
+        Action::NextPage,
+        Action::PrevPage,
+        Action::SelectNext,
+        Action::SelectPrev,
+        Action::PlaySelected,
+        Action::AddToPlaylist,
+        Action::PlayPause,
+        Action::NextTrack,
+        Action::PrevTrack,
+        Action::ToggleShuffle,
+        Action::ToggleLike,
+        Action::ToggleFm,
+        Action::FmTrash,
+        Action::VolumeUp,
+        Action::VolumeDown,
+        Action::Help,
+    ];
+
+    /// The name used in the config file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Back => "back",
+            Action::ResumeCheckpoint => "resume_checkpoint",
+            Action::Search => "search",
+            Action::FilterTracks => "filter_tracks",
+            Action::Lyrics => "lyrics",
+            Action::Comments => "comments",
+            Action::ToggleCommentSort => "toggle_comment_sort",
+            Action::NextPage => "next_page",
+            Action::PrevPage => "prev_page",
+            Action::SelectNext => "select_next",
+            Action::SelectPrev => "select_prev",
+            Action::PlaySelected => "play_selected",
+            Action::AddToPlaylist => "add_to_playlist",
+            Action::PlayPause => "play_pause",
+            Action::NextTrack => "next_track",
+            Action::PrevTrack => "prev_track",
+            Action::ToggleShuffle => "toggle_shuffle",
+            Action::ToggleLike => "toggle_like",
+            Action::ToggleFm => "toggle_fm",
+            Action::FmTrash => "fm_trash",
+            Action::VolumeUp => "volume_up",
+            Action::VolumeDown => "volume_down",
+            Action::Help => "help",
+        }
+    }
+
+    /// What it does, as told in the help.
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::Quit => "Quit",
+            Action::Back => "Back to the previous screen",
+            Action::ResumeCheckpoint => "Resume where the last session stopped",
+            Action::Search => "Search",
+            Action::FilterTracks => "Filter the tracks listed",
+            Action::Lyrics => "Lyrics of the playing song",
+            Action::Comments => "Comments on the playing song",
+            Action::ToggleCommentSort => "Hot or latest comments first",
+            Action::NextPage => "Next page",
+            Action::PrevPage => "Previous page",
+            Action::SelectNext => "Select the next row",
+            Action::SelectPrev => "Select the previous row",
+            Action::PlaySelected => "Play the selected row",
+            Action::AddToPlaylist => "Add the selected song to a playlist",
+            Action::PlayPause => "Play or pause",
+            Action::NextTrack => "Next track",
+            Action::PrevTrack => "Previous track",
+            Action::ToggleShuffle => "Shuffle on or off",
+            Action::ToggleLike => "Like or unlike the playing song",
+            Action::ToggleFm => "Personal FM on or off",
+            Action::FmTrash => "Never play this FM song again",
+            Action::VolumeUp => "Volume up",
+            Action::VolumeDown => "Volume down",
+            Action::Help => "This help",
+        }
+    }
+
+    pub fn category(self) -> Category {
+        match self {
+            Action::ResumeCheckpoint
+            | Action::PlaySelected
+            | Action::PlayPause
+            | Action::NextTrack
+            | Action::PrevTrack
+            | Action::ToggleShuffle
+            | Action::ToggleFm
+            | Action::VolumeUp
+            | Action::VolumeDown => Category::Playback,
+            Action::Quit
+            | Action::Back
+            | Action::Search
+            | Action::FilterTracks
+            | Action::Lyrics
+            | Action::Comments
+            | Action::ToggleCommentSort
+            | Action::NextPage
+            | Action::PrevPage
+            | Action::SelectNext
+            | Action::SelectPrev
+            | Action::Help => Category::Navigation,
+            Action::AddToPlaylist | Action::ToggleLike | Action::FmTrash => Category::Library,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|action| action.name() == name)
+    }
+
+    fn default_keys(self) -> &'static [Key] {
+        match self {
+            Action::Quit => &[Key::Char('q')],
+            Action::Back => &[Key::Esc],
+            Action::ResumeCheckpoint => &[Key::Char('R')],
+            Action::Search => &[Key::Char('/')],
+            Action::FilterTracks => &[Key::Ctrl('f')],
+            Action::Lyrics => &[Key::Char('l')],
+            Action::Comments => &[Key::Char('c')],
+            Action::ToggleCommentSort => &[Key::Char('o')],
+            Action::NextPage => &[Key::Char(']')],
+            Action::PrevPage => &[Key::Char('[')],
+            Action::SelectNext => &[Key::Char('j'), Key::Down],
+            Action::SelectPrev => &[Key::Char('k'), Key::Up],
+            Action::PlaySelected => &[Key::Char('\n')],
+            Action::AddToPlaylist => &[Key::Char('a')],
+            Action::PlayPause => &[Key::Char(' ')],
+            Action::NextTrack => &[Key::Char('n')],
+            Action::PrevTrack => &[Key::Char('p')],
+            Action::ToggleShuffle => &[Key::Ctrl('s')],
+            Action::ToggleLike => &[Key::Char('f')],
+            Action::ToggleFm => &[Key::Char('F')],
+            Action::FmTrash => &[Key::Char('t')],
+            Action::VolumeUp => &[Key::Char('+'), Key::Char('=')],
+            Action::VolumeDown => &[Key::Char('-')],
+            Action::Help => &[Key::Char('?')],
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum KeyBindingsError {
+    Read(io::Error),
+    Parse(toml::de::Error),
+    // the file isn't a table of action = key(s)
+    Invalid(String),
+    UnknownAction(String),
+    UnknownKey { action: Action, key: String },
+    // `key` was bound to both actions
+    Conflict { key: Key, first: Action, second: Action },
+}
+
+impl std::fmt::Display for KeyBindingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyBindingsError::Read(e) => write!(f, "read key bindings: {}", e),
+            KeyBindingsError::Parse(e) => write!(f, "parse key bindings: {}", e),
+            KeyBindingsError::Invalid(entry) => {
+                write!(f, "{}: expected a key or a list of keys", entry)
+            }
+            KeyBindingsError::UnknownAction(name) => write!(f, "unknown action {:?}", name),
+            KeyBindingsError::UnknownKey { action, key } => {
+                write!(f, "{}: unknown key {:?}", action.name(), key)
+            }
+            KeyBindingsError::Conflict { key, first, second } => write!(
+                f,
+                "{} is bound to both {} and {}",
+                key_name(*key),
+                first.name(),
+                second.name()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for KeyBindingsError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyBindings {
+    actions: HashMap<Key, Action>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let bindings = Action::ALL
+            .iter()
+            .map(|&action| (action, action.default_keys().to_vec()));
+        // the defaults are known not to conflict
+        Self::from_bindings(bindings).unwrap()
+    }
+}
+
+impl KeyBindings {
+    /// `<config dir>/keys.toml`.
+    pub fn default_path() -> Option<PathBuf> {
+        session::config_dir().map(|dir| dir.join("keys.toml"))
+    }
+
+    /// The bindings in `path`, just the defaults when there's no such file.
+    pub fn load(path: &Path) -> Result<Self, KeyBindingsError> {
+        match fs::read_to_string(path) {
+            Ok(raw) => Self::from_toml(&raw),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(KeyBindingsError::Read(e)),
+        }
+    }
+
+    pub fn from_toml(raw: &str) -> Result<Self, KeyBindingsError> {
+        let table = match raw.parse::<toml::Value>().map_err(KeyBindingsError::Parse)? {
+            toml::Value::Table(table) => table,
+            _ => return Err(KeyBindingsError::Invalid("key bindings".to_owned())),
+        };
+
+        let mut configured = HashMap::new();
+        for (name, value) in &table {
+            let action = Action::from_name(name)
+                .ok_or_else(|| KeyBindingsError::UnknownAction(name.clone()))?;
+            let names = match value {
+                toml::Value::String(key) => vec![key.as_str()],
+                toml::Value::Array(keys) => keys
+                    .iter()
+                    .map(|key| key.as_str())
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| KeyBindingsError::Invalid(name.clone()))?,
+                _ => return Err(KeyBindingsError::Invalid(name.clone())),
+            };
+            let keys = names
+                .into_iter()
+                .map(|key| {
+                    parse_key(key).ok_or_else(|| KeyBindingsError::UnknownKey {
+                        action,
+                        key: key.to_owned(),
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            configured.insert(action, keys);
+        }
+
+        let bindings = Action::ALL.iter().map(|&action| {
+            let keys = configured
+                .remove(&action)
+                .unwrap_or_else(|| action.default_keys().to_vec());
+            (action, keys)
+        });
+        Self::from_bindings(bindings)
+    }
+
+    fn from_bindings(
+        bindings: impl Iterator<Item = (Action, Vec<Key>)>,
+    ) -> Result<Self, KeyBindingsError> {
+        let mut actions = HashMap::new();
+        for (action, keys) in bindings {
+            for key in keys {
+                match actions.insert(key, action) {
+                    Some(first) if first != action => {
+                        return Err(KeyBindingsError::Conflict {
+                            key,
+                            first,
+                            second: action,
+                        })
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(Self { actions })
+    }
+
+    /// What pressing `key` does, if anything.
+    pub fn action(&self, key: Key) -> Option<Action> {
+        self.actions.get(&key).copied()
+    }
+
+    /// Every key bound to `action`, for showing in help texts.
+    pub fn keys(&self, action: Action) -> Vec<Key> {
+        let mut keys: Vec<Key> = self
+            .actions
+            .iter()
+            .filter(|(_, &bound)| bound == action)
+            .map(|(&key, _)| key)
+            .collect();
+        keys.sort_by_key(|&key| key_name(key));
+        keys
+    }
+
+    /// Every action and its keys by category, in the order of `Action::ALL`.
+    /// Unbound actions are left out.
+    pub fn help(&self) -> Vec<(Category, Vec<(Action, Vec<Key>)>)> {
+        Category::ALL
+            .iter()
+            .map(|&category| {
+                let actions = Action::ALL
+                    .iter()
+                    .filter(|action| action.category() == category)
+                    .map(|&action| (action, self.keys(action)))
+                    .filter(|(_, keys)| !keys.is_empty())
+                    .collect();
+                (category, actions)
+            })
+            .collect()
+    }
+}
+
+/// Reads `"q"`, `"space"`, `"enter"`, `"ctrl-x"`, `"alt-x"`, `"f5"` and the like.
+pub fn parse_key(name: &str) -> Option<Key> {
+    let single = |s: &str| {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+    if let Some(c) = single(name) {
+        return Some(Key::Char(c));
+    }
+    let lower = name.to_ascii_lowercase();
+    if let Some(rest) = lower.strip_prefix("ctrl-") {
+        return single(rest).map(Key::Ctrl);
+    }
+    if let Some(rest) = name.get(4..).filter(|_| lower.starts_with("alt-")) {
+        return single(rest).map(Key::Alt);
+    }
+    if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse().ok()) {
+        return Some(Key::F(n));
+    }
+    let key = match lower.as_str() {
+        "space" => Key::Char(' '),
+        "enter" => Key::Char('\n'),
+        "tab" => Key::Char('\t'),
+        "backtab" => Key::BackTab,
+        "esc" => Key::Esc,
+        "backspace" => Key::Backspace,
+        "delete" => Key::Delete,
+        "insert" => Key::Insert,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        _ => return None,
+    };
+    Some(key)
+}
+
+/// The name `parse_key` reads back as `key`.
+pub fn key_name(key: Key) -> String {
+    match key {
+        Key::Char(' ') => "space".to_owned(),
+        Key::Char('\n') => "enter".to_owned(),
+        Key::Char('\t') => "tab".to_owned(),
+        Key::Char(c) => c.to_string(),
+        Key::Ctrl(c) => format!("ctrl-{}", c),
+        Key::Alt(c) => format!("alt-{}", c),
+        Key::F(n) => format!("f{}", n),
+        Key::BackTab => "backtab".to_owned(),
+        Key::Esc => "esc".to_owned(),
+        Key::Backspace => "backspace".to_owned(),
+        Key::Delete => "delete".to_owned(),
+        Key::Insert => "insert".to_owned(),
+        Key::Up => "up".to_owned(),
+        Key::Down => "down".to_owned(),
+        Key::Left => "left".to_owned(),
+        Key::Right => "right".to_owned(),
+        Key::Home => "home".to_owned(),
+        Key::End => "end".to_owned(),
+        Key::PageUp => "pageup".to_owned(),
+        Key::PageDown => "pagedown".to_owned(),
+        _ => format!("{:?}", key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use termion::event::Key;
+
+    use super::{key_name, parse_key, Action, Category, KeyBindings, KeyBindingsError};
+
+    #[test]
+    fn test_defaults() {
+        let keys = KeyBindings::default();
+        assert_eq!(keys.action(Key::Char('q')), Some(Action::Quit));
+        assert_eq!(keys.action(Key::Down), Some(Action::SelectNext));
+        assert_eq!(keys.action(Key::Char(' ')), Some(Action::PlayPause));
+        assert_eq!(keys.action(Key::Char('z')), None);
+        assert_eq!(keys.keys(Action::SelectNext), vec![Key::Down, Key::Char('j')]);
+    }
+
+    #[test]
+    fn test_help() {
+        let keys = KeyBindings::from_toml("volume_down = []").unwrap();
+        let help = keys.help();
+        let categories: Vec<Category> = help.iter().map(|(category, _)| *category).collect();
+        assert_eq!(categories, Category::ALL);
+        let (_, playback) = &help[0];
+        assert_eq!(playback[2], (Action::PlayPause, vec![Key::Char(' ')]));
+        // unbound
+        assert!(playback.iter().all(|(action, _)| *action != Action::VolumeDown));
+        let listed: usize = help.iter().map(|(_, actions)| actions.len()).sum();
+        assert_eq!(listed, Action::ALL.len() - 1);
+    }
+
+    #[test]
+    fn test_parse_key() {
+        for name in &["q", "space", "enter", "ctrl-x", "alt-X", "f5", "pagedown", "-"] {
+            let key = parse_key(name).unwrap();
+            assert_eq!(parse_key(&key_name(key)), Some(key));
+        }
+        assert_eq!(parse_key("ctrl-x"), Some(Key::Ctrl('x')));
+        assert_eq!(parse_key("Alt-X"), Some(Key::Alt('X')));
+        assert_eq!(parse_key("F12"), Some(Key::F(12)));
+        assert_eq!(parse_key("ctrl-"), None);
+        assert_eq!(parse_key("hyper-x"), None);
+        assert_eq!(parse_key(""), None);
+    }
+
+    #[test]
+    fn test_from_toml_replaces_defaults() {
+        let raw = r#"
+            quit = "ctrl-q"
+            select_next = ["n", "down"]
+            next_track = "N"
+        "#;
+        let keys = KeyBindings::from_toml(raw).unwrap();
+        assert_eq!(keys.action(Key::Ctrl('q')), Some(Action::Quit));
+        assert_eq!(keys.action(Key::Char('q')), None);
+        assert_eq!(keys.action(Key::Char('n')), Some(Action::SelectNext));
+        assert_eq!(keys.action(Key::Char('j')), None);
+        assert_eq!(keys.action(Key::Char('N')), Some(Action::NextTrack));
+        assert_eq!(keys.action(Key::Char('/')), Some(Action::Search));
+    }
+
+    #[test]
+    fn test_from_toml_errors() {
+        // `n` is still next_track's default
+        match KeyBindings::from_toml("select_next = \"n\"") {
+            Err(KeyBindingsError::Conflict { key, first, second }) => {
+                assert_eq!(key, Key::Char('n'));
+                assert_eq!((first, second), (Action::SelectNext, Action::NextTrack));
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(
+            KeyBindings::from_toml("dance = \"d\""),
+            Err(KeyBindingsError::UnknownAction(_))
+        ));
+        assert!(matches!(
+            KeyBindings::from_toml("quit = \"hyper-q\""),
+            Err(KeyBindingsError::UnknownKey { action: Action::Quit, .. })
+        ));
+        assert!(matches!(
+            KeyBindings::from_toml("quit = 1"),
+            Err(KeyBindingsError::Invalid(_))
+        ));
+        assert!(matches!(
+            KeyBindings::from_toml("quit = "),
+            Err(KeyBindingsError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        let path = std::env::temp_dir().join(format!("lekima-keys-{}.toml", std::process::id()));
+        assert_eq!(KeyBindings::load(&path).unwrap(), KeyBindings::default());
+    }
+}
@@ -0,0 +1,24 @@
+pub mod app;
+pub mod browser;
+pub mod cache;
+pub mod checkpoint;
+pub mod clipboard;
+pub mod config;
+pub mod cover;
+#[cfg(feature = "discord")]
+pub mod discord;
+pub mod event;
+pub mod fuzzy;
+pub mod keybindings;
+pub mod logger;
+pub mod lyric;
+pub mod m3u;
+pub mod media;
+#[cfg(all(feature = "mpris", target_os = "linux"))]
+pub mod mpris;
+pub mod player;
+pub mod scrobble;
+pub mod session;
+pub mod theme;
+pub mod ui;
+pub mod undo;
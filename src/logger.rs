@@ -0,0 +1,88 @@
+//! File backed logger. The terminal belongs to the tui, so log records
+//! must never go to stdout or stderr.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+pub struct FileLogger {
+    level: LevelFilter,
+    file: Mutex<File>,
+}
+
+impl FileLogger {
+    pub fn open(path: &Path, level: LevelFilter) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            level,
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        if let Ok(mut file) = self.file.lock() {
+            let _ = writeln!(
+                file,
+                "{} {:<5} {}: {}",
+                ts,
+                record.level(),
+                record.target(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.flush();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LoggerError {
+    Open(io::Error),
+    // a logger is installed already
+    Set(SetLoggerError),
+}
+
+impl std::fmt::Display for LoggerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoggerError::Open(e) => write!(f, "open log file: {}", e),
+            LoggerError::Set(e) => write!(f, "install logger: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LoggerError {}
+
+/// Installs the global logger writing to `path`. Only the first call in a process succeeds.
+pub fn init(path: &Path, level: LevelFilter) -> Result<(), LoggerError> {
+    let logger = FileLogger::open(path, level).map_err(LoggerError::Open)?;
+    log::set_boxed_logger(Box::new(logger)).map_err(LoggerError::Set)?;
+    log::set_max_level(level);
+    Ok(())
+}
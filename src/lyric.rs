@@ -0,0 +1,140 @@
+//! LRC lyrics: lines of text behind `[mm:ss.xx]` time tags.
+
+/// Parsed lyrics, in the order they're sung.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Lyric {
+    // (milliseconds into the track, text)
+    lines: Vec<(u64, String)>,
+    // whether any line had a time tag, unsynced lyrics don't follow playback
+    synced: bool,
+}
+
+impl Lyric {
+    pub fn parse(raw: &str) -> Self {
+        let mut lines = Vec::new();
+        let mut synced = false;
+        // time of the last tagged line, untimed lines stay after it
+        let mut last_at = 0;
+        for line in raw.lines() {
+            let (times, text) = split_tags(line.trim());
+            if times.is_empty() {
+                // metadata like `[ar:...]` only has tags and no text
+                if !text.is_empty() || !line.trim_start().starts_with('[') {
+                    lines.push((last_at, text.to_owned()));
+                }
+                continue;
+            }
+            synced = true;
+            last_at = times.iter().copied().min().unwrap_or(0);
+            for at in times {
+                lines.push((at, text.to_owned()));
+            }
+        }
+        // a line sung several times was listed once with all its tags
+        lines.sort_by_key(|(at, _)| *at);
+        Self { lines, synced }
+    }
+
+    pub fn lines(&self) -> &[(u64, String)] {
+        &self.lines
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.synced
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.iter().all(|(_, text)| text.trim().is_empty())
+    }
+
+    /// Index of the line sung at `progress_ms`, `None` before the first one or when unsynced.
+    pub fn current_line(&self, progress_ms: u64) -> Option<usize> {
+        if !self.synced {
+            return None;
+        }
+        let next = self.lines.partition_point(|(at, _)| *at <= progress_ms);
+        next.checked_sub(1)
+    }
+}
+
+// the times of the leading tags of `line` and the text after them
+fn split_tags(line: &str) -> (Vec<u64>, &str) {
+    let mut times = Vec::new();
+    let mut rest = line;
+    while let Some(tag) = rest.strip_prefix('[') {
+        let end = match tag.find(']') {
+            Some(end) => end,
+            None => break,
+        };
+        if let Some(at) = parse_time(&tag[..end]) {
+            times.push(at);
+        }
+        rest = &tag[end + 1..];
+    }
+    (times, rest.trim())
+}
+
+// `mm:ss`, `mm:ss.xx` or `mm:ss.xxx` in milliseconds
+fn parse_time(tag: &str) -> Option<u64> {
+    let (min, sec) = tag.split_once(':')?;
+    let min: u64 = min.parse().ok()?;
+    let (sec, frac) = match sec.split_once('.') {
+        Some((sec, frac)) => (sec, frac),
+        None => (sec, ""),
+    };
+    let sec: u64 = sec.parse().ok()?;
+    let frac_ms = match frac.len() {
+        0 => 0,
+        1..=3 => frac.parse::<u64>().ok()? * 10u64.pow(3 - frac.len() as u32),
+        _ => return None,
+    };
+    Some(min * 60_000 + sec * 1000 + frac_ms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Lyric;
+
+    #[test]
+    fn test_parse() {
+        let lyric = Lyric::parse(concat!(
+            "[ar:Jay Chou]\n[ti:晴天]\n",
+            "[00:01.50]故事的小黄花\n",
+            "[00:05.00][01:00.00]chorus\n",
+            "\n",
+            "[00:03.250]从出生那年",
+        ));
+        assert!(lyric.is_synced());
+        let lines: Vec<(u64, &str)> =
+            lyric.lines().iter().map(|(at, text)| (*at, text.as_str())).collect();
+        assert_eq!(
+            lines,
+            vec![
+                (1500, "故事的小黄花"),
+                (3250, "从出生那年"),
+                (5000, "chorus"),
+                (5000, ""),
+                (60_000, "chorus"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_current_line() {
+        let lyric = Lyric::parse("[00:01.00]one\n[00:02.00]two\n[00:03.00]three");
+        assert_eq!(lyric.current_line(500), None);
+        assert_eq!(lyric.current_line(1000), Some(0));
+        assert_eq!(lyric.current_line(2999), Some(1));
+        assert_eq!(lyric.current_line(90_000), Some(2));
+    }
+
+    #[test]
+    fn test_unsynced() {
+        let lyric = Lyric::parse("one\ntwo");
+        assert!(!lyric.is_synced());
+        assert_eq!(lyric.lines().len(), 2);
+        assert_eq!(lyric.current_line(5000), None);
+        assert!(Lyric::parse("[ti:nothing]\n").is_empty());
+        assert!(Lyric::parse("").is_empty());
+    }
+}
@@ -0,0 +1,116 @@
+//! Extended M3U playlists: a location per line, each after an optional
+//! `#EXTINF:<seconds>,<title>` line.
+
+/// One track of a playlist.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    // seconds, `None` when unknown
+    pub duration_secs: Option<u64>,
+    pub title: Option<String>,
+    // a file path or url
+    pub location: String,
+}
+
+/// The entries of `raw`, comments and blank lines left out.
+pub fn parse(raw: &str) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    // the `#EXTINF` line waiting for its location
+    let mut info = None;
+    for line in raw.lines() {
+        let line = line.trim().trim_start_matches('\u{feff}');
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            info = Some(parse_extinf(extinf));
+        } else if !line.is_empty() && !line.starts_with('#') {
+            let (duration_secs, title) = info.take().unwrap_or((None, None));
+            entries.push(Entry {
+                duration_secs,
+                title,
+                location: line.to_owned(),
+            });
+        }
+    }
+    entries
+}
+
+/// `entries` as an extended M3U playlist.
+pub fn write(entries: &[Entry]) -> String {
+    let mut out = String::from("#EXTM3U\n");
+    for entry in entries {
+        // -1 stands for an unknown length
+        let duration = entry.duration_secs.map_or(-1, |secs| secs as i64);
+        let title = entry.title.as_deref().unwrap_or_default();
+        out += &format!("#EXTINF:{},{}\n{}\n", duration, title, entry.location);
+    }
+    out
+}
+
+// `<seconds>[ attributes],<title>`
+fn parse_extinf(extinf: &str) -> (Option<u64>, Option<String>) {
+    let (head, title) = extinf.split_once(',').unwrap_or((extinf, ""));
+    let duration = head
+        .split_whitespace()
+        .next()
+        .and_then(|secs| secs.parse::<i64>().ok())
+        .filter(|secs| *secs >= 0)
+        .map(|secs| secs as u64);
+    let title = Some(title.trim()).filter(|title| !title.is_empty());
+    (duration, title.map(str::to_owned))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, write, Entry};
+
+    #[test]
+    fn test_parse() {
+        let raw = concat!(
+            "#EXTM3U\n",
+            "#EXTINF:215,Jay Chou - 晴天\n",
+            "/music/qingtian.flac\n",
+            "\n",
+            "# a comment\n",
+            "#EXTINF:-1 tvg-id=\"x\",Radio\n",
+            "http://radio/stream\n",
+            "plain.mp3\n",
+        );
+        assert_eq!(
+            parse(raw),
+            vec![
+                Entry {
+                    duration_secs: Some(215),
+                    title: Some("Jay Chou - 晴天".to_owned()),
+                    location: "/music/qingtian.flac".to_owned(),
+                },
+                Entry {
+                    duration_secs: None,
+                    title: Some("Radio".to_owned()),
+                    location: "http://radio/stream".to_owned(),
+                },
+                Entry {
+                    duration_secs: None,
+                    title: None,
+                    location: "plain.mp3".to_owned(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let entries = vec![
+            Entry {
+                duration_secs: Some(3),
+                title: Some("a".to_owned()),
+                location: "a.mp3".to_owned(),
+            },
+            Entry {
+                duration_secs: None,
+                title: None,
+                location: "b.ogg".to_owned(),
+            },
+        ];
+        let raw = write(&entries);
+        assert_eq!(raw, "#EXTM3U\n#EXTINF:3,a\na.mp3\n#EXTINF:-1,\nb.ogg\n");
+        assert_eq!(parse(&raw), entries);
+    }
+}
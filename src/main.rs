@@ -1,8 +1,3 @@
-mod app;
-mod event;
-mod player;
-mod ui;
-
 fn main() {
     println!("Hello, world!");
 }
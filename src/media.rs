@@ -0,0 +1,73 @@
+//! Playback control from outside the terminal, e.g. desktop media keys
+//! through MPRIS. The controls send `MediaCommand`s for `App::handle_media`
+//! and read what's playing from a shared `NowPlaying`, as does the Discord
+//! presence.
+
+use std::sync::{Arc, Mutex};
+
+use ncmapi::types::Song;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MediaCommand {
+    Play,
+    Pause,
+    PlayPause,
+    Stop,
+    Next,
+    Previous,
+    // milliseconds forward, or back when negative
+    Seek(i64),
+    // milliseconds into the track
+    SetPosition(u64),
+    // 0.0 to 1.0
+    SetVolume(f64),
+}
+
+/// The song playing, as media controls show it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MediaMetadata {
+    pub song_id: usize,
+    pub title: String,
+    pub artists: Vec<String>,
+    // picUrl of the album cover
+    pub art_url: Option<String>,
+}
+
+impl MediaMetadata {
+    pub fn from_song(song: &Song) -> Self {
+        Self {
+            song_id: song.id,
+            title: song.name.clone(),
+            artists: song.artists.iter().map(|ar| ar.name.clone()).collect(),
+            art_url: None,
+        }
+    }
+}
+
+/// Updated by the app whenever another song starts, `None` while stopped.
+pub type NowPlaying = Arc<Mutex<Option<MediaMetadata>>>;
+
+#[cfg(test)]
+mod tests {
+    use ncmapi::types::{Artist, Song};
+
+    use super::MediaMetadata;
+
+    #[test]
+    fn test_from_song() {
+        let song = Song {
+            id: 3,
+            name: "晴天".to_owned(),
+            artists: vec![Artist {
+                name: "Jay Chou".to_owned(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let metadata = MediaMetadata::from_song(&song);
+        assert_eq!(metadata.song_id, 3);
+        assert_eq!(metadata.title, "晴天");
+        assert_eq!(metadata.artists, vec!["Jay Chou"]);
+        assert_eq!(metadata.art_url, None);
+    }
+}
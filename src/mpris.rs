@@ -0,0 +1,145 @@
+//! MPRIS2 `MediaPlayer2` on the session bus, so media keys and `playerctl`
+//! control playback. Only built on Linux with the `mpris` feature.
+
+use std::{
+    convert::TryFrom,
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use log::error;
+use mpris_server::{Metadata, PlaybackStatus, Player, Time, TrackId};
+use tokio::{runtime::Builder, task::LocalSet};
+
+use crate::{
+    media::{MediaCommand, MediaMetadata, NowPlaying},
+    player::PlaybackContext,
+};
+
+// how often position, status and metadata are published
+const POLL: Duration = Duration::from_millis(500);
+
+/// The MPRIS server thread, it stops when this is dropped.
+pub struct MprisHandle {
+    running: Arc<AtomicBool>,
+}
+
+impl Drop for MprisHandle {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Serves MPRIS on its own thread: calls turn into `commands`, and what's
+/// shown follows `context` and `now_playing`.
+pub fn spawn(
+    context: Arc<PlaybackContext>,
+    now_playing: NowPlaying,
+    commands: Sender<MediaCommand>,
+) -> io::Result<MprisHandle> {
+    let runtime = Builder::new_current_thread().enable_all().build()?;
+    let running = Arc::new(AtomicBool::new(true));
+    let handle = MprisHandle {
+        running: running.clone(),
+    };
+    thread::Builder::new().name("mpris".to_owned()).spawn(move || {
+        // the player isn't `Send`, it lives on this thread only
+        let local = LocalSet::new();
+        let served = local.block_on(&runtime, serve(context, now_playing, commands, running));
+        if let Err(e) = served {
+            error!("mpris error: {}", e);
+        }
+    })?;
+    Ok(handle)
+}
+
+async fn serve(
+    context: Arc<PlaybackContext>,
+    now_playing: NowPlaying,
+    commands: Sender<MediaCommand>,
+    running: Arc<AtomicBool>,
+) -> mpris_server::zbus::Result<()> {
+    let player = Player::builder("lekima")
+        .identity("lekima")
+        .can_play(true)
+        .can_pause(true)
+        .can_go_next(true)
+        .can_go_previous(true)
+        .can_seek(true)
+        .can_control(true)
+        .build()
+        .await?;
+
+    let send = |cmd: MediaCommand| {
+        let commands = commands.clone();
+        move |_: &Player| {
+            let _ = commands.send(cmd);
+        }
+    };
+    player.connect_play(send(MediaCommand::Play));
+    player.connect_pause(send(MediaCommand::Pause));
+    player.connect_play_pause(send(MediaCommand::PlayPause));
+    player.connect_stop(send(MediaCommand::Stop));
+    player.connect_next(send(MediaCommand::Next));
+    player.connect_previous(send(MediaCommand::Previous));
+    let seek = commands.clone();
+    player.connect_seek(move |_, offset: Time| {
+        let _ = seek.send(MediaCommand::Seek(offset.as_millis()));
+    });
+    let set_position = commands.clone();
+    player.connect_set_position(move |_, _track_id, position: Time| {
+        let _ = set_position.send(MediaCommand::SetPosition(position.as_millis().max(0) as u64));
+    });
+    let set_volume = commands.clone();
+    player.connect_set_volume(move |_, volume| {
+        let _ = set_volume.send(MediaCommand::SetVolume(volume.max(0.0).min(1.0)));
+    });
+    tokio::task::spawn_local(player.run());
+
+    // (metadata, duration) last published
+    let mut shown = None;
+    while running.load(Ordering::SeqCst) {
+        let playing = now_playing.lock().unwrap().clone();
+        let status = match &playing {
+            None => PlaybackStatus::Stopped,
+            Some(_) if context.is_paused() => PlaybackStatus::Paused,
+            Some(_) => PlaybackStatus::Playing,
+        };
+        let current = (playing, context.duration_ms());
+        if shown.as_ref() != Some(&current) {
+            player.set_metadata(metadata(current.0.as_ref(), current.1)).await?;
+            shown = Some(current);
+        }
+        player.set_playback_status(status).await?;
+        player.set_position(Time::from_millis(context.progress_ms() as i64));
+        player.set_volume(context.volume_percent() as f64 / 100.0).await?;
+        tokio::time::sleep(POLL).await;
+    }
+    Ok(())
+}
+
+fn metadata(playing: Option<&MediaMetadata>, duration_ms: Option<u64>) -> Metadata {
+    let playing = match playing {
+        Some(playing) => playing,
+        None => return Metadata::new(),
+    };
+    let mut builder = Metadata::builder()
+        .title(playing.title.clone())
+        .artist(playing.artists.clone());
+    if let Ok(id) = TrackId::try_from(format!("/org/lekima/track/{}", playing.song_id)) {
+        builder = builder.trackid(id);
+    }
+    if let Some(url) = &playing.art_url {
+        builder = builder.art_url(url.clone());
+    }
+    if let Some(duration_ms) = duration_ms {
+        builder = builder.length(Time::from_millis(duration_ms as i64));
+    }
+    builder.build()
+}
@@ -0,0 +1,339 @@
+use std::f32::consts::FRAC_PI_2;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rodio::source::UniformSourceIterator;
+use rodio::Source;
+
+use super::TrackSource;
+
+/// How the outgoing and incoming tracks are weighted while crossfading.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CrossfadeCurve {
+    /// gains add up to 1.0, which sounds quieter around the midpoint
+    Linear,
+    /// cosine/sine gains whose powers add up to 1.0, keeping the loudness steady
+    EqualPower,
+}
+
+impl Default for CrossfadeCurve {
+    fn default() -> Self {
+        CrossfadeCurve::EqualPower
+    }
+}
+
+impl CrossfadeCurve {
+    /// Gains of the `(outgoing, incoming)` tracks at `progress` through the
+    /// fade, from 0.0 (only outgoing) to 1.0 (only incoming).
+    pub fn gains(self, progress: f32) -> (f32, f32) {
+        let t = progress.max(0.0).min(1.0);
+        match self {
+            CrossfadeCurve::Linear => (1.0 - t, t),
+            CrossfadeCurve::EqualPower => ((t * FRAC_PI_2).cos(), (t * FRAC_PI_2).sin()),
+        }
+    }
+}
+
+/// How long one played track fades in and out, each has its own as two
+/// play at once while crossfading. 0 means no fade.
+#[derive(Debug, Default)]
+pub struct Fades {
+    in_ms: AtomicU64,
+    out_ms: AtomicU64,
+    // the end was told, ahead of it to crossfade or when it came
+    end_told: AtomicBool,
+}
+
+impl Fades {
+    pub fn fading_in(ms: u64) -> Self {
+        Self {
+            in_ms: AtomicU64::new(ms),
+            out_ms: AtomicU64::new(0),
+            end_told: AtomicBool::new(false),
+        }
+    }
+
+    #[inline]
+    pub fn in_ms(&self) -> u64 {
+        self.in_ms.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub fn set_in_ms(&self, ms: u64) {
+        self.in_ms.store(ms, Ordering::Relaxed);
+    }
+
+    /// `None` until it's told to fade out.
+    #[inline]
+    pub fn out_ms(&self) -> Option<u64> {
+        Some(self.out_ms.load(Ordering::Relaxed)).filter(|ms| *ms > 0)
+    }
+
+    /// Fades out over `ms` from now, then stops.
+    #[inline]
+    pub fn fade_out(&self, ms: u64) {
+        self.out_ms.store(ms.max(1), Ordering::Relaxed);
+    }
+
+    /// True the first time only, a track ends once.
+    #[inline]
+    pub fn tell_end(&self) -> bool {
+        !self.end_told.swap(true, Ordering::SeqCst)
+    }
+}
+
+/// Where the next track is handed to the `Overlay` of the one playing.
+#[derive(Default)]
+pub struct Handover {
+    // skips locking on every sample while nothing's handed over
+    ready: AtomicBool,
+    // `None` once the overlay ended, nothing handed over would be heard
+    slot: Mutex<Option<Option<TrackSource>>>,
+}
+
+impl Handover {
+    pub fn new() -> Self {
+        Self {
+            ready: AtomicBool::new(false),
+            slot: Mutex::new(Some(None)),
+        }
+    }
+
+    /// Gives `source` back when the overlay already ended.
+    pub fn put(&self, source: TrackSource) -> Result<(), TrackSource> {
+        let mut slot = self.slot.lock().unwrap();
+        match slot.as_mut() {
+            Some(next) => {
+                *next = Some(source);
+                self.ready.store(true, Ordering::SeqCst);
+                Ok(())
+            }
+            None => Err(source),
+        }
+    }
+
+    fn take(&self) -> Option<TrackSource> {
+        if !self.ready.swap(false, Ordering::SeqCst) {
+            return None;
+        }
+        self.slot.lock().unwrap().as_mut()?.take()
+    }
+
+    // the last chance to hand something over
+    fn close(&self) -> Option<TrackSource> {
+        self.ready.store(false, Ordering::SeqCst);
+        self.slot.lock().unwrap().take().flatten()
+    }
+}
+
+/// Plays `inner` and mixes the track handed over on top of it, so both are
+/// heard at once while crossfading. It ends once both have.
+pub struct Overlay<S> {
+    inner: S,
+    inner_done: bool,
+    handover: Arc<Handover>,
+    incoming: Option<TrackSource>,
+    // sample within the current frame, the incoming track joins on a frame boundary
+    channel: u16,
+    // format kept once the incoming track is mixed in
+    channels: u16,
+    sample_rate: u32,
+}
+
+impl<S> Overlay<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(inner: S, handover: Arc<Handover>) -> Self {
+        let (channels, sample_rate) = (inner.channels(), inner.sample_rate());
+        Self {
+            inner,
+            inner_done: false,
+            handover,
+            incoming: None,
+            channel: 0,
+            channels,
+            sample_rate,
+        }
+    }
+
+    // converted to the format of `inner`, the output can't change it midway
+    fn conform(&self, source: TrackSource) -> TrackSource {
+        if source.channels() == self.channels && source.sample_rate() == self.sample_rate {
+            return source;
+        }
+        Box::new(UniformSourceIterator::<TrackSource, f32>::new(
+            source,
+            self.channels,
+            self.sample_rate,
+        ))
+    }
+
+    fn mixing(&self) -> bool {
+        self.inner_done || self.incoming.is_some()
+    }
+}
+
+impl<S> Iterator for Overlay<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.channel == 0 {
+            if !self.mixing() {
+                self.channels = self.inner.channels().max(1);
+                self.sample_rate = self.inner.sample_rate();
+            }
+            if self.incoming.is_none() {
+                self.incoming = self.handover.take().map(|source| self.conform(source));
+            }
+        }
+        self.channel = (self.channel + 1) % self.channels;
+
+        let own = if self.inner_done { None } else { self.inner.next() };
+        self.inner_done = own.is_none();
+        let incoming = self.incoming.as_mut().and_then(|source| source.next());
+        match (own, incoming) {
+            (None, None) if self.incoming.is_none() => {
+                self.incoming = self.handover.close().map(|source| self.conform(source));
+                self.channel = 1 % self.channels;
+                self.incoming.as_mut()?.next()
+            }
+            (None, None) => {
+                self.handover.close();
+                None
+            }
+            (own, incoming) => Some(own.unwrap_or(0.0) + incoming.unwrap_or(0.0)),
+        }
+    }
+}
+
+impl<S> Source for Overlay<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        if self.mixing() {
+            None
+        } else {
+            self.inner.current_frame_len()
+        }
+    }
+
+    fn channels(&self) -> u16 {
+        if self.mixing() {
+            self.channels
+        } else {
+            self.inner.channels()
+        }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        if self.mixing() {
+            self.sample_rate
+        } else {
+            self.inner.sample_rate()
+        }
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rodio::buffer::SamplesBuffer;
+    use rodio::Source;
+
+    use super::{CrossfadeCurve, Fades, Handover, Overlay};
+    use crate::player::TrackSource;
+
+    fn track(channels: u16, rate: u32, value: f32, len: usize) -> TrackSource {
+        Box::new(SamplesBuffer::new(channels, rate, vec![value; len]))
+    }
+
+    fn summed_power(curve: CrossfadeCurve) -> Vec<f32> {
+        (0..=100)
+            .map(|i| {
+                let (out, inc) = curve.gains(i as f32 / 100.0);
+                out * out + inc * inc
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_equal_power_constant() {
+        for power in summed_power(CrossfadeCurve::EqualPower) {
+            assert!((power - 1.0).abs() < 1e-4, "power {}", power);
+        }
+    }
+
+    #[test]
+    fn test_linear_dips() {
+        let power = summed_power(CrossfadeCurve::Linear);
+        assert!((power[0] - 1.0).abs() < 1e-4);
+        assert!((power[50] - 0.5).abs() < 1e-4);
+        assert!((power[100] - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_ends_and_clamping() {
+        for curve in [CrossfadeCurve::Linear, CrossfadeCurve::EqualPower] {
+            let (out, inc) = curve.gains(-1.0);
+            assert!((out - 1.0).abs() < 1e-6 && inc.abs() < 1e-6);
+            let (out, inc) = curve.gains(2.0);
+            assert!(out.abs() < 1e-6 && (inc - 1.0).abs() < 1e-6);
+        }
+        assert_eq!(CrossfadeCurve::default(), CrossfadeCurve::EqualPower);
+    }
+
+    #[test]
+    fn test_overlay_mixes_handed_over() {
+        let handover = Arc::new(Handover::new());
+        let mut overlay = Overlay::new(track(1, 8000, 0.25, 4), handover.clone());
+        assert_eq!(overlay.next(), Some(0.25));
+
+        assert!(handover.put(track(1, 8000, 0.5, 6)).is_ok());
+        let rest: Vec<f32> = overlay.by_ref().collect();
+        assert_eq!(rest, vec![0.75, 0.75, 0.75, 0.5, 0.5, 0.5]);
+
+        // ended, a late track comes back
+        assert!(handover.put(track(1, 8000, 0.5, 6)).is_err());
+    }
+
+    #[test]
+    fn test_overlay_conforms_format() {
+        let handover = Arc::new(Handover::new());
+        let overlay = Overlay::new(track(2, 8000, 0.0, 4), handover.clone());
+        assert!(handover.put(track(1, 8000, 0.5, 2)).is_ok());
+        assert_eq!(overlay.channels(), 2);
+
+        // the mono track is played on both channels
+        let mixed: Vec<f32> = overlay.collect();
+        assert_eq!(mixed, vec![0.5; 4]);
+    }
+
+    #[test]
+    fn test_handed_over_after_inner() {
+        let handover = Arc::new(Handover::new());
+        let mut overlay = Overlay::new(track(1, 8000, 0.25, 1), handover.clone());
+        assert_eq!(overlay.next(), Some(0.25));
+        handover.put(track(1, 8000, 0.5, 2)).unwrap();
+        assert_eq!(overlay.collect::<Vec<_>>(), vec![0.5, 0.5]);
+    }
+
+    #[test]
+    fn test_fades() {
+        let fades = Fades::fading_in(300);
+        assert_eq!(fades.in_ms(), 300);
+        assert_eq!(fades.out_ms(), None);
+        fades.fade_out(0);
+        assert_eq!(fades.out_ms(), Some(1));
+    }
+}
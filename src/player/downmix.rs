@@ -0,0 +1,138 @@
+use std::time::Duration;
+
+use rodio::Source;
+
+/// Averages the left and right channel of a stereo source into both of them.
+/// Sources with any other channel count pass through.
+pub struct Downmix<I> {
+    input: I,
+    enabled: bool,
+    // right-channel copy of the averaged sample just returned
+    pending: Option<f32>,
+    // position inside the current frame, toggling only applies between frames
+    channel: u16,
+}
+
+impl<I> Downmix<I> {
+    pub fn new(input: I, enabled: bool) -> Self {
+        Self {
+            input,
+            enabled,
+            pending: None,
+            channel: 0,
+        }
+    }
+
+    #[inline]
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+}
+
+impl<I> Iterator for Downmix<I>
+where
+    I: Source<Item = f32>,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        if let Some(sample) = self.pending.take() {
+            return Some(sample);
+        }
+
+        let channels = self.input.channels();
+        if self.enabled && channels == 2 && self.channel == 0 {
+            let left = self.input.next()?;
+            let right = match self.input.next() {
+                Some(right) => right,
+                None => return Some(left),
+            };
+            let mid = (left + right) / 2.0;
+            self.pending = Some(mid);
+            return Some(mid);
+        }
+
+        let sample = self.input.next()?;
+        self.channel = (self.channel + 1) % channels.max(1);
+        Some(sample)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> Source for Downmix<I>
+where
+    I: Source<Item = f32>,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rodio::buffer::SamplesBuffer;
+
+    use super::Downmix;
+
+    // hard panned left
+    fn panned() -> SamplesBuffer<f32> {
+        SamplesBuffer::new(2, 44100, vec![1.0, 0.0, 0.5, 0.0, -0.8, 0.0])
+    }
+
+    #[test]
+    fn test_mono() {
+        let out: Vec<f32> = Downmix::new(panned(), true).collect();
+        assert_eq!(out, vec![0.5, 0.5, 0.25, 0.25, -0.4, -0.4]);
+        assert!(out.chunks(2).all(|frame| frame[0] == frame[1]));
+    }
+
+    #[test]
+    fn test_stereo_untouched() {
+        let out: Vec<f32> = Downmix::new(panned(), false).collect();
+        assert_eq!(out, vec![1.0, 0.0, 0.5, 0.0, -0.8, 0.0]);
+    }
+
+    #[test]
+    fn test_toggle_between_frames() {
+        let mut mix = Downmix::new(panned(), false);
+        // half way into the first frame
+        assert_eq!(mix.next(), Some(1.0));
+        mix.set_enabled(true);
+        assert_eq!(mix.next(), Some(0.0));
+        assert_eq!(mix.next(), Some(0.25));
+        assert_eq!(mix.next(), Some(0.25));
+    }
+
+    #[test]
+    fn test_mono_source_untouched() {
+        let samples = vec![1.0, 0.0, 0.5];
+        let out: Vec<f32> = Downmix::new(SamplesBuffer::new(1, 44100, samples.clone()), true).collect();
+        assert_eq!(out, samples);
+    }
+}
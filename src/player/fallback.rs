@@ -0,0 +1,207 @@
+//! Symphonia backed source, used when rodio's `Decoder` can't handle a track.
+
+use std::{io::Cursor, time::Duration};
+
+use bytes::Bytes;
+use rodio::Source;
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::{Decoder, DecoderOptions},
+    errors::Error,
+    formats::{FormatOptions, FormatReader},
+    io::MediaSourceStream,
+    meta::MetadataOptions,
+    probe::Hint,
+};
+
+pub struct SymphoniaSource {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    // interleaved samples of the last decoded packet
+    buf: Vec<f32>,
+    pos: usize,
+    channels: u16,
+    sample_rate: u32,
+    total_duration: Option<Duration>,
+}
+
+impl SymphoniaSource {
+    pub fn new(track: Bytes) -> Result<Self, Error> {
+        let mss = MediaSourceStream::new(Box::new(Cursor::new(track)), Default::default());
+        let probed = symphonia::default::get_probe().format(
+            &Hint::new(),
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )?;
+        let format = probed.format;
+        let track = format
+            .default_track()
+            .ok_or(Error::Unsupported("no audio track"))?;
+        let decoder =
+            symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+        let params = &track.codec_params;
+        let total_duration = match (params.n_frames, params.sample_rate) {
+            (Some(frames), Some(rate)) if rate > 0 => {
+                Some(Duration::from_micros(frames * 1_000_000 / rate as u64))
+            }
+            _ => None,
+        };
+
+        let mut source = Self {
+            track_id: track.id,
+            format,
+            decoder,
+            buf: Vec::new(),
+            pos: 0,
+            channels: 1,
+            sample_rate: 44100,
+            total_duration,
+        };
+        // the first packet tells the real channel count and rate
+        if !source.decode_next() {
+            return Err(Error::Unsupported("no audio frames"));
+        }
+        Ok(source)
+    }
+
+    fn decode_next(&mut self) -> bool {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                // end of stream or broken container
+                Err(_) => return false,
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                // skip corrupt packets
+                Err(Error::DecodeError(_)) => continue,
+                Err(_) => return false,
+            };
+
+            let spec = *decoded.spec();
+            let mut samples = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+            samples.copy_interleaved_ref(decoded);
+            if samples.samples().is_empty() {
+                continue;
+            }
+
+            self.channels = spec.channels.count() as u16;
+            self.sample_rate = spec.rate;
+            self.buf.clear();
+            self.buf.extend_from_slice(samples.samples());
+            self.pos = 0;
+            return true;
+        }
+    }
+}
+
+impl Iterator for SymphoniaSource {
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        let sample = *self.buf.get(self.pos)?;
+        self.pos += 1;
+        // refilled right away, an empty frame reads as the end of the track
+        if self.pos >= self.buf.len() && !self.decode_next() {
+            self.buf.clear();
+            self.pos = 0;
+        }
+        Some(sample)
+    }
+}
+
+impl Source for SymphoniaSource {
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        // channels and rate may change between packets, 0 only once it's all played
+        Some(self.buf.len() - self.pos)
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.total_duration
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Cursor;
+
+    use bytes::Bytes;
+    use rodio::{Decoder, Source};
+
+    use super::SymphoniaSource;
+
+    const BLOCK_ALIGN: usize = 256;
+    // (256 - 4) * 2 nibbles + the sample in the block header
+    pub(crate) const SAMPLES_PER_BLOCK: usize = 505;
+
+    /// Mono 8kHz IMA ADPCM wav, which hound (and so rodio) can't read.
+    pub(crate) fn ima_adpcm_wav(blocks: usize) -> Bytes {
+        let data_len = blocks * BLOCK_ALIGN;
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&((4 + 28 + 12 + 8 + data_len) as u32).to_le_bytes());
+        wav.extend_from_slice(b"WAVE");
+
+        wav.extend_from_slice(b"fmt ");
+        wav.extend_from_slice(&20u32.to_le_bytes());
+        wav.extend_from_slice(&0x0011u16.to_le_bytes()); // IMA ADPCM
+        wav.extend_from_slice(&1u16.to_le_bytes()); // channels
+        wav.extend_from_slice(&8000u32.to_le_bytes()); // sample rate
+        wav.extend_from_slice(&4055u32.to_le_bytes()); // byte rate
+        wav.extend_from_slice(&(BLOCK_ALIGN as u16).to_le_bytes());
+        wav.extend_from_slice(&4u16.to_le_bytes()); // bits per sample
+        wav.extend_from_slice(&2u16.to_le_bytes()); // extra size
+        wav.extend_from_slice(&(SAMPLES_PER_BLOCK as u16).to_le_bytes());
+
+        wav.extend_from_slice(b"fact");
+        wav.extend_from_slice(&4u32.to_le_bytes());
+        wav.extend_from_slice(&((blocks * SAMPLES_PER_BLOCK) as u32).to_le_bytes());
+
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(data_len as u32).to_le_bytes());
+        // predictor 0, step index 0 and all-zero nibbles: near silence
+        wav.extend(std::iter::repeat(0u8).take(data_len));
+        Bytes::from(wav)
+    }
+
+    #[test]
+    fn test_rodio_rejects_adpcm() {
+        assert!(Decoder::new(Cursor::new(ima_adpcm_wav(4))).is_err());
+    }
+
+    #[test]
+    fn test_symphonia_decodes_adpcm() {
+        let source = SymphoniaSource::new(ima_adpcm_wav(4)).unwrap();
+        assert_eq!(source.channels(), 1);
+        assert_eq!(source.sample_rate(), 8000);
+        assert_eq!(source.count(), 4 * SAMPLES_PER_BLOCK);
+
+        // past the first packet, which `Buffered` would take for the end
+        let source = SymphoniaSource::new(ima_adpcm_wav(4)).unwrap();
+        assert_eq!(source.buffered().count(), 4 * SAMPLES_PER_BLOCK);
+    }
+
+    #[test]
+    fn test_symphonia_rejects_garbage() {
+        assert!(SymphoniaSource::new(Bytes::from_static(&[7u8; 512])).is_err());
+    }
+}
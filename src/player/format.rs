@@ -0,0 +1,115 @@
+//! Audio container sniffing from the first bytes of a track, so a decode
+//! error can tell what the track looked like and which rodio decoder it needed.
+
+use std::fmt;
+
+/// Container of a track, as told by its magic bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AudioFormat {
+    Mp3,
+    Wav,
+    Flac,
+    Ogg,
+    // raw ADTS frames
+    Aac,
+    // mp4/m4a, usually AAC inside
+    Mp4,
+    Unknown,
+}
+
+impl AudioFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::Wav => "wav",
+            AudioFormat::Flac => "flac",
+            AudioFormat::Ogg => "ogg",
+            AudioFormat::Aac => "aac",
+            AudioFormat::Mp4 => "mp4",
+            AudioFormat::Unknown => "unknown",
+        }
+    }
+
+    /// The rodio feature decoding this format, `None` when only the
+    /// symphonia fallback can.
+    pub fn rodio_feature(self) -> Option<&'static str> {
+        match self {
+            AudioFormat::Mp3 => Some("mp3"),
+            AudioFormat::Wav => Some("wav"),
+            AudioFormat::Flac => Some("flac"),
+            AudioFormat::Ogg => Some("vorbis"),
+            AudioFormat::Aac | AudioFormat::Mp4 | AudioFormat::Unknown => None,
+        }
+    }
+
+    /// Whether this build has the rodio decoder of the format.
+    pub fn rodio_enabled(self) -> bool {
+        match self {
+            AudioFormat::Mp3 => cfg!(feature = "mp3"),
+            AudioFormat::Wav => cfg!(feature = "wav"),
+            AudioFormat::Flac => cfg!(feature = "flac"),
+            AudioFormat::Ogg => cfg!(feature = "vorbis"),
+            AudioFormat::Aac | AudioFormat::Mp4 | AudioFormat::Unknown => false,
+        }
+    }
+}
+
+impl fmt::Display for AudioFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if *self == AudioFormat::Unknown {
+            return write!(f, "unknown container");
+        }
+        match self.rodio_feature() {
+            Some(feature) if self.rodio_enabled() => write!(f, "{}, rodio `{}` enabled", self.name(), feature),
+            Some(feature) => write!(f, "{}, rodio `{}` disabled", self.name(), feature),
+            None => write!(f, "{}, symphonia only", self.name()),
+        }
+    }
+}
+
+/// The container `bytes` start with.
+pub fn detect_format(bytes: &[u8]) -> AudioFormat {
+    match bytes {
+        [b'I', b'D', b'3', ..] => AudioFormat::Mp3,
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => AudioFormat::Wav,
+        [b'f', b'L', b'a', b'C', ..] => AudioFormat::Flac,
+        [b'O', b'g', b'g', b'S', ..] => AudioFormat::Ogg,
+        [_, _, _, _, b'f', b't', b'y', b'p', ..] => AudioFormat::Mp4,
+        // frame sync, then the layer: 0 for ADTS, 1 to 3 for MPEG audio
+        [0xFF, b, ..] if b & 0xF6 == 0xF0 => AudioFormat::Aac,
+        [0xFF, b, ..] if b & 0xE0 == 0xE0 && b & 0x06 != 0 => AudioFormat::Mp3,
+        _ => AudioFormat::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{detect_format, AudioFormat};
+    use crate::player::fallback::tests::ima_adpcm_wav;
+
+    #[test]
+    fn test_detect_format() {
+        let mp3 = fs::read("test-data/bfs.mp3").unwrap();
+        assert_eq!(detect_format(&mp3), AudioFormat::Mp3);
+        assert_eq!(detect_format(&ima_adpcm_wav(1)), AudioFormat::Wav);
+        assert_eq!(detect_format(b"fLaC\0\0\0\x22"), AudioFormat::Flac);
+        assert_eq!(detect_format(b"OggS\0\x02"), AudioFormat::Ogg);
+        assert_eq!(detect_format(b"\0\0\0\x20ftypM4A "), AudioFormat::Mp4);
+        assert_eq!(detect_format(&[0xFF, 0xF1, 0x50, 0x80]), AudioFormat::Aac);
+        // MPEG-1 layer 3 without tags
+        assert_eq!(detect_format(&[0xFF, 0xFB, 0x90, 0x64]), AudioFormat::Mp3);
+        assert_eq!(detect_format(b"<html>"), AudioFormat::Unknown);
+        assert_eq!(detect_format(&[]), AudioFormat::Unknown);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(AudioFormat::Aac.to_string(), "aac, symphonia only");
+        assert_eq!(AudioFormat::Unknown.to_string(), "unknown container");
+        if cfg!(feature = "flac") {
+            assert_eq!(AudioFormat::Flac.to_string(), "flac, rodio `flac` enabled");
+        }
+    }
+}
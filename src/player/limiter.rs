@@ -0,0 +1,118 @@
+use std::time::Duration;
+
+use rodio::Source;
+
+// samples quieter than this pass through untouched
+const THRESHOLD: f32 = 0.8;
+
+/// Soft clips samples approaching full scale, so an amplified track bends
+/// smoothly towards ±1.0 instead of hard clipping into distortion.
+pub struct Limiter<I> {
+    input: I,
+    enabled: bool,
+}
+
+impl<I> Limiter<I> {
+    pub fn new(input: I, enabled: bool) -> Self {
+        Self { input, enabled }
+    }
+
+    #[inline]
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+}
+
+#[inline]
+fn soft_clip(sample: f32) -> f32 {
+    let level = sample.abs();
+    if level <= THRESHOLD {
+        return sample;
+    }
+    let knee = 1.0 - THRESHOLD;
+    sample.signum() * (THRESHOLD + knee * ((level - THRESHOLD) / knee).tanh())
+}
+
+impl<I> Iterator for Limiter<I>
+where
+    I: Source<Item = f32>,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.input.next()?;
+        Some(if self.enabled { soft_clip(sample) } else { sample })
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> Source for Limiter<I>
+where
+    I: Source<Item = f32>,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rodio::buffer::SamplesBuffer;
+    use rodio::Source;
+
+    use super::Limiter;
+
+    fn loud() -> impl Source<Item = f32> {
+        let samples: Vec<f32> = (0..1000).map(|i| (i as f32 / 20.0).sin() * 0.9).collect();
+        SamplesBuffer::new(2, 44100, samples).amplify(2.0)
+    }
+
+    fn peak(source: impl Iterator<Item = f32>) -> f32 {
+        source.map(f32::abs).fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn test_limiter_on() {
+        let peak = peak(Limiter::new(loud(), true));
+        assert!(peak <= 1.0, "peak {}", peak);
+        assert!(peak > 0.8);
+    }
+
+    #[test]
+    fn test_limiter_off() {
+        assert!(peak(Limiter::new(loud(), false)) > 1.0);
+    }
+
+    #[test]
+    fn test_quiet_untouched() {
+        let samples = vec![0.1, -0.5, 0.79, -0.8];
+        let out: Vec<f32> = Limiter::new(SamplesBuffer::new(1, 44100, samples.clone()), true).collect();
+        assert_eq!(out, samples);
+    }
+}
@@ -0,0 +1,168 @@
+//! Integrated loudness after ITU-R BS.1770 / EBU R128, for normalizing tracks.
+
+use std::f64::consts::PI;
+
+use rodio::Source;
+
+// gating blocks of 400ms, overlapping by 75%
+const BLOCK_STEPS: usize = 4;
+const STEP_MS: u64 = 100;
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_LU: f64 = -10.0;
+// louder than this the limiter would be busy all track long
+const MAX_BOOST_DB: f32 = 12.0;
+
+// second order IIR filter, direct form I
+#[derive(Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self {
+            b,
+            a,
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+}
+
+// the K-weighting of BS.1770 at `rate`: a high shelf for the head's
+// acoustics, then a high pass cutting what's barely heard
+fn k_weighting(rate: u32) -> (Biquad, Biquad) {
+    let rate = rate.max(1) as f64;
+
+    let (f0, gain_db, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::new(
+        [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::new(
+        [1.0, -2.0, 1.0],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+    (shelf, high_pass)
+}
+
+fn lufs(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+/// Integrated loudness of `source` in LUFS, decoding all of it. `None` when
+/// it's too short or too quiet to measure. All channels are weighted alike.
+pub fn integrated<S>(source: S) -> Option<f32>
+where
+    S: Source<Item = f32>,
+{
+    let channels = source.channels().max(1) as usize;
+    let step_frames = (source.sample_rate() as u64 * STEP_MS / 1000).max(1) as usize;
+    let mut filters = vec![k_weighting(source.sample_rate()); channels];
+
+    // mean square of each 100ms step, summed over the channels
+    let mut steps = Vec::new();
+    let mut sum = 0.0;
+    let mut frames = 0;
+    for (i, sample) in source.enumerate() {
+        let (shelf, high_pass) = &mut filters[i % channels];
+        let y = high_pass.process(shelf.process(sample as f64));
+        sum += y * y;
+        if i % channels == channels - 1 {
+            frames += 1;
+            if frames == step_frames {
+                steps.push(sum / step_frames as f64);
+                sum = 0.0;
+                frames = 0;
+            }
+        }
+    }
+
+    let blocks: Vec<f64> = steps
+        .windows(BLOCK_STEPS)
+        .map(|w| w.iter().sum::<f64>() / BLOCK_STEPS as f64)
+        .filter(|power| lufs(*power) > ABSOLUTE_GATE_LUFS)
+        .collect();
+    if blocks.is_empty() {
+        return None;
+    }
+    let ungated = blocks.iter().sum::<f64>() / blocks.len() as f64;
+    let threshold = lufs(ungated) + RELATIVE_GATE_LU;
+    let gated: Vec<f64> = blocks.into_iter().filter(|p| lufs(*p) > threshold).collect();
+    let power = gated.iter().sum::<f64>() / gated.len() as f64;
+    Some(lufs(power) as f32)
+}
+
+/// Amplitude factor bringing a track at `integrated` LUFS to `target`,
+/// boosting by 12 dB at most.
+pub fn gain(integrated: f32, target: f32) -> f32 {
+    10f32.powf((target - integrated).min(MAX_BOOST_DB) / 20.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use rodio::buffer::SamplesBuffer;
+
+    use super::{gain, integrated};
+
+    // 3s of a 1kHz sine at `amplitude`, on both channels
+    fn sine(amplitude: f32) -> SamplesBuffer<f32> {
+        let rate = 48_000;
+        let samples = (0..rate * 3)
+            .flat_map(|i| {
+                let s = (i as f32 * 1000.0 * TAU / rate as f32).sin() * amplitude;
+                vec![s, s]
+            })
+            .collect::<Vec<_>>();
+        SamplesBuffer::new(2, rate as u32, samples)
+    }
+
+    #[test]
+    fn test_sine_loudness() {
+        // a full scale 1kHz sine reads -3 LUFS per channel, 0 in stereo
+        let loud = integrated(sine(1.0)).unwrap();
+        assert!(loud.abs() < 0.2, "{}", loud);
+        let quiet = integrated(sine(0.1)).unwrap();
+        assert!((quiet - loud + 20.0).abs() < 0.1, "{} {}", quiet, loud);
+    }
+
+    #[test]
+    fn test_silence_unmeasured() {
+        assert_eq!(integrated(SamplesBuffer::new(1, 8000, vec![0.0f32; 8000])), None);
+        assert_eq!(integrated(SamplesBuffer::new(1, 8000, vec![0.5f32; 100])), None);
+    }
+
+    #[test]
+    fn test_gain() {
+        assert!((gain(-14.0, -14.0) - 1.0).abs() < 1e-6);
+        assert!((gain(-8.0, -14.0) - 0.5012).abs() < 1e-3);
+        // boosts are capped
+        assert!((gain(-60.0, -14.0) - gain(-26.0, -14.0)).abs() < 1e-6);
+    }
+}
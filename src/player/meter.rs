@@ -0,0 +1,164 @@
+//! Recent output samples for level meters and visualizers. The audio thread
+//! writes into a fixed ring of atomics, overwriting the oldest samples, so
+//! neither side ever waits on the other.
+
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rodio::Source;
+
+use super::PlaybackContext;
+
+/// The last `capacity` samples written, interleaved as played.
+pub struct SampleRing {
+    // f32 bits
+    slots: Box<[AtomicU32]>,
+    // samples written so far, the next one goes to `written % capacity`
+    written: AtomicUsize,
+}
+
+impl SampleRing {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            slots: (0..capacity.max(1)).map(|_| AtomicU32::new(0)).collect(),
+            written: AtomicUsize::new(0),
+        }
+    }
+
+    #[inline]
+    pub fn push(&self, sample: f32) {
+        let i = self.written.fetch_add(1, Ordering::Relaxed) % self.slots.len();
+        self.slots[i].store(sample.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Up to the `n` latest samples, oldest first. A write racing the read
+    /// may show up in place of the oldest one, which a meter can't tell.
+    pub fn peek(&self, n: usize) -> Vec<f32> {
+        let written = self.written.load(Ordering::Relaxed);
+        let n = n.min(written).min(self.slots.len());
+        (written - n..written)
+            .map(|i| f32::from_bits(self.slots[i % self.slots.len()].load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    /// Samples written since the last `clear`.
+    pub fn written(&self) -> usize {
+        self.written.load(Ordering::Relaxed)
+    }
+
+    /// Root mean square of the `n` latest samples, 0.0 for none.
+    pub fn rms(&self, n: usize) -> f32 {
+        let samples = self.peek(n);
+        if samples.is_empty() {
+            return 0.0;
+        }
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    pub fn clear(&self) {
+        for slot in self.slots.iter() {
+            slot.store(0, Ordering::Relaxed);
+        }
+        self.written.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Passes samples through untouched, copying each into the context's ring.
+pub struct Meter<I> {
+    input: I,
+    context: Arc<PlaybackContext>,
+}
+
+impl<I: Source<Item = f32>> Meter<I> {
+    pub fn new(input: I, context: Arc<PlaybackContext>) -> Self {
+        Self { input, context }
+    }
+}
+
+impl<I> Iterator for Meter<I>
+where
+    I: Source<Item = f32>,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.input.next()?;
+        self.context.samples.push(sample);
+        Some(sample)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> Source for Meter<I>
+where
+    I: Source<Item = f32>,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rodio::buffer::SamplesBuffer;
+
+    use super::{Meter, SampleRing};
+    use crate::player::PlaybackContext;
+
+    #[test]
+    fn test_ring_overwrites_oldest() {
+        let ring = SampleRing::new(4);
+        assert!(ring.peek(4).is_empty());
+        for i in 0..6 {
+            ring.push(i as f32);
+        }
+        assert_eq!(ring.peek(10), vec![2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(ring.peek(2), vec![4.0, 5.0]);
+
+        ring.clear();
+        assert!(ring.peek(4).is_empty());
+        assert_eq!(ring.rms(4), 0.0);
+    }
+
+    #[test]
+    fn test_rms() {
+        let ring = SampleRing::new(8);
+        for s in [0.5, -0.5, 0.5, -0.5] {
+            ring.push(s);
+        }
+        assert!((ring.rms(4) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_meter_taps_samples() {
+        let context = Arc::new(PlaybackContext::default());
+        let source = SamplesBuffer::new(1, 8000, vec![0.1f32, 0.2, 0.3]);
+        let played: Vec<f32> = Meter::new(source, context.clone()).collect();
+        assert_eq!(played, vec![0.1, 0.2, 0.3]);
+        assert_eq!(context.peek_samples(2), vec![0.2, 0.3]);
+    }
+}
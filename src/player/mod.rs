@@ -1,394 +1,2676 @@
-use std::io::Cursor;
+mod crossfade;
+mod downmix;
+mod fallback;
+mod format;
+mod limiter;
+mod loudness;
+mod meter;
+mod mp3;
+mod null;
+mod repeat;
+mod signal;
+mod silence;
+mod stream;
+mod volume;
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::mpsc::Receiver;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use bytes::Bytes;
-use rodio::source::Buffered;
+use rodio::cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use rodio::cpal::{self, BufferSize, SampleFormat, SupportedBufferSize};
+use rodio::source::{Buffered, UniformSourceIterator};
 use rodio::{queue, Source};
 use rodio::{Decoder, OutputStream, OutputStreamHandle};
+use serde_json::{json, Value};
+
+pub use crossfade::CrossfadeCurve;
+use crossfade::{Fades, Handover, Overlay};
+use downmix::Downmix;
+use fallback::SymphoniaSource;
+pub use format::{detect_format, AudioFormat};
+use limiter::Limiter;
+use meter::{Meter, SampleRing};
+pub use null::{NullAudioPlayer, RecordingPlayer};
+pub use signal::EndSignal;
+use signal::NotifyEnd;
+use repeat::Repeat;
+use silence::Edges;
+use stream::{RangeReader, ReadAhead};
 
 #[derive(Debug, Clone)]
 pub enum PlayerError {
-    DecodeTrackError(String),
+    // neither decoder could open the track, `format` is what it looked like
+    DecodeTrackError { format: AudioFormat, reason: String },
+    // decodable but nothing to play, e.g. empty bytes
+    Unsupported(String),
+    // no track at the given queue index
+    InvalidIndex(usize),
+    // fetching a streamed track failed
+    Stream(String),
+    // a range that's empty once clamped to the track
+    InvalidRange { start_ms: u64, end_ms: u64 },
+    // no sound card, or not reachable e.g. over ssh
+    NoOutputDevice(String),
+}
+
+impl fmt::Display for PlayerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlayerError::DecodeTrackError { format, reason } => {
+                write!(f, "decode track ({}): {}", format, reason)
+            }
+            PlayerError::Unsupported(e) => write!(f, "unsupported track: {}", e),
+            PlayerError::InvalidIndex(i) => write!(f, "no track at index {}", i),
+            PlayerError::Stream(e) => write!(f, "stream track: {}", e),
+            PlayerError::InvalidRange { start_ms, end_ms } => {
+                write!(f, "empty range {}ms..{}ms", start_ms, end_ms)
+            }
+            PlayerError::NoOutputDevice(e) => write!(f, "no output device: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PlayerError {}
+
+/// Which decoder managed to open the current track.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecoderBackend {
+    Rodio,
+    Symphonia,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectedFormat {
+    pub backend: DecoderBackend,
+}
+
+/// What happens once the current track ends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RepeatMode {
+    /// move on to the next track
+    Off,
+    /// loop the current track forever, the player restarts it by itself
+    One,
+    /// move on to the next track, starting the queue over once it ends
+    All,
+    /// play the current track this many times in total, then move on; 0 plays it once
+    Count(u32),
+}
+
+impl Default for RepeatMode {
+    fn default() -> Self {
+        RepeatMode::Off
+    }
+}
+
+impl RepeatMode {
+    // "off", "one", "all" or the count
+    fn to_json(self) -> Value {
+        match self {
+            RepeatMode::Off => json!("off"),
+            RepeatMode::One => json!("one"),
+            RepeatMode::All => json!("all"),
+            RepeatMode::Count(n) => json!(n),
+        }
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        match value.as_str() {
+            Some("off") => Some(RepeatMode::Off),
+            Some("one") => Some(RepeatMode::One),
+            Some("all") => Some(RepeatMode::All),
+            Some(_) => None,
+            None => Some(RepeatMode::Count(value.as_u64()? as u32)),
+        }
+    }
+}
+
+/// What a track is, each kind keeps its own speed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TrackKind {
+    Music,
+    Podcast,
+}
+
+impl Default for TrackKind {
+    fn default() -> Self {
+        TrackKind::Music
+    }
+}
+
+impl TrackKind {
+    pub const ALL: [TrackKind; 2] = [TrackKind::Music, TrackKind::Podcast];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TrackKind::Music => "music",
+            TrackKind::Podcast => "podcast",
+        }
+    }
+}
+
+/// Playback modes read at once, so indicators drawn from it agree with each other.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PlaybackSnapshot {
+    pub repeat_mode: RepeatMode,
+    pub shuffle_enabled: bool,
+    pub is_muted: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlaybackEvent {
+    /// the track at the given queue index started playing
+    Started(usize),
+    /// the current track played to its end
+    Ended,
 }
 
 pub trait AudioPlayer {
-    /// play a sound track
+    /// play a sound track, the only one queued until `enqueue` adds what comes next
     fn play(&mut self, track: Bytes) -> Result<(), PlayerError>;
+    /// queue `track` after the last one, for `next` and gapless playback.
+    /// `Unsupported` by players without a queue
+    fn enqueue(&mut self, _track: Bytes) -> Result<(), PlayerError> {
+        Err(PlayerError::Unsupported("queueing tracks".to_owned()))
+    }
+    /// empty the queue, the current track keeps playing
+    fn clear_queue(&mut self) {}
+    /// forget the queued tracks before the current one, `prev` can't go back to them
+    fn trim_queue(&mut self) {}
+    /// what the tracks played from now on are, switching to the speed last used for that kind
+    fn set_track_kind(&self, kind: TrackKind) {
+        self.playback_context().set_track_kind(kind);
+    }
+    /// play a track as `reader` gives it, by default once it's all read
+    fn play_stream(&mut self, mut reader: Box<dyn Read + Send>) -> Result<(), PlayerError> {
+        let mut track = Vec::new();
+        reader
+            .read_to_end(&mut track)
+            .map_err(|e| PlayerError::Stream(e.to_string()))?;
+        self.play(Bytes::from(track))
+    }
+    /// play the track at `url` while it downloads, `Unsupported` by players that can't
+    fn play_url(&mut self, url: &str) -> Result<(), PlayerError> {
+        Err(PlayerError::Unsupported(format!("streaming {}", url)))
+    }
     /// stop play
     fn stop(&self);
     /// pause the audio player
     fn pause(&self);
     /// resume to play
     fn resume(&self);
-    /// increase volume
+    /// increase volume by `delta` percent, returning the new percentage
     fn increase_volume(&self, delta: u8) -> u8;
-    /// decrease volume
+    /// decrease volume by `delta` percent, returning the new percentage
     fn decrease_volume(&self, delta: u8) -> u8;
-    /// slide progress bar
+    /// slide progress bar, in milliseconds. Same as `seek_to`, which it backs
     fn seek_ms(&self, progress_ms: u64);
-    /// forward (millisecond)
-    fn forward(&self, dur_millis: u64);
-    /// rewind (millisecond)
-    fn rewind(&self, dur_millis: u64);
+    /// slide progress bar to `pos`, sub millisecond precision is dropped
+    fn seek_to(&self, pos: Duration) {
+        self.seek_ms(pos.as_millis() as u64);
+    }
+    /// slide progress bar to a fraction of the track, clamped to `0.0..=1.0`.
+    /// Nothing happens while the duration is unknown
+    fn seek_percent(&self, pct: f32) {
+        if let Some(duration) = self.duration_ms().filter(|_| !pct.is_nan()) {
+            self.seek_ms((pct.clamp(0.0, 1.0) as f64 * duration as f64) as u64);
+        }
+    }
+    /// forward (millisecond), no closer than a second to the end. Forwarding past
+    /// it restarts the track repeating one, otherwise the track ends as if played
+    /// through and the track-end callback moves on. Returns the progress it lands on
+    fn forward(&mut self, dur_millis: u64) -> u64;
+    /// rewind (millisecond), not before 0. Returns the progress it lands on
+    fn rewind(&self, dur_millis: u64) -> u64;
     /// change speed (millisecond)
     fn speed(&self, speed: f32);
+    /// play the next queued track, stopping after the last one unless repeating all
+    fn next(&mut self) -> Result<(), PlayerError>;
+    /// play the previous queued track, or restart the current one past its first seconds
+    fn prev(&mut self) -> Result<(), PlayerError>;
+    /// silence playback or bring the volume from before back, `true` when muted now
+    fn toggle_mute(&self) -> bool {
+        self.playback_context().toggle_mute()
+    }
+    /// length of the current track in milliseconds, `None` until it's known
+    fn duration_ms(&self) -> Option<u64> {
+        self.playback_context().duration_ms()
+    }
+    /// play queued tracks back to back, the next one decoded ahead of time.
+    /// Turns the crossfade off, they can't go together
+    fn set_gapless(&self, enabled: bool) {
+        self.playback_context().set_gapless(enabled);
+    }
+    /// what happens once the current track ends, `One` restarts it from 0
+    fn set_repeat_mode(&self, mode: RepeatMode) {
+        self.playback_context().set_repeat_mode(mode);
+    }
     /// current playback context
     fn playback_context(&self) -> Arc<PlaybackContext>;
+    /// let the current track finish, then release the player
+    fn detach(self: Box<Self>);
 }
 
 pub struct PlaybackContext {
     pause: AtomicBool,
     stopped: AtomicBool,
+    // perceptual, in percent, see `volume::amplitude`
     volume: Mutex<f32>,
     speed: Mutex<f32>,
+    // of the track playing, whose kind's speed `speed` is
+    track_kind: Mutex<TrackKind>,
+    // the speed last set for each kind, 1.0 for kinds never changed
+    speeds: Mutex<HashMap<TrackKind, f32>>,
     // milliseconds of the progress bar
     progress_ms: AtomicU64,
     progress_interval_ms: AtomicU64,
+    // length of the current track, 0 while unknown
+    duration_ms: AtomicU64,
+    // soft clipping, `None` enables it only while amplifying above 1.0
+    limiter: Mutex<Option<bool>>,
+    // average left and right into both channels
+    mono: AtomicBool,
+    crossfade_curve: Mutex<CrossfadeCurve>,
+    // overlap of consecutive tracks, 0 cuts from one to the next
+    crossfade_ms: AtomicU64,
+    // queued tracks play back to back, never along with a crossfade
+    gapless: AtomicBool,
+    // part of the track played over and over, in milliseconds
+    ab_loop: Mutex<Option<(u64, u64)>>,
+    // playback fades out and pauses here
+    sleep_at: Mutex<Option<Instant>>,
+    // loudness tracks are brought to, in LUFS
+    normalization: Mutex<Option<f32>>,
+    // skip silence at both ends of a track
+    trim_silence: AtomicBool,
+    repeat_mode: Mutex<RepeatMode>,
+    shuffle: AtomicBool,
+    // volume to restore on unmute, `Some` while muted
+    pre_mute_volume: Mutex<Option<f32>>,
+    on_track_end: Mutex<Option<Box<dyn Fn() + Send>>>,
+    // what was just played, for level meters
+    samples: SampleRing,
 }
 
-pub(crate) struct LAudioPlayer {
-    queue_tx: Arc<queue::SourcesQueueInput<f32>>,
-    end_signal: Mutex<Option<Receiver<()>>>,
-
-    playback_context: Arc<PlaybackContext>,
+impl PlaybackContext {
+    /// Up to the `n` latest samples played, oldest first, channels
+    /// interleaved. At most `METER_SAMPLES` are kept.
+    pub fn peek_samples(&self, n: usize) -> Vec<f32> {
+        self.samples.peek(n)
+    }
 
-    detached: bool,
-    _output_stream_handle: OutputStreamHandle,
-    _output_stream: OutputStream,
-    current_track: Option<Buffered<Decoder<Cursor<Bytes>>>>,
-}
+    /// Loudness of the last `RMS_WINDOW` samples played, from 0.0 for silence.
+    pub fn rms_level(&self) -> f32 {
+        self.samples.rms(RMS_WINDOW)
+    }
 
-impl LAudioPlayer {
+    /// Position in the current track, in milliseconds.
     #[inline]
-    pub fn try_new() -> Result<Self, Box<dyn std::error::Error>> {
-        let (player, queue_rx) = Self::new_idle();
-        player._output_stream_handle.play_raw(queue_rx)?;
-        Ok(player)
+    pub fn progress_ms(&self) -> u64 {
+        self.progress_ms.load(Ordering::SeqCst)
     }
 
     #[inline]
-    pub fn new_idle() -> (Self, queue::SourcesQueueOutput<f32>) {
-        let (stream, handle) = rodio::OutputStream::try_default().unwrap();
-        let (queue_tx, queue_rx) = queue::queue(true);
-        let player = Self {
-            queue_tx,
-            end_signal: Mutex::new(None),
-
-            playback_context: Arc::new(PlaybackContext {
-                pause: AtomicBool::new(false),
-                stopped: AtomicBool::new(false),
-                volume: Mutex::new(1.0),
-                speed: Mutex::new(1.0),
-                progress_ms: AtomicU64::new(0),
-                progress_interval_ms: AtomicU64::new(5),
-            }),
-            detached: false,
-
-            _output_stream: stream,
-            _output_stream_handle: handle,
-            current_track: None,
-        };
-        (player, queue_rx)
+    pub(crate) fn set_progress_ms(&self, progress_ms: u64) {
+        self.progress_ms.store(progress_ms, Ordering::SeqCst);
     }
 
+    /// Length of the current track in milliseconds, `None` while unknown.
     #[inline]
-    fn set_current_track(&mut self, track: Bytes) -> Result<(), PlayerError> {
-        let buf = Cursor::new(track);
-        let source = Decoder::new(buf).map_err(|e| PlayerError::DecodeTrackError(e.to_string()))?;
-
-        self.current_track = Some(source.buffered());
-        Ok(())
+    pub fn duration_ms(&self) -> Option<u64> {
+        match self.duration_ms.load(Ordering::Relaxed) {
+            0 => None,
+            ms => Some(ms),
+        }
     }
 
     #[inline]
-    fn start_play(&self) {
-        let context = self.playback_context.clone();
-
-        if let Some(ref source) = self.current_track {
-            // clip source by progress cursor
-            let source = source
-                .clone()
-                .skip_duration(Duration::from_millis(
-                    context.progress_ms.load(Ordering::SeqCst),
-                ))
-                .speed(*context.speed.lock().unwrap());
-
-            let source = source
-                .pausable(false)
-                .amplify(1.0)
-                .stoppable()
-                .periodic_access(
-                    Duration::from_millis(context.progress_interval_ms.load(Ordering::Relaxed)),
-                    move |src| {
-                        if context.stopped.load(Ordering::SeqCst) {
-                            return src.stop();
-                        }
-
-                        src.inner_mut().set_factor(*context.volume.lock().unwrap());
-
-                        let paused = context.pause.load(Ordering::SeqCst);
-                        src.inner_mut().inner_mut().set_paused(paused);
-
-                        if !paused {
-                            context.progress_ms.fetch_add(5, Ordering::Relaxed);
-                        }
-                    },
-                )
-                .convert_samples();
-            *self.end_signal.lock().unwrap() = Some(self.queue_tx.append_with_signal(source));
-        }
+    pub(crate) fn set_duration_ms(&self, duration_ms: Option<u64>) {
+        self.duration_ms
+            .store(duration_ms.unwrap_or(0), Ordering::Relaxed);
     }
 
-    /// The value `1.0` is the "normal" volume (unfiltered input). Any value other than 1.0 will
-    /// multiply each sample by this value.
     #[inline]
-    pub fn volume(&self) -> f32 {
-        *self.playback_context.volume.lock().unwrap()
+    pub fn repeat_mode(&self) -> RepeatMode {
+        *self.repeat_mode.lock().unwrap()
     }
 
     #[inline]
-    pub fn set_volume(&self, value: f32) {
-        *self.playback_context.volume.lock().unwrap() = value;
+    pub fn set_repeat_mode(&self, mode: RepeatMode) {
+        *self.repeat_mode.lock().unwrap() = mode;
     }
 
     #[inline]
-    pub fn set_speed(&self, value: f32) {
-        *self.playback_context.speed.lock().unwrap() = value;
+    pub fn shuffle_enabled(&self) -> bool {
+        self.shuffle.load(Ordering::Relaxed)
     }
 
-    /// Resumes playback of a paused sink.
-    /// No effect if not paused.
     #[inline]
-    pub fn resume(&self) {
-        self.playback_context.pause.store(false, Ordering::SeqCst);
+    pub fn set_shuffle(&self, enabled: bool) {
+        self.shuffle.store(enabled, Ordering::Relaxed);
     }
 
-    /// Pauses playback of this sink.
-    pub fn pause(&self) {
-        self.playback_context.pause.store(true, Ordering::SeqCst);
+    /// The volume as it's heard, 0 to 100.
+    #[inline]
+    pub fn volume_percent(&self) -> u8 {
+        self.volume.lock().unwrap().round() as u8
     }
 
-    /// Gets if a sink is paused
-    pub fn is_paused(&self) -> bool {
-        self.playback_context.pause.load(Ordering::SeqCst)
+    /// Sets the volume, heard once unmuted while muted.
+    pub fn set_volume_percent(&self, percent: u8) {
+        self.change_volume(|_| percent.min(100) as f32);
     }
 
-    /// Stops the sink by emptying the queue.
     #[inline]
-    pub fn drain_sink(&self) {
-        self.playback_context.stopped.store(true, Ordering::SeqCst);
+    pub fn speed(&self) -> f32 {
+        *self.speed.lock().unwrap()
     }
 
-    /// Replay the current track but with new playback_context
-    pub fn replay(&self) {
-        self.drain_sink();
-        self.sleep_until_end();
-        self.playback_context.stopped.store(false, Ordering::SeqCst);
-        self.start_play();
+    /// Plays at `speed` from now on, also the next tracks of the same kind.
+    pub(crate) fn set_speed(&self, speed: f32) {
+        *self.speed.lock().unwrap() = speed;
+        let kind = self.track_kind();
+        self.speeds.lock().unwrap().insert(kind, speed);
     }
-    /// Destroys the sink without stopping the sounds that are still playing.
-    #[inline]
-    pub fn detach(mut self) {
-        self.detached = true;
+
+    /// The speed tracks of `kind` play at.
+    pub fn speed_for(&self, kind: TrackKind) -> f32 {
+        self.speeds.lock().unwrap().get(&kind).copied().unwrap_or(1.0)
+    }
+
+    pub fn track_kind(&self) -> TrackKind {
+        *self.track_kind.lock().unwrap()
+    }
+
+    /// Switches to the speed of `kind`, heard from the next track played.
+    pub(crate) fn set_track_kind(&self, kind: TrackKind) {
+        *self.track_kind.lock().unwrap() = kind;
+        *self.speed.lock().unwrap() = self.speed_for(kind);
     }
 
-    /// Sleeps the current thread until the sound ends.
     #[inline]
-    pub fn sleep_until_end(&self) {
-        if let Some(end_signal) = self.end_signal.lock().unwrap().take() {
-            let _ = end_signal.recv();
-        }
+    pub fn is_paused(&self) -> bool {
+        self.pause.load(Ordering::SeqCst)
     }
-}
 
-impl Drop for LAudioPlayer {
     #[inline]
-    fn drop(&mut self) {
-        self.queue_tx.set_keep_alive_if_empty(false);
+    pub fn is_muted(&self) -> bool {
+        self.pre_mute_volume.lock().unwrap().is_some()
+    }
 
-        if !self.detached {
-            self.playback_context.stopped.store(true, Ordering::Relaxed);
+    /// Silences playback or restores the volume it had, returning whether it's muted now.
+    pub fn toggle_mute(&self) -> bool {
+        let mut saved = self.pre_mute_volume.lock().unwrap();
+        let mut volume = self.volume.lock().unwrap();
+        match saved.take() {
+            Some(restored) => {
+                *volume = restored;
+                false
+            }
+            None => {
+                *saved = Some(*volume);
+                *volume = 0.0;
+                true
+            }
         }
     }
-}
 
-impl AudioPlayer for LAudioPlayer {
-    fn play(&mut self, track: Bytes) -> Result<(), PlayerError> {
-        self.playback_context.progress_ms.store(0, Ordering::SeqCst);
-        self.set_current_track(track)?;
-        self.start_play();
-        Ok(())
+    // applies `f` to the volume, while muted to the one restored on unmute
+    fn change_volume(&self, f: impl FnOnce(f32) -> f32) -> f32 {
+        let mut saved = self.pre_mute_volume.lock().unwrap();
+        match saved.as_mut() {
+            Some(saved) => {
+                *saved = f(*saved);
+                *saved
+            }
+            None => {
+                let mut volume = self.volume.lock().unwrap();
+                *volume = f(*volume);
+                *volume
+            }
+        }
     }
 
-    fn pause(&self) {
-        self.pause();
+    /// How long consecutive tracks overlap. Starting the next track this
+    /// early before the end crossfades over the last part of the current one.
+    #[inline]
+    pub fn crossfade_ms(&self) -> u64 {
+        self.crossfade_ms.load(Ordering::Relaxed)
     }
 
-    fn resume(&self) {
-        self.resume();
+    #[inline]
+    pub fn is_gapless(&self) -> bool {
+        self.gapless.load(Ordering::Relaxed)
     }
 
-    fn stop(&self) {
-        self.drain_sink();
+    // crossfading is off while gapless, they can't go together
+    pub(crate) fn set_gapless(&self, enabled: bool) {
+        self.gapless.store(enabled, Ordering::Relaxed);
+        if enabled {
+            self.crossfade_ms.store(0, Ordering::Relaxed);
+        }
     }
 
-    fn increase_volume(&self, delta: u8) -> u8 {
-        let vol = self.volume();
-        if vol >= 2.0 {
-            return (vol * 100.0) as u8;
-        }
+    /// The A-B loop as `(start_ms, end_ms)`, `None` plays tracks normally.
+    #[inline]
+    pub fn ab_loop(&self) -> Option<(u64, u64)> {
+        *self.ab_loop.lock().unwrap()
+    }
 
-        let new_vol = delta as f32 / 100.0 + vol;
-        self.set_volume(new_vol);
-        (new_vol * 100.0) as u8
+    /// Time left until the sleep timer pauses playback, `None` without one.
+    pub fn sleep_timer_remaining(&self) -> Option<Duration> {
+        let sleep_at = (*self.sleep_at.lock().unwrap())?;
+        Some(sleep_at.saturating_duration_since(Instant::now()))
     }
 
-    fn decrease_volume(&self, delta: u8) -> u8 {
-        let vol = self.volume();
-        if vol <= 0.0 {
-            return 0;
+    // gain of the sleep timer fade, pausing once it's over
+    fn sleep_gain(&self) -> f32 {
+        let mut sleep_at = self.sleep_at.lock().unwrap();
+        let left = match *sleep_at {
+            Some(at) => at.saturating_duration_since(Instant::now()),
+            None => return 1.0,
+        };
+        if left.is_zero() {
+            *sleep_at = None;
+            self.pause.store(true, Ordering::SeqCst);
         }
-
-        let new_vol = vol - (delta as f32 / 100.0);
-        self.set_volume(new_vol);
-        (new_vol * 100.0) as u8
+        (left.as_secs_f32() / SLEEP_FADE.as_secs_f32()).min(1.0)
     }
 
-    fn seek_ms(&self, progress_ms: u64) {
-        self.playback_context
-            .progress_ms
-            .store(progress_ms, Ordering::SeqCst);
-        self.replay();
+    /// Calls `f` whenever a track plays to its end, not when it's stopped.
+    /// It runs on the audio thread, anything slow belongs on a channel.
+    pub fn on_track_end(&self, f: Box<dyn Fn() + Send>) {
+        *self.on_track_end.lock().unwrap() = Some(f);
     }
 
-    fn forward(&self, dur_millis: u64) {
-        self.playback_context
-            .progress_ms
-            .fetch_add(dur_millis, Ordering::SeqCst);
-        self.replay();
+    pub(crate) fn track_ended(&self) {
+        if let Some(f) = self.on_track_end.lock().unwrap().as_ref() {
+            f();
+        }
     }
 
-    fn rewind(&self, dur_millis: u64) {
-        let cursor = {
-            let old = self.playback_context.progress_ms.load(Ordering::SeqCst);
-            let new = if old <= dur_millis {
-                0
-            } else {
-                old - dur_millis
-            };
-            new
+    /// Writes volume, speed, repeat mode and mute to `path`, for `load` on the next start.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let muted = self.is_muted();
+        // the volume to come back to, not the silence of a mute
+        let volume = match *self.pre_mute_volume.lock().unwrap() {
+            Some(saved) => saved,
+            None => *self.volume.lock().unwrap(),
         };
-        self.playback_context
-            .progress_ms
-            .store(cursor, Ordering::SeqCst);
-        self.replay();
+        let speeds: serde_json::Map<String, Value> = self
+            .speeds
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(kind, speed)| (kind.name().to_owned(), json!(speed)))
+            .collect();
+        let raw = json!({
+            "volume": volume,
+            "speeds": speeds,
+            "repeat": self.repeat_mode().to_json(),
+            "muted": muted,
+        });
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, raw.to_string())
     }
 
-    fn speed(&self, speed: f32) {
-        self.set_speed(speed);
-        self.replay();
+    /// Restores what `save` wrote to `path`. Fields missing there are left as they are.
+    pub fn load(&self, path: &Path) -> io::Result<()> {
+        let raw: Value = serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if let Some(volume) = raw.get("volume").and_then(Value::as_f64) {
+            *self.volume.lock().unwrap() = (volume as f32).max(0.0).min(100.0);
+            *self.pre_mute_volume.lock().unwrap() = None;
+        }
+        for kind in TrackKind::ALL.iter() {
+            let speed = raw["speeds"][kind.name()].as_f64().filter(|s| *s > 0.0);
+            if let Some(speed) = speed {
+                self.speeds.lock().unwrap().insert(*kind, speed as f32);
+            }
+        }
+        // the speed of whatever kind plays now, not the one saved last
+        *self.speed.lock().unwrap() = self.speed_for(self.track_kind());
+        if let Some(mode) = raw.get("repeat").and_then(RepeatMode::from_json) {
+            self.set_repeat_mode(mode);
+        }
+        if raw.get("muted").and_then(Value::as_bool) == Some(true) && !self.is_muted() {
+            self.toggle_mute();
+        }
+        Ok(())
     }
 
-    fn playback_context(&self) -> Arc<PlaybackContext> {
-        self.playback_context.clone()
+    /// Repeat, shuffle and mute as they are right now.
+    pub fn snapshot(&self) -> PlaybackSnapshot {
+        PlaybackSnapshot {
+            repeat_mode: self.repeat_mode(),
+            shuffle_enabled: self.shuffle_enabled(),
+            is_muted: self.is_muted(),
+        }
     }
 }
 
-#[cfg(test)]
-mod light_audio_player_tests {
-    use std::io::Read;
-    use std::sync::atomic::Ordering;
-    use std::{fs::File, thread, time::Duration};
+impl Default for PlaybackContext {
+    fn default() -> Self {
+        Self {
+            pause: AtomicBool::new(false),
+            stopped: AtomicBool::new(false),
+            volume: Mutex::new(volume::percent(1.0)),
+            speed: Mutex::new(1.0),
+            track_kind: Mutex::new(TrackKind::default()),
+            speeds: Mutex::new(HashMap::new()),
+            progress_ms: AtomicU64::new(0),
+            progress_interval_ms: AtomicU64::new(5),
+            duration_ms: AtomicU64::new(0),
+            limiter: Mutex::new(None),
+            mono: AtomicBool::new(false),
+            crossfade_curve: Mutex::new(CrossfadeCurve::default()),
+            crossfade_ms: AtomicU64::new(0),
+            gapless: AtomicBool::new(false),
+            ab_loop: Mutex::new(None),
+            sleep_at: Mutex::new(None),
+            normalization: Mutex::new(None),
+            trim_silence: AtomicBool::new(false),
+            repeat_mode: Mutex::new(RepeatMode::default()),
+            shuffle: AtomicBool::new(false),
+            pre_mute_volume: Mutex::new(None),
+            on_track_end: Mutex::new(None),
+            samples: SampleRing::new(METER_SAMPLES),
+        }
+    }
+}
 
-    use bytes::Bytes;
+// samples kept for `peek_samples`, over 40ms of 48kHz stereo
+const METER_SAMPLES: usize = 4096;
+// samples `rms_level` averages, about 20ms of 48kHz stereo
+const RMS_WINDOW: usize = 2048;
 
-    use super::{AudioPlayer, LAudioPlayer};
+// the sleep timer fades out over its last part
+const SLEEP_FADE: Duration = Duration::from_secs(10);
 
-    fn new() -> (LAudioPlayer, Bytes) {
-        let p = LAudioPlayer::try_new().unwrap();
-        let mut file = File::open("test-data/bfs.mp3").unwrap();
-        let mut buf = Vec::new();
-        file.read_to_end(&mut buf).unwrap();
-        let track = Bytes::from(buf);
-        (p, track)
-    }
+// read before decoding a stream starts, a couple of seconds of a 320kbps mp3
+const STREAM_PREBUFFER: usize = 128 * 1024;
 
-    #[test]
-    fn test_play() {
-        let (mut p, track) = new();
-        p.play(track).unwrap();
-        p.sleep_until_end();
-    }
+/// `prev` goes back a track only this early into the current one, later it
+/// restarts it.
+pub const PREV_RESTART_MS: u64 = 3000;
 
-    #[test]
-    fn test_volume() {
-        let p = LAudioPlayer::try_new().unwrap();
-        p.increase_volume(10);
-        p.decrease_volume(10);
+// `forward` stops short of the end by this much, the track's last second still plays
+const FORWARD_MARGIN_MS: u64 = 1000;
 
-        assert_eq!(p.volume(), 1.0);
+/// Where forwarding `dur_millis` from `progress_ms` lands in a track `end_ms`
+/// long: a second before the end at the latest, `None` once past it.
+pub(crate) fn forward_target(
+    progress_ms: u64,
+    dur_millis: u64,
+    end_ms: Option<u64>,
+) -> Option<u64> {
+    let target = progress_ms.saturating_add(dur_millis);
+    match end_ms {
+        Some(end) if target >= end => None,
+        Some(end) => Some(target.min(end.saturating_sub(FORWARD_MARGIN_MS)).max(progress_ms)),
+        None => Some(target),
     }
+}
 
-    #[test]
-    fn test_pause() {
-        let (mut p, track) = new();
-        p.play(track).unwrap();
+/// Decoded samples fed into the playback chain.
+type TrackSource = Box<dyn Source<Item = f32> + Send>;
 
-        for i in [0, 1, 2, 3] {
-            thread::sleep(Duration::from_secs(5));
-            if i % 2 == 0 {
-                p.pause();
-            } else {
-                p.resume();
-            }
+/// Names of the output devices of the default host, for `try_new_with_device`.
+pub fn list_output_devices() -> Vec<String> {
+    match cpal::default_host().output_devices() {
+        Ok(devices) => devices.filter_map(|device| device.name().ok()).collect(),
+        Err(e) => {
+            log::warn!("list output devices error: {}", e);
+            Vec::new()
         }
-
-        println!("must be passed about 4/2 * 5 = 10 seconds");
-        println!(
-            "timeline cursor: {}s",
-            p.playback_context.progress_ms.load(Ordering::Relaxed) / 1000
-        );
     }
+}
 
-    #[test]
-    fn test_drain_sink() {
-        let (mut p, track) = new();
-        p.play(track).unwrap();
-        thread::sleep(Duration::from_secs(5));
-        p.drain_sink();
-        p.sleep_until_end();
+// length of `source` from its decoded samples, decoding all of it
+fn counted_duration<S: Source<Item = f32>>(source: S) -> Option<Duration> {
+    let frame = source.channels() as u64 * source.sample_rate() as u64;
+    if frame == 0 {
+        return None;
     }
+    let samples = source.count() as u64;
+    Some(Duration::from_nanos(samples * 1_000_000_000 / frame))
+}
 
-    #[test]
-    fn test_reload() {
-        let (mut p, track) = new();
-        p.play(track).unwrap();
-        thread::sleep(Duration::from_secs(5));
-        p.drain_sink();
-        thread::sleep(Duration::from_secs(3));
-
-        p.replay();
-        thread::sleep(Duration::from_secs(5));
+// hands the track over again from its start while repeating one, called by
+// `Repeat` on the audio thread once the track ends
+fn restart(
+    context: Arc<PlaybackContext>,
+    track: Buffered<TrackSource>,
+    edges: Option<Edges>,
+) -> impl FnMut() -> Option<TrackSource> + Send {
+    move || {
+        if context.repeat_mode() != RepeatMode::One || context.stopped.load(Ordering::SeqCst) {
+            return None;
+        }
+        context.set_progress_ms(0);
+        let track = track.clone();
+        Some(match edges {
+            Some(edges) => Box::new(silence::trim(track, edges, Duration::default())),
+            None => Box::new(track),
+        })
     }
+}
 
-    // do_skip_duration will resolve to infinite loop when skip duration too large
-    #[test]
-    fn test_forward() {
-        let (mut p, track) = new();
-        p.play(track).unwrap();
+// the chain playing the current track
+struct Chain {
+    fades: Arc<Fades>,
+    handover: Arc<Handover>,
+}
 
-        for _ in [0; 5] {
-            thread::sleep(Duration::from_secs(5));
-            println!("fast forward 50 seconds");
-            p.forward(50 * 1000);
-        }
-        p.sleep_until_end();
-    }
+// a chain queued behind the current one, it takes the context over as it starts
+struct Successor {
+    duration_ms: Option<u64>,
+    started: Arc<AtomicBool>,
+}
 
-    #[test]
+// what's measured of a track by decoding all of it, off the thread that plays
+// it. `None` until measured
+#[derive(Default)]
+struct Analysis {
+    // audible part, while trimming silence
+    edges: Option<Option<Edges>>,
+    // integrated LUFS, while normalizing
+    loudness: Option<Option<f32>>,
+    // counted from the samples for tracks that tell nothing of their length
+    duration: Option<Option<Duration>>,
+    // a thread is measuring it
+    measuring: bool,
+    // never measured, e.g. a source from `play_source` that may be endless
+    unmeasured: bool,
+    // another track is current by now
+    stale: bool,
+}
+
+// a track decoded and checked, ready to become the current one
+struct Decoded {
+    source: Buffered<TrackSource>,
+    duration: Option<Duration>,
+    backend: DecoderBackend,
+    bytes: Bytes,
+}
+
+// the next queued track, already appended behind the current one
+struct Preloaded {
+    index: usize,
+    decoded: Decoded,
+    analysis: Arc<Mutex<Analysis>>,
+    chain: Chain,
+    started: Arc<AtomicBool>,
+}
+
+// the A-B loop of `track`, played while it's still the context's loop
+fn ab_restart(
+    context: Arc<PlaybackContext>,
+    track: Buffered<TrackSource>,
+    edges: Option<Edges>,
+    (start_ms, end_ms): (u64, u64),
+) -> impl FnMut() -> Option<TrackSource> + Send {
+    move || {
+        if context.ab_loop() != Some((start_ms, end_ms)) || context.stopped.load(Ordering::SeqCst) {
+            return None;
+        }
+        context.set_progress_ms(start_ms);
+        let start = Duration::from_millis(start_ms);
+        let track: TrackSource = match edges {
+            Some(edges) => Box::new(silence::trim(track.clone(), edges, start)),
+            None => Box::new(track.clone().skip_duration(start)),
+        };
+        Some(Box::new(track.take_duration(Duration::from_millis(end_ms - start_ms))))
+    }
+}
+
+// a track played from `play_url`
+struct StreamTrack {
+    url: String,
+    ranged: bool,
+    // from the first frame's bitrate, maps positions to byte offsets
+    bytes_per_ms: Option<f64>,
+    // where the current source starts in the track
+    offset_ms: u64,
+    start_byte: u64,
+    // bytes downloaded from `start_byte` on
+    contiguous: Arc<AtomicU64>,
+}
+
+// the device stream, alive as long as the player
+enum Output {
+    Rodio(OutputStream, OutputStreamHandle),
+    // opened by hand with a fixed buffer size
+    Fixed(cpal::Stream),
+}
+
+pub(crate) struct LAudioPlayer {
+    queue_tx: Arc<queue::SourcesQueueInput<f32>>,
+    end_signal: Arc<EndSignal>,
+
+    playback_context: Arc<PlaybackContext>,
+
+    detached: bool,
+    output: Output,
+    current_track: Mutex<Option<Buffered<TrackSource>>>,
+    current_stream: Mutex<Option<StreamTrack>>,
+    // what's measured of the current track so far
+    current_analysis: Arc<Mutex<Analysis>>,
+    // playback stops here instead of at the end of the track
+    range_end_ms: Option<u64>,
+    // tracks moved through by `next` and `prev`
+    tracks: VecDeque<Bytes>,
+    // the queued track being played
+    track_index: Option<usize>,
+    // duration estimated from the raw bytes, preferred over the decoder's
+    current_duration: Option<Duration>,
+    current_format: Option<DetectedFormat>,
+    // raw bytes of the current track while rodio can decode them, seeks
+    // decode afresh from there
+    current_bytes: Option<Bytes>,
+    current_chain: Mutex<Option<Chain>>,
+    // with gapless on, the queued track playing once the current one ends
+    preloaded: Arc<Mutex<Option<Preloaded>>>,
+    // bumped whenever what's preloaded is dropped, a preload still decoding
+    // for an older value is thrown away
+    preload_generation: Arc<AtomicU64>,
+}
+
+impl LAudioPlayer {
+    #[inline]
+    pub fn try_new() -> Result<Self, Box<dyn std::error::Error>> {
+        let (player, queue_rx) = Self::new_idle()?;
+        if let Output::Rodio(_, handle) = &player.output {
+            handle.play_raw(queue_rx)?;
+        }
+        Ok(player)
+    }
+
+    /// Opens the default device asking for a buffer of about `latency`.
+    ///
+    /// A larger buffer survives a busy or slow machine without underruns
+    /// (crackles, dropouts), but pausing, seeking and volume changes are
+    /// heard only once the buffered audio has played, so they lag by up to
+    /// `latency`. When the device can't do the requested size, or only takes
+    /// samples in a format other than f32, its default buffer is used instead.
+    pub fn try_new_with_latency(latency: Duration) -> Result<Self, Box<dyn std::error::Error>> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or_else(|| PlayerError::NoOutputDevice("no default device".to_owned()))?;
+        let supported = device.default_output_config()?;
+        let frames = buffer_frames(latency, supported.sample_rate().0, supported.buffer_size());
+        let frames = match frames {
+            Some(frames) if supported.sample_format() == SampleFormat::F32 => frames,
+            _ => {
+                log::warn!("latency {:?} unsupported by the output device, using its default", latency);
+                return Self::try_new();
+            }
+        };
+
+        let mut config = supported.config();
+        config.buffer_size = BufferSize::Fixed(frames);
+        let (queue_tx, queue_rx) = queue::queue(true);
+        // what rodio's mixer would do: match the device's channels and rate
+        let mut samples: UniformSourceIterator<_, f32> =
+            UniformSourceIterator::new(queue_rx, config.channels, config.sample_rate.0);
+        let stream = device.build_output_stream(
+            &config,
+            move |data: &mut [f32], _| {
+                for out in data.iter_mut() {
+                    *out = samples.next().unwrap_or(0.0);
+                }
+            },
+            |e| log::error!("output stream error: {}", e),
+        )?;
+        stream.play()?;
+        Ok(Self::with_output(queue_tx, Output::Fixed(stream)))
+    }
+
+    /// Plays through the output device called `name`, one of `list_output_devices`.
+    pub fn try_new_with_device(name: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let device = cpal::default_host()
+            .output_devices()?
+            .find(|device| device.name().map_or(false, |n| n == name))
+            .ok_or_else(|| PlayerError::NoOutputDevice(format!("none named {:?}", name)))?;
+        let (stream, handle) = rodio::OutputStream::try_from_device(&device)?;
+        let (queue_tx, queue_rx) = queue::queue(true);
+        handle.play_raw(queue_rx)?;
+        Ok(Self::with_output(queue_tx, Output::Rodio(stream, handle)))
+    }
+
+    /// A player on the default device with nothing playing the queue yet,
+    /// `NoOutputDevice` when there's no device to open.
+    #[inline]
+    pub fn new_idle() -> Result<(Self, queue::SourcesQueueOutput<f32>), PlayerError> {
+        let (stream, handle) = rodio::OutputStream::try_default()
+            .map_err(|e| PlayerError::NoOutputDevice(e.to_string()))?;
+        let (queue_tx, queue_rx) = queue::queue(true);
+        Ok((Self::with_output(queue_tx, Output::Rodio(stream, handle)), queue_rx))
+    }
+
+    fn with_output(queue_tx: Arc<queue::SourcesQueueInput<f32>>, output: Output) -> Self {
+        Self {
+            queue_tx,
+            end_signal: Arc::default(),
+
+            playback_context: Arc::new(PlaybackContext::default()),
+            detached: false,
+
+            output,
+            current_track: Mutex::new(None),
+            current_stream: Mutex::new(None),
+            current_analysis: Arc::default(),
+            range_end_ms: None,
+            tracks: VecDeque::new(),
+            track_index: None,
+            current_duration: None,
+            current_format: None,
+            current_bytes: None,
+            current_chain: Mutex::new(None),
+            preloaded: Arc::default(),
+            preload_generation: Arc::default(),
+        }
+    }
+
+    #[inline]
+    fn set_current_track(&mut self, track: Bytes) -> Result<(), PlayerError> {
+        let decoded = decode_track(track)?;
+        self.playback_context
+            .set_duration_ms(decoded.duration.map(|d| d.as_millis() as u64));
+        self.set_decoded(decoded);
+        Ok(())
+    }
+
+    fn set_decoded(&mut self, decoded: Decoded) {
+        let Decoded {
+            source,
+            duration,
+            backend,
+            bytes,
+        } = decoded;
+        self.reset_current(source, None);
+        self.current_duration = duration;
+        self.current_format = Some(DetectedFormat { backend });
+        self.current_bytes = Some(bytes).filter(|_| backend == DecoderBackend::Rodio);
+    }
+
+    #[inline]
+    fn set_current_source(&mut self, source: TrackSource) {
+        self.reset_current(source.buffered(), None);
+    }
+
+    // makes `source` the current track, forgetting all about the one before
+    fn reset_current(&mut self, source: Buffered<TrackSource>, stream: Option<StreamTrack>) {
+        *self.current_track.lock().unwrap() = Some(source);
+        *self.current_stream.lock().unwrap() = stream;
+        self.current_analysis.lock().unwrap().stale = true;
+        self.current_analysis = Arc::default();
+        self.range_end_ms = None;
+        self.current_duration = None;
+        self.current_format = None;
+        self.current_bytes = None;
+    }
+
+    /// Streams the track at `url`, playback starts while it downloads.
+    /// Seeking past what's downloaded jumps there with a ranged request if
+    /// the server supports it, otherwise it waits for the download.
+    pub fn play_url(&mut self, url: &str) -> Result<(), PlayerError> {
+        let stream_err = |e: std::io::Error| PlayerError::Stream(e.to_string());
+        let mut reader = RangeReader::open(url).map_err(stream_err)?;
+
+        let mut probe = Vec::new();
+        (&mut reader).take(8192).read_to_end(&mut probe).map_err(stream_err)?;
+        reader.seek(SeekFrom::Start(0)).map_err(stream_err)?;
+        let bitrate = mp3::first_frame_bitrate(&probe);
+
+        let stream = StreamTrack {
+            url: url.to_owned(),
+            ranged: reader.supports_range(),
+            bytes_per_ms: bitrate.map(|bitrate| bitrate as f64 / 8000.0),
+            offset_ms: 0,
+            start_byte: 0,
+            contiguous: reader.contiguous(),
+        };
+        let duration = match (reader.len(), stream.bytes_per_ms) {
+            (Some(len), Some(rate)) => Some(Duration::from_millis((len as f64 / rate) as u64)),
+            _ => None,
+        };
+
+        let source = decode_stream(reader)?;
+        self.reset_current(source, Some(stream));
+        self.current_duration = duration;
+        self.current_format = Some(DetectedFormat {
+            backend: DecoderBackend::Rodio,
+        });
+        // seeks reopen the stream instead
+        self.current_bytes = None;
+
+        self.playback_context.progress_ms.store(0, Ordering::SeqCst);
+        self.replay();
+        Ok(())
+    }
+
+    /// Plays `reader` as it arrives, e.g. a download: decoding starts once
+    /// `STREAM_PREBUFFER` is read and the rest is read ahead meanwhile.
+    /// The track isn't kept as bytes, seeking within it still works.
+    pub fn play_stream(&mut self, reader: Box<dyn Read + Send>) -> Result<(), PlayerError> {
+        let reader = ReadAhead::spawn(reader);
+        reader.prebuffer(STREAM_PREBUFFER);
+        let source = decode_stream(reader)?;
+        self.reset_current(source, None);
+        self.current_format = Some(DetectedFormat {
+            backend: DecoderBackend::Rodio,
+        });
+        // there are no bytes to queue
+        self.clear_queue();
+
+        self.playback_context.progress_ms.store(0, Ordering::SeqCst);
+        self.switch_track();
+        Ok(())
+    }
+
+    // restarts a ranged stream at `progress_ms` when that part isn't downloaded
+    // yet, instead of decoding all the way there
+    fn seek_stream(&self, progress_ms: u64) {
+        let mut current = self.current_stream.lock().unwrap();
+        let stream = match current.as_mut() {
+            Some(stream) if stream.ranged => stream,
+            _ => return,
+        };
+        let rate = match stream.bytes_per_ms {
+            Some(rate) => rate,
+            None => return,
+        };
+        let target = (progress_ms as f64 * rate) as u64;
+        let downloaded = stream.start_byte + stream.contiguous.load(Ordering::Relaxed);
+        if progress_ms >= stream.offset_ms && target <= downloaded {
+            return;
+        }
+
+        let source = match RangeReader::open_at(&stream.url, target)
+            .map_err(|e| PlayerError::Stream(e.to_string()))
+            .and_then(|reader| {
+                let contiguous = reader.contiguous();
+                decode_stream(reader).map(|source| (source, contiguous))
+            }) {
+            Ok(source) => source,
+            Err(e) => {
+                log::error!("seek stream to {}ms: {:?}", progress_ms, e);
+                return;
+            }
+        };
+        stream.offset_ms = progress_ms;
+        stream.start_byte = target;
+        stream.contiguous = source.1;
+        *self.current_track.lock().unwrap() = Some(source.0);
+    }
+
+    // where the current source starts in the track, only streams start past 0
+    fn source_offset_ms(&self) -> u64 {
+        self.current_stream
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map_or(0, |stream| stream.offset_ms)
+    }
+
+    /// How the current track was decoded, `None` for sources given to `play_source`.
+    #[inline]
+    pub fn detected_format(&self) -> Option<&DetectedFormat> {
+        self.current_format.as_ref()
+    }
+
+    /// Plays an already decoded source through the same pause/volume/progress chain as `play`.
+    /// Its length is only what it reports, and it's neither trimmed nor normalized.
+    pub fn play_source<S>(&mut self, source: S)
+    where
+        S: Source<Item = f32> + Send + 'static,
+    {
+        self.playback_context.progress_ms.store(0, Ordering::SeqCst);
+        self.set_current_source(Box::new(source));
+        self.current_analysis.lock().unwrap().unmeasured = true;
+        self.start_play(0);
+    }
+
+    /// Index of the playing track in the queue.
+    #[inline]
+    pub fn queue_index(&self) -> Option<usize> {
+        self.track_index
+    }
+
+    #[inline]
+    pub fn queue_len(&self) -> usize {
+        self.tracks.len()
+    }
+
+    // decodes the queued track at `index` and plays it from the start
+    fn play_queued(&mut self, index: usize) -> Result<(), PlayerError> {
+        let track = self
+            .tracks
+            .get(index)
+            .cloned()
+            .ok_or(PlayerError::InvalidIndex(index))?;
+        self.set_current_track(track)?;
+        self.track_index = Some(index);
+        self.playback_context.progress_ms.store(0, Ordering::SeqCst);
+        self.switch_track();
+        Ok(())
+    }
+
+    /// Plays `track` from `start_ms` and stops at `end_ms` as if it ended there,
+    /// e.g. for previews. The range is clamped to the track.
+    pub fn play_range(
+        &mut self,
+        track: Bytes,
+        start_ms: u64,
+        end_ms: u64,
+    ) -> Result<(), PlayerError> {
+        self.set_current_track(track)?;
+        let end_ms = match self.total_duration() {
+            Some(total) => end_ms.min(total.as_millis() as u64),
+            None => end_ms,
+        };
+        if start_ms >= end_ms {
+            return Err(PlayerError::InvalidRange { start_ms, end_ms });
+        }
+        self.range_end_ms = Some(end_ms);
+        self.playback_context
+            .progress_ms
+            .store(start_ms, Ordering::SeqCst);
+        self.replay();
+        Ok(())
+    }
+
+    /// Plays `start_ms..end_ms` of the current track over and over, e.g. to
+    /// practice a passage. It keeps looping through speed changes, seeking
+    /// out of it plays on normally.
+    pub fn set_ab_loop(&self, start_ms: u64, end_ms: u64) -> Result<(), PlayerError> {
+        let end_ms = match self.total_duration() {
+            Some(total) => end_ms.min(total.as_millis() as u64),
+            None => end_ms,
+        };
+        if start_ms >= end_ms {
+            return Err(PlayerError::InvalidRange { start_ms, end_ms });
+        }
+        *self.playback_context.ab_loop.lock().unwrap() = Some((start_ms, end_ms));
+        let progress = self.playback_context.progress_ms();
+        if progress < start_ms || progress >= end_ms {
+            self.playback_context.set_progress_ms(start_ms);
+        }
+        self.replay_playing();
+        Ok(())
+    }
+
+    /// Stops looping, the track plays on from where it is.
+    pub fn clear_ab_loop(&self) {
+        if self.playback_context.ab_loop.lock().unwrap().take().is_some() {
+            self.replay_playing();
+        }
+    }
+
+    // the chain playing is built for how things were, a stopped one stays so
+    fn replay_playing(&self) {
+        if !self.playback_context.stopped.load(Ordering::SeqCst) {
+            self.replay();
+        }
+    }
+
+    /// Pauses playback once `dur` passed, fading out over the last 10
+    /// seconds. Replaces a timer that's already set.
+    pub fn set_sleep_timer(&self, dur: Duration) {
+        *self.playback_context.sleep_at.lock().unwrap() = Some(Instant::now() + dur);
+    }
+
+    pub fn cancel_sleep_timer(&self) {
+        *self.playback_context.sleep_at.lock().unwrap() = None;
+    }
+
+    #[inline]
+    pub fn sleep_timer_remaining(&self) -> Option<Duration> {
+        self.playback_context.sleep_timer_remaining()
+    }
+
+    /// Skips the silence at both ends of tracks, from the next (re)start on
+    /// once a track's been measured.
+    #[inline]
+    pub fn set_trim_silence(&self, enabled: bool) {
+        self.playback_context.trim_silence.store(enabled, Ordering::Relaxed);
+        self.analyse();
+    }
+
+    // where the current track is audible while trimming, `None` until it's
+    // measured
+    fn edges(&self) -> Option<Edges> {
+        if !self.playback_context.trim_silence.load(Ordering::Relaxed) {
+            return None;
+        }
+        self.analyse();
+        self.current_analysis.lock().unwrap().edges.flatten()
+    }
+
+    /// Brings every track to `target_lufs` of integrated loudness, as soon as
+    /// it's measured. -14 is about what streaming services use, `None` plays
+    /// tracks as loud as they were mastered.
+    #[inline]
+    pub fn set_normalization(&self, target_lufs: Option<f32>) {
+        *self.playback_context.normalization.lock().unwrap() = target_lufs;
+        self.analyse();
+    }
+
+    // amplitude factor normalizing the current track as it's heard now
+    fn normalization_gain(&self) -> f32 {
+        normalization_gain(&self.playback_context, &self.current_analysis)
+    }
+
+    // measures what trimming, normalizing and the duration still need of the
+    // current track on a thread of its own. Streams aren't, that would
+    // download them whole first
+    fn analyse(&self) {
+        let context = &self.playback_context;
+        let track = self.current_track.lock().unwrap().clone();
+        let track = match track {
+            Some(track) if self.current_stream.lock().unwrap().is_none() => track,
+            _ => return,
+        };
+        let trimming = context.trim_silence.load(Ordering::Relaxed);
+        let normalizing = context.normalization.lock().unwrap().is_some();
+        let uncounted = self.current_duration.is_none() && track.total_duration().is_none();
+        let mut analysis = self.current_analysis.lock().unwrap();
+        if analysis.unmeasured {
+            return;
+        }
+        let edges = trimming && analysis.edges.is_none();
+        let loudness = normalizing && analysis.loudness.is_none();
+        let duration = uncounted && analysis.duration.is_none();
+        if analysis.measuring || !(edges || loudness || duration) {
+            return;
+        }
+        analysis.measuring = true;
+        drop(analysis);
+
+        let context = context.clone();
+        let shared = self.current_analysis.clone();
+        thread::spawn(move || {
+            let edges = Some(track.clone()).filter(|_| edges).map(silence::detect);
+            let loudness = Some(track.clone()).filter(|_| loudness).map(loudness::integrated);
+            let duration = Some(track).filter(|_| duration).map(counted_duration);
+            let mut analysis = shared.lock().unwrap();
+            analysis.measuring = false;
+            analysis.edges = analysis.edges.or(edges);
+            analysis.loudness = analysis.loudness.or(loudness);
+            if let Some(counted) = duration {
+                analysis.duration = Some(counted);
+                if !analysis.stale && context.duration_ms().is_none() {
+                    context.set_duration_ms(counted.map(|d| d.as_millis() as u64));
+                }
+            }
+        });
+    }
+
+    /// Total duration of the current track, the mp3 frame based estimate
+    /// wins over what the source reports. Trimmed silence isn't counted.
+    #[inline]
+    pub fn total_duration(&self) -> Option<Duration> {
+        if let Some(edges) = self.edges() {
+            return Some(edges.len);
+        }
+        self.current_duration
+            .or_else(|| {
+                self.current_track
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .and_then(|track| track.total_duration())
+            })
+            .or_else(|| self.current_analysis.lock().unwrap().duration.flatten())
+    }
+
+    // the current track decoded afresh and seeked right to `pos`, `None` when the
+    // decoder can't seek and the buffered track has to be skipped through
+    fn seeked(&self, pos: Duration) -> Option<TrackSource> {
+        if pos == Duration::default() {
+            return None;
+        }
+        let bytes = self.current_bytes.clone()?;
+        let mut decoder = Decoder::new(Cursor::new(bytes)).ok()?;
+        if let Err(e) = decoder.try_seek(pos) {
+            log::debug!("seek to {:?} unsupported, skipping there: {:?}", pos, e);
+            return None;
+        }
+        Some(Box::new(decoder.convert_samples()))
+    }
+
+    /// Overlaps consecutive tracks by `ms`: the one playing fades out while
+    /// the next fades in. 0 cuts straight from one to the next.
+    /// Turns gapless playback off for any `ms` above 0.
+    #[inline]
+    pub fn set_crossfade_ms(&self, ms: u64) {
+        self.playback_context.crossfade_ms.store(ms, Ordering::Relaxed);
+        if ms > 0 {
+            self.set_gapless(false);
+        }
+    }
+
+    // plays the new current track over the one that's playing, fading between
+    // both, or cuts to it
+    fn switch_track(&self) {
+        let context = &self.playback_context;
+        let ms = context.crossfade_ms();
+        if ms == 0 || context.stopped.load(Ordering::SeqCst) || self.is_paused() {
+            return self.replay();
+        }
+        self.start_play(ms);
+    }
+
+    // `fade_in_ms` above 0 mixes the track into the current chain, fading that out
+    #[inline]
+    fn start_play(&self, fade_in_ms: u64) {
+        let context = self.playback_context.clone();
+        let offset = self.source_offset_ms();
+        context.set_duration_ms(self.total_duration().map(|d| d.as_millis() as u64));
+
+        let edges = self.edges();
+        let analysis = self.current_analysis.clone();
+        let track = self.current_track.lock().unwrap().clone();
+        if let Some(source) = track {
+            let whole = source.clone();
+            // clip source by progress cursor
+            let progress = context.progress_ms.load(Ordering::SeqCst);
+            // skipping to or past the end would never return, the track is over
+            if let Some(total) = context.duration_ms().filter(|total| progress >= *total) {
+                context.set_progress_ms(total);
+                context.track_ended();
+                return;
+            }
+            let skip = Duration::from_millis(progress.saturating_sub(offset));
+            let source: TrackSource = match edges {
+                Some(edges) => Box::new(silence::trim(source, edges, skip)),
+                None => match self.seeked(skip) {
+                    Some(seeked) => seeked,
+                    None => Box::new(source.skip_duration(skip)),
+                },
+            };
+            let source: TrackSource = match self.range_end_ms {
+                Some(end) => {
+                    let left = Duration::from_millis(end.saturating_sub(progress));
+                    Box::new(source.take_duration(left))
+                }
+                // a stream reopened mid track can't start over by itself
+                None if offset == 0 => match context.ab_loop() {
+                    Some(ab) => {
+                        let left = Duration::from_millis(ab.1.saturating_sub(progress));
+                        let source: TrackSource = Box::new(source.take_duration(left));
+                        Box::new(Repeat::new(source, ab_restart(context.clone(), whole, edges, ab)))
+                    }
+                    None => Box::new(Repeat::new(source, restart(context.clone(), whole, edges))),
+                },
+                None => source,
+            };
+            let (source, chain) = chain(&self.playback_context, source, fade_in_ms, analysis, None);
+            let chain_fades = chain.fades.clone();
+            let previous = self.current_chain.lock().unwrap().replace(chain);
+            let source = match previous {
+                Some(previous) if fade_in_ms > 0 => match previous.handover.put(source) {
+                    Ok(()) => return previous.fades.fade_out(fade_in_ms),
+                    // it ended meanwhile, there's nothing to fade from
+                    Err(source) => {
+                        chain_fades.set_in_ms(0);
+                        source
+                    }
+                },
+                _ => source,
+            };
+            self.end_signal.set(self.queue_tx.append_with_signal(source));
+            self.preload();
+        }
+    }
+
+    // with gapless on, decodes the queued track after the current one and
+    // appends it right behind, it starts the moment this one runs out. The
+    // decoding happens on a thread of its own, starts and seeks don't wait for it
+    fn preload(&self) {
+        let generation = self.preload_generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let previous = self.preloaded.lock().unwrap().take();
+        if let Some(previous) = &previous {
+            previous.chain.fades.fade_out(1);
+        }
+        let context = self.playback_context.clone();
+        if !context.is_gapless() || context.repeat_mode() == RepeatMode::One {
+            return;
+        }
+        let index = match self.next_queued() {
+            Some(index) => index,
+            None => return,
+        };
+        // a seek replays the current track, what's decoded of the next is kept
+        let previous = previous.filter(|previous| previous.index == index);
+        let track = self.tracks[index].clone();
+        let queue_tx = self.queue_tx.clone();
+        let end_signal = self.end_signal.clone();
+        let preloaded = self.preloaded.clone();
+        let current = self.preload_generation.clone();
+        thread::spawn(move || {
+            let (decoded, analysis) = match previous {
+                Some(previous) => (previous.decoded, previous.analysis),
+                None => match decode_track(track) {
+                    Ok(decoded) => (decoded, Arc::default()),
+                    Err(e) => {
+                        log::warn!("preload queued track {}: {}", index, e);
+                        return;
+                    }
+                },
+            };
+            let whole = decoded.source.clone();
+            // already off the playing thread, measured right here
+            let (trimmed, duration) = {
+                let mut measured = analysis.lock().unwrap();
+                if context.trim_silence.load(Ordering::Relaxed) && measured.edges.is_none() {
+                    measured.edges = Some(silence::detect(whole.clone()));
+                }
+                let normalizing = context.normalization.lock().unwrap().is_some();
+                if normalizing && measured.loudness.is_none() {
+                    measured.loudness = Some(loudness::integrated(whole.clone()));
+                }
+                let uncounted = decoded.duration.is_none() && whole.total_duration().is_none();
+                if uncounted && measured.duration.is_none() {
+                    measured.duration = Some(counted_duration(whole.clone()));
+                }
+                let trimmed = measured.edges.flatten().filter(|_| {
+                    context.trim_silence.load(Ordering::Relaxed)
+                });
+                (trimmed, decoded.duration.or(measured.duration.flatten()))
+            };
+            let duration = trimmed.map(|edges| edges.len).or(duration);
+            let source: TrackSource = match trimmed {
+                Some(edges) => Box::new(silence::trim(whole.clone(), edges, Duration::default())),
+                None => Box::new(whole.clone()),
+            };
+            let source: TrackSource =
+                Box::new(Repeat::new(source, restart(context.clone(), whole, trimmed)));
+            let started = Arc::new(AtomicBool::new(false));
+            let successor = Successor {
+                duration_ms: duration.map(|d| d.as_millis() as u64),
+                started: started.clone(),
+            };
+
+            // held while appending, a cancel waits for the preload to be in place
+            let mut slot = preloaded.lock().unwrap();
+            let stale = current.load(Ordering::SeqCst) != generation;
+            if stale || context.stopped.load(Ordering::SeqCst) {
+                return;
+            }
+            let (source, chain) =
+                chain(&context, source, 0, analysis.clone(), Some(successor));
+            // the signal of the last source appended, when it fires nothing's left
+            end_signal.set(queue_tx.append_with_signal(source));
+            *slot = Some(Preloaded {
+                index,
+                decoded,
+                analysis,
+                chain,
+                started,
+            });
+        });
+    }
+
+    // keeps a preloaded track that hasn't started from ever playing
+    fn cancel_preload(&self) {
+        self.preload_generation.fetch_add(1, Ordering::SeqCst);
+        if let Some(preloaded) = self.preloaded.lock().unwrap().take() {
+            preloaded.chain.fades.fade_out(1);
+        }
+    }
+
+    // the queued track after the current one, per repeat mode
+    fn next_queued(&self) -> Option<usize> {
+        let next = self.track_index? + 1;
+        if next < self.tracks.len() {
+            Some(next)
+        } else if self.playback_context.repeat_mode() == RepeatMode::All && !self.tracks.is_empty() {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    // makes the preloaded track the current one once it's playing, `false`
+    // while it hasn't started
+    fn adopt_preloaded(&mut self) -> bool {
+        let preloaded = self.preloaded.lock().unwrap().take();
+        let preloaded = match preloaded {
+            Some(preloaded) if preloaded.started.load(Ordering::SeqCst) => preloaded,
+            other => {
+                *self.preloaded.lock().unwrap() = other;
+                return false;
+            }
+        };
+        let Preloaded {
+            index,
+            decoded,
+            analysis,
+            chain,
+            ..
+        } = preloaded;
+        self.set_decoded(decoded);
+        self.current_analysis = analysis;
+        *self.current_chain.lock().unwrap() = Some(chain);
+        self.track_index = Some(index);
+        self.preload();
+        true
+    }
+
+    /// The value `1.0` is the "normal" volume (unfiltered input). Any value other than 1.0 will
+    /// multiply each sample by this value.
+    #[inline]
+    pub fn volume(&self) -> f32 {
+        volume::amplitude(*self.playback_context.volume.lock().unwrap())
+    }
+
+    /// The volume as it's heard, 0 to 100. 90 is the "normal" volume.
+    #[inline]
+    pub fn volume_percent(&self) -> u8 {
+        self.playback_context.volume.lock().unwrap().round() as u8
+    }
+
+    #[inline]
+    pub fn set_volume(&self, value: f32) {
+        *self.playback_context.volume.lock().unwrap() = volume::percent(value);
+    }
+
+    /// Forces the soft clipping limiter on or off. By default it's only
+    /// active while the volume is above 1.0.
+    #[inline]
+    pub fn set_limiter(&self, enabled: bool) {
+        *self.playback_context.limiter.lock().unwrap() = Some(enabled);
+    }
+
+    /// Downmixes stereo tracks to mono, for single-ear listening or a mono speaker.
+    #[inline]
+    pub fn set_mono(&self, enabled: bool) {
+        self.playback_context.mono.store(enabled, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn crossfade_curve(&self) -> CrossfadeCurve {
+        *self.playback_context.crossfade_curve.lock().unwrap()
+    }
+
+    /// Picks the gain law used when fading between tracks.
+    #[inline]
+    pub fn set_crossfade_curve(&self, curve: CrossfadeCurve) {
+        *self.playback_context.crossfade_curve.lock().unwrap() = curve;
+    }
+
+    /// Plays at `value` from now on, remembered for the kind of track playing.
+    #[inline]
+    pub fn set_speed(&self, value: f32) {
+        self.playback_context.set_speed(value);
+    }
+
+    /// Resumes playback of a paused sink.
+    /// No effect if not paused.
+    #[inline]
+    pub fn resume(&self) {
+        self.playback_context.pause.store(false, Ordering::SeqCst);
+    }
+
+    /// Pauses playback of this sink.
+    pub fn pause(&self) {
+        self.playback_context.pause.store(true, Ordering::SeqCst);
+    }
+
+    /// Gets if a sink is paused
+    pub fn is_paused(&self) -> bool {
+        self.playback_context.pause.load(Ordering::SeqCst)
+    }
+
+    /// Stops the sink by emptying the queue.
+    #[inline]
+    pub fn drain_sink(&self) {
+        self.playback_context.stopped.store(true, Ordering::SeqCst);
+        // a preload finishing now would start playing by itself
+        self.preload_generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Replay the current track but with new playback_context
+    pub fn replay(&self) {
+        self.drain_sink();
+        self.sleep_until_end();
+        self.playback_context.stopped.store(false, Ordering::SeqCst);
+        self.start_play(0);
+    }
+
+    /// Moves the progress cursor within `[0, total_duration]` and replays from there.
+    /// Landing right on the end finishes the track instead, skipping past the
+    /// end of a source would never return.
+    pub fn set_progress_clamped(&self, progress_ms: u64) {
+        let end = self.total_duration().map(|d| d.as_millis() as u64);
+        let progress = match end {
+            Some(end) => progress_ms.min(end),
+            None => progress_ms,
+        };
+        let mut ab_loop = self.playback_context.ab_loop.lock().unwrap();
+        if matches!(*ab_loop, Some((start, end)) if progress < start || progress > end) {
+            *ab_loop = None;
+        }
+        drop(ab_loop);
+        self.playback_context
+            .progress_ms
+            .store(progress, Ordering::SeqCst);
+
+        if Some(progress) == end {
+            self.finish();
+        } else {
+            self.seek_stream(progress);
+            self.replay();
+        }
+    }
+
+    // the track is over, there's nothing left to replay
+    fn finish(&self) {
+        self.drain_sink();
+        self.sleep_until_end();
+        self.playback_context.track_ended();
+    }
+    /// Destroys the sink without stopping the sounds that are still playing.
+    /// The output stream can't outlive the player, so this blocks until the
+    /// queued sound ends and releases the stream afterwards.
+    #[inline]
+    pub fn detach(mut self) {
+        self.detached = true;
+        self.sleep_until_end();
+    }
+
+    /// Sleeps the current thread until the sound ends or `wake` is called.
+    #[inline]
+    pub fn sleep_until_end(&self) {
+        self.end_signal.wait();
+    }
+
+    /// Returns early from a `sleep_until_end` blocked on another thread.
+    #[inline]
+    pub fn wake(&self) {
+        self.end_signal.wake();
+    }
+
+    /// Handle to `wake` the player from threads it can't be shared with.
+    #[inline]
+    pub fn waker(&self) -> Arc<EndSignal> {
+        self.end_signal.clone()
+    }
+}
+
+// the track as it's heard, from `source` on: speed, volume and fades, the
+// progress and the end notice
+fn chain(
+    playback_context: &Arc<PlaybackContext>,
+    source: TrackSource,
+    fade_in_ms: u64,
+    analysis: Arc<Mutex<Analysis>>,
+    mut successor: Option<Successor>,
+) -> (TrackSource, Chain) {
+    let context = playback_context.clone();
+    let speed = *context.speed.lock().unwrap();
+    let source = source.speed(speed);
+
+    // track time advances `speed` times faster than the wall clock, the
+    // fraction left over is carried so nothing is lost to rounding
+    let interval_ms = context.progress_interval_ms.load(Ordering::Relaxed);
+    let mut played_ms = 0.0f64;
+    // fades go by wall clock
+    let fades = Arc::new(Fades::fading_in(fade_in_ms));
+    let chain_fades = fades.clone();
+    let curve = *context.crossfade_curve.lock().unwrap();
+    let (mut faded_in, mut faded_out) = (0u64, 0u64);
+    let source = Downmix::new(source.pausable(false), false).amplify(1.0);
+    let source = Limiter::new(source, false)
+        .stoppable()
+        .periodic_access(
+            Duration::from_millis(interval_ms),
+            move |src| {
+                if context.stopped.load(Ordering::SeqCst) {
+                    return src.stop();
+                }
+                if let Some(successor) = successor.take() {
+                    // cancelled before it got to play
+                    if fades.out_ms().is_some() {
+                        return src.stop();
+                    }
+                    context.set_progress_ms(0);
+                    context.set_duration_ms(successor.duration_ms);
+                    successor.started.store(true, Ordering::SeqCst);
+                }
+
+                let mut gain = 1.0;
+                let in_ms = fades.in_ms();
+                if faded_in < in_ms {
+                    gain *= curve.gains(faded_in as f32 / in_ms as f32).1;
+                }
+                let out_ms = fades.out_ms();
+                if let Some(out_ms) = out_ms {
+                    if faded_out >= out_ms {
+                        return src.stop();
+                    }
+                    gain *= curve.gains(faded_out as f32 / out_ms as f32).0;
+                }
+
+                gain *= context.sleep_gain();
+
+                let volume = volume::amplitude(*context.volume.lock().unwrap())
+                    * normalization_gain(&context, &analysis);
+                let limiter = context.limiter.lock().unwrap().unwrap_or(volume > 1.0);
+                let limited = src.inner_mut();
+                limited.set_enabled(limiter);
+                let amplified = limited.inner_mut();
+                amplified.set_factor(volume * gain);
+                let mixed = amplified.inner_mut();
+                mixed.set_enabled(context.mono.load(Ordering::Relaxed));
+
+                let paused = context.pause.load(Ordering::SeqCst);
+                mixed.inner_mut().set_paused(paused);
+
+                if paused {
+                    return;
+                }
+                faded_in = (faded_in + interval_ms).min(in_ms);
+                // the progress is the incoming track's from here on
+                if out_ms.is_some() {
+                    faded_out += interval_ms;
+                    return;
+                }
+                played_ms += interval_ms as f64 * speed as f64;
+                let whole = played_ms as u64;
+                played_ms -= whole as f64;
+                let progress = context.progress_ms.fetch_add(whole, Ordering::Relaxed) + whole;
+                // told `crossfade_ms` early, the next track fades in over the end of this one
+                let crossfade_ms = context.crossfade_ms();
+                if crossfade_ms > 0 {
+                    let total = context.duration_ms().filter(|total| *total > crossfade_ms);
+                    if let Some(total) = total {
+                        if progress + crossfade_ms >= total && fades.tell_end() {
+                            context.track_ended();
+                        }
+                    }
+                }
+            },
+        )
+        .convert_samples();
+    // what's heard, after volume and fades
+    let source = Meter::new(source, playback_context.clone());
+    let source = NotifyEnd::new(source, playback_context.clone(), chain_fades.clone());
+    let handover = Arc::new(Handover::new());
+    let source: TrackSource = Box::new(Overlay::new(source, handover.clone()));
+
+    let chain = Chain {
+        fades: chain_fades,
+        handover,
+    };
+    (source, chain)
+}
+
+// amplitude factor bringing a track measured in `analysis` to the loudness
+// targeted, 1.0 while normalization is off or it's not measured yet
+fn normalization_gain(context: &PlaybackContext, analysis: &Mutex<Analysis>) -> f32 {
+    let target = match *context.normalization.lock().unwrap() {
+        Some(target) => target,
+        None => return 1.0,
+    };
+    match analysis.lock().unwrap().loudness.flatten() {
+        Some(lufs) => loudness::gain(lufs, target),
+        None => 1.0,
+    }
+}
+
+fn decode_track(track: Bytes) -> Result<Decoded, PlayerError> {
+    if track.is_empty() {
+        return Err(PlayerError::Unsupported("empty track".to_owned()));
+    }
+
+    let duration = mp3::estimate_duration(&track);
+    let bytes = track.clone();
+    let (source, backend): (TrackSource, _) = match Decoder::new(Cursor::new(track.clone())) {
+        Ok(source) => (Box::new(source.convert_samples()), DecoderBackend::Rodio),
+        Err(e) => match SymphoniaSource::new(track) {
+            Ok(source) => (Box::new(source), DecoderBackend::Symphonia),
+            Err(fallback) => {
+                return Err(PlayerError::DecodeTrackError {
+                    format: detect_format(&bytes),
+                    reason: format!("{}, symphonia: {}", e, fallback),
+                })
+            }
+        },
+    };
+    let source = source.buffered();
+
+    // a valid header without any frame would end instantly
+    if source.clone().next().is_none() {
+        return Err(PlayerError::Unsupported("no audio frames".to_owned()));
+    }
+
+    // some streamed mp3s tell nothing about their length, it's counted by
+    // `LAudioPlayer::analyse` then
+    let duration = duration.or_else(|| source.total_duration());
+    Ok(Decoded {
+        source,
+        duration,
+        backend,
+        bytes,
+    })
+}
+
+fn decode_stream<R>(mut reader: R) -> Result<Buffered<TrackSource>, PlayerError>
+where
+    R: Read + Seek + Send + Sync + 'static,
+{
+    let format = sniff(&mut reader);
+    let source = Decoder::new(reader).map_err(|e| PlayerError::DecodeTrackError {
+        format,
+        reason: e.to_string(),
+    })?;
+    let source: TrackSource = Box::new(source.convert_samples());
+    Ok(source.buffered())
+}
+
+// the container at the reader's position, which is left where it was
+fn sniff<R: Read + Seek>(reader: &mut R) -> AudioFormat {
+    let start = match reader.stream_position() {
+        Ok(start) => start,
+        Err(_) => return AudioFormat::Unknown,
+    };
+    let mut head = [0u8; 12];
+    let mut len = 0;
+    while len < head.len() {
+        match reader.read(&mut head[len..]) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => len += n,
+        }
+    }
+    match reader.seek(SeekFrom::Start(start)) {
+        Ok(_) => detect_format(&head[..len]),
+        Err(_) => AudioFormat::Unknown,
+    }
+}
+
+// frames for a buffer of `latency`, `None` if the device can't take that many
+fn buffer_frames(latency: Duration, sample_rate: u32, supported: &SupportedBufferSize) -> Option<u32> {
+    let frames = (latency.as_secs_f64() * sample_rate as f64).round() as u32;
+    match *supported {
+        SupportedBufferSize::Range { min, max } if frames > 0 && (min..=max).contains(&frames) => Some(frames),
+        _ => None,
+    }
+}
+
+impl Drop for LAudioPlayer {
+    #[inline]
+    fn drop(&mut self) {
+        self.queue_tx.set_keep_alive_if_empty(false);
+
+        if !self.detached {
+            self.playback_context.stopped.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+impl AudioPlayer for LAudioPlayer {
+    fn play(&mut self, track: Bytes) -> Result<(), PlayerError> {
+        self.cancel_sleep_timer();
+        self.set_current_track(track.clone())?;
+        // a queue of its own, whoever plays it queues what follows
+        self.cancel_preload();
+        self.tracks = VecDeque::from(vec![track]);
+        self.track_index = Some(0);
+        self.playback_context.progress_ms.store(0, Ordering::SeqCst);
+        self.switch_track();
+        Ok(())
+    }
+
+    // the one coming right after the current track is preloaded if it's playing
+    fn enqueue(&mut self, track: Bytes) -> Result<(), PlayerError> {
+        self.tracks.push_back(track);
+        let playing = !self.playback_context.stopped.load(Ordering::SeqCst);
+        if playing && self.next_queued() == Some(self.tracks.len() - 1) {
+            self.preload();
+        }
+        Ok(())
+    }
+
+    fn clear_queue(&mut self) {
+        self.cancel_preload();
+        self.tracks.clear();
+        self.track_index = None;
+    }
+
+    fn trim_queue(&mut self) {
+        let played = match self.track_index {
+            Some(index) => index,
+            None => return self.clear_queue(),
+        };
+        self.tracks.drain(..played);
+        self.track_index = Some(0);
+        let kept = match self.preloaded.lock().unwrap().as_mut() {
+            Some(preloaded) if preloaded.index >= played => {
+                preloaded.index -= played;
+                true
+            }
+            // one of those dropped, repeating all, or still decoding for
+            // an index that's moved
+            _ => false,
+        };
+        if !kept {
+            self.preload();
+        }
+    }
+
+    fn play_url(&mut self, url: &str) -> Result<(), PlayerError> {
+        self.cancel_sleep_timer();
+        // played outside of the queue
+        self.clear_queue();
+        LAudioPlayer::play_url(self, url)
+    }
+
+    fn pause(&self) {
+        self.pause();
+    }
+
+    fn resume(&self) {
+        self.resume();
+    }
+
+    fn stop(&self) {
+        self.drain_sink();
+        self.playback_context.samples.clear();
+    }
+
+    fn next(&mut self) -> Result<(), PlayerError> {
+        if self.adopt_preloaded() {
+            return Ok(());
+        }
+        let next = self.track_index.map_or(0, |i| i + 1);
+        if next < self.tracks.len() {
+            return self.play_queued(next);
+        }
+        if self.playback_context.repeat_mode() == RepeatMode::All && !self.tracks.is_empty() {
+            return self.play_queued(0);
+        }
+        // past the last track
+        self.drain_sink();
+        self.track_index = None;
+        Ok(())
+    }
+
+    fn prev(&mut self) -> Result<(), PlayerError> {
+        self.adopt_preloaded();
+        match self.track_index {
+            Some(i) if i > 0 && self.playback_context.progress_ms() < PREV_RESTART_MS => {
+                self.play_queued(i - 1)
+            }
+            Some(_) => {
+                self.set_progress_clamped(0);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn increase_volume(&self, delta: u8) -> u8 {
+        let new_vol = self
+            .playback_context
+            .change_volume(|vol| (vol + delta as f32).min(100.0));
+        new_vol.round() as u8
+    }
+
+    fn decrease_volume(&self, delta: u8) -> u8 {
+        let new_vol = self
+            .playback_context
+            .change_volume(|vol| (vol - delta as f32).max(0.0));
+        new_vol.round() as u8
+    }
+
+    fn seek_ms(&self, progress_ms: u64) {
+        self.set_progress_clamped(progress_ms);
+    }
+
+    fn forward(&mut self, dur_millis: u64) -> u64 {
+        let cursor = self.playback_context.progress_ms();
+        let end = self.total_duration().map(|d| d.as_millis() as u64);
+        match forward_target(cursor, dur_millis, end) {
+            Some(target) => self.set_progress_clamped(target),
+            None if self.playback_context.repeat_mode() == RepeatMode::One => {
+                self.set_progress_clamped(0)
+            }
+            // whoever queues the tracks hears of the end and moves on
+            None => self.set_progress_clamped(cursor.saturating_add(dur_millis)),
+        }
+        self.playback_context.progress_ms()
+    }
+
+    fn rewind(&self, dur_millis: u64) -> u64 {
+        let cursor = self.playback_context.progress_ms();
+        self.set_progress_clamped(cursor.saturating_sub(dur_millis));
+        self.playback_context.progress_ms()
+    }
+
+    fn speed(&self, speed: f32) {
+        self.set_speed(speed);
+        self.replay();
+    }
+
+    // from the next track started on. The track-end callback still fires,
+    // `next` then takes over the track already playing
+    fn set_gapless(&self, enabled: bool) {
+        self.playback_context.set_gapless(enabled);
+        if !enabled {
+            self.cancel_preload();
+        }
+    }
+
+    fn playback_context(&self) -> Arc<PlaybackContext> {
+        self.playback_context.clone()
+    }
+
+    fn play_stream(&mut self, reader: Box<dyn Read + Send>) -> Result<(), PlayerError> {
+        LAudioPlayer::play_stream(self, reader)
+    }
+
+    fn detach(self: Box<Self>) {
+        LAudioPlayer::detach(*self);
+    }
+}
+
+#[cfg(test)]
+mod light_audio_player_tests {
+    use std::io::Read;
+    use std::sync::atomic::Ordering;
+    use std::{fs::File, thread, time::{Duration, Instant}};
+
+    use bytes::Bytes;
+    use rodio::buffer::SamplesBuffer;
+    use rodio::source::{SineWave, Source};
+
+    use super::fallback::tests::{ima_adpcm_wav, SAMPLES_PER_BLOCK};
+    use super::silence::tests::padded_tone;
+    use super::stream::tests::serve;
+    use rodio::cpal::SupportedBufferSize;
+
+    use super::{
+        buffer_frames, counted_duration, forward_target, list_output_devices, AudioFormat,
+        AudioPlayer, DecoderBackend, LAudioPlayer, PlaybackContext, PlayerError, RepeatMode,
+        TrackKind,
+    };
+
+    fn new() -> (LAudioPlayer, Bytes) {
+        let p = LAudioPlayer::try_new().unwrap();
+        let mut file = File::open("test-data/bfs.mp3").unwrap();
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf).unwrap();
+        let track = Bytes::from(buf);
+        (p, track)
+    }
+
+    #[test]
+    fn test_play() {
+        let (mut p, track) = new();
+        p.play(track).unwrap();
+        assert_eq!(p.detected_format().unwrap().backend, DecoderBackend::Rodio);
+        p.sleep_until_end();
+    }
+
+    #[test]
+    fn test_play_empty_track() {
+        let mut p = LAudioPlayer::try_new().unwrap();
+        let res = p.play(Bytes::new());
+        assert!(matches!(res, Err(PlayerError::Unsupported(_))));
+        assert!(p.current_track.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_play_truncated_track() {
+        let (mut p, track) = new();
+        let res = p.play(track.slice(..64));
+        assert!(matches!(
+            res,
+            Err(PlayerError::Unsupported(_)) | Err(PlayerError::DecodeTrackError { .. })
+        ));
+        assert!(p.current_track.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_decode_error_names_format() {
+        let mut p = LAudioPlayer::try_new().unwrap();
+        let mut wav = b"RIFF\x6c\0\0\0WAVE".to_vec();
+        wav.extend_from_slice(&[7u8; 100]);
+        let err = p.play(Bytes::from(wav)).unwrap_err();
+        assert!(matches!(
+            err,
+            PlayerError::DecodeTrackError {
+                format: AudioFormat::Wav,
+                ..
+            }
+        ));
+        assert!(err.to_string().starts_with("decode track (wav, rodio `wav` "), "{}", err);
+    }
+
+    #[test]
+    fn test_play_fallback_decoder() {
+        let mut p = LAudioPlayer::try_new().unwrap();
+        p.play(ima_adpcm_wav(16)).unwrap();
+        assert_eq!(p.detected_format().unwrap().backend, DecoderBackend::Symphonia);
+        p.sleep_until_end();
+        assert_eq!(p.playback_context.samples.written(), 16 * SAMPLES_PER_BLOCK);
+    }
+
+    #[test]
+    fn test_play_range() {
+        let (mut p, track) = new();
+        let started = Instant::now();
+        p.play_range(track, 2000, 4000).unwrap();
+        p.sleep_until_end();
+
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(1800), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(3000), "{:?}", elapsed);
+        let progress = p.playback_context.progress_ms();
+        assert!((3800..=4200).contains(&progress), "{}", progress);
+    }
+
+    #[test]
+    fn test_play_invalid_range() {
+        let (mut p, track) = new();
+        let res = p.play_range(track.clone(), 3000, 3000);
+        assert!(matches!(res, Err(PlayerError::InvalidRange { .. })));
+
+        // past the end once clamped
+        let res = p.play_range(track, 3_600_000, 3_700_000);
+        assert!(matches!(res, Err(PlayerError::InvalidRange { .. })));
+    }
+
+    #[test]
+    fn test_play_source() {
+        let mut p = LAudioPlayer::try_new().unwrap();
+        // two seconds at 44.1kHz mono
+        let source = SamplesBuffer::new(1, 44100, vec![0.1f32; 88200]);
+        p.play_source(source);
+        assert_eq!(p.total_duration(), Some(Duration::from_secs(2)));
+
+        thread::sleep(Duration::from_secs(1));
+        assert!(p.playback_context.progress_ms.load(Ordering::Relaxed) > 0);
+        p.sleep_until_end();
+    }
+
+    #[test]
+    fn test_play_endless_source() {
+        let mut p = LAudioPlayer::try_new().unwrap();
+        p.set_trim_silence(true);
+        p.set_normalization(Some(-14.0));
+        p.play_source(SineWave::new(440.0));
+
+        // measuring it would never end
+        assert!(!p.current_analysis.lock().unwrap().measuring);
+        assert_eq!(p.total_duration(), None);
+        assert_eq!(p.normalization_gain(), 1.0);
+        p.stop();
+    }
+
+    #[test]
+    fn test_detach() {
+        let mut p = LAudioPlayer::try_new().unwrap();
+        let context = p.playback_context.clone();
+        p.play_source(SineWave::new(440.0).take_duration(Duration::from_secs(1)));
+
+        p.detach();
+        assert!(!context.stopped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_volume() {
+        let p = LAudioPlayer::try_new().unwrap();
+        p.increase_volume(10);
+        p.decrease_volume(10);
+
+        assert_eq!(p.volume(), 1.0);
+        assert_eq!(p.volume_percent(), 90);
+    }
+
+    #[test]
+    fn test_volume_clamped() {
+        let p = LAudioPlayer::try_new().unwrap();
+        assert_eq!(p.increase_volume(50), 100);
+        assert!((p.volume() - 2.0).abs() < 1e-6);
+        assert_eq!(p.decrease_volume(250), 0);
+        assert_eq!(p.volume(), 0.0);
+    }
+
+    #[test]
+    fn test_pause() {
+        let (mut p, track) = new();
+        p.play(track).unwrap();
+
+        for i in [0, 1, 2, 3] {
+            thread::sleep(Duration::from_secs(5));
+            if i % 2 == 0 {
+                p.pause();
+            } else {
+                p.resume();
+            }
+        }
+
+        println!("must be passed about 4/2 * 5 = 10 seconds");
+        println!(
+            "timeline cursor: {}s",
+            p.playback_context.progress_ms.load(Ordering::Relaxed) / 1000
+        );
+    }
+
+    #[test]
+    fn test_drain_sink() {
+        let (mut p, track) = new();
+        p.play(track).unwrap();
+        thread::sleep(Duration::from_secs(5));
+        p.drain_sink();
+        p.sleep_until_end();
+    }
+
+    #[test]
+    fn test_on_track_end() {
+        let (mut p, track) = new();
+        let (tx, rx) = std::sync::mpsc::channel();
+        p.playback_context.on_track_end(Box::new(move || {
+            let _ = tx.send(());
+        }));
+
+        p.play_range(track.clone(), 0, 1000).unwrap();
+        p.sleep_until_end();
+        rx.recv_timeout(Duration::from_secs(1)).unwrap();
+
+        // stopping isn't the end of the track
+        p.play(track).unwrap();
+        thread::sleep(Duration::from_millis(500));
+        p.drain_sink();
+        p.sleep_until_end();
+        assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+    }
+
+    #[test]
+    fn test_crossfade() {
+        let (mut p, track) = new();
+        p.set_crossfade_ms(1000);
+        assert_eq!(p.playback_context.crossfade_ms(), 1000);
+        p.play(track.clone()).unwrap();
+        thread::sleep(Duration::from_secs(2));
+
+        // the next track starts right away, over the fading one
+        let switched = Instant::now();
+        p.play(track).unwrap();
+        assert!(switched.elapsed() < Duration::from_millis(100));
+        thread::sleep(Duration::from_millis(1500));
+        // only the incoming track moves the progress
+        let progress = p.playback_context.progress_ms();
+        assert!((1200..=1800).contains(&progress), "{}", progress);
+
+        p.drain_sink();
+        p.sleep_until_end();
+    }
+
+    #[test]
+    fn test_crossfade_natural_end() {
+        let (mut p, track) = new();
+        let (tx, rx) = std::sync::mpsc::channel();
+        p.playback_context.on_track_end(Box::new(move || {
+            let _ = tx.send(Instant::now());
+        }));
+        p.set_crossfade_ms(1000);
+        p.play(track.clone()).unwrap();
+        let total = p.playback_context.duration_ms().unwrap();
+        p.seek_ms(total - 3000);
+        let seeked = Instant::now();
+
+        // told a second ahead, for the next track to fade in over the end
+        let ended = rx.recv_timeout(Duration::from_secs(3)).unwrap();
+        let ms = (ended - seeked).as_millis();
+        assert!((1500..=2600).contains(&ms), "{}", ms);
+        p.play(track).unwrap();
+        // the outgoing track running out isn't another end
+        assert!(rx.recv_timeout(Duration::from_millis(1500)).is_err());
+
+        p.drain_sink();
+        p.sleep_until_end();
+    }
+
+    #[test]
+    fn test_reload() {
+        let (mut p, track) = new();
+        p.play(track).unwrap();
+        thread::sleep(Duration::from_secs(5));
+        p.drain_sink();
+        thread::sleep(Duration::from_secs(3));
+
+        p.replay();
+        thread::sleep(Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_forward_past_end() {
+        let (mut p, track) = new();
+        p.play(track).unwrap();
+        let end = p.total_duration().unwrap().as_millis() as u64;
+
+        p.forward(end * 2);
+        assert_eq!(p.playback_context.progress_ms(), end);
+        assert!(p.playback_context.stopped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_rewind_past_start() {
+        let (mut p, track) = new();
+        p.play(track).unwrap();
+        thread::sleep(Duration::from_secs(1));
+
+        p.rewind(u64::MAX);
+        assert!(p.playback_context.progress_ms() < 1000);
+        assert!(!p.playback_context.stopped.load(Ordering::SeqCst));
+    }
+
+    // progress after playing a long sine for about `wall`
+    fn progress_after(interval_ms: u64, speed: f32, wall: Duration) -> u64 {
+        let mut p = LAudioPlayer::try_new().unwrap();
+        p.playback_context
+            .progress_interval_ms
+            .store(interval_ms, Ordering::Relaxed);
+        p.set_speed(speed);
+        p.play_source(SineWave::new(440.0).take_duration(Duration::from_secs(10)));
+        thread::sleep(wall);
+        let progress = p.playback_context.progress_ms();
+        p.stop();
+        progress
+    }
+
+    #[test]
+    fn test_progress_interval() {
+        let progress = progress_after(20, 1.0, Duration::from_millis(1000));
+        assert!((800..=1200).contains(&progress), "{}", progress);
+    }
+
+    #[test]
+    fn test_progress_scaled_by_speed() {
+        let progress = progress_after(5, 2.0, Duration::from_millis(1000));
+        assert!((1600..=2400).contains(&progress), "{}", progress);
+
+        let progress = progress_after(5, 0.5, Duration::from_millis(1000));
+        assert!((400..=600).contains(&progress), "{}", progress);
+    }
+
+    #[test]
+    fn test_forward_target() {
+        assert_eq!(forward_target(10_000, 5_000, Some(200_000)), Some(15_000));
+        assert_eq!(forward_target(195_000, 4_500, Some(200_000)), Some(199_000));
+        // already in the last second, it doesn't go back
+        assert_eq!(forward_target(199_500, 300, Some(200_000)), Some(199_500));
+        assert_eq!(forward_target(195_000, 5_000, Some(200_000)), None);
+        assert_eq!(forward_target(195_000, 5_000, None), Some(200_000));
+    }
+
+    #[test]
+    fn test_speed_per_track_kind() {
+        let context = PlaybackContext::default();
+        context.set_track_kind(TrackKind::Podcast);
+        context.set_speed(1.5);
+
+        // music plays at its own speed, 1.0x until changed
+        context.set_track_kind(TrackKind::Music);
+        assert_eq!(context.speed(), 1.0);
+        context.set_speed(1.25);
+
+        context.set_track_kind(TrackKind::Podcast);
+        assert_eq!(context.speed(), 1.5);
+        assert_eq!(context.speed_for(TrackKind::Music), 1.25);
+    }
+
+    #[test]
+    fn test_queue_next() {
+        let (mut p, track) = new();
+        p.enqueue(track.clone()).unwrap();
+        p.enqueue(track).unwrap();
+        assert_eq!(p.queue_index(), None);
+
+        p.next().unwrap();
+        assert_eq!(p.queue_index(), Some(0));
+        p.seek_ms(5000);
+        p.next().unwrap();
+        assert_eq!(p.queue_index(), Some(1));
+        assert!(p.playback_context.progress_ms() < 1000);
+
+        // nothing after the last one
+        p.next().unwrap();
+        assert_eq!(p.queue_index(), None);
+        assert!(p.playback_context.stopped.load(Ordering::SeqCst));
+        p.sleep_until_end();
+
+        p.set_repeat_mode(RepeatMode::All);
+        p.next().unwrap();
+        p.next().unwrap();
+        p.next().unwrap();
+        assert_eq!(p.queue_index(), Some(0));
+        p.clear_queue();
+        assert_eq!(p.queue_len(), 0);
+        p.stop();
+    }
+
+    #[test]
+    fn test_gapless() {
+        let (mut p, track) = new();
+        let (tx, rx) = std::sync::mpsc::channel();
+        p.playback_context.on_track_end(Box::new(move || {
+            let _ = tx.send(());
+        }));
+        p.set_crossfade_ms(1000);
+        p.set_gapless(true);
+        assert_eq!(p.playback_context.crossfade_ms(), 0);
+        p.enqueue(track.clone()).unwrap();
+        p.enqueue(track).unwrap();
+        p.next().unwrap();
+        let end = p.total_duration().unwrap().as_millis() as u64;
+        p.seek_ms(end - 1000);
+        rx.recv_timeout(Duration::from_secs(3)).unwrap();
+
+        // the next track is already playing, there's nothing to decode
+        let switched = Instant::now();
+        p.next().unwrap();
+        assert!(switched.elapsed() < Duration::from_millis(50));
+        assert_eq!(p.queue_index(), Some(1));
+        thread::sleep(Duration::from_millis(500));
+        let progress = p.playback_context.progress_ms();
+        assert!((300..=800).contains(&progress), "{}", progress);
+
+        p.set_crossfade_ms(500);
+        assert!(!p.playback_context.is_gapless());
+        p.stop();
+    }
+
+    #[test]
+    fn test_queue_prev() {
+        let (mut p, track) = new();
+        p.enqueue(track.clone()).unwrap();
+        p.enqueue(track).unwrap();
+        p.next().unwrap();
+        p.next().unwrap();
+
+        // past the first seconds it starts over
+        p.seek_ms(5000);
+        p.prev().unwrap();
+        assert_eq!(p.queue_index(), Some(1));
+        assert!(p.playback_context.progress_ms() < 1000);
+
+        p.prev().unwrap();
+        assert_eq!(p.queue_index(), Some(0));
+        // the first track can only restart
+        p.prev().unwrap();
+        assert_eq!(p.queue_index(), Some(0));
+        p.stop();
+    }
+
+    #[test]
+    fn test_repeat_one() {
+        let mut p = LAudioPlayer::try_new().unwrap();
+        p.set_repeat_mode(RepeatMode::One);
+        p.play_source(SineWave::new(440.0).take_duration(Duration::from_millis(200)));
+        // a manual seek replays, the mode stays
+        p.seek_ms(100);
+        assert_eq!(p.playback_context.repeat_mode(), RepeatMode::One);
+
+        let context = p.playback_context.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(700));
+            context.set_repeat_mode(RepeatMode::Off);
+        });
+        let started = Instant::now();
+        p.sleep_until_end();
+        // looped until the mode was turned off, then played to the end once more
+        assert!(started.elapsed() >= Duration::from_millis(700));
+        assert!(p.playback_context.progress_ms() <= 250);
+    }
+
+    #[test]
+    fn test_output_devices() {
+        let err = LAudioPlayer::try_new_with_device("no such device").err().unwrap();
+        assert!(matches!(
+            err.downcast_ref::<PlayerError>(),
+            Some(PlayerError::NoOutputDevice(_))
+        ));
+        if let Some(name) = list_output_devices().first() {
+            assert!(LAudioPlayer::try_new_with_device(name).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_toggle_mute() {
+        let p = LAudioPlayer::try_new().unwrap();
+        p.set_volume(0.8);
+        assert!(p.toggle_mute());
+        assert_eq!(p.volume(), 0.0);
+        assert!(p.playback_context.is_muted());
+
+        assert!(!p.toggle_mute());
+        assert!((p.volume() - 0.8).abs() < 1e-6);
+        assert!(!p.playback_context.is_muted());
+    }
+
+    #[test]
+    fn test_volume_change_while_muted() {
+        let p = LAudioPlayer::try_new().unwrap();
+        p.set_volume(0.5);
+        p.toggle_mute();
+
+        // still silent, but unmuting brings the new volume
+        assert_eq!(p.increase_volume(10), 90);
+        assert_eq!(p.volume(), 0.0);
+        assert!(!p.toggle_mute());
+        assert!((p.volume() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_duration_ms() {
+        let (mut p, track) = new();
+        assert_eq!(p.duration_ms(), None);
+        p.play(track).unwrap();
+        let total = p.total_duration().unwrap().as_millis() as u64;
+        assert_eq!(p.duration_ms(), Some(total));
+        p.stop();
+    }
+
+    #[test]
+    fn test_counted_duration() {
+        // 1.5s of stereo at 8kHz, `from_iter` doesn't announce a length
+        let buffer = SamplesBuffer::new(2, 8000, vec![0.1f32; 24_000]);
+        let source = rodio::source::from_iter(std::iter::once(buffer));
+        assert_eq!(counted_duration(source), Some(Duration::from_millis(1500)));
+    }
+
+    #[test]
+    fn test_seek_to() {
+        let (mut p, track) = new();
+        p.play(track).unwrap();
+
+        p.seek_to(Duration::from_secs(5));
+        let by_duration = p.playback_context.progress_ms();
+        p.seek_ms(5000);
+        assert_eq!(by_duration, 5000);
+        assert_eq!(p.playback_context.progress_ms(), by_duration);
+        p.stop();
+    }
+
+    #[test]
+    fn test_seek_to_end() {
+        let (mut p, track) = new();
+        p.play(track).unwrap();
+        let end = p.total_duration().unwrap().as_millis() as u64;
+
+        p.seek_ms(end);
+        assert_eq!(p.playback_context.progress_ms(), end);
+        assert!(p.playback_context.stopped.load(Ordering::SeqCst));
+        // nothing queued anymore
+        p.sleep_until_end();
+    }
+
+    #[test]
+    fn test_play_stream() {
+        let (mut p, track) = new();
+        let started = Instant::now();
+        p.play_stream(Box::new(std::io::Cursor::new(track.to_vec()))).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(p.current_bytes.is_none());
+
+        thread::sleep(Duration::from_millis(500));
+        p.seek_ms(10_000);
+        assert!(p.playback_context.progress_ms() >= 10_000);
+        p.drain_sink();
+        p.sleep_until_end();
+
+        let err = p.play_stream(Box::new(std::io::Cursor::new(vec![0u8; 64])));
+        assert!(matches!(
+            err,
+            Err(PlayerError::DecodeTrackError {
+                format: AudioFormat::Unknown,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_play_url_seek_ranged() {
+        let (mut p, track) = new();
+        let (url, ranges) = serve(track.to_vec(), true);
+        p.play_url(&url).unwrap();
+        thread::sleep(Duration::from_millis(500));
+        assert_eq!(ranges.lock().unwrap().len(), 1);
+
+        // far past what a few hundred milliseconds could have downloaded
+        let end = p.total_duration().unwrap().as_millis() as u64;
+        p.seek_ms(end * 3 / 4);
+        let ranges = ranges.lock().unwrap().clone();
+        assert_eq!(ranges.len(), 2);
+        assert!(ranges[1].unwrap() > 0);
+        assert!(p.playback_context.progress_ms() >= end * 3 / 4);
+        assert!(!p.playback_context.stopped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_play_url_seek_sequential() {
+        let (mut p, track) = new();
+        let (url, ranges) = serve(track.to_vec(), false);
+        p.play_url(&url).unwrap();
+
+        let end = p.total_duration().unwrap().as_millis() as u64;
+        p.seek_ms(end / 2);
+        assert_eq!(ranges.lock().unwrap().len(), 1);
+        thread::sleep(Duration::from_millis(500));
+        assert!(p.playback_context.progress_ms() >= end / 2);
+    }
+
+    #[test]
+    fn test_trim_silence() {
+        let mut p = LAudioPlayer::try_new().unwrap();
+        p.set_trim_silence(true);
+        p.play_source(padded_tone());
+        measured(&p);
+
+        // 1s of tone plus the look-ahead on both sides, the 1.5s of silence is gone
+        let duration = p.total_duration().unwrap();
+        assert!(duration > Duration::from_millis(1000), "{:?}", duration);
+        assert!(duration < Duration::from_millis(1200), "{:?}", duration);
+
+        p.set_trim_silence(false);
+        assert_eq!(p.total_duration(), Some(Duration::from_millis(2500)));
+    }
+
+    #[test]
+    fn test_buffer_frames() {
+        let range = SupportedBufferSize::Range { min: 64, max: 8192 };
+        assert_eq!(buffer_frames(Duration::from_millis(50), 48000, &range), Some(2400));
+        assert_eq!(buffer_frames(Duration::from_secs(1), 48000, &range), None);
+        assert_eq!(buffer_frames(Duration::from_millis(0), 48000, &range), None);
+        assert_eq!(
+            buffer_frames(Duration::from_millis(50), 48000, &SupportedBufferSize::Unknown),
+            None
+        );
+    }
+
+    #[test]
+    fn test_with_latency() {
+        let mut p = LAudioPlayer::try_new_with_latency(Duration::from_millis(50)).unwrap();
+        let (_, track) = new();
+        p.play(track).unwrap();
+        thread::sleep(Duration::from_secs(1));
+        assert!(p.playback_context.progress_ms() > 0);
+    }
+
+    #[test]
+    fn test_with_unsupported_latency() {
+        // way past any device buffer, opened with the default one
+        assert!(LAudioPlayer::try_new_with_latency(Duration::from_secs(3600)).is_ok());
+    }
+
+    #[test]
+    fn test_seek_near_end() {
+        let (mut p, track) = new();
+        p.play(track).unwrap();
+        let end = p.total_duration().unwrap().as_millis() as u64;
+
+        let started = Instant::now();
+        p.seek_ms(end - 2000);
+        assert!(started.elapsed() < Duration::from_secs(1), "{:?}", started.elapsed());
+        assert!(p.playback_context.progress_ms() >= end - 2000);
+        p.sleep_until_end();
+        assert!(p.playback_context.progress_ms() <= end + 100);
+    }
+
+    #[test]
+    fn test_forward_past_end_notifies() {
+        let (mut p, track) = new();
+        let (tx, rx) = std::sync::mpsc::channel();
+        p.playback_context.on_track_end(Box::new(move || {
+            let _ = tx.send(());
+        }));
+        p.play(track).unwrap();
+        let end = p.total_duration().unwrap().as_millis() as u64;
+
+        let started = Instant::now();
+        for _ in 0..5 {
+            p.forward(end);
+        }
+        assert!(started.elapsed() < Duration::from_secs(1), "{:?}", started.elapsed());
+        assert_eq!(p.playback_context.progress_ms(), end);
+        rx.recv_timeout(Duration::from_millis(100)).unwrap();
+
+        // replaying at the end doesn't start a skip either
+        p.replay();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        p.sleep_until_end();
+    }
+
+    #[test]
+    fn test_forward() {
+        let (mut p, track) = new();
+        p.play(track).unwrap();
+
+        for _ in [0; 5] {
+            thread::sleep(Duration::from_secs(5));
+            println!("fast forward 50 seconds");
+            p.forward(50 * 1000);
+        }
+        p.sleep_until_end();
+    }
+
+    #[test]
     fn test_rewind() {
         let (mut p, track) = new();
         p.play(track).unwrap();
@@ -413,4 +2695,145 @@ mod light_audio_player_tests {
         }
         p.sleep_until_end();
     }
+
+    #[test]
+    fn test_save_and_load_context() {
+        let dir = std::env::temp_dir().join(format!("lekima-playback-{}", std::process::id()));
+        let path = dir.join("playback.json");
+
+        let context = PlaybackContext::default();
+        context.change_volume(|_| 70.0);
+        context.set_speed(1.5);
+        context.set_repeat_mode(RepeatMode::Count(3));
+        context.toggle_mute();
+        context.save(&path).unwrap();
+
+        let restored = PlaybackContext::default();
+        restored.load(&path).unwrap();
+        assert!(restored.is_muted());
+        assert!(!restored.toggle_mute());
+        assert_eq!(*restored.volume.lock().unwrap(), 70.0);
+        assert_eq!(*restored.speed.lock().unwrap(), 1.5);
+        assert_eq!(restored.repeat_mode(), RepeatMode::Count(3));
+        assert_eq!(restored.speed_for(TrackKind::Music), 1.5);
+
+        // what's left out is kept
+        std::fs::write(&path, "{\"repeat\": \"all\"}").unwrap();
+        restored.load(&path).unwrap();
+        assert_eq!(restored.repeat_mode(), RepeatMode::All);
+        assert_eq!(*restored.volume.lock().unwrap(), 70.0);
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(restored.load(&path).is_err());
+
+        // saved while a podcast played, music still comes back at its own speed
+        context.set_track_kind(TrackKind::Podcast);
+        context.set_speed(2.0);
+        context.save(&path).unwrap();
+        let restored = PlaybackContext::default();
+        restored.load(&path).unwrap();
+        assert_eq!(restored.speed(), 1.5);
+        assert_eq!(restored.speed_for(TrackKind::Podcast), 2.0);
+        let restored = PlaybackContext::default();
+        restored.set_track_kind(TrackKind::Podcast);
+        restored.load(&path).unwrap();
+        assert_eq!(restored.speed(), 2.0);
+        assert_eq!(restored.speed_for(TrackKind::Music), 1.5);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_ab_loop() {
+        let (mut p, track) = new();
+        p.play(track).unwrap();
+        assert!(matches!(p.set_ab_loop(2000, 2000), Err(PlayerError::InvalidRange { .. })));
+
+        p.set_ab_loop(1000, 1500).unwrap();
+        assert_eq!(p.playback_context.ab_loop(), Some((1000, 1500)));
+        thread::sleep(Duration::from_millis(1200));
+        let progress = p.playback_context.progress_ms();
+        assert!((1000..=1500).contains(&progress), "{}", progress);
+
+        // still looping at another speed
+        p.speed(2.0);
+        thread::sleep(Duration::from_millis(700));
+        let progress = p.playback_context.progress_ms();
+        assert!((1000..=1500).contains(&progress), "{}", progress);
+
+        // seeking within keeps it, out of it plays on
+        p.seek_ms(1200);
+        assert!(p.playback_context.ab_loop().is_some());
+        p.seek_ms(5000);
+        assert_eq!(p.playback_context.ab_loop(), None);
+        thread::sleep(Duration::from_millis(300));
+        assert!(p.playback_context.progress_ms() > 5000);
+
+        p.set_ab_loop(0, 300).unwrap();
+        p.clear_ab_loop();
+        thread::sleep(Duration::from_millis(500));
+        assert!(p.playback_context.progress_ms() > 300);
+        p.drain_sink();
+        p.sleep_until_end();
+    }
+
+    #[test]
+    fn test_sleep_timer() {
+        let (mut p, track) = new();
+        p.play(track.clone()).unwrap();
+        assert_eq!(p.sleep_timer_remaining(), None);
+
+        p.set_sleep_timer(Duration::from_millis(500));
+        assert!(p.sleep_timer_remaining().unwrap() <= Duration::from_millis(500));
+        // already within the fade
+        assert!(p.playback_context.sleep_gain() < 0.1);
+        thread::sleep(Duration::from_millis(800));
+        assert!(p.is_paused());
+        assert_eq!(p.sleep_timer_remaining(), None);
+        assert_eq!(p.playback_context.sleep_gain(), 1.0);
+
+        p.resume();
+        p.set_sleep_timer(Duration::from_secs(60));
+        p.cancel_sleep_timer();
+        assert_eq!(p.sleep_timer_remaining(), None);
+
+        // a new track starts without the timer
+        p.set_sleep_timer(Duration::from_secs(60));
+        p.play(track).unwrap();
+        assert_eq!(p.sleep_timer_remaining(), None);
+        p.drain_sink();
+        p.sleep_until_end();
+    }
+
+    // waits for the current track to be measured, at most a few seconds
+    fn measured(p: &LAudioPlayer) {
+        let started = Instant::now();
+        while p.current_analysis.lock().unwrap().measuring {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_normalization() {
+        let (mut p, track) = new();
+        p.play(track).unwrap();
+        assert_eq!(p.normalization_gain(), 1.0);
+        assert!(p.current_analysis.lock().unwrap().loudness.is_none());
+
+        // measured in the background, played as is meanwhile
+        let started = Instant::now();
+        p.set_normalization(Some(-14.0));
+        assert!(started.elapsed() < Duration::from_millis(50));
+        measured(&p);
+        let gain = p.normalization_gain();
+        let lufs = p.current_analysis.lock().unwrap().loudness.unwrap().unwrap();
+        assert!((-40.0..0.0).contains(&lufs), "{}", lufs);
+        assert!(((20.0 * gain.log10()) - (-14.0 - lufs).min(12.0)).abs() < 1e-3);
+
+        // a quieter target turns the same track down
+        p.set_normalization(Some(-30.0));
+        assert!(p.normalization_gain() < gain);
+        p.drain_sink();
+        p.sleep_until_end();
+    }
 }
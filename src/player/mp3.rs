@@ -0,0 +1,301 @@
+//! MPEG audio duration estimation.
+//!
+//! Decoders usually guess the duration of an mp3 from the first frame's bitrate,
+//! which is way off for VBR files. The Xing/Info or VBRI header stores the real
+//! frame count, and when neither is present the frames are counted one by one.
+
+use std::time::Duration;
+
+// only look this far past the tags for the first frame
+const SYNC_WINDOW: usize = 4096;
+
+// kbps, indexed by the 4 bit bitrate field (0 = free, 15 = bad)
+const BITRATE_V1_L1: [u32; 16] = [0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448, 0];
+const BITRATE_V1_L2: [u32; 16] = [0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384, 0];
+const BITRATE_V1_L3: [u32; 16] = [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 0];
+const BITRATE_V2_L1: [u32; 16] = [0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256, 0];
+const BITRATE_V2_L23: [u32; 16] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160, 0];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Version {
+    V1,
+    V2,
+    V25,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FrameHeader {
+    version: Version,
+    layer: u8,
+    bitrate: u32,
+    sample_rate: u32,
+    padding: bool,
+    mono: bool,
+}
+
+impl FrameHeader {
+    fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() < 4 || buf[0] != 0xFF || buf[1] & 0xE0 != 0xE0 {
+            return None;
+        }
+
+        let version = match (buf[1] >> 3) & 0b11 {
+            0 => Version::V25,
+            2 => Version::V2,
+            3 => Version::V1,
+            _ => return None,
+        };
+        let layer = match (buf[1] >> 1) & 0b11 {
+            1 => 3,
+            2 => 2,
+            3 => 1,
+            _ => return None,
+        };
+
+        let table = match (version, layer) {
+            (Version::V1, 1) => &BITRATE_V1_L1,
+            (Version::V1, 2) => &BITRATE_V1_L2,
+            (Version::V1, _) => &BITRATE_V1_L3,
+            (_, 1) => &BITRATE_V2_L1,
+            _ => &BITRATE_V2_L23,
+        };
+        let bitrate = table[(buf[2] >> 4) as usize] * 1000;
+        if bitrate == 0 {
+            return None;
+        }
+
+        let sample_rate = match ((buf[2] >> 2) & 0b11, version) {
+            (0, Version::V1) => 44100,
+            (1, Version::V1) => 48000,
+            (2, Version::V1) => 32000,
+            (0, Version::V2) => 22050,
+            (1, Version::V2) => 24000,
+            (2, Version::V2) => 16000,
+            (0, Version::V25) => 11025,
+            (1, Version::V25) => 12000,
+            (2, Version::V25) => 8000,
+            _ => return None,
+        };
+
+        Some(Self {
+            version,
+            layer,
+            bitrate,
+            sample_rate,
+            padding: buf[2] & 0b10 != 0,
+            mono: buf[3] >> 6 == 0b11,
+        })
+    }
+
+    fn samples(&self) -> u32 {
+        match (self.layer, self.version) {
+            (1, _) => 384,
+            (3, Version::V2) | (3, Version::V25) => 576,
+            _ => 1152,
+        }
+    }
+
+    fn len(&self) -> usize {
+        let pad = self.padding as u32;
+        let len = match (self.layer, self.version) {
+            (1, _) => (12 * self.bitrate / self.sample_rate + pad) * 4,
+            (3, Version::V2) | (3, Version::V25) => 72 * self.bitrate / self.sample_rate + pad,
+            _ => 144 * self.bitrate / self.sample_rate + pad,
+        };
+        len as usize
+    }
+
+    // layer III side information, the Xing tag follows it
+    fn side_info_len(&self) -> usize {
+        match (self.version, self.mono) {
+            (Version::V1, false) => 32,
+            (Version::V1, true) => 17,
+            (_, false) => 17,
+            (_, true) => 9,
+        }
+    }
+
+    fn duration_of(&self, frames: u64) -> Duration {
+        let samples = frames * self.samples() as u64;
+        Duration::from_micros(samples * 1_000_000 / self.sample_rate as u64)
+    }
+}
+
+/// Estimates the duration of an mp3, `None` if `buf` doesn't look like one.
+pub(crate) fn estimate_duration(buf: &[u8]) -> Option<Duration> {
+    for magic in [&b"fLaC"[..], &b"OggS"[..], &b"RIFF"[..]] {
+        if buf.starts_with(magic) {
+            return None;
+        }
+    }
+
+    let start = first_frame(buf)?;
+    let header = FrameHeader::parse(&buf[start..])?;
+    if let Some(frames) = vbr_frames(&buf[start..], &header) {
+        return Some(header.duration_of(frames));
+    }
+    count_frames(&buf[start..])
+}
+
+/// Bitrate in bits per second of the first frame, good enough to map a
+/// position to a byte offset in a CBR stream.
+pub(crate) fn first_frame_bitrate(buf: &[u8]) -> Option<u32> {
+    let start = first_frame(buf)?;
+    FrameHeader::parse(&buf[start..]).map(|header| header.bitrate)
+}
+
+fn id3v2_len(buf: &[u8]) -> usize {
+    if buf.len() < 10 || &buf[..3] != b"ID3" {
+        return 0;
+    }
+    let size = buf[6..10]
+        .iter()
+        .fold(0usize, |acc, b| (acc << 7) | (*b & 0x7F) as usize);
+    let footer = if buf[5] & 0x10 != 0 { 10 } else { 0 };
+    10 + size + footer
+}
+
+// a sync is only trusted when the next frame follows right after it
+fn first_frame(buf: &[u8]) -> Option<usize> {
+    let offset = id3v2_len(buf);
+    let end = buf.len().min(offset + SYNC_WINDOW);
+    (offset..end).find(|&pos| match FrameHeader::parse(&buf[pos..]) {
+        Some(header) => {
+            let next = pos + header.len();
+            next >= buf.len() || FrameHeader::parse(&buf[next..]).is_some()
+        }
+        None => false,
+    })
+}
+
+fn read_u32(buf: &[u8], pos: usize) -> Option<u32> {
+    let b = buf.get(pos..pos + 4)?;
+    Some(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+// frame count stored in a Xing/Info or VBRI header of the first frame
+fn vbr_frames(frame: &[u8], header: &FrameHeader) -> Option<u64> {
+    if header.layer != 3 {
+        return None;
+    }
+
+    let xing = 4 + header.side_info_len();
+    if let Some(tag) = frame.get(xing..xing + 4) {
+        if tag == b"Xing" || tag == b"Info" {
+            let flags = read_u32(frame, xing + 4)?;
+            // bit 0: frame count present
+            return if flags & 1 != 0 {
+                read_u32(frame, xing + 8).map(u64::from)
+            } else {
+                None
+            };
+        }
+    }
+
+    // VBRI always sits 32 bytes after the header
+    if frame.get(36..40) == Some(&b"VBRI"[..]) {
+        return read_u32(frame, 36 + 14).map(u64::from);
+    }
+    None
+}
+
+fn count_frames(buf: &[u8]) -> Option<Duration> {
+    let mut pos = 0;
+    let mut total = Duration::default();
+    let mut found = false;
+    while pos + 4 <= buf.len() {
+        match FrameHeader::parse(&buf[pos..]) {
+            Some(header) if pos + header.len() <= buf.len() => {
+                total += header.duration_of(1);
+                pos += header.len();
+                found = true;
+            }
+            // truncated last frame
+            Some(_) => break,
+            // junk between frames, resync
+            None => pos += 1,
+        }
+    }
+    if found {
+        Some(total)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{estimate_duration, first_frame_bitrate, FrameHeader};
+
+    // MPEG1 layer III, 44.1kHz, stereo
+    fn frame(bitrate_index: u8) -> Vec<u8> {
+        let header = [0xFF, 0xFB, bitrate_index << 4, 0x00];
+        let len = FrameHeader::parse(&header).unwrap().len();
+        let mut frame = vec![0u8; len];
+        frame[..4].copy_from_slice(&header);
+        frame
+    }
+
+    fn xing_frame(frames: u32) -> Vec<u8> {
+        let mut frame = frame(9);
+        frame[36..40].copy_from_slice(b"Xing");
+        frame[40..44].copy_from_slice(&1u32.to_be_bytes());
+        frame[44..48].copy_from_slice(&frames.to_be_bytes());
+        frame
+    }
+
+    fn vbr_track(frames: usize) -> Vec<u8> {
+        // 128, 320 and 64 kbps frames mixed, like a VBR encoder would
+        (0..frames).flat_map(|i| frame([9, 14, 5][i % 3])).collect()
+    }
+
+    fn assert_close(actual: Duration, expected: Duration) {
+        let diff = if actual > expected {
+            actual - expected
+        } else {
+            expected - actual
+        };
+        assert!(diff < Duration::from_millis(50), "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn test_xing_frame_count() {
+        let mut buf = xing_frame(1000);
+        buf.extend(vbr_track(10));
+
+        // 1000 * 1152 / 44100
+        assert_close(estimate_duration(&buf).unwrap(), Duration::from_millis(26122));
+    }
+
+    #[test]
+    fn test_count_vbr_frames() {
+        let buf = vbr_track(300);
+
+        // 300 * 1152 / 44100, while the first frame's bitrate alone would give
+        // a different length for the same byte count
+        assert_close(estimate_duration(&buf).unwrap(), Duration::from_millis(7837));
+    }
+
+    #[test]
+    fn test_skip_id3v2() {
+        let mut buf = b"ID3\x04\x00\x00\x00\x00\x00\x20".to_vec();
+        buf.extend(vec![0u8; 32]);
+        buf.extend(vbr_track(30));
+
+        assert_close(estimate_duration(&buf).unwrap(), Duration::from_millis(784));
+    }
+
+    #[test]
+    fn test_first_frame_bitrate() {
+        assert_eq!(first_frame_bitrate(&vbr_track(3)), Some(128_000));
+        assert_eq!(first_frame_bitrate(&[0u8; 64]), None);
+    }
+
+    #[test]
+    fn test_not_mp3() {
+        assert_eq!(estimate_duration(b"fLaC\x00\x00\x00\x22"), None);
+        assert_eq!(estimate_duration(&[0u8; 1024]), None);
+    }
+}
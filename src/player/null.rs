@@ -0,0 +1,370 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use super::{forward_target, AudioPlayer, PlaybackContext, PlayerError, RepeatMode};
+
+/// Plays nothing but keeps its `PlaybackContext` moving like a real player,
+/// for running without a sound card and for tests.
+pub struct NullAudioPlayer {
+    context: Arc<PlaybackContext>,
+    // pretended length of every track
+    duration_ms: Option<u64>,
+    // the progress thread runs while set
+    running: Arc<AtomicBool>,
+}
+
+impl NullAudioPlayer {
+    pub fn new() -> Self {
+        let context = Arc::new(PlaybackContext::default());
+        // nothing to play yet
+        context.stopped.store(true, Ordering::SeqCst);
+        let running = Arc::new(AtomicBool::new(true));
+        spawn_progress(context.clone(), running.clone());
+        Self {
+            context,
+            duration_ms: None,
+            running,
+        }
+    }
+
+    /// Tracks played from now on end after `duration_ms`, they never end with `None`.
+    pub fn with_duration_ms(mut self, duration_ms: Option<u64>) -> Self {
+        self.duration_ms = duration_ms;
+        self
+    }
+
+    /// Stops the clock: progress only moves when it's seeked, e.g. for tests
+    /// setting it by hand.
+    pub fn without_clock(self) -> Self {
+        self.running.store(false, Ordering::SeqCst);
+        self
+    }
+}
+
+impl Default for NullAudioPlayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for NullAudioPlayer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+}
+
+// advances the progress of `context` in real time, scaled by its speed
+fn spawn_progress(context: Arc<PlaybackContext>, running: Arc<AtomicBool>) {
+    thread::spawn(move || {
+        let mut played_ms = 0.0f64;
+        while running.load(Ordering::SeqCst) {
+            let interval_ms = context.progress_interval_ms.load(Ordering::Relaxed).max(1);
+            thread::sleep(Duration::from_millis(interval_ms));
+            if context.stopped.load(Ordering::SeqCst) || context.pause.load(Ordering::SeqCst) {
+                continue;
+            }
+            played_ms += interval_ms as f64 * *context.speed.lock().unwrap() as f64;
+            let whole = played_ms as u64;
+            played_ms -= whole as f64;
+
+            let progress = context.progress_ms() + whole;
+            match context.duration_ms() {
+                Some(end) if progress >= end => {
+                    context.set_progress_ms(end);
+                    context.stopped.store(true, Ordering::SeqCst);
+                    context.track_ended();
+                }
+                _ => context.set_progress_ms(progress),
+            }
+        }
+    });
+}
+
+impl AudioPlayer for NullAudioPlayer {
+    fn play(&mut self, _track: Bytes) -> Result<(), PlayerError> {
+        self.context.set_duration_ms(self.duration_ms);
+        self.context.set_progress_ms(0);
+        self.context.stopped.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    // nothing is downloaded either
+    fn play_url(&mut self, _url: &str) -> Result<(), PlayerError> {
+        self.play(Bytes::new())
+    }
+
+    fn stop(&self) {
+        self.context.stopped.store(true, Ordering::SeqCst);
+    }
+
+    fn pause(&self) {
+        self.context.pause.store(true, Ordering::SeqCst);
+    }
+
+    fn resume(&self) {
+        self.context.pause.store(false, Ordering::SeqCst);
+    }
+
+    fn increase_volume(&self, delta: u8) -> u8 {
+        let vol = self.context.change_volume(|vol| (vol + delta as f32).min(100.0));
+        vol.round() as u8
+    }
+
+    fn decrease_volume(&self, delta: u8) -> u8 {
+        let vol = self.context.change_volume(|vol| (vol - delta as f32).max(0.0));
+        vol.round() as u8
+    }
+
+    fn seek_ms(&self, progress_ms: u64) {
+        let progress_ms = match self.context.duration_ms() {
+            Some(end) => progress_ms.min(end),
+            None => progress_ms,
+        };
+        self.context.set_progress_ms(progress_ms);
+    }
+
+    fn forward(&mut self, dur_millis: u64) -> u64 {
+        let cursor = self.context.progress_ms();
+        match forward_target(cursor, dur_millis, self.context.duration_ms()) {
+            Some(target) => self.seek_ms(target),
+            None if self.context.repeat_mode() == RepeatMode::One => self.seek_ms(0),
+            // there's no next track, the end is where it stops
+            None => self.seek_ms(cursor.saturating_add(dur_millis)),
+        }
+        self.context.progress_ms()
+    }
+
+    fn rewind(&self, dur_millis: u64) -> u64 {
+        self.seek_ms(self.context.progress_ms().saturating_sub(dur_millis));
+        self.context.progress_ms()
+    }
+
+    fn speed(&self, speed: f32) {
+        self.context.set_speed(speed);
+    }
+
+    // there's no queue to move through
+    fn next(&mut self) -> Result<(), PlayerError> {
+        self.stop();
+        Ok(())
+    }
+
+    fn prev(&mut self) -> Result<(), PlayerError> {
+        self.seek_ms(0);
+        Ok(())
+    }
+
+    fn playback_context(&self) -> Arc<PlaybackContext> {
+        self.context.clone()
+    }
+
+    fn detach(self: Box<Self>) {}
+}
+
+/// A `NullAudioPlayer` keeping every track it's handed, for tests checking
+/// what reaches the player. Queued tracks are kept but never played.
+pub struct RecordingPlayer {
+    player: NullAudioPlayer,
+    played: Arc<Mutex<Vec<Bytes>>>,
+    queued: Arc<Mutex<Vec<Bytes>>>,
+}
+
+impl RecordingPlayer {
+    pub fn new(player: NullAudioPlayer) -> Self {
+        Self {
+            player,
+            played: Arc::default(),
+            queued: Arc::default(),
+        }
+    }
+
+    /// Tracks given to `play`, oldest first.
+    pub fn played(&self) -> Arc<Mutex<Vec<Bytes>>> {
+        self.played.clone()
+    }
+
+    /// Tracks given to `enqueue`, oldest first.
+    pub fn queued(&self) -> Arc<Mutex<Vec<Bytes>>> {
+        self.queued.clone()
+    }
+}
+
+impl AudioPlayer for RecordingPlayer {
+    fn play(&mut self, track: Bytes) -> Result<(), PlayerError> {
+        self.played.lock().unwrap().push(track.clone());
+        self.player.play(track)
+    }
+
+    fn play_url(&mut self, url: &str) -> Result<(), PlayerError> {
+        self.player.play_url(url)
+    }
+
+    fn enqueue(&mut self, track: Bytes) -> Result<(), PlayerError> {
+        self.queued.lock().unwrap().push(track);
+        Ok(())
+    }
+
+    fn stop(&self) {
+        self.player.stop();
+    }
+
+    fn pause(&self) {
+        self.player.pause();
+    }
+
+    fn resume(&self) {
+        self.player.resume();
+    }
+
+    fn increase_volume(&self, delta: u8) -> u8 {
+        self.player.increase_volume(delta)
+    }
+
+    fn decrease_volume(&self, delta: u8) -> u8 {
+        self.player.decrease_volume(delta)
+    }
+
+    fn seek_ms(&self, progress_ms: u64) {
+        self.player.seek_ms(progress_ms);
+    }
+
+    fn forward(&mut self, dur_millis: u64) -> u64 {
+        self.player.forward(dur_millis)
+    }
+
+    fn rewind(&self, dur_millis: u64) -> u64 {
+        self.player.rewind(dur_millis)
+    }
+
+    fn speed(&self, speed: f32) {
+        self.player.speed(speed);
+    }
+
+    fn next(&mut self) -> Result<(), PlayerError> {
+        self.player.next()
+    }
+
+    fn prev(&mut self) -> Result<(), PlayerError> {
+        self.player.prev()
+    }
+
+    fn playback_context(&self) -> Arc<PlaybackContext> {
+        self.player.playback_context()
+    }
+
+    fn detach(self: Box<Self>) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+    use std::thread;
+    use std::time::Duration;
+
+    use bytes::Bytes;
+
+    use super::{NullAudioPlayer, RecordingPlayer};
+    use crate::player::{AudioPlayer, RepeatMode};
+
+    #[test]
+    fn test_progress_advances() {
+        let mut p = NullAudioPlayer::new();
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(p.playback_context().progress_ms(), 0);
+
+        p.play(Bytes::new()).unwrap();
+        thread::sleep(Duration::from_millis(300));
+        let progress = p.playback_context().progress_ms();
+        assert!((200..=400).contains(&progress), "{}", progress);
+
+        p.pause();
+        thread::sleep(Duration::from_millis(50));
+        let paused = p.playback_context().progress_ms();
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(p.playback_context().progress_ms(), paused);
+    }
+
+    #[test]
+    fn test_ends_at_duration() {
+        let mut p = NullAudioPlayer::new().with_duration_ms(Some(1000));
+        p.play(Bytes::new()).unwrap();
+        assert_eq!(p.duration_ms(), Some(1000));
+
+        p.seek_ms(900);
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(p.playback_context().progress_ms(), 1000);
+        p.forward(5000);
+        assert_eq!(p.playback_context().progress_ms(), 1000);
+    }
+
+    #[test]
+    fn test_forward_clamped() {
+        let mut p = NullAudioPlayer::new().with_duration_ms(Some(200_000));
+        p.play(Bytes::new()).unwrap();
+        p.pause();
+        assert_eq!(p.forward(10_000), 10_000);
+        assert_eq!(p.rewind(15_000), 0);
+
+        // the last second still plays, forwarding past it ends the track
+        p.seek_ms(195_000);
+        assert_eq!(p.forward(4_500), 199_000);
+        assert_eq!(p.forward(5_000), 200_000);
+
+        p.set_repeat_mode(RepeatMode::One);
+        p.seek_ms(195_000);
+        assert_eq!(p.forward(10_000), 0);
+    }
+
+    #[test]
+    fn test_seek_percent() {
+        let mut p = NullAudioPlayer::new().with_duration_ms(None);
+        p.play(Bytes::new()).unwrap();
+        p.pause();
+        p.seek_percent(0.5);
+        assert_eq!(p.playback_context().progress_ms(), 0);
+
+        p.playback_context().set_duration_ms(Some(200_000));
+        p.seek_percent(0.6);
+        assert_eq!(p.playback_context().progress_ms(), 120_000);
+        p.seek_percent(1.5);
+        assert_eq!(p.playback_context().progress_ms(), 200_000);
+        p.seek_percent(-1.0);
+        assert_eq!(p.playback_context().progress_ms(), 0);
+    }
+
+    #[test]
+    fn test_without_clock() {
+        let mut p = NullAudioPlayer::new().without_clock();
+        p.play(Bytes::new()).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(p.playback_context().progress_ms(), 0);
+        p.seek_ms(1500);
+        assert_eq!(p.playback_context().progress_ms(), 1500);
+    }
+
+    #[test]
+    fn test_recording() {
+        let mut p = RecordingPlayer::new(NullAudioPlayer::new());
+        let (played, queued) = (p.played(), p.queued());
+        p.play(Bytes::from("a")).unwrap();
+        p.enqueue(Bytes::from("b")).unwrap();
+        p.play_url("http://fake/c.mp3").unwrap();
+
+        assert_eq!(*played.lock().unwrap(), vec![Bytes::from("a")]);
+        assert_eq!(*queued.lock().unwrap(), vec![Bytes::from("b")]);
+        assert!(!p.playback_context().stopped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_volume() {
+        let p = NullAudioPlayer::new();
+        assert_eq!(p.increase_volume(150), 100);
+        assert_eq!(p.decrease_volume(250), 0);
+        assert!(p.toggle_mute());
+        assert_eq!(p.increase_volume(30), 30);
+    }
+}
@@ -0,0 +1,96 @@
+use std::time::Duration;
+
+use rodio::Source;
+
+use super::TrackSource;
+
+/// Plays `current`, then whatever `restart` hands over once it ends, until
+/// `restart` gives `None`. Restarting inside the audio thread leaves no gap.
+pub struct Repeat<F> {
+    current: TrackSource,
+    restart: F,
+}
+
+impl<F> Repeat<F>
+where
+    F: FnMut() -> Option<TrackSource>,
+{
+    pub fn new(current: TrackSource, restart: F) -> Self {
+        Self { current, restart }
+    }
+}
+
+impl<F> Iterator for Repeat<F>
+where
+    F: FnMut() -> Option<TrackSource>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if let Some(sample) = self.current.next() {
+            return Some(sample);
+        }
+        // an empty restart would spin forever, it ends the repeat instead
+        self.current = (self.restart)()?;
+        self.current.next()
+    }
+}
+
+impl<F> Source for Repeat<F>
+where
+    F: FnMut() -> Option<TrackSource>,
+{
+    // restarts replay the same track, its format doesn't change
+    fn current_frame_len(&self) -> Option<usize> {
+        self.current.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.current.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.current.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rodio::buffer::SamplesBuffer;
+    use rodio::Source;
+
+    use super::Repeat;
+    use crate::player::TrackSource;
+
+    fn track() -> TrackSource {
+        Box::new(SamplesBuffer::new(1, 8000, vec![0.5f32; 4]))
+    }
+
+    #[test]
+    fn test_restarts_until_none() {
+        let mut left = 2;
+        let repeat = Repeat::new(track(), move || {
+            if left == 0 {
+                return None;
+            }
+            left -= 1;
+            Some(track())
+        });
+        assert_eq!(repeat.count(), 12);
+    }
+
+    #[test]
+    fn test_empty_restart_ends() {
+        let repeat = Repeat::new(track(), || -> Option<TrackSource> {
+            Some(Box::new(SamplesBuffer::new(1, 8000, Vec::<f32>::new())))
+        });
+        assert_eq!(repeat.total_duration(), None::<Duration>);
+        assert_eq!(repeat.count(), 4);
+    }
+}
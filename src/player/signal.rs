@@ -0,0 +1,226 @@
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use rodio::Source;
+
+use super::crossfade::Fades;
+use super::PlaybackContext;
+
+/// Fires when the queued source ends, waiters can also be woken early.
+#[derive(Default)]
+pub struct EndSignal {
+    shared: Arc<Shared>,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    // bumped by every `set`, the end of an older source is stale
+    generation: u64,
+    // a source is set and hasn't ended yet
+    armed: bool,
+    // `wake` calls, and how many of them a `wait` returned for
+    wakes: u64,
+    seen_wakes: u64,
+}
+
+impl EndSignal {
+    pub(crate) fn set(&self, rx: Receiver<()>) {
+        let generation = {
+            let mut state = self.shared.state.lock().unwrap();
+            state.generation += 1;
+            state.armed = true;
+            state.generation
+        };
+        let shared = self.shared.clone();
+        // sent when the source ends, or dropped along with it
+        thread::spawn(move || {
+            let _ = rx.recv();
+            let mut state = shared.state.lock().unwrap();
+            if state.generation == generation {
+                state.armed = false;
+                shared.changed.notify_all();
+            }
+        });
+    }
+
+    /// Blocks until the source ends or `wake` is called, also when that was
+    /// before this. A woken wait leaves the signal in place for the next one.
+    pub fn wait(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            if state.wakes != state.seen_wakes {
+                state.seen_wakes = state.wakes;
+                return;
+            }
+            if !state.armed {
+                return;
+            }
+            state = self.shared.changed.wait(state).unwrap();
+        }
+    }
+
+    /// Makes a thread blocked in `wait` return, or the next one to wait.
+    pub fn wake(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.wakes += 1;
+        self.shared.changed.notify_all();
+    }
+}
+
+/// Tells the context once `inner` runs out by itself. A source ended by
+/// `drain_sink` has the context stopped and ends silently, as does one
+/// faded out for the next track or already told ahead of its end.
+pub struct NotifyEnd<S> {
+    inner: S,
+    context: Arc<PlaybackContext>,
+    fades: Arc<Fades>,
+    done: bool,
+}
+
+impl<S> NotifyEnd<S> {
+    pub fn new(inner: S, context: Arc<PlaybackContext>, fades: Arc<Fades>) -> Self {
+        Self {
+            inner,
+            context,
+            fades,
+            done: false,
+        }
+    }
+}
+
+impl<S> Iterator for NotifyEnd<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.inner.next();
+        if sample.is_none() && !self.done {
+            self.done = true;
+            let stopped = self.context.stopped.load(Ordering::SeqCst);
+            if !stopped && self.fades.out_ms().is_none() && self.fades.tell_end() {
+                self.context.track_ended();
+            }
+        }
+        sample
+    }
+}
+
+impl<S> Source for NotifyEnd<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use rodio::buffer::SamplesBuffer;
+    use rodio::Source;
+
+    use super::{EndSignal, NotifyEnd};
+    use crate::player::PlaybackContext;
+
+    #[test]
+    fn test_wake() {
+        let (tx, rx) = mpsc::channel();
+        let signal = Arc::new(EndSignal::default());
+        signal.set(rx);
+
+        let waiter = signal.clone();
+        let (done_tx, done_rx) = mpsc::channel();
+        thread::spawn(move || {
+            waiter.wait();
+            done_tx.send(()).unwrap();
+        });
+        thread::sleep(Duration::from_millis(50));
+        assert!(done_rx.try_recv().is_err());
+
+        let woken = Instant::now();
+        signal.wake();
+        done_rx.recv_timeout(Duration::from_millis(100)).unwrap();
+        assert!(woken.elapsed() < Duration::from_millis(100));
+
+        // the end is still signalled afterwards
+        tx.send(()).unwrap();
+        signal.wait();
+    }
+
+    #[test]
+    fn test_wake_before_wait() {
+        let (tx, rx) = mpsc::channel();
+        let signal = EndSignal::default();
+        signal.set(rx);
+        signal.wake();
+        signal.wait();
+
+        tx.send(()).unwrap();
+        signal.wait();
+    }
+
+    #[test]
+    fn test_wait_without_signal() {
+        EndSignal::default().wait();
+    }
+
+    fn counting(context: &PlaybackContext) -> Arc<AtomicUsize> {
+        let ends = Arc::new(AtomicUsize::new(0));
+        let counter = ends.clone();
+        context.on_track_end(Box::new(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }));
+        ends
+    }
+
+    #[test]
+    fn test_notify_natural_end() {
+        let context = Arc::new(PlaybackContext::default());
+        let ends = counting(&context);
+        let track = SamplesBuffer::new(1, 8000, vec![0.5f32; 4]);
+        let mut source = NotifyEnd::new(track, context, Arc::default());
+        assert_eq!(source.by_ref().count(), 4);
+        assert_eq!(source.next(), None);
+        assert_eq!(ends.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_stop_isnt_an_end() {
+        let context = Arc::new(PlaybackContext::default());
+        let ends = counting(&context);
+        context.stopped.store(true, Ordering::SeqCst);
+        let track = SamplesBuffer::new(1, 8000, vec![0.5f32; 4]);
+        let source = NotifyEnd::new(track, context, Arc::default());
+        assert_eq!(source.total_duration(), Some(Duration::from_micros(500)));
+        source.count();
+        assert_eq!(ends.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use rodio::source::{SkipDuration, TakeDuration};
+use rodio::Source;
+
+// samples quieter than this count as silence, about -54 dBFS
+const THRESHOLD: f32 = 0.002;
+// kept ahead of the first and after the last loud sample so soft onsets and
+// fade outs aren't clipped
+const LOOKAHEAD: Duration = Duration::from_millis(50);
+
+/// The audible part of a track: where it starts and how long it lasts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Edges {
+    pub start: Duration,
+    pub len: Duration,
+}
+
+fn frames_to_duration(frames: u64, sample_rate: u32) -> Duration {
+    Duration::from_nanos(frames * 1_000_000_000 / sample_rate.max(1) as u64)
+}
+
+/// Finds the leading and trailing silence of `source`, decoding all of it.
+/// `None` when it's silent from start to end, nothing is trimmed then.
+pub fn detect<S>(source: S) -> Option<Edges>
+where
+    S: Source<Item = f32>,
+{
+    let channels = source.channels().max(1) as u64;
+    let sample_rate = source.sample_rate();
+
+    let mut first = None;
+    let mut last = 0;
+    let mut samples = 0;
+    for (i, sample) in source.enumerate() {
+        if sample.abs() > THRESHOLD {
+            first.get_or_insert(i as u64);
+            last = i as u64;
+        }
+        samples += 1;
+    }
+
+    let first = frames_to_duration(first? / channels, sample_rate);
+    let last = frames_to_duration(last / channels + 1, sample_rate);
+    let total = frames_to_duration(samples / channels, sample_rate);
+
+    let start = first.checked_sub(LOOKAHEAD).unwrap_or_default();
+    let end = (last + LOOKAHEAD).min(total);
+    Some(Edges {
+        start,
+        len: end - start,
+    })
+}
+
+/// Plays `source` from `progress` into its audible part, skipping what's outside of `edges`.
+pub fn trim<S>(source: S, edges: Edges, progress: Duration) -> TakeDuration<SkipDuration<S>>
+where
+    S: Source<Item = f32>,
+{
+    source
+        .skip_duration(edges.start + progress)
+        .take_duration(edges.len.checked_sub(progress).unwrap_or_default())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::f32::consts::TAU;
+    use std::time::Duration;
+
+    use rodio::buffer::SamplesBuffer;
+    use rodio::Source;
+
+    use super::{detect, trim, Edges, LOOKAHEAD};
+
+    /// 1s of silence, 1s of a 440Hz tone then 0.5s of silence, mono 8kHz.
+    pub(crate) fn padded_tone() -> SamplesBuffer<f32> {
+        let rate = 8000;
+        let mut samples = vec![0.0; rate];
+        let tone = (0..rate).map(|i| (i as f32 * 440.0 * TAU / rate as f32).sin() * 0.5);
+        samples.extend(tone);
+        samples.extend(vec![0.0; rate / 2]);
+        SamplesBuffer::new(1, rate as u32, samples)
+    }
+
+    fn close(a: Duration, b: Duration) -> bool {
+        let diff = if a > b { a - b } else { b - a };
+        diff < Duration::from_millis(2)
+    }
+
+    #[test]
+    fn test_detect() {
+        let edges = detect(padded_tone()).unwrap();
+        assert!(close(edges.start, Duration::from_secs(1) - LOOKAHEAD), "{:?}", edges);
+        assert!(close(edges.len, Duration::from_secs(1) + LOOKAHEAD * 2), "{:?}", edges);
+    }
+
+    #[test]
+    fn test_all_silent() {
+        assert_eq!(detect(SamplesBuffer::new(2, 8000, vec![0.0f32; 8000])), None);
+    }
+
+    #[test]
+    fn test_trim_starts_past_silence() {
+        let edges = detect(padded_tone()).unwrap();
+        let trimmed: Vec<f32> = trim(padded_tone(), edges, Duration::default()).collect();
+
+        // only the look-ahead is left before the tone
+        let onset = trimmed.iter().position(|s| s.abs() > 0.01).unwrap();
+        assert!(onset <= 8000 * LOOKAHEAD.as_millis() as usize / 1000 + 1, "onset {}", onset);
+        assert!(close(
+            Duration::from_secs_f64(trimmed.len() as f64 / 8000.0),
+            edges.len
+        ));
+    }
+
+    #[test]
+    fn test_trim_with_progress() {
+        let edges = Edges {
+            start: Duration::from_secs(1),
+            len: Duration::from_secs(1),
+        };
+        let rest = trim(padded_tone(), edges, Duration::from_millis(500)).count();
+        assert_eq!(rest, 4000);
+    }
+}
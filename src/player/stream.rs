@@ -0,0 +1,534 @@
+//! Reading a track over http while it downloads.
+//!
+//! Servers that honor `Range` let a seek past the downloaded part start a
+//! new request right there. Otherwise the body is buffered sequentially and
+//! a forward seek waits until the download gets there.
+
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+
+use reqwest::blocking::{Client, Response};
+use reqwest::header::{CONTENT_LENGTH, CONTENT_RANGE, RANGE};
+use reqwest::StatusCode;
+
+// start of the file kept in memory for ranged streams, decoders probe it
+// more than once before settling
+const HEAD_LIMIT: usize = 1 << 20;
+
+/// Reads the file at `url` from byte `base` on, which the reader sees as its start.
+pub struct RangeReader {
+    client: Client,
+    url: String,
+    base: u64,
+    // length from `base` to the end
+    len: Option<u64>,
+    ranged: bool,
+    // open response with the offset it reads at
+    body: Option<(Mutex<Response>, u64)>,
+    // bytes from 0 read sequentially, everything for non ranged streams
+    head: Vec<u8>,
+    // bytes from 0 downloaded without a gap
+    contiguous: Arc<AtomicU64>,
+    pos: u64,
+    // requests made so far
+    requests: usize,
+}
+
+fn to_io(e: reqwest::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e)
+}
+
+impl RangeReader {
+    pub fn open(url: &str) -> io::Result<Self> {
+        Self::open_at(url, 0)
+    }
+
+    /// Starts at byte `base` of the file, a ranged request when it isn't 0.
+    pub fn open_at(url: &str, base: u64) -> io::Result<Self> {
+        let mut reader = Self {
+            client: Client::new(),
+            url: url.to_owned(),
+            base,
+            len: None,
+            ranged: false,
+            body: None,
+            head: Vec::new(),
+            contiguous: Arc::default(),
+            pos: 0,
+            requests: 0,
+        };
+        let resp = reader.request(0)?;
+        reader.ranged = resp.status() == StatusCode::PARTIAL_CONTENT;
+        reader.len = if reader.ranged {
+            // bytes 0-99/1234
+            resp.headers()
+                .get(CONTENT_RANGE)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.rsplit('/').next())
+                .and_then(|total| total.parse::<u64>().ok())
+                .map(|total| total.saturating_sub(base))
+        } else {
+            resp.headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|len| len.parse().ok())
+        };
+        reader.body = Some((Mutex::new(resp), 0));
+        if !reader.ranged && base > 0 {
+            // the whole file came back, drop what's before `base`
+            let (body, _) = reader.body.as_mut().unwrap();
+            skip(body.get_mut().unwrap(), base)?;
+        }
+        Ok(reader)
+    }
+
+    /// Length of the whole file, when the server told.
+    pub fn len(&self) -> Option<u64> {
+        self.len
+    }
+
+    /// Whether seeks past the downloaded part become ranged requests.
+    pub fn supports_range(&self) -> bool {
+        self.ranged
+    }
+
+    /// Number of http requests made, one per ranged jump plus the first.
+    pub fn requests(&self) -> usize {
+        self.requests
+    }
+
+    /// Bytes downloaded from the start without a gap, updated while reading.
+    pub fn contiguous(&self) -> Arc<AtomicU64> {
+        self.contiguous.clone()
+    }
+
+    fn request(&mut self, from: u64) -> io::Result<Response> {
+        self.requests += 1;
+        let resp = self
+            .client
+            .get(&self.url)
+            .header(RANGE, format!("bytes={}-", self.base + from))
+            .send()
+            .map_err(to_io)?;
+        if !resp.status().is_success() {
+            let msg = format!("stream responded with {}", resp.status());
+            return Err(io::Error::new(io::ErrorKind::Other, msg));
+        }
+        Ok(resp)
+    }
+
+    // point the body at `self.pos`
+    fn reposition(&mut self) -> io::Result<()> {
+        match &self.body {
+            Some((_, offset)) if *offset == self.pos => return Ok(()),
+            Some((_, offset)) if !self.ranged && *offset < self.pos => {
+                // no other way than downloading up to there
+                let target = self.pos;
+                self.pos = *offset;
+                skip(self, target - self.pos)?;
+                debug_assert_eq!(self.pos, target);
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        if self.ranged {
+            let resp = self.request(self.pos)?;
+            if resp.status() == StatusCode::PARTIAL_CONTENT {
+                self.body = Some((Mutex::new(resp), self.pos));
+                return Ok(());
+            }
+            // the server changed its mind, start over sequentially
+            self.ranged = false;
+            self.body = Some((Mutex::new(resp), 0));
+        } else {
+            self.body = Some((Mutex::new(self.request(0)?), 0));
+        }
+        // a whole file again, from its very first byte
+        let (body, _) = self.body.as_mut().unwrap();
+        skip(body.get_mut().unwrap(), self.base)?;
+
+        self.head.clear();
+        self.contiguous.store(0, Ordering::Relaxed);
+        let target = self.pos;
+        self.pos = 0;
+        skip(self, target)
+    }
+}
+
+// reads `n` bytes into nothing, without holding them all at once
+fn skip<R: Read>(reader: &mut R, n: u64) -> io::Result<()> {
+    let skipped = io::copy(&mut reader.by_ref().take(n), &mut io::sink())?;
+    if skipped < n {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "stream ended early"));
+    }
+    Ok(())
+}
+
+impl Read for RangeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if (self.pos as usize) < self.head.len() {
+            let start = self.pos as usize;
+            let n = buf.len().min(self.head.len() - start);
+            buf[..n].copy_from_slice(&self.head[start..start + n]);
+            self.pos += n as u64;
+            return Ok(n);
+        }
+        if self.len.map_or(false, |len| self.pos >= len) {
+            return Ok(0);
+        }
+
+        self.reposition()?;
+        let (body, offset) = self.body.as_mut().unwrap();
+        let n = body.get_mut().unwrap().read(buf)?;
+        // keep the head contiguous, read from a response covering it
+        if *offset as usize == self.head.len() && (!self.ranged || self.head.len() < HEAD_LIMIT) {
+            self.head.extend_from_slice(&buf[..n]);
+        }
+        let _ = self.contiguous.compare_exchange(
+            *offset,
+            *offset + n as u64,
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        );
+        *offset += n as u64;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for RangeReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::Current(d) => checked_offset(self.pos, d),
+            SeekFrom::End(d) => match self.len {
+                Some(len) => checked_offset(len, d),
+                None => {
+                    let msg = "stream length unknown";
+                    return Err(io::Error::new(io::ErrorKind::Other, msg));
+                }
+            },
+        };
+        match target {
+            Some(target) => {
+                self.pos = target;
+                Ok(target)
+            }
+            None => Err(io::Error::new(io::ErrorKind::InvalidInput, "seek before start")),
+        }
+    }
+}
+
+// chunk read from the source at a time
+const READ_AHEAD_CHUNK: usize = 64 * 1024;
+
+#[derive(Default)]
+struct Filled {
+    data: Vec<u8>,
+    // the source ended, with the error it ended on
+    done: bool,
+    error: Option<io::Error>,
+}
+
+/// Reads a plain `Read` into memory on its own thread, as fast as it gives
+/// bytes, so decoding runs behind the download and rides out stalls of it.
+/// Seeks move within what's read, past it reading waits for the bytes.
+pub struct ReadAhead {
+    filled: Arc<(Mutex<Filled>, Condvar)>,
+    pos: u64,
+    // set once the reader is dropped, the thread stops reading then
+    stop: Arc<AtomicBool>,
+}
+
+impl ReadAhead {
+    pub fn spawn(mut source: Box<dyn Read + Send>) -> Self {
+        let filled = Arc::new((Mutex::new(Filled::default()), Condvar::new()));
+        let writer = filled.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        thread::spawn(move || {
+            let mut chunk = vec![0u8; READ_AHEAD_CHUNK];
+            loop {
+                // nobody left to read it, e.g. another track was played
+                if stopped.load(Ordering::SeqCst) {
+                    return;
+                }
+                let read = source.read(&mut chunk);
+                let (lock, cvar) = &*writer;
+                let mut filled = lock.lock().unwrap();
+                match read {
+                    Ok(0) => filled.done = true,
+                    Ok(n) => filled.data.extend_from_slice(&chunk[..n]),
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => {
+                        filled.done = true;
+                        filled.error = Some(e);
+                    }
+                }
+                cvar.notify_all();
+                if filled.done {
+                    return;
+                }
+            }
+        });
+        Self {
+            filled,
+            pos: 0,
+            stop,
+        }
+    }
+
+    /// Blocks until `len` bytes are read ahead or the source ended.
+    pub fn prebuffer(&self, len: usize) {
+        let (lock, cvar) = &*self.filled;
+        let _filled = cvar
+            .wait_while(lock.lock().unwrap(), |f| !f.done && f.data.len() < len)
+            .unwrap();
+    }
+
+    // waits for the byte at `pos` or the end of the source
+    fn wait_until(&self, pos: u64) -> MutexGuard<Filled> {
+        let (lock, cvar) = &*self.filled;
+        cvar.wait_while(lock.lock().unwrap(), |f| !f.done && f.data.len() as u64 <= pos)
+            .unwrap()
+    }
+}
+
+impl Read for ReadAhead {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut filled = self.wait_until(self.pos);
+        let start = self.pos as usize;
+        if start >= filled.data.len() {
+            // an error only shows once everything before it is read
+            return match filled.error.take() {
+                Some(e) => Err(e),
+                None => Ok(0),
+            };
+        }
+        let n = buf.len().min(filled.data.len() - start);
+        buf[..n].copy_from_slice(&filled.data[start..start + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Drop for ReadAhead {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Seek for ReadAhead {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::Current(d) => checked_offset(self.pos, d),
+            // only known once it's all read
+            SeekFrom::End(d) => checked_offset(self.wait_until(u64::MAX).data.len() as u64, d),
+        };
+        match target {
+            Some(target) => {
+                self.pos = target;
+                Ok(target)
+            }
+            None => Err(io::Error::new(io::ErrorKind::InvalidInput, "seek before start")),
+        }
+    }
+}
+
+fn checked_offset(base: u64, delta: i64) -> Option<u64> {
+    if delta < 0 {
+        base.checked_sub(delta.unsigned_abs())
+    } else {
+        base.checked_add(delta as u64)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{RangeReader, ReadAhead};
+
+    /// Serves `body` over http, honoring `Range` when `ranged`. The returned
+    /// list records the range of every request, `None` when it had none.
+    pub(crate) fn serve(body: Vec<u8>, ranged: bool) -> (String, Arc<Mutex<Vec<Option<u64>>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/track.mp3", listener.local_addr().unwrap());
+        let ranges = Arc::new(Mutex::new(Vec::new()));
+        let seen = ranges.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().filter_map(Result::ok) {
+                let body = body.clone();
+                let seen = seen.clone();
+                thread::spawn(move || {
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut from = None;
+                    let mut line = String::new();
+                    while reader.read_line(&mut line).unwrap_or(0) > 2 {
+                        let lower = line.to_ascii_lowercase();
+                        if let Some(range) = lower.strip_prefix("range: bytes=") {
+                            from = range.trim().trim_end_matches('-').parse().ok();
+                        }
+                        line.clear();
+                    }
+                    seen.lock().unwrap().push(from);
+
+                    let mut stream = stream;
+                    let head = match from.filter(|_| ranged) {
+                        Some(from) => format!(
+                            "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\n\r\n",
+                            from,
+                            body.len() - 1,
+                            body.len(),
+                            body.len() as u64 - from
+                        ),
+                        None => format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len()),
+                    };
+                    let start = from.filter(|_| ranged).unwrap_or(0) as usize;
+                    let _ = stream.write_all(head.as_bytes());
+                    let _ = stream.write_all(&body[start..]);
+                });
+            }
+        });
+        (url, ranges)
+    }
+
+    fn body() -> Vec<u8> {
+        (0..200_000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_ranged_seek_forward() {
+        let (url, ranges) = serve(body(), true);
+        let mut reader = RangeReader::open(&url).unwrap();
+        assert!(reader.supports_range());
+        assert_eq!(reader.len(), Some(200_000));
+
+        let mut buf = [0u8; 100];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf[..], &body()[..100]);
+
+        reader.seek(SeekFrom::Start(150_000)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf[..], &body()[150_000..150_100]);
+        assert_eq!(*ranges.lock().unwrap(), vec![Some(0), Some(150_000)]);
+
+        // the start is still around without asking again
+        reader.seek(SeekFrom::Start(10)).unwrap();
+        let mut start = [0u8; 50];
+        reader.read_exact(&mut start).unwrap();
+        assert_eq!(&start[..], &body()[10..60]);
+        assert_eq!(reader.requests(), 2);
+    }
+
+    #[test]
+    fn test_sequential_fallback() {
+        let (url, ranges) = serve(body(), false);
+        let mut reader = RangeReader::open(&url).unwrap();
+        assert!(!reader.supports_range());
+
+        reader.seek(SeekFrom::Start(150_000)).unwrap();
+        let mut buf = [0u8; 100];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf[..], &body()[150_000..150_100]);
+
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        let mut all = Vec::new();
+        reader.read_to_end(&mut all).unwrap();
+        assert_eq!(all, body());
+        assert_eq!(ranges.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_open_at() {
+        let (url, ranges) = serve(body(), true);
+        let mut reader = RangeReader::open_at(&url, 150_000).unwrap();
+        assert_eq!(reader.len(), Some(50_000));
+
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, &body()[150_000..]);
+        assert_eq!(reader.contiguous().load(Ordering::Relaxed), 50_000);
+        assert_eq!(*ranges.lock().unwrap(), vec![Some(150_000)]);
+    }
+
+    #[test]
+    fn test_seek_end() {
+        let (url, _) = serve(body(), true);
+        let mut reader = RangeReader::open(&url).unwrap();
+
+        assert_eq!(reader.seek(SeekFrom::End(-10)).unwrap(), 199_990);
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, &body()[199_990..]);
+        assert!(reader.seek(SeekFrom::Current(-300_000)).is_err());
+    }
+
+    // gives `body` in small pieces, pausing before each like a slow network
+    struct Trickle {
+        body: Vec<u8>,
+        pos: usize,
+    }
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            thread::sleep(Duration::from_millis(1));
+            let n = buf.len().min(1000).min(self.body.len() - self.pos);
+            buf[..n].copy_from_slice(&self.body[self.pos..self.pos + n]);
+            self.pos += n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_read_ahead() {
+        let mut reader = ReadAhead::spawn(Box::new(Trickle { body: body(), pos: 0 }));
+        reader.prebuffer(10_000);
+
+        // forward seeks wait for the bytes, backward ones are served from memory
+        reader.seek(SeekFrom::Start(150_000)).unwrap();
+        let mut buf = [0u8; 4];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, body()[150_000..150_004]);
+        reader.seek(SeekFrom::Current(-150_004)).unwrap();
+        let mut all = Vec::new();
+        reader.read_to_end(&mut all).unwrap();
+        assert_eq!(all, body());
+
+        assert_eq!(reader.seek(SeekFrom::End(-10)).unwrap(), 199_990);
+        assert!(reader.seek(SeekFrom::Current(-300_000)).is_err());
+    }
+
+    // an endless stream, counting how often it's read
+    struct Endless(Arc<AtomicUsize>);
+
+    impl Read for Endless {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            thread::sleep(Duration::from_millis(1));
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(buf.len().min(1000))
+        }
+    }
+
+    #[test]
+    fn test_read_ahead_stops_on_drop() {
+        let reads = Arc::new(AtomicUsize::new(0));
+        let reader = ReadAhead::spawn(Box::new(Endless(reads.clone())));
+        reader.prebuffer(10_000);
+        drop(reader);
+        thread::sleep(Duration::from_millis(20));
+
+        let stopped_at = reads.load(Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(reads.load(Ordering::SeqCst), stopped_at);
+    }
+}
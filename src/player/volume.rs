@@ -0,0 +1,58 @@
+//! Perceptual volume: equal steps in percent sound like equal steps in loudness.
+
+// 90% plays at unity gain, every 10% up or down doubles or halves the amplitude
+// (about 6 dB), so 100% is 2.0
+const UNITY_PERCENT: f32 = 90.0;
+const PERCENT_PER_DOUBLING: f32 = 10.0;
+
+/// Amplitude factor for `percent` in [0, 100], `10^(dB/20)` with 0.6 dB a
+/// percent. 0 is silence rather than the -54 dB the curve would give.
+pub fn amplitude(percent: f32) -> f32 {
+    if percent <= 0.0 {
+        return 0.0;
+    }
+    2f32.powf((percent.min(100.0) - UNITY_PERCENT) / PERCENT_PER_DOUBLING)
+}
+
+/// The percent `amplitude` is played at, clamped to [0, 100].
+pub fn percent(amplitude: f32) -> f32 {
+    if amplitude <= 0.0 {
+        return 0.0;
+    }
+    (UNITY_PERCENT + amplitude.log2() * PERCENT_PER_DOUBLING)
+        .max(0.0)
+        .min(100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{amplitude, percent};
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn test_curve() {
+        assert_eq!(amplitude(0.0), 0.0);
+        assert!(close(amplitude(90.0), 1.0));
+        assert!(close(amplitude(100.0), 2.0));
+        assert!(close(amplitude(80.0), 0.5));
+        assert!(close(amplitude(150.0), 2.0));
+
+        // the same step is the same ratio anywhere on the curve
+        let low = amplitude(20.0) / amplitude(10.0);
+        let high = amplitude(100.0) / amplitude(90.0);
+        assert!(close(low, high));
+    }
+
+    #[test]
+    fn test_percent_inverts_amplitude() {
+        for p in [1.0, 25.0, 50.0, 90.0, 100.0] {
+            assert!(close(percent(amplitude(p)), p), "{}", p);
+        }
+        assert_eq!(percent(0.0), 0.0);
+        assert_eq!(percent(1e-9), 0.0);
+        assert_eq!(percent(4.0), 100.0);
+    }
+}
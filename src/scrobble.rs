@@ -0,0 +1,356 @@
+//! Last.fm scrobbling: "now playing" when a song starts, and a scrobble once
+//! it was listened to long enough. Failed submissions wait to be retried.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::mpsc::{self, RecvTimeoutError, Sender},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::{error, warn};
+use reqwest::blocking::Client;
+use serde_json::Value;
+
+const API_URL: &str = "https://ws.audioscrobbler.com/2.0/";
+// tracks one `track.scrobble` call can submit
+const BATCH: usize = 50;
+// the spawned scrobbler retries what's pending this often
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+// shorter tracks are never scrobbled
+const MIN_DURATION_MS: u64 = 30_000;
+// a track counts once half of it, or this much, was listened to
+const LISTEN_ENOUGH_MS: u64 = 4 * 60 * 1000;
+
+/// What's needed to scrobble for a user, see the `lastfm_*` settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Credentials {
+    pub api_key: String,
+    pub api_secret: String,
+    // of the user, from the Last.fm auth flow
+    pub session_key: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track {
+    pub artist: String,
+    pub title: String,
+    pub duration_secs: Option<u64>,
+    // unix time the track started playing
+    pub started_at: u64,
+}
+
+#[derive(Debug)]
+pub enum ScrobbleError {
+    // Last.fm couldn't be reached
+    Network(String),
+    // an error of the api, with its code
+    Api { code: i64, message: String },
+}
+
+impl ScrobbleError {
+    /// Whether the same call may succeed later.
+    pub fn is_temporary(&self) -> bool {
+        match self {
+            ScrobbleError::Network(_) => true,
+            // service offline, temporarily unavailable, rate limited
+            ScrobbleError::Api { code, .. } => matches!(code, 11 | 16 | 29),
+        }
+    }
+}
+
+impl fmt::Display for ScrobbleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScrobbleError::Network(e) => write!(f, "last.fm unreachable: {}", e),
+            ScrobbleError::Api { code, message } => write!(f, "last.fm error {}: {}", code, message),
+        }
+    }
+}
+
+impl std::error::Error for ScrobbleError {}
+
+/// Calls of the Last.fm api, swappable for a fake in tests.
+pub trait LastFmApi {
+    /// Posts the signed `params` of one call.
+    fn call(&mut self, params: &[(String, String)]) -> Result<(), ScrobbleError>;
+}
+
+pub struct HttpApi {
+    client: Client,
+}
+
+impl Default for HttpApi {
+    fn default() -> Self {
+        Self { client: Client::new() }
+    }
+}
+
+impl LastFmApi for HttpApi {
+    fn call(&mut self, params: &[(String, String)]) -> Result<(), ScrobbleError> {
+        let network = |e: reqwest::Error| ScrobbleError::Network(e.to_string());
+        let resp = self
+            .client
+            .post(API_URL)
+            .query(&[("format", "json")])
+            .form(params)
+            .send()
+            .map_err(network)?;
+        let status = resp.status();
+        let body: Value = match resp.json() {
+            Ok(body) => body,
+            Err(_) if status.is_server_error() => return Err(ScrobbleError::Network(status.to_string())),
+            Err(e) => return Err(network(e)),
+        };
+        match body.get("error").and_then(Value::as_i64) {
+            Some(code) => Err(ScrobbleError::Api {
+                code,
+                message: body["message"].as_str().unwrap_or_default().to_owned(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+pub struct Scrobbler<A = HttpApi> {
+    api: A,
+    credentials: Credentials,
+    // scrobbles not submitted yet, oldest first
+    pending: VecDeque<Track>,
+}
+
+impl Scrobbler {
+    pub fn new(credentials: Credentials) -> Self {
+        Self::with_api(HttpApi::default(), credentials)
+    }
+}
+
+impl<A: LastFmApi> Scrobbler<A> {
+    pub fn with_api(api: A, credentials: Credentials) -> Self {
+        Self {
+            api,
+            credentials,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Scrobbles waiting for a retry.
+    pub fn pending(&self) -> &VecDeque<Track> {
+        &self.pending
+    }
+
+    /// Shows `track` as playing on the user's profile, it's not retried.
+    pub fn now_playing(&mut self, track: &Track) -> Result<(), ScrobbleError> {
+        let mut params = vec![
+            ("method".to_owned(), "track.updateNowPlaying".to_owned()),
+            ("artist".to_owned(), track.artist.clone()),
+            ("track".to_owned(), track.title.clone()),
+        ];
+        if let Some(secs) = track.duration_secs {
+            params.push(("duration".to_owned(), secs.to_string()));
+        }
+        self.call(params)
+    }
+
+    /// Queues `track` and submits everything pending.
+    pub fn scrobble(&mut self, track: Track) -> Result<(), ScrobbleError> {
+        self.pending.push_back(track);
+        self.flush()
+    }
+
+    /// Submits the pending scrobbles, keeping them when Last.fm can't take
+    /// them right now. The ones it rejects are dropped.
+    pub fn flush(&mut self) -> Result<(), ScrobbleError> {
+        while !self.pending.is_empty() {
+            let batch = self.pending.len().min(BATCH);
+            let mut params = vec![("method".to_owned(), "track.scrobble".to_owned())];
+            for (i, track) in self.pending.iter().take(batch).enumerate() {
+                params.push((format!("artist[{}]", i), track.artist.clone()));
+                params.push((format!("track[{}]", i), track.title.clone()));
+                params.push((format!("timestamp[{}]", i), track.started_at.to_string()));
+                if let Some(secs) = track.duration_secs {
+                    params.push((format!("duration[{}]", i), secs.to_string()));
+                }
+            }
+            match self.call(params) {
+                Err(e) if e.is_temporary() => return Err(e),
+                result => {
+                    self.pending.drain(..batch);
+                    result?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // signs and sends one call
+    fn call(&mut self, mut params: Vec<(String, String)>) -> Result<(), ScrobbleError> {
+        params.push(("api_key".to_owned(), self.credentials.api_key.clone()));
+        params.push(("sk".to_owned(), self.credentials.session_key.clone()));
+        let sig = sign(&params, &self.credentials.api_secret);
+        params.push(("api_sig".to_owned(), sig));
+        self.api.call(&params)
+    }
+}
+
+pub enum ScrobbleEvent {
+    NowPlaying(Track),
+    Scrobble(Track),
+}
+
+/// Runs `scrobbler` on its own thread, retrying what's pending every
+/// `RETRY_INTERVAL`. It stops once the sender is dropped.
+pub fn spawn<A>(mut scrobbler: Scrobbler<A>) -> Sender<ScrobbleEvent>
+where
+    A: LastFmApi + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || loop {
+        let result = match rx.recv_timeout(RETRY_INTERVAL) {
+            Ok(ScrobbleEvent::NowPlaying(track)) => scrobbler.now_playing(&track),
+            Ok(ScrobbleEvent::Scrobble(track)) => scrobbler.scrobble(track),
+            Err(RecvTimeoutError::Timeout) => scrobbler.flush(),
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        match result {
+            Err(e) if e.is_temporary() => warn!("{}, {} scrobbles pending", e, scrobbler.pending().len()),
+            Err(e) => error!("{}", e),
+            Ok(()) => {}
+        }
+    });
+    tx
+}
+
+/// Whether listening to `listened_ms` of a track makes a scrobble.
+pub fn should_scrobble(duration_ms: Option<u64>, listened_ms: u64) -> bool {
+    match duration_ms {
+        Some(duration) if duration < MIN_DURATION_MS => false,
+        Some(duration) => listened_ms >= (duration / 2).min(LISTEN_ENOUGH_MS),
+        None => listened_ms >= LISTEN_ENOUGH_MS,
+    }
+}
+
+/// Seconds since the unix epoch, what `Track::started_at` holds.
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+// md5 of the params sorted by name, concatenated, then the secret
+fn sign(params: &[(String, String)], secret: &str) -> String {
+    let mut sorted: Vec<_> = params.iter().collect();
+    sorted.sort();
+    let mut raw: String = sorted.iter().map(|(k, v)| format!("{}{}", k, v)).collect();
+    raw += secret;
+    format!("{:x}", md5::compute(raw))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::{should_scrobble, sign, Credentials, LastFmApi, ScrobbleError, Scrobbler, Track};
+
+    // answers with the queued results, `Ok` once they run out
+    #[derive(Default)]
+    struct FakeApi {
+        results: VecDeque<Result<(), ScrobbleError>>,
+        calls: Vec<Vec<(String, String)>>,
+    }
+
+    impl LastFmApi for FakeApi {
+        fn call(&mut self, params: &[(String, String)]) -> Result<(), ScrobbleError> {
+            self.calls.push(params.to_vec());
+            self.results.pop_front().unwrap_or(Ok(()))
+        }
+    }
+
+    fn scrobbler(results: Vec<Result<(), ScrobbleError>>) -> Scrobbler<FakeApi> {
+        let api = FakeApi {
+            results: results.into(),
+            ..Default::default()
+        };
+        let credentials = Credentials {
+            api_key: "key".to_owned(),
+            api_secret: "secret".to_owned(),
+            session_key: "sk".to_owned(),
+        };
+        Scrobbler::with_api(api, credentials)
+    }
+
+    fn track(title: &str) -> Track {
+        Track {
+            artist: "Jay Chou".to_owned(),
+            title: title.to_owned(),
+            duration_secs: Some(200),
+            started_at: 1_600_000_000,
+        }
+    }
+
+    fn param<'a>(params: &'a [(String, String)], name: &str) -> Option<&'a str> {
+        params.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn test_should_scrobble() {
+        // half of 3 minutes
+        assert!(!should_scrobble(Some(180_000), 89_000));
+        assert!(should_scrobble(Some(180_000), 90_000));
+        // 4 minutes of a 20 minute track
+        assert!(should_scrobble(Some(1_200_000), 240_000));
+        assert!(!should_scrobble(Some(20_000), 20_000));
+        assert!(!should_scrobble(None, 200_000));
+    }
+
+    #[test]
+    fn test_sign() {
+        let params = vec![
+            ("track".to_owned(), "b".to_owned()),
+            ("artist".to_owned(), "a".to_owned()),
+        ];
+        assert_eq!(sign(&params, "s"), format!("{:x}", md5::compute("artistatrackbs")));
+    }
+
+    #[test]
+    fn test_now_playing() {
+        let mut scrobbler = scrobbler(vec![]);
+        scrobbler.now_playing(&track("晴天")).unwrap();
+        let call = &scrobbler.api.calls[0];
+        assert_eq!(param(call, "method"), Some("track.updateNowPlaying"));
+        assert_eq!(param(call, "track"), Some("晴天"));
+        assert_eq!(param(call, "duration"), Some("200"));
+        assert_eq!(param(call, "sk"), Some("sk"));
+        assert!(param(call, "api_sig").is_some());
+        assert!(scrobbler.pending().is_empty());
+    }
+
+    #[test]
+    fn test_retry_after_network_failure() {
+        let offline = ScrobbleError::Network("timed out".to_owned());
+        let mut scrobbler = scrobbler(vec![Err(offline)]);
+        assert!(scrobbler.scrobble(track("a")).is_err());
+        assert_eq!(scrobbler.pending().len(), 1);
+
+        // both go in the next batch
+        scrobbler.scrobble(track("b")).unwrap();
+        assert!(scrobbler.pending().is_empty());
+        let call = scrobbler.api.calls.last().unwrap();
+        assert_eq!(param(call, "method"), Some("track.scrobble"));
+        assert_eq!(param(call, "track[0]"), Some("a"));
+        assert_eq!(param(call, "track[1]"), Some("b"));
+        assert_eq!(param(call, "timestamp[1]"), Some("1600000000"));
+    }
+
+    #[test]
+    fn test_rejected_scrobbles_are_dropped() {
+        let invalid = ScrobbleError::Api {
+            code: 6,
+            message: "Invalid parameters".to_owned(),
+        };
+        let mut scrobbler = scrobbler(vec![Err(invalid)]);
+        assert!(scrobbler.scrobble(track("a")).is_err());
+        assert!(scrobbler.pending().is_empty());
+    }
+}
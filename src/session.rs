@@ -0,0 +1,157 @@
+//! Login session kept on disk between launches.
+
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use ncmapi::types::UserProfile;
+use serde_json::Value;
+
+use crate::event::LoginStatus;
+
+/// `$XDG_CONFIG_HOME/lekima`, falling back to `~/.config/lekima`.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("lekima"))
+}
+
+/// `$XDG_CACHE_HOME/lekima`, falling back to `~/.cache/lekima`.
+pub fn cache_dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".cache"),
+    };
+    Some(base.join("lekima"))
+}
+
+pub fn cookie_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("cookie"))
+}
+
+/// Reads the stored cookie jar, `None` if it's missing, empty or not valid json.
+pub fn read_cookie(path: &Path) -> Option<Value> {
+    let raw = fs::read_to_string(path).ok()?;
+    match serde_json::from_str::<Value>(&raw).ok()? {
+        Value::Null => None,
+        cookie => Some(cookie),
+    }
+}
+
+/// Makes the cookie jar at `path` readable by its owner only, it holds the login.
+/// A missing jar is created that way, so the login is never written to a file
+/// others can read.
+pub fn protect_cookie(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // the mode only applies to a new file, an old one is fixed below
+        fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o600)
+            .open(path)?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Forgets the stored session, e.g. on logout.
+pub fn clear_cookie(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Checks a stored session with `validate`, which asks the api for the
+/// profile the cookie belongs to.
+pub fn refresh_login<F>(path: &Path, validate: F) -> LoginStatus
+where
+    F: FnOnce(&Value) -> Option<UserProfile>,
+{
+    match read_cookie(path).as_ref().and_then(validate) {
+        Some(user) => LoginStatus::Valid(user),
+        None => LoginStatus::Expired,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use ncmapi::types::UserProfile;
+
+    use super::{clear_cookie, protect_cookie, refresh_login};
+    use crate::event::LoginStatus;
+
+    fn cookie_file(name: &str, content: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("lekima-{}-{}", name, std::process::id()));
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_valid_cookie() {
+        let path = cookie_file("valid", r#"{"MUSIC_U": "token"}"#);
+        let status = refresh_login(&path, |cookie| {
+            assert_eq!(cookie["MUSIC_U"], "token");
+            Some(UserProfile::default())
+        });
+        assert!(matches!(status, LoginStatus::Valid(_)));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_expired_cookie() {
+        let path = cookie_file("expired", r#"{"MUSIC_U": "old"}"#);
+        let status = refresh_login(&path, |_| None);
+        assert!(matches!(status, LoginStatus::Expired));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_missing_cookie() {
+        let path = std::env::temp_dir().join("lekima-no-such-cookie");
+        let status = refresh_login(&path, |_| panic!("api must not be called"));
+        assert!(matches!(status, LoginStatus::Expired));
+    }
+
+    #[test]
+    fn test_corrupt_cookie() {
+        let path = cookie_file("corrupt", "{not json");
+        let status = refresh_login(&path, |_| panic!("api must not be called"));
+        assert!(matches!(status, LoginStatus::Expired));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_protect_and_clear_cookie() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = cookie_file("protect", r#"{"MUSIC_U": "token"}"#);
+        protect_cookie(&path).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        clear_cookie(&path).unwrap();
+        assert!(!path.exists());
+        clear_cookie(&path).unwrap();
+
+        // created private before anything is written to it
+        protect_cookie(&path).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,206 @@
+//! Colors the ui is drawn with, a built-in palette or one from the config:
+//!
+//! ```toml
+//! theme = "mine"
+//!
+//! [themes.mine]
+//! primary = "#d0d0d0"
+//! highlight = "#61afef"
+//! ```
+//!
+//! Colors left out of a custom theme are those of the default one.
+
+use std::collections::HashMap;
+
+use tui::style::{Color, Modifier, Style};
+
+/// The palette, `Color::Reset` keeps the terminal's own color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    // text
+    pub primary: Color,
+    // accents: warnings, the loading spinner
+    pub secondary: Color,
+    // the selected row
+    pub highlight: Color,
+    pub error: Color,
+    // unavailable tracks, lyric lines not playing
+    pub inactive: Color,
+    pub background: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            primary: Color::Reset,
+            secondary: Color::Yellow,
+            highlight: Color::Reset,
+            error: Color::Red,
+            inactive: Color::Reset,
+            background: Color::Reset,
+        }
+    }
+}
+
+impl Theme {
+    pub const BUILT_IN: [&'static str; 3] = ["default", "dark", "solarized"];
+
+    /// One of `BUILT_IN`.
+    pub fn built_in(name: &str) -> Option<Self> {
+        let theme = match name {
+            "default" => Self::default(),
+            "dark" => Self {
+                primary: Color::Rgb(0xd0, 0xd0, 0xd0),
+                secondary: Color::Rgb(0xe5, 0xc0, 0x7b),
+                highlight: Color::Rgb(0x61, 0xaf, 0xef),
+                error: Color::Rgb(0xe0, 0x6c, 0x75),
+                inactive: Color::Rgb(0x5c, 0x63, 0x70),
+                background: Color::Rgb(0x1e, 0x21, 0x27),
+            },
+            "solarized" => Self {
+                primary: Color::Rgb(0x83, 0x94, 0x96),
+                secondary: Color::Rgb(0xb5, 0x89, 0x00),
+                highlight: Color::Rgb(0x26, 0x8b, 0xd2),
+                error: Color::Rgb(0xdc, 0x32, 0x2f),
+                inactive: Color::Rgb(0x58, 0x6e, 0x75),
+                background: Color::Rgb(0x00, 0x2b, 0x36),
+            },
+            _ => return None,
+        };
+        Some(theme)
+    }
+
+    /// The theme called `name`, from `custom` before the built-in ones. The
+    /// default stands in for an unknown theme, and for each color of a custom
+    /// one that isn't `#rrggbb`. Every such mistake comes back as a warning.
+    pub fn load(
+        name: &str,
+        custom: &HashMap<String, HashMap<String, String>>,
+    ) -> (Self, Vec<String>) {
+        let colors = match custom.get(name) {
+            Some(colors) => colors,
+            None => {
+                return match Self::built_in(name) {
+                    Some(theme) => (theme, Vec::new()),
+                    None => (Self::default(), vec![format!("Unknown theme {:?}", name)]),
+                }
+            }
+        };
+        let mut theme = Self::default();
+        let mut warnings = Vec::new();
+        let mut names: Vec<&String> = colors.keys().collect();
+        names.sort();
+        for field in names {
+            let value = &colors[field];
+            let slot = match field.as_str() {
+                "primary" => &mut theme.primary,
+                "secondary" => &mut theme.secondary,
+                "highlight" => &mut theme.highlight,
+                "error" => &mut theme.error,
+                "inactive" => &mut theme.inactive,
+                "background" => &mut theme.background,
+                _ => {
+                    warnings.push(format!("Theme {}: unknown color {:?}", name, field));
+                    continue;
+                }
+            };
+            match parse_hex(value) {
+                Some(color) => *slot = color,
+                None => {
+                    warnings.push(format!("Theme {}: {} {:?} isn't #rrggbb", name, field, value))
+                }
+            }
+        }
+        (theme, warnings)
+    }
+
+    /// Plain text.
+    pub fn text(&self) -> Style {
+        Style::default().fg(self.primary).bg(self.background)
+    }
+
+    /// Headers and what's current, e.g. the playing lyric line.
+    pub fn bold(&self) -> Style {
+        Style::default().fg(self.primary).add_modifier(Modifier::BOLD)
+    }
+
+    pub fn accent(&self) -> Style {
+        Style::default().fg(self.secondary)
+    }
+
+    /// The selected row.
+    pub fn highlight(&self) -> Style {
+        Style::default().fg(self.highlight).add_modifier(Modifier::REVERSED)
+    }
+
+    pub fn error(&self) -> Style {
+        Style::default().fg(self.error)
+    }
+
+    pub fn inactive(&self) -> Style {
+        Style::default().fg(self.inactive).add_modifier(Modifier::DIM)
+    }
+}
+
+// `#rrggbb`
+fn parse_hex(hex: &str) -> Option<Color> {
+    let digits = hex.strip_prefix('#')?;
+    if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).ok();
+    Some(Color::Rgb(channel(0)?, channel(2)?, channel(4)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use tui::style::Color;
+
+    use super::{parse_hex, Theme};
+
+    #[test]
+    fn test_built_in() {
+        for name in Theme::BUILT_IN.iter() {
+            let (theme, warnings) = Theme::load(name, &HashMap::new());
+            assert_eq!(Some(theme), Theme::built_in(name));
+            assert!(warnings.is_empty());
+        }
+        let (theme, warnings) = Theme::load("neon", &HashMap::new());
+        assert_eq!(theme, Theme::default());
+        assert_eq!(warnings, vec!["Unknown theme \"neon\""]);
+    }
+
+    #[test]
+    fn test_custom() {
+        let colors: HashMap<String, String> = vec![
+            ("primary", "#268bd2"),
+            ("error", "red"),
+            ("background", "#12345"),
+            ("border", "#000000"),
+        ]
+        .into_iter()
+        .map(|(field, value)| (field.to_owned(), value.to_owned()))
+        .collect();
+        let custom = vec![("mine".to_owned(), colors)].into_iter().collect();
+        let (theme, warnings) = Theme::load("mine", &custom);
+
+        assert_eq!(theme.primary, Color::Rgb(0x26, 0x8b, 0xd2));
+        // the default's for the invalid ones
+        assert_eq!(theme.error, Theme::default().error);
+        assert_eq!(theme.background, Theme::default().background);
+        assert_eq!(warnings.len(), 3);
+        assert!(warnings[0].contains("background"));
+        assert!(warnings[1].contains("unknown color \"border\""));
+    }
+
+    #[test]
+    fn test_parse_hex() {
+        assert_eq!(parse_hex("#FFa000"), Some(Color::Rgb(0xff, 0xa0, 0x00)));
+        assert_eq!(parse_hex("ffa000"), None);
+        assert_eq!(parse_hex("#ffa00"), None);
+        assert_eq!(parse_hex("#ffa00g"), None);
+        assert_eq!(parse_hex("#ffa0é"), None);
+    }
+}
@@ -0,0 +1,165 @@
+use unicode_width::UnicodeWidthChar;
+
+/// Scrolls text that is wider than its field, one char every `speed` ticks,
+/// holding for `pause` ticks at both ends before starting over.
+///
+/// The window is computed from the tick count at draw time, so the same
+/// marquee can be rendered into any width.
+pub struct Marquee {
+    text: String,
+    speed: u64,
+    pause: u64,
+    ticks: u64,
+}
+
+impl Marquee {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            speed: 2,
+            pause: 10,
+            ticks: 0,
+        }
+    }
+
+    pub fn with_speed(mut self, speed: u64) -> Self {
+        self.speed = speed.max(1);
+        self
+    }
+
+    pub fn with_pause(mut self, pause: u64) -> Self {
+        self.pause = pause;
+        self
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Replaces the text, restarting from the beginning when it changed.
+    pub fn set_text(&mut self, text: impl Into<String>) {
+        let text = text.into();
+        if text != self.text {
+            self.text = text;
+            self.ticks = 0;
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.ticks = self.ticks.wrapping_add(1);
+    }
+
+    /// Whether the last tick may have moved the window, offsets change every `speed` ticks.
+    pub fn stepped(&self) -> bool {
+        !self.text.is_empty() && self.ticks % self.speed == 0
+    }
+
+    /// The visible part of the text, padded with spaces to exactly `width` columns.
+    pub fn window(&self, width: usize) -> String {
+        let chars: Vec<(char, usize)> = self
+            .text
+            .chars()
+            .map(|c| (c, c.width().unwrap_or(0)))
+            .collect();
+        let offset = self.offset(&chars, width);
+
+        let mut window = String::new();
+        let mut used = 0;
+        for &(c, w) in &chars[offset..] {
+            if used + w > width {
+                break;
+            }
+            window.push(c);
+            used += w;
+        }
+        window.extend(std::iter::repeat(' ').take(width - used));
+        window
+    }
+
+    fn offset(&self, chars: &[(char, usize)], width: usize) -> usize {
+        // first offset from which the rest of the text fits
+        let mut rest: usize = chars.iter().map(|(_, w)| w).sum();
+        let mut max_offset = 0;
+        while rest > width && max_offset < chars.len() {
+            rest -= chars[max_offset].1;
+            max_offset += 1;
+        }
+        if max_offset == 0 {
+            return 0;
+        }
+
+        // every offset shows for `speed` ticks, both ends are held `pause` ticks longer
+        let scroll = (max_offset as u64 + 1) * self.speed;
+        let t = self.ticks % (self.pause * 2 + scroll);
+        if t < self.pause {
+            0
+        } else if t < self.pause + scroll {
+            ((t - self.pause) / self.speed) as usize
+        } else {
+            max_offset
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Marquee;
+
+    fn windows(m: &mut Marquee, width: usize, n: usize) -> Vec<String> {
+        (0..n)
+            .map(|_| {
+                let w = m.window(width);
+                m.tick();
+                w
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ascii_scroll() {
+        let mut m = Marquee::new("hello world").with_speed(1).with_pause(2);
+        assert_eq!(
+            windows(&mut m, 5, 12),
+            vec![
+                "hello", "hello", "hello", "ello ", "llo w", "lo wo", "o wor", " worl", "world",
+                "world", "world", "hello",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_speed() {
+        let mut m = Marquee::new("abcdef").with_speed(2).with_pause(0);
+        assert_eq!(
+            windows(&mut m, 4, 7),
+            vec!["abcd", "abcd", "bcde", "bcde", "cdef", "cdef", "abcd"]
+        );
+    }
+
+    #[test]
+    fn test_cjk_scroll() {
+        let mut m = Marquee::new("晴天周杰伦").with_speed(1).with_pause(1);
+        assert_eq!(
+            windows(&mut m, 5, 7),
+            vec!["晴天 ", "晴天 ", "天周 ", "周杰 ", "杰伦 ", "杰伦 ", "晴天 "]
+        );
+    }
+
+    #[test]
+    fn test_short_text_not_scrolled() {
+        let mut m = Marquee::new("abc").with_speed(1).with_pause(0);
+        assert_eq!(windows(&mut m, 6, 3), vec!["abc   "; 3]);
+
+        let mut m = Marquee::new("晴天").with_speed(1).with_pause(0);
+        assert_eq!(windows(&mut m, 4, 2), vec!["晴天"; 2]);
+    }
+
+    #[test]
+    fn test_set_text_restarts() {
+        let mut m = Marquee::new("hello world").with_speed(1).with_pause(0);
+        m.tick();
+        m.tick();
+        m.set_text("goodbye world");
+        assert_eq!(m.window(5), "goodb");
+    }
+}
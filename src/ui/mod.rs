@@ -1,6 +1,34 @@
-use tui::{backend::Backend, layout::Rect, Frame};
+mod marquee;
 
-use crate::app::App;
+pub use marquee::Marquee;
+
+use tui::{
+    backend::Backend,
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Span, Spans},
+    widgets::{
+        Block, Borders, Cell, Clear, Gauge, List, ListItem, ListState, Paragraph, Row, Table,
+        TableState,
+    },
+    Frame,
+};
+use termion::event::Key;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::{
+    app::{self, App, Level, PodcastEpisode, Route, SearchResult},
+    keybindings::key_name,
+    player::{PlaybackContext, PlaybackSnapshot, RepeatMode},
+    theme::Theme,
+};
+
+// columns of the block bouncing in the progress bar while the duration is unknown
+const SPINNER_WIDTH: usize = 3;
+// playback it takes the block to move one column
+const SPINNER_STEP_MS: u64 = 100;
+// shown on the content border while requests are pending, a frame per tick
+const LOADING_FRAMES: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
 
 pub enum TableType {
     Album,
@@ -13,7 +41,10 @@ pub enum TableType {
 #[derive(PartialEq)]
 pub enum ColumnType {
     None,
+    // cut with an ellipsis when too long
     Title,
+    // counts and durations, right aligned
+    Number,
 }
 
 impl Default for ColumnType {
@@ -28,6 +59,10 @@ pub struct TableHeader {
 }
 
 impl TableHeader {
+    pub fn new(id: TableType, items: Vec<TableHeaderItem>) -> Self {
+        Self { id, items }
+    }
+
     pub fn get_index(&self, id: ColumnType) -> Option<usize> {
         self.items.iter().position(|item| item.id == id)
     }
@@ -39,18 +74,1115 @@ pub struct TableHeaderItem {
     width: u16,
 }
 
+impl TableHeaderItem {
+    pub fn new(id: ColumnType, text: impl Into<String>, width: u16) -> Self {
+        Self {
+            id,
+            text: text.into(),
+            width,
+        }
+    }
+}
+
 pub struct TableItem {
     id: String,
     format: Vec<String>,
 }
 
+impl TableItem {
+    /// A row with one cell per column of the header it's shown under.
+    pub fn new(id: impl Into<String>, format: Vec<String>) -> Self {
+        Self {
+            id: id.into(),
+            format,
+        }
+    }
+}
+
+/// `items` under `header`, each cell fitted to its column and the `selected` row highlighted.
+pub fn draw_table<B: Backend>(
+    f: &mut Frame<B>,
+    theme: &Theme,
+    area: Rect,
+    header: &TableHeader,
+    items: &[TableItem],
+    selected: usize,
+) {
+    let fit = |column: &TableHeaderItem, text: &str| {
+        let width = column.width as usize;
+        match column.id {
+            ColumnType::Title => truncate(text, width, true),
+            ColumnType::Number => {
+                let text = truncate(text, width, false);
+                format!("{}{}", " ".repeat(width - text.width()), text)
+            }
+            ColumnType::None => truncate(text, width, false),
+        }
+    };
+    let head = Row::new(header.items.iter().map(|column| {
+        Cell::from(fit(column, &column.text)).style(theme.bold())
+    }));
+    let rows = items.iter().map(|item| {
+        let cells = header
+            .items
+            .iter()
+            .zip(&item.format)
+            .map(|(column, text)| Cell::from(fit(column, text)));
+        Row::new(cells)
+    });
+    let widths: Vec<Constraint> = header
+        .items
+        .iter()
+        .map(|column| Constraint::Length(column.width))
+        .collect();
+
+    let table = Table::new(rows)
+        .header(head)
+        .block(Block::default().borders(Borders::ALL))
+        .widths(&widths)
+        .highlight_style(theme.highlight());
+    let mut state = TableState::default();
+    if !items.is_empty() {
+        state.select(Some(selected.min(items.len() - 1)));
+    }
+    f.render_stateful_widget(table, area, &mut state);
+}
+
+/// Header and rows for a page of `results`, fitted into a table `width` columns wide.
+pub fn search_table(results: &SearchResult, width: u16) -> (TableHeader, Vec<TableItem>) {
+    // borders and the gap between columns
+    let inner = width.saturating_sub(3);
+    let second = inner / 3;
+    let columns = |id, title: &str, second_title: &str| {
+        let items = vec![
+            TableHeaderItem::new(ColumnType::Title, title, inner - second),
+            TableHeaderItem::new(ColumnType::None, second_title, second),
+        ];
+        TableHeader::new(id, items)
+    };
+    let only = |id, title: &str| {
+        let items = vec![TableHeaderItem::new(ColumnType::Title, title, inner + 1)];
+        TableHeader::new(id, items)
+    };
+    match results {
+        SearchResult::Song(songs) => {
+            let rows = songs.iter().map(|song| {
+                let artists: Vec<&str> = song.artists.iter().map(|ar| ar.name.as_str()).collect();
+                TableItem::new(song.id.to_string(), vec![song.name.clone(), artists.join(" / ")])
+            });
+            (columns(TableType::Song, "Title", "Artist"), rows.collect())
+        }
+        SearchResult::Album(albums) => {
+            let rows = albums
+                .iter()
+                .map(|album| TableItem::new(album.id.to_string(), vec![album.name.clone()]));
+            (only(TableType::Album, "Album"), rows.collect())
+        }
+        SearchResult::Artist(artists) => {
+            let rows = artists
+                .iter()
+                .map(|artist| TableItem::new(artist.id.to_string(), vec![artist.name.clone()]));
+            (only(TableType::Artist, "Artist"), rows.collect())
+        }
+        SearchResult::Playlist(playlists) => {
+            let rows = playlists
+                .iter()
+                .map(|playlist| TableItem::new(playlist.id.to_string(), vec![playlist.name.clone()]));
+            (only(TableType::Playlist, "Playlist"), rows.collect())
+        }
+        SearchResult::Podcast(podcasts) => {
+            let rows = podcasts.iter().map(|podcast| {
+                TableItem::new(podcast.id.to_string(), vec![podcast.name.clone(), podcast.dj.clone()])
+            });
+            (columns(TableType::Podcast, "Podcast", "Host"), rows.collect())
+        }
+    }
+}
+
+// at most `width` columns of `text`, the last one an ellipsis when cut and `ellipsis` is set
+fn truncate(text: &str, width: usize, ellipsis: bool) -> String {
+    if text.width() <= width {
+        return text.to_owned();
+    }
+    let budget = if ellipsis { width.saturating_sub(1) } else { width };
+    let mut cut = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w > budget {
+            break;
+        }
+        cut.push(c);
+        used += w;
+    }
+    if ellipsis && width > 0 {
+        cut.push('…');
+    }
+    cut
+}
+
+// `text` in lines of at most `width` columns, broken at the last space when
+// there's one since CJK text has none
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        let mut used = 0;
+        for c in paragraph.chars() {
+            let w = c.width().unwrap_or(0);
+            if used + w > width && !line.is_empty() {
+                if c == ' ' {
+                    lines.push(std::mem::take(&mut line));
+                    used = 0;
+                    continue;
+                }
+                let rest = match line.rfind(' ') {
+                    Some(i) => line.split_off(i + 1),
+                    None => String::new(),
+                };
+                lines.push(line.trim_end().to_owned());
+                line = rest;
+                used = line.width();
+            }
+            line.push(c);
+            used += w;
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+// library entries of the sidebar and the route each opens
+const LIBRARY: [(&str, Route); 4] = [
+    ("Home", Route::Home),
+    ("Recommended", Route::Recommended),
+    ("Personal FM", Route::PersonalFm),
+    ("Last played", Route::LastPlayed),
+];
+
+/// The sidebar on the left, the content next to it and the playbar at the bottom.
 pub fn draw_main_layout<B: Backend>(f: &mut Frame<B>, app: &App, layout_chunk: Rect) {
-    unimplemented!()
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(4)])
+        .split(layout_chunk);
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(20), Constraint::Percentage(80)])
+        .split(rows[0]);
+
+    let theme = app.theme();
+    f.render_widget(Block::default().style(theme.text()), layout_chunk);
+    draw_sidebar(f, theme, app, columns[0]);
+    draw_content(f, theme, app, columns[1]);
+    draw_playbar(f, theme, app, rows[1]);
+    draw_toasts(f, theme, app, rows[0]);
+
+    if app.is_confirming_quit() {
+        draw_confirm(f, theme, "Quit?", layout_chunk);
+    } else if app.confirm_remove().is_some() {
+        draw_confirm(f, theme, "Remove from playlist?", layout_chunk);
+    } else if let Some(scroll) = app.help_scroll() {
+        draw_help(f, theme, app, scroll, layout_chunk);
+    }
+}
+
+/// The library on top of the user's playlists, the current route in bold.
+pub fn draw_sidebar<B: Backend>(f: &mut Frame<B>, theme: &Theme, app: &App, layout_chunk: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(LIBRARY.len() as u16 + 2), Constraint::Min(1)])
+        .split(layout_chunk);
+
+    let library: Vec<ListItem> = LIBRARY
+        .iter()
+        .map(|(name, route)| {
+            ListItem::new(*name).style(indicator_style(theme, app.route() == route))
+        })
+        .collect();
+    let library = List::new(library).block(Block::default().title("Library").borders(Borders::ALL));
+    f.render_widget(library, chunks[0]);
+
+    let playlists: Vec<ListItem> = app
+        .user_playlists_loaded()
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .map(|(i, playlist)| {
+            let playing = app.active_playlist_index() == Some(i);
+            let style = if playing { theme.bold() } else { theme.text() };
+            ListItem::new(playlist.name.as_str()).style(style)
+        })
+        .collect();
+    let mut state = ListState::default();
+    if !playlists.is_empty() {
+        state.select(Some(app.selected_playlist_index()));
+    }
+    let playlists = List::new(playlists)
+        .block(Block::default().title("Playlists").borders(Borders::ALL))
+        .highlight_style(theme.highlight());
+    f.render_stateful_widget(playlists, chunks[1], &mut state);
+}
+
+// what the current route shows, with the search input over it while searching
+fn draw_content<B: Backend>(f: &mut Frame<B>, theme: &Theme, app: &App, layout_chunk: Rect) {
+    let layout_chunk = match app.search_input() {
+        Some(input) => {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(3), Constraint::Min(1)])
+                .split(layout_chunk);
+            let search =
+                Paragraph::new(input).block(Block::default().title("Search").borders(Borders::ALL));
+            f.render_widget(search, chunks[0]);
+            if input.is_empty() && !app.search_history().is_empty() {
+                return draw_search_history(f, theme, app, chunks[1]);
+            }
+            chunks[1]
+        }
+        None => layout_chunk,
+    };
+    draw_routes(f, theme, app, layout_chunk);
+    draw_loading(f, theme, app, layout_chunk);
+}
+
+// a spinner in the top right corner of the block waiting for the io worker
+fn draw_loading<B: Backend>(f: &mut Frame<B>, theme: &Theme, app: &App, layout_chunk: Rect) {
+    if !app.is_loading() || layout_chunk.width < 4 || layout_chunk.height == 0 {
+        return;
+    }
+    let frame = LOADING_FRAMES[app.spinner_frame() % LOADING_FRAMES.len()];
+    let area = Rect::new(layout_chunk.right() - 3, layout_chunk.y, 1, 1);
+    f.render_widget(Paragraph::new(Span::styled(frame, theme.accent())), area);
+}
+
+/// The tracks listed in the content area, unavailable ones dimmed.
+pub fn draw_track_table<B: Backend>(
+    f: &mut Frame<B>,
+    theme: &Theme,
+    app: &App,
+    layout_chunk: Rect,
+) {
+    let title = match app.track_filter() {
+        Some(filter) => format!("Tracks /{}", filter),
+        None => "Tracks".to_owned(),
+    };
+    let block = Block::default().title(title).borders(Borders::ALL);
+    let tracks = match app.track_table() {
+        Some(tracks) if !tracks.is_empty() => tracks,
+        _ => {
+            let text = if app.is_loading() { "Loading…" } else { "No tracks" };
+            let empty = Paragraph::new(text).alignment(Alignment::Center).block(block);
+            return f.render_widget(empty, layout_chunk);
+        }
+    };
+    let rows = app.filtered_indices();
+    let items: Vec<ListItem> = rows
+        .iter()
+        .map(|&i| {
+            let song = &tracks[i];
+            let style = if app.is_available(song.id) {
+                theme.text()
+            } else {
+                theme.inactive()
+            };
+            ListItem::new(app::playbar_title(song)).style(style)
+        })
+        .collect();
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(theme.highlight());
+    let mut state = ListState::default();
+    state.select(rows.iter().position(|&i| i == app.track_table_index()));
+    f.render_stateful_widget(list, layout_chunk, &mut state);
+}
+
+/// The view of the route on top of the navigation stack.
+pub fn draw_routes<B: Backend>(f: &mut Frame<B>, theme: &Theme, app: &App, layout_chunk: Rect) {
+    match app.route() {
+        Route::Login => {
+            let text = if app.is_loading() { "Logging in…" } else { "Not logged in" };
+            let login = Paragraph::new(text)
+                .alignment(Alignment::Center)
+                .block(Block::default().title("Login").borders(Borders::ALL));
+            f.render_widget(login, layout_chunk);
+        }
+        Route::Podcast(_) => draw_podcast(f, theme, app, layout_chunk),
+        Route::Lyrics => draw_lyrics(f, theme, app, layout_chunk),
+        Route::Comments => draw_comments(f, theme, app, layout_chunk),
+        // found songs are in the track table, where they can be played
+        Route::Search => match app.search_results() {
+            Some(results) if !matches!(results, SearchResult::Song(_)) && !results.is_empty() => {
+                let (header, items) = search_table(results, layout_chunk.width);
+                draw_table(f, theme, layout_chunk, &header, &items, app.search_index());
+            }
+            _ => draw_track_table(f, theme, app, layout_chunk),
+        },
+        Route::Home
+        | Route::Recommended
+        | Route::PersonalFm
+        | Route::LastPlayed
+        | Route::Album(_)
+        | Route::Artist(_)
+        | Route::Playlist(_) => draw_track_table(f, theme, app, layout_chunk),
+    }
+}
+
+/// The playing title with the status glyphs, above the progress bar.
+pub fn draw_playbar<B: Backend>(f: &mut Frame<B>, theme: &Theme, app: &App, layout_chunk: Rect) {
+    let block = Block::default().borders(Borders::ALL);
+    let inner = block.inner(layout_chunk);
+    f.render_widget(block, layout_chunk);
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Length(1)])
+        .split(inner);
+
+    let mut status = playbar_status(theme, &app.playback_snapshot());
+    if let Some(song) = app.now_playing() {
+        status.0.insert(0, like_glyph(theme, app.is_liked(song.id)));
+        status.0.insert(1, Span::raw(" "));
+    }
+    if app.is_buffering() {
+        status.0.insert(0, Span::styled("Buffering… ", theme.inactive()));
+    }
+    let status_width: usize = status.0.iter().map(|span| span.content.width()).sum();
+    let width = (inner.width as usize).saturating_sub(status_width + 1);
+
+    let mut line = vec![Span::raw(app.title_marquee().window(width)), Span::raw(" ")];
+    line.extend(status.0);
+    f.render_widget(Paragraph::new(Spans::from(line)), rows[0]);
+    draw_progress(f, theme, rows[1], &app.playback_context());
+}
+
+/// Elapsed and remaining time over a gauge of the track, speed and volume on the right.
+pub fn draw_progress<B: Backend>(
+    f: &mut Frame<B>,
+    theme: &Theme,
+    area: Rect,
+    ctx: &PlaybackContext,
+) {
+    let settings = format!(" {}x {:>3}%", ctx.speed(), ctx.volume_percent());
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Min(1), Constraint::Length(settings.width() as u16)])
+        .split(area);
+    f.render_widget(Paragraph::new(settings), chunks[1]);
+
+    let progress = ctx.progress_ms();
+    match ctx.duration_ms() {
+        Some(duration) => {
+            let label = format!(
+                "{} / -{}",
+                format_duration(progress),
+                format_duration(duration.saturating_sub(progress))
+            );
+            let gauge = Gauge::default()
+                .gauge_style(Style::default().fg(theme.highlight).bg(theme.background))
+                .ratio((progress as f64 / duration as f64).min(1.0))
+                .label(label);
+            f.render_widget(gauge, chunks[0]);
+        }
+        None => {
+            let label = format!("{} ", format_duration(progress));
+            let width = (chunks[0].width as usize).saturating_sub(label.width());
+            let line = Spans::from(vec![Span::raw(label), Span::raw(spinner(width, progress))]);
+            f.render_widget(Paragraph::new(line), chunks[0]);
+        }
+    }
+}
+
+// a short block going back and forth over `width` columns as playback goes on
+fn spinner(width: usize, progress_ms: u64) -> String {
+    let block = SPINNER_WIDTH.min(width);
+    let span = width - block;
+    let step = (progress_ms / SPINNER_STEP_MS) as usize;
+    let offset = match span {
+        0 => 0,
+        _ if step % (2 * span) > span => 2 * span - step % (2 * span),
+        _ => step % (2 * span),
+    };
+    format!("{}{}{}", "─".repeat(offset), "━".repeat(block), "─".repeat(span - offset))
+}
+
+fn indicator_style(theme: &Theme, on: bool) -> Style {
+    if on {
+        theme.bold()
+    } else {
+        theme.inactive()
+    }
+}
+
+/// Repeat, shuffle and mute glyphs, dimmed while off.
+pub fn playbar_status(theme: &Theme, snapshot: &PlaybackSnapshot) -> Spans<'static> {
+    let repeat = match snapshot.repeat_mode {
+        RepeatMode::Off | RepeatMode::All => "↻".to_owned(),
+        RepeatMode::One => "↻1".to_owned(),
+        RepeatMode::Count(n) => format!("↻{}", n),
+    };
+    let mute = if snapshot.is_muted { "♪×" } else { "♪" };
+    Spans::from(vec![
+        Span::styled(repeat, indicator_style(theme, snapshot.repeat_mode != RepeatMode::Off)),
+        Span::raw(" "),
+        Span::styled("⇄", indicator_style(theme, snapshot.shuffle_enabled)),
+        Span::raw(" "),
+        Span::styled(mute, indicator_style(theme, snapshot.is_muted)),
+    ])
+}
+
+/// A filled heart for a liked song, an empty one otherwise.
+pub fn like_glyph(theme: &Theme, liked: bool) -> Span<'static> {
+    if liked {
+        Span::styled("♥", indicator_style(theme, true))
+    } else {
+        Span::styled("♡", indicator_style(theme, false))
+    }
+}
+
+/// Yes/no question centered over `layout_chunk`.
+pub fn draw_confirm<B: Backend>(
+    f: &mut Frame<B>,
+    theme: &Theme,
+    question: &str,
+    layout_chunk: Rect,
+) {
+    let text = format!("{} (y/n)", question);
+    let area = centered(layout_chunk, text.width() as u16 + 4, 3);
+    let modal = Paragraph::new(text)
+        .style(theme.text())
+        .alignment(Alignment::Center)
+        .block(Block::default().borders(Borders::ALL));
+    f.render_widget(Clear, area);
+    f.render_widget(modal, area);
+}
+
+/// The latest notifications stacked in the bottom right corner, until they expire.
+pub fn draw_toasts<B: Backend>(f: &mut Frame<B>, theme: &Theme, app: &App, layout_chunk: Rect) {
+    let toasts = app.toasts();
+    if toasts.is_empty() {
+        return;
+    }
+    let max_width = (layout_chunk.width / 2).max(12);
+    let widest = toasts.iter().map(|n| n.message.width()).max().unwrap_or(0) as u16;
+    let width = (widest + 2).min(max_width).min(layout_chunk.width);
+    let height = (toasts.len() as u16 + 2).min(layout_chunk.height);
+    let area = Rect::new(
+        layout_chunk.x + layout_chunk.width - width,
+        layout_chunk.y + layout_chunk.height - height,
+        width,
+        height,
+    );
+    let lines: Vec<Spans> = toasts
+        .iter()
+        .map(|n| {
+            let style = match n.level {
+                Level::Error => theme.error(),
+                Level::Warning => theme.accent(),
+                Level::Info => theme.text(),
+            };
+            let message = truncate(&n.message, width.saturating_sub(2) as usize, true);
+            Spans::from(Span::styled(message, style))
+        })
+        .collect();
+    let toasts = Paragraph::new(lines)
+        .style(theme.text())
+        .block(Block::default().borders(Borders::ALL));
+    f.render_widget(Clear, area);
+    f.render_widget(toasts, area);
+}
+
+/// What every key does, by category, over the dimmed screen. `scroll` rows
+/// are skipped, as far as there are more rows than fit.
+pub fn draw_help<B: Backend>(
+    f: &mut Frame<B>,
+    theme: &Theme,
+    app: &App,
+    scroll: usize,
+    layout_chunk: Rect,
+) {
+    let help = app.key_bindings().help();
+    let keys_width = help
+        .iter()
+        .flat_map(|(_, actions)| actions.iter())
+        .map(|(_, keys)| help_keys(keys).width())
+        .max()
+        .unwrap_or(0);
+    let mut lines = Vec::new();
+    for (i, (category, actions)) in help.iter().enumerate() {
+        if i > 0 {
+            lines.push(Spans::default());
+        }
+        lines.push(Spans::from(Span::styled(category.name(), theme.bold())));
+        for (action, keys) in actions {
+            let keys = format!("{:width$}", help_keys(keys), width = keys_width);
+            lines.push(Spans::from(vec![
+                Span::styled(keys, theme.bold()),
+                Span::raw("  "),
+                Span::raw(action.description()),
+            ]));
+        }
+    }
+
+    let dim = Block::default().style(Style::default().add_modifier(Modifier::DIM));
+    f.render_widget(dim, layout_chunk);
+    let width = (layout_chunk.width * 3 / 4).max(40);
+    let area = centered(layout_chunk, width, lines.len() as u16 + 2);
+    let scroll = scroll.min(lines.len().saturating_sub(area.height.saturating_sub(2) as usize));
+    let help = Paragraph::new(lines)
+        .style(theme.text())
+        .scroll((scroll as u16, 0))
+        .block(Block::default().title("Help").borders(Borders::ALL));
+    f.render_widget(Clear, area);
+    f.render_widget(help, area);
+}
+
+// `j, down`
+fn help_keys(keys: &[Key]) -> String {
+    keys.iter().map(|&key| key_name(key)).collect::<Vec<_>>().join(", ")
+}
+
+// a `width` x `height` rect in the middle of `outer`, shrunk to fit
+fn centered(outer: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(outer.width);
+    let height = height.min(outer.height);
+    Rect::new(
+        outer.x + (outer.width - width) / 2,
+        outer.y + (outer.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+/// Past searches shown under an empty search input.
+pub fn draw_search_history<B: Backend>(
+    f: &mut Frame<B>,
+    theme: &Theme,
+    app: &App,
+    layout_chunk: Rect,
+) {
+    let items: Vec<ListItem> = app
+        .search_history()
+        .iter()
+        .map(|query| ListItem::new(query.as_str()))
+        .collect();
+    let list = List::new(items)
+        .block(Block::default().title("Recent searches").borders(Borders::ALL))
+        .highlight_style(theme.highlight());
+    let mut state = ListState::default();
+    state.select(app.search_history_index());
+    f.render_stateful_widget(list, layout_chunk, &mut state);
 }
 
-pub fn draw_routes<B: Backend>(f: &mut Frame<B>, app: &App, layout_chunk: Rect) {
-    unimplemented!()
+/// Lyrics of the playing song, the line being sung in bold and kept in the middle.
+pub fn draw_lyrics<B: Backend>(f: &mut Frame<B>, theme: &Theme, app: &App, layout_chunk: Rect) {
+    let block = Block::default().title("Lyrics").borders(Borders::ALL);
+    let lyric = match app.now_playing_lyric() {
+        Some(lyric) if !lyric.is_empty() => lyric,
+        _ => {
+            let empty = Paragraph::new("No lyrics").alignment(Alignment::Center).block(block);
+            return f.render_widget(empty, layout_chunk);
+        }
+    };
+    let current = lyric.current_line(app.playback_context().progress_ms());
+    let lines: Vec<Spans> = lyric
+        .lines()
+        .iter()
+        .enumerate()
+        .map(|(i, (_, text))| {
+            let style = if Some(i) == current { theme.bold() } else { theme.text() };
+            Spans::from(Span::styled(text.as_str(), style))
+        })
+        .collect();
+    let height = block.inner(layout_chunk).height as usize;
+    let scroll = current.map_or(0, |i| i.saturating_sub(height / 2));
+    let lyrics = Paragraph::new(lines)
+        .alignment(Alignment::Center)
+        .scroll((scroll as u16, 0))
+        .block(block);
+    f.render_widget(lyrics, layout_chunk);
+}
+
+/// What was said about the resource asked for, hot or latest first: author,
+/// likes and date over the wrapped content.
+pub fn draw_comments<B: Backend>(f: &mut Frame<B>, theme: &Theme, app: &App, layout_chunk: Rect) {
+    let title = format!("Comments · {}", app.comment_sort().name());
+    let block = Block::default().title(title).borders(Borders::ALL);
+    let comments = app.comment_list();
+    if comments.is_empty() {
+        let text = if app.is_loading() { "Loading…" } else { "No comments" };
+        let empty = Paragraph::new(text).alignment(Alignment::Center).block(block);
+        return f.render_widget(empty, layout_chunk);
+    }
+    let width = block.inner(layout_chunk).width as usize;
+    let mut items: Vec<ListItem> = comments
+        .iter()
+        .map(|comment| {
+            let mut lines = vec![Spans::from(vec![
+                Span::styled(comment.author.clone(), theme.bold()),
+                Span::raw(format!("  ♥ {}  {}", comment.liked_count, format_date(comment.time))),
+            ])];
+            lines.extend(wrap(&comment.content, width).into_iter().map(Spans::from));
+            ListItem::new(lines)
+        })
+        .collect();
+    // the next page loads once the last comment is selected
+    if app.has_more_comments() {
+        items.push(ListItem::new("…"));
+    }
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(theme.highlight());
+    let mut state = ListState::default();
+    state.select(Some(app.comment_index()));
+    f.render_stateful_widget(list, layout_chunk, &mut state);
+}
+
+pub fn draw_podcast<B: Backend>(f: &mut Frame<B>, theme: &Theme, app: &App, layout_chunk: Rect) {
+    let items: Vec<ListItem> = app
+        .podcast_episodes()
+        .iter()
+        .map(|ep| ListItem::new(format_episode(ep)))
+        .collect();
+    let list = List::new(items)
+        .block(Block::default().title("Episodes").borders(Borders::ALL))
+        .highlight_style(theme.highlight());
+    let mut state = ListState::default();
+    if !app.podcast_episodes().is_empty() {
+        state.select(Some(app.episode_index()));
+    }
+    f.render_stateful_widget(list, layout_chunk, &mut state);
+}
+
+fn format_episode(ep: &PodcastEpisode) -> String {
+    let state = if ep.played {
+        "✓".to_owned()
+    } else if let Some(resume) = ep.resume_ms {
+        format!("▶ {}", format_duration(resume))
+    } else {
+        " ".to_owned()
+    };
+    format!(
+        "{} {}  {}  {}",
+        state,
+        ep.title,
+        format_duration(ep.duration_ms),
+        format_date(ep.publish_time)
+    )
+}
+
+/// `mm:ss`, or `h:mm:ss` for an hour or longer.
+pub fn format_duration(ms: u64) -> String {
+    let secs = ms / 1000;
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+/// `yyyy-mm-dd` (UTC) from milliseconds since epoch.
+pub fn format_date(ms: i64) -> String {
+    // days to civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = ms.div_euclid(86_400_000) + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use tui::{
+        backend::TestBackend,
+        style::{Color, Modifier},
+        widgets::Paragraph,
+        Terminal,
+    };
+
+    use std::sync::mpsc;
+
+    use ncmapi::{types::Song, ResourceType};
+    use termion::event::Key;
+
+    use super::{
+        draw_comments, draw_confirm, draw_lyrics, draw_main_layout, draw_playbar, draw_podcast, draw_table, draw_toasts, format_date, format_duration, playbar_status,
+        draw_progress, search_table, spinner, truncate, ColumnType, TableHeader, TableHeaderItem,
+        TableItem, TableType, wrap,
+    };
+    use crate::app::{App, Comment, CommentSort, Level, Podcast, PodcastEpisode, SearchResult};
+    use crate::config::AppConfig;
+    use crate::event::DataEvent;
+    use crate::player::{NullAudioPlayer, PlaybackContext, PlaybackSnapshot, RepeatMode};
+    use crate::theme::Theme;
+
+    #[test]
+    fn test_playbar_status() {
+        let snapshot = PlaybackSnapshot {
+            repeat_mode: RepeatMode::One,
+            shuffle_enabled: false,
+            is_muted: true,
+        };
+        let mut terminal = Terminal::new(TestBackend::new(10, 1)).unwrap();
+        let status = playbar_status(&Theme::default(), &snapshot);
+        terminal.draw(|f| f.render_widget(Paragraph::new(status.clone()), f.size())).unwrap();
+
+        assert_eq!(row(&terminal, 0), "↻1 ⇄ ♪×   ");
+        let buffer = terminal.backend().buffer();
+        // shuffle is off, repeat and mute are on
+        assert!(buffer.get(0, 0).modifier.contains(Modifier::BOLD));
+        assert!(buffer.get(3, 0).modifier.contains(Modifier::DIM));
+        assert!(buffer.get(5, 0).modifier.contains(Modifier::BOLD));
+    }
+
+    fn row(terminal: &Terminal<TestBackend>, y: u16) -> String {
+        let buffer = terminal.backend().buffer();
+        (0..buffer.area.width).map(|x| buffer.get(x, y).symbol.as_str()).collect()
+    }
+
+    #[test]
+    fn test_draw_confirm() {
+        let mut terminal = Terminal::new(TestBackend::new(30, 7)).unwrap();
+        terminal.draw(|f| draw_confirm(f, &Theme::default(), "Quit?", f.size())).unwrap();
+
+        // "Quit? (y/n)" in a 15x3 box, in the middle of the screen
+        assert_eq!(row(&terminal, 2), "       ┌─────────────┐        ");
+        assert_eq!(row(&terminal, 3), "       │ Quit? (y/n) │        ");
+        assert_eq!(row(&terminal, 4), "       └─────────────┘        ");
+        assert!(row(&terminal, 1).trim().is_empty());
+    }
+
+    #[test]
+    fn test_draw_main_layout() {
+        let (io_tx, _io_rx) = mpsc::channel();
+        let mut app = App::new(Box::new(NullAudioPlayer::new()), io_tx);
+        let songs = ["Hello", "Love Story"]
+            .iter()
+            .enumerate()
+            .map(|(id, name)| Song {
+                id,
+                name: name.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        app.handle_data(DataEvent::SearchResults(SearchResult::Song(songs)));
+
+        let mut terminal = Terminal::new(TestBackend::new(60, 12)).unwrap();
+        terminal.draw(|f| draw_main_layout(f, &app, f.size())).unwrap();
+
+        // a 12 column sidebar, the tracks next to it and the playbar below
+        assert!(row(&terminal, 0).starts_with("┌Library───┐┌Tracks"));
+        assert!(row(&terminal, 1).starts_with("│Home      ││Hello "));
+        assert!(row(&terminal, 2).starts_with("│Recommende││Love Story "));
+        assert!(row(&terminal, 6).starts_with("┌Playlists─┐│"));
+        assert!(row(&terminal, 8).starts_with("┌─────"));
+        let buffer = terminal.backend().buffer();
+        // the selected track is highlighted, the current route is bold
+        assert!(buffer.get(13, 1).modifier.contains(Modifier::REVERSED));
+        assert!(!buffer.get(13, 2).modifier.contains(Modifier::REVERSED));
+        assert!(buffer.get(1, 1).modifier.contains(Modifier::BOLD));
+    }
+
+    #[test]
+    fn test_draw_theme() {
+        let (io_tx, _io_rx) = mpsc::channel();
+        let mut config = AppConfig::from_toml("theme = \"solarized\"").unwrap();
+        config.key_bindings_path = None;
+        let mut app = App::new(Box::new(NullAudioPlayer::new()), io_tx).set_config(config);
+        let songs = vec![Song::default(), Song { id: 1, ..Default::default() }];
+        app.handle_data(DataEvent::SearchResults(SearchResult::Song(songs)));
+
+        let mut terminal = Terminal::new(TestBackend::new(60, 12)).unwrap();
+        terminal.draw(|f| draw_main_layout(f, &app, f.size())).unwrap();
+        let theme = Theme::built_in("solarized").unwrap();
+        let buffer = terminal.backend().buffer();
+        assert_eq!(buffer.get(5, 10).bg, theme.background);
+        assert_eq!(buffer.get(13, 2).fg, theme.primary);
+        // the selected row
+        assert_eq!(buffer.get(13, 1).fg, theme.highlight);
+        assert!(buffer.get(13, 1).modifier.contains(Modifier::REVERSED));
+    }
+
+    #[test]
+    fn test_draw_help() {
+        let (io_tx, _io_rx) = mpsc::channel();
+        let mut app = App::new(Box::new(NullAudioPlayer::new()), io_tx);
+        app.handle_action(Key::Char('?'));
+
+        let mut terminal = Terminal::new(TestBackend::new(60, 40)).unwrap();
+        terminal.draw(|f| draw_main_layout(f, &app, f.size())).unwrap();
+        let rows: Vec<String> = (0..40).map(|y| row(&terminal, y)).collect();
+        let top = rows.iter().position(|row| row.contains("┌Help")).unwrap();
+        assert!(rows[top + 1].contains(&format!("│Playback{:35}│", "")));
+        assert!(rows.iter().any(|row| row.contains("│space    Play or pause")));
+        assert!(rows.iter().any(|row| row.contains("│Library")));
+        let buffer = terminal.backend().buffer();
+        // the screen behind is dimmed, the help isn't
+        assert!(buffer.get(0, 0).modifier.contains(Modifier::DIM));
+        assert!(!buffer.get(10, top as u16 + 1).modifier.contains(Modifier::DIM));
+
+        // scrolled down to the last rows
+        app.handle_action(Key::Char('j'));
+        let mut terminal = Terminal::new(TestBackend::new(60, 12)).unwrap();
+        terminal.draw(|f| draw_main_layout(f, &app, f.size())).unwrap();
+        assert!(row(&terminal, 1).contains("│R        Resume where"));
+    }
+
+    #[test]
+    fn test_draw_loading() {
+        let (io_tx, _io_rx) = mpsc::channel();
+        let mut app = App::new(Box::new(NullAudioPlayer::new()), io_tx);
+        app.liked_songs();
+        app.lyric(1);
+
+        let mut terminal = Terminal::new(TestBackend::new(60, 12)).unwrap();
+        terminal.draw(|f| draw_main_layout(f, &app, f.size())).unwrap();
+        assert!(row(&terminal, 0).ends_with("⠋─┐"));
+
+        // up until the last request is answered
+        app.handle_data(DataEvent::LikedSongs(Vec::new()));
+        terminal.draw(|f| draw_main_layout(f, &app, f.size())).unwrap();
+        assert!(row(&terminal, 0).ends_with("⠋─┐"));
+        app.handle_data(DataEvent::Lyric(1, String::new()));
+        terminal.draw(|f| draw_main_layout(f, &app, f.size())).unwrap();
+        assert!(row(&terminal, 0).ends_with("───┐"));
+    }
+
+    #[test]
+    fn test_draw_toasts() {
+        let (io_tx, _io_rx) = mpsc::channel();
+        let mut app = App::new(Box::new(NullAudioPlayer::new()), io_tx);
+        app.notify(Level::Info, "Saved");
+        app.notify(Level::Error, "Network timed out");
+
+        let mut terminal = Terminal::new(TestBackend::new(40, 6)).unwrap();
+        terminal.draw(|f| draw_toasts(f, &Theme::default(), &app, f.size())).unwrap();
+
+        // the oldest on top, in the bottom right corner
+        assert_eq!(row(&terminal, 2).trim_end(), format!("{:21}┌─────────────────┐", ""));
+        assert!(row(&terminal, 3).ends_with("│Saved            │"));
+        assert!(row(&terminal, 4).ends_with("│Network timed out│"));
+        let buffer = terminal.backend().buffer();
+        assert_eq!(buffer.get(22, 4).fg, Color::Red);
+        assert_eq!(buffer.get(22, 3).fg, Color::Reset);
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("Hello", 5, true), "Hello");
+        assert_eq!(truncate("Hello", 4, true), "Hel…");
+        assert_eq!(truncate("Hello", 4, false), "Hell");
+        // wide chars aren't split
+        assert_eq!(truncate("晴天晴天", 6, true), "晴天…");
+        assert_eq!(truncate("Hello", 0, true), "");
+    }
+
+    #[test]
+    fn test_draw_table() {
+        let header = TableHeader::new(
+            TableType::Song,
+            vec![
+                TableHeaderItem::new(ColumnType::Title, "Title", 8),
+                TableHeaderItem::new(ColumnType::Number, "Time", 5),
+            ],
+        );
+        let items = vec![
+            TableItem::new("1", vec!["Love Story".to_owned(), "3:55".to_owned()]),
+            TableItem::new("2", vec!["Hello".to_owned(), "10:02".to_owned()]),
+        ];
+        let mut terminal = Terminal::new(TestBackend::new(16, 5)).unwrap();
+        terminal.draw(|f| draw_table(f, &Theme::default(), f.size(), &header, &items, 1)).unwrap();
+
+        assert_eq!(row(&terminal, 1), "│Title     Time│");
+        assert_eq!(row(&terminal, 2), "│Love St…  3:55│");
+        assert_eq!(row(&terminal, 3), "│Hello    10:02│");
+        let buffer = terminal.backend().buffer();
+        assert!(buffer.get(1, 3).modifier.contains(Modifier::REVERSED));
+        assert!(!buffer.get(1, 2).modifier.contains(Modifier::REVERSED));
+    }
+
+    #[test]
+    fn test_search_table() {
+        let podcast = Podcast {
+            id: 7,
+            name: "talk".to_owned(),
+            dj: "host".to_owned(),
+        };
+        let (header, items) = search_table(&SearchResult::Podcast(vec![podcast]), 20);
+        assert!(matches!(header.id, TableType::Podcast));
+        assert_eq!(header.get_index(ColumnType::None), Some(1));
+        let widths: Vec<u16> = header.items.iter().map(|column| column.width).collect();
+        assert_eq!(widths, vec![12, 5]);
+        assert_eq!(items[0].id, "7");
+        assert_eq!(items[0].format, vec!["talk", "host"]);
+
+        let (header, items) = search_table(&SearchResult::Album(Vec::new()), 20);
+        assert!(matches!(header.id, TableType::Album));
+        assert_eq!(header.items.len(), 1);
+        assert!(items.is_empty());
+    }
+
+    #[test]
+    fn test_draw_progress() {
+        let ctx = PlaybackContext::default();
+        ctx.set_duration_ms(Some(120_000));
+        ctx.set_progress_ms(30_000);
+        let mut terminal = Terminal::new(TestBackend::new(40, 1)).unwrap();
+        terminal.draw(|f| draw_progress(f, &Theme::default(), f.size(), &ctx)).unwrap();
+        let line = row(&terminal, 0);
+        assert!(line.contains("00:30 / -01:30"), "{}", line);
+        assert!(line.ends_with(" 1x  90%"), "{}", line);
+
+        // no duration, no division by zero
+        ctx.set_duration_ms(None);
+        terminal.draw(|f| draw_progress(f, &Theme::default(), f.size(), &ctx)).unwrap();
+        assert_eq!(row(&terminal, 0), "00:30 ──────────────────────━━━─ 1x  90%");
+    }
+
+    #[test]
+    fn test_spinner() {
+        assert_eq!(spinner(6, 0), "━━━───");
+        assert_eq!(spinner(6, 300), "───━━━");
+        assert_eq!(spinner(6, 400), "──━━━─");
+        assert_eq!(spinner(2, 1000), "━━");
+    }
+
+    #[test]
+    fn test_playbar_like() {
+        let (io_tx, _io_rx) = mpsc::channel();
+        let mut app = App::new(Box::new(NullAudioPlayer::new()), io_tx);
+        let song = Song {
+            id: 1,
+            ..Default::default()
+        };
+        app.handle_data(DataEvent::SearchResults(SearchResult::Song(vec![song])));
+        app.handle_action(Key::Char('\n'));
+        let mut terminal = Terminal::new(TestBackend::new(30, 4)).unwrap();
+        terminal.draw(|f| draw_playbar(f, &Theme::default(), &app, f.size())).unwrap();
+        assert!(row(&terminal, 1).contains("♡ ↻"), "{}", row(&terminal, 1));
+
+        app.handle_action(Key::Char('f'));
+        terminal.draw(|f| draw_playbar(f, &Theme::default(), &app, f.size())).unwrap();
+        assert!(row(&terminal, 1).contains("♥ ↻"), "{}", row(&terminal, 1));
+    }
+
+    #[test]
+    fn test_draw_lyrics() {
+        let (io_tx, _io_rx) = mpsc::channel();
+        let mut app = App::new(Box::new(NullAudioPlayer::new()), io_tx);
+        let mut terminal = Terminal::new(TestBackend::new(20, 7)).unwrap();
+        terminal.draw(|f| draw_lyrics(f, &Theme::default(), &app, f.size())).unwrap();
+        assert!(row(&terminal, 1).contains("No lyrics"));
+
+        let song = Song {
+            id: 1,
+            ..Default::default()
+        };
+        app.handle_data(DataEvent::SearchResults(SearchResult::Song(vec![song])));
+        app.handle_action(Key::Char('\n'));
+        let raw = "[00:01.00]a\n[00:02.00]b\n[00:03.00]c\n[00:04.00]d\n[00:05.00]e\n[00:06.00]f";
+        app.handle_data(DataEvent::Lyric(1, raw.to_owned()));
+        app.playback_context().set_progress_ms(4500);
+        terminal.draw(|f| draw_lyrics(f, &Theme::default(), &app, f.size())).unwrap();
+
+        // "d" is sung, in the middle of the 5 rows shown
+        let rows: Vec<String> = (1..6).map(|y| row(&terminal, y)).collect();
+        let texts: Vec<&str> = rows
+            .iter()
+            .map(|r| r.trim_matches(|c| c == '│' || c == ' '))
+            .collect();
+        assert_eq!(texts, vec!["b", "c", "d", "e", "f"]);
+        let x = rows[2].chars().position(|c| c == 'd').unwrap() as u16;
+        let buffer = terminal.backend().buffer();
+        assert!(buffer.get(x, 3).modifier.contains(Modifier::BOLD));
+        assert!(!buffer.get(x, 2).modifier.contains(Modifier::BOLD));
+    }
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(wrap("so good I played it twice", 10), vec!["so good I", "played it", "twice"]);
+        assert_eq!(wrap("晴天晴天", 5), vec!["晴天", "晴天"]);
+        assert_eq!(wrap("a\nb", 5), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_draw_comments() {
+        let (io_tx, _io_rx) = mpsc::channel();
+        let mut app = App::new(Box::new(NullAudioPlayer::new()), io_tx);
+        app.comments(1, ResourceType::Song);
+        let mut terminal = Terminal::new(TestBackend::new(26, 6)).unwrap();
+        terminal.draw(|f| draw_comments(f, &Theme::default(), &app, f.size())).unwrap();
+        assert!(row(&terminal, 1).contains("Loading…"));
+
+        let comment = Comment {
+            id: 7,
+            author: "fan".to_owned(),
+            content: "so good I played it twice".to_owned(),
+            liked_count: 12,
+            time: 1_600_000_000_000,
+        };
+        app.handle_data(DataEvent::Comments {
+            id: 1,
+            kind: ResourceType::Song,
+            sort: CommentSort::Hot,
+            offset: 0,
+            comments: vec![comment],
+            more: true,
+        });
+        terminal.draw(|f| draw_comments(f, &Theme::default(), &app, f.size())).unwrap();
+
+        assert_eq!(row(&terminal, 0), "┌Comments · Hot──────────┐");
+        assert_eq!(row(&terminal, 1), "│fan  ♥ 12  2020-09-13   │");
+        assert_eq!(row(&terminal, 2), "│so good I played it     │");
+        assert_eq!(row(&terminal, 3), "│twice                   │");
+        assert_eq!(row(&terminal, 4), "│…                       │");
+    }
+
+    #[test]
+    fn test_draw_podcast() {
+        let (io_tx, _io_rx) = mpsc::channel();
+        let mut app = App::new(Box::new(NullAudioPlayer::new()), io_tx);
+        app.open_podcast(7);
+        let episodes = (1..=2)
+            .map(|id| PodcastEpisode {
+                id,
+                song_id: id * 100,
+                title: format!("ep{}", id),
+                ..Default::default()
+            })
+            .collect();
+        app.handle_data(DataEvent::PodcastEpisodes(7, episodes));
+        app.handle_action(Key::Down);
+        let mut terminal = Terminal::new(TestBackend::new(40, 4)).unwrap();
+        terminal.draw(|f| draw_podcast(f, &Theme::default(), &app, f.size())).unwrap();
+
+        assert!(row(&terminal, 2).starts_with("│  ep2  00:00  1970-01-01"));
+        let buffer = terminal.backend().buffer();
+        assert!(!buffer.get(1, 1).modifier.contains(Modifier::REVERSED));
+        assert!(buffer.get(1, 2).modifier.contains(Modifier::REVERSED));
+
+        // waiting for the episode's url
+        app.handle_action(Key::Char('\n'));
+        let mut terminal = Terminal::new(TestBackend::new(40, 4)).unwrap();
+        terminal.draw(|f| draw_playbar(f, &Theme::default(), &app, f.size())).unwrap();
+        assert!(row(&terminal, 1).contains("Buffering…"));
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(0), "00:00");
+        assert_eq!(format_duration(61_500), "01:01");
+        assert_eq!(format_duration(3_725_000), "1:02:05");
+    }
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(951_782_400_000), "2000-02-29");
+        assert_eq!(format_date(1_633_046_400_000), "2021-10-01");
+    }
+}
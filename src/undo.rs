@@ -0,0 +1,131 @@
+//! Destructive actions kept around so they can be taken back.
+
+use std::collections::VecDeque;
+
+use ncmapi::types::Song;
+
+use crate::event::IoEvent;
+
+#[derive(Debug, Clone)]
+pub enum UndoAction {
+    Unlike(usize),
+    RemoveFromPlaylist {
+        playlist_id: usize,
+        // (row in the track table, song)
+        rows: Vec<(usize, Song)>,
+    },
+    // the api has no way to take a trashed FM song back
+    FmTrash(usize),
+}
+
+impl UndoAction {
+    pub fn is_reversible(&self) -> bool {
+        !matches!(self, UndoAction::FmTrash(_))
+    }
+
+    /// The request reversing the action, `None` when it can't be reversed.
+    pub fn inverse(&self) -> Option<IoEvent> {
+        match self {
+            UndoAction::Unlike(id) => Some(IoEvent::Fav(*id)),
+            UndoAction::RemoveFromPlaylist { playlist_id, rows } => Some(IoEvent::AddToPlaylist {
+                playlist_id: *playlist_id,
+                song_ids: rows.iter().map(|(_, song)| song.id).collect(),
+            }),
+            UndoAction::FmTrash(_) => None,
+        }
+    }
+}
+
+/// Most recent actions first, the oldest are dropped past `depth`.
+pub struct UndoStack {
+    actions: VecDeque<UndoAction>,
+    depth: usize,
+}
+
+impl UndoStack {
+    pub fn new(depth: usize) -> Self {
+        Self {
+            actions: VecDeque::with_capacity(depth),
+            depth,
+        }
+    }
+
+    pub fn push(&mut self, action: UndoAction) {
+        if self.depth == 0 {
+            return;
+        }
+        if self.actions.len() == self.depth {
+            self.actions.pop_front();
+        }
+        self.actions.push_back(action);
+    }
+
+    /// Pops the latest reversible action, dropping irreversible ones on the way.
+    pub fn pop(&mut self) -> Option<UndoAction> {
+        while let Some(action) = self.actions.pop_back() {
+            if action.is_reversible() {
+                return Some(action);
+            }
+        }
+        None
+    }
+
+    /// Takes out the latest action matching `pred`, e.g. one the api refused
+    /// so there's nothing left to reverse.
+    pub fn remove<F>(&mut self, pred: F) -> Option<UndoAction>
+    where
+        F: Fn(&UndoAction) -> bool,
+    {
+        let index = self.actions.iter().rposition(pred)?;
+        self.actions.remove(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.actions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{UndoAction, UndoStack};
+
+    #[test]
+    fn test_depth_capped() {
+        let mut stack = UndoStack::new(2);
+        for id in 1..=3 {
+            stack.push(UndoAction::Unlike(id));
+        }
+        assert_eq!(stack.len(), 2);
+        assert!(matches!(stack.pop(), Some(UndoAction::Unlike(3))));
+        assert!(matches!(stack.pop(), Some(UndoAction::Unlike(2))));
+        assert!(stack.pop().is_none());
+    }
+
+    #[test]
+    fn test_irreversible_skipped() {
+        let mut stack = UndoStack::new(10);
+        stack.push(UndoAction::Unlike(1));
+        stack.push(UndoAction::FmTrash(2));
+
+        assert!(matches!(stack.pop(), Some(UndoAction::Unlike(1))));
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn test_remove_latest_match() {
+        let mut stack = UndoStack::new(10);
+        for id in &[1, 2, 1] {
+            stack.push(UndoAction::Unlike(*id));
+        }
+
+        let is_one = |action: &UndoAction| matches!(action, UndoAction::Unlike(1));
+        assert!(stack.remove(is_one).is_some());
+        assert!(matches!(stack.pop(), Some(UndoAction::Unlike(2))));
+        assert!(matches!(stack.pop(), Some(UndoAction::Unlike(1))));
+        assert!(stack.remove(is_one).is_none());
+    }
+}
@@ -0,0 +1,115 @@
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bytes::Bytes;
+use ncmapi::types::Song;
+use termion::event::Key;
+
+use lekima::app::{App, SearchResult};
+use lekima::event::{poll_from, spawn_worker, DataEvent, Event, IoEvent};
+use lekima::player::{NullAudioPlayer, RecordingPlayer};
+
+fn song(id: usize, name: &str) -> Song {
+    Song {
+        id,
+        name: name.to_owned(),
+        ..Default::default()
+    }
+}
+
+/// Answers io events with canned data, no network involved.
+fn stub_worker(evt: IoEvent) -> Option<DataEvent> {
+    match evt {
+        IoEvent::Search { key, .. } => Some(DataEvent::SearchResults(SearchResult::Song(vec![
+            song(1, &format!("{} 1", key)),
+            song(2, &format!("{} 2", key)),
+        ]))),
+        IoEvent::SongUrls(ids) => Some(DataEvent::SongUrls(
+            ids.into_iter()
+                .map(|id| (id, Some(format!("http://stub/{}.mp3", id))))
+                .collect(),
+        )),
+        IoEvent::Download(id, _) => Some(DataEvent::Track(id, Bytes::from(format!("track-{}", id)))),
+        _ => None,
+    }
+}
+
+struct Harness {
+    app: App,
+    // tracks the app handed to the player
+    played: Arc<Mutex<Vec<Bytes>>>,
+    data_rx: Receiver<DataEvent>,
+}
+
+impl Harness {
+    fn new() -> Self {
+        let (io_tx, io_rx) = mpsc::channel();
+        let player = RecordingPlayer::new(NullAudioPlayer::new());
+        let played = player.played();
+        let app = App::new(Box::new(player), io_tx);
+        let data_rx = spawn_worker(io_rx, stub_worker);
+        Self {
+            app,
+            played,
+            data_rx,
+        }
+    }
+
+    /// Feeds `keys` through the event poller, letting the worker settle after each one.
+    fn run(&mut self, keys: Vec<Key>) {
+        let total = keys.len();
+        let events = poll_from(keys.into_iter(), Duration::from_secs(3600));
+        let mut handled = 0;
+        while handled < total {
+            match events.recv().unwrap() {
+                Event::Input(key) => {
+                    self.app.handle_action(key);
+                    handled += 1;
+                    self.settle();
+                }
+                Event::Mouse(_) | Event::Resize(..) | Event::Tick => {}
+            }
+        }
+    }
+
+    fn settle(&mut self) {
+        while let Ok(data) = self.data_rx.recv_timeout(Duration::from_millis(100)) {
+            self.app.handle_data(data);
+        }
+    }
+}
+
+#[test]
+fn test_search_select_play() {
+    let mut h = Harness::new();
+    h.run(vec![
+        Key::Char('/'),
+        Key::Char('b'),
+        Key::Char('f'),
+        Key::Char('s'),
+        Key::Char('\n'),
+        Key::Down,
+        Key::Char('\n'),
+    ]);
+
+    let tracks = h.app.track_table().unwrap();
+    assert_eq!(tracks.len(), 2);
+    assert_eq!(tracks[0].name, "bfs 1");
+    assert_eq!(h.app.track_table_index(), 1);
+    assert!(h.app.search_input().is_none());
+    assert!(!h.app.is_loading());
+
+    let played = h.played.lock().unwrap();
+    assert_eq!(played.as_slice(), &[Bytes::from("track-2")]);
+}
+
+#[test]
+fn test_escape_cancels_search() {
+    let mut h = Harness::new();
+    h.run(vec![Key::Char('/'), Key::Char('x'), Key::Esc, Key::Char('\n')]);
+
+    assert!(h.app.search_input().is_none());
+    assert!(h.app.track_table().is_none());
+    assert!(h.played.lock().unwrap().is_empty());
+}